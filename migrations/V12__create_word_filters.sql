@@ -0,0 +1,7 @@
+CREATE TABLE IF NOT EXISTS word_filters (
+    id SERIAL8 PRIMARY KEY,
+    server_id INT8 NOT NULL,
+    kind TEXT NOT NULL, -- word, wildcard or regex
+    pattern TEXT NOT NULL,
+    action JSONB NOT NULL -- automod action applied on a match
+);
\ No newline at end of file
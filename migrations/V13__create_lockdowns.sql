@@ -0,0 +1,6 @@
+CREATE TABLE IF NOT EXISTS lockdowns (
+    id SERIAL8 PRIMARY KEY,
+    server_id INT8 NOT NULL,
+    channel_id INT8, -- locked channel, NULL if the whole server is locked
+    end_time TIMESTAMPTZ NOT NULL
+);
\ No newline at end of file
@@ -0,0 +1,14 @@
+CREATE TABLE IF NOT EXISTS sanctions (
+    id SERIAL8 PRIMARY KEY,
+    server_id INT8 NOT NULL,
+    user_id INT8 NOT NULL,
+    moderator_id INT8 NOT NULL,
+    kind TEXT NOT NULL, -- mute or ban
+    reason TEXT NOT NULL,
+    end_time TIMESTAMPTZ, -- NULL for permanent sanctions
+    created_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    appeal TEXT, -- text of the appeal, NULL if the user did not appeal
+    appeal_msg_id INT8, -- message in the appeals channel
+    appeal_status TEXT NOT NULL DEFAULT 'none', -- none, pending, accepted or denied
+    decided_by INT8
+);
\ No newline at end of file
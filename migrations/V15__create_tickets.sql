@@ -0,0 +1,10 @@
+CREATE TABLE IF NOT EXISTS tickets (
+    id SERIAL8 PRIMARY KEY,
+    server_id INT8 NOT NULL,
+    user_id INT8 NOT NULL,
+    channel_id INT8 NOT NULL, -- staff channel or thread the ticket is relayed to
+    open BOOL NOT NULL DEFAULT TRUE,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    closed_at TIMESTAMPTZ,
+    closed_by INT8
+);
\ No newline at end of file
@@ -0,0 +1,9 @@
+CREATE TABLE IF NOT EXISTS ticket_messages (
+    id SERIAL8 PRIMARY KEY,
+    ticket_id INT8 NOT NULL,
+    author_id INT8 NOT NULL,
+    from_staff BOOL NOT NULL,
+    anonymous BOOL NOT NULL DEFAULT FALSE,
+    content TEXT NOT NULL,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT NOW()
+);
\ No newline at end of file
@@ -0,0 +1,7 @@
+ALTER TABLE favs
+    ADD COLUMN content TEXT, -- snapshot of the message, NULL until backfilled
+    ADD COLUMN author_name TEXT,
+    ADD COLUMN author_avatar TEXT,
+    ADD COLUMN msg_timestamp TIMESTAMPTZ,
+    ADD COLUMN channel_name TEXT,
+    ADD COLUMN attachments JSONB NOT NULL DEFAULT '[]';
\ No newline at end of file
@@ -0,0 +1,8 @@
+CREATE TABLE IF NOT EXISTS collections (
+    id SERIAL8 PRIMARY KEY,
+    server_id INT8 NOT NULL, -- Server the collection belongs to and is shared on
+    user_id INT8 NOT NULL,
+    name TEXT NOT NULL,
+    public BOOL NOT NULL DEFAULT FALSE,
+    UNIQUE (server_id, user_id, name)
+);
\ No newline at end of file
@@ -0,0 +1,6 @@
+CREATE TABLE IF NOT EXISTS collection_favs (
+    id SERIAL8 PRIMARY KEY,
+    collection_id INT8 NOT NULL,
+    fav_id INT8 NOT NULL,
+    UNIQUE (collection_id, fav_id)
+);
\ No newline at end of file
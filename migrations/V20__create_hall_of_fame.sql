@@ -0,0 +1,8 @@
+CREATE TABLE IF NOT EXISTS hall_of_fame (
+    id SERIAL8 PRIMARY KEY,
+    server_id INT8 NOT NULL,
+    channel_id INT8 NOT NULL, -- Channel id of the reposted message
+    msg_id INT8 NOT NULL, -- Message id of the reposted message
+    post_id INT8, -- Message id of the repost in the hall of fame channel
+    UNIQUE (channel_id, msg_id)
+);
\ No newline at end of file
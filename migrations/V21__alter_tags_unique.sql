@@ -0,0 +1,5 @@
+-- labels are case folded and unique per fav from now on
+UPDATE tags SET label = LOWER(TRIM(label));
+DELETE FROM tags WHERE label = '';
+DELETE FROM tags USING tags AS duplicate WHERE tags.fav_id = duplicate.fav_id AND tags.label = duplicate.label AND tags.id > duplicate.id;
+ALTER TABLE tags ADD CONSTRAINT tags_fav_id_label_key UNIQUE (fav_id, label);
\ No newline at end of file
@@ -0,0 +1,8 @@
+CREATE TABLE IF NOT EXISTS fav_posts (
+    id SERIAL8 PRIMARY KEY,
+    fav_id INT8 NOT NULL,
+    server_id INT8, -- Server the fav was posted on, NULL in DMs
+    channel_id INT8 NOT NULL,
+    user_id INT8 NOT NULL, -- Who posted the fav
+    posted_at TIMESTAMPTZ NOT NULL DEFAULT NOW()
+);
\ No newline at end of file
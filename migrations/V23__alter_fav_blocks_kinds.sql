@@ -0,0 +1,5 @@
+-- besides single messages whole channels and authors can be blocked
+ALTER TABLE fav_blocks ADD COLUMN kind TEXT NOT NULL DEFAULT 'message'; -- message, channel or author
+ALTER TABLE fav_blocks ADD COLUMN author_id INT8; -- Blocked author for author blocks
+ALTER TABLE fav_blocks ALTER COLUMN msg_id DROP NOT NULL;
+ALTER TABLE fav_blocks ALTER COLUMN channel_id DROP NOT NULL;
\ No newline at end of file
@@ -0,0 +1,12 @@
+CREATE TABLE IF NOT EXISTS bank_transactions (
+    id SERIAL8 PRIMARY KEY,
+    user_id INT8 NOT NULL,
+    kind TEXT NOT NULL, -- opening, payday, transfer, slot_bet, slot_payout or adjustment
+    amount INT8 NOT NULL, -- Change of the balance, negative for debits
+    balance INT8 NOT NULL, -- Balance after the change
+    counterparty_id INT8, -- Other user of a transfer
+    created_at TIMESTAMPTZ NOT NULL DEFAULT NOW()
+);
+CREATE INDEX IF NOT EXISTS bank_transactions_user_id ON bank_transactions (user_id, id);
+-- Existing balances become the opening entries, so every balance is the sum of its ledger
+INSERT INTO bank_transactions (user_id, kind, amount, balance) SELECT user_id, 'opening', amount, amount FROM banks;
\ No newline at end of file
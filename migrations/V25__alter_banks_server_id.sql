@@ -0,0 +1,9 @@
+-- Banks are kept per server, the global banks from before get server 0 and are taken over
+-- by the first server their owner creates a bank on
+ALTER TABLE banks ADD COLUMN IF NOT EXISTS server_id INT8 NOT NULL DEFAULT 0;
+ALTER TABLE banks ALTER COLUMN server_id DROP DEFAULT;
+CREATE UNIQUE INDEX IF NOT EXISTS banks_server_id_user_id ON banks (server_id, user_id) WHERE server_id <> 0;
+ALTER TABLE bank_transactions ADD COLUMN IF NOT EXISTS server_id INT8 NOT NULL DEFAULT 0;
+ALTER TABLE bank_transactions ALTER COLUMN server_id DROP DEFAULT;
+DROP INDEX IF EXISTS bank_transactions_user_id;
+CREATE INDEX IF NOT EXISTS bank_transactions_server_id_user_id ON bank_transactions (server_id, user_id, id);
\ No newline at end of file
@@ -0,0 +1,10 @@
+CREATE TABLE IF NOT EXISTS duels (
+    id SERIAL8 PRIMARY KEY,
+    server_id INT8 NOT NULL,
+    challenger_id INT8 NOT NULL,
+    opponent_id INT8 NOT NULL,
+    stake INT8 NOT NULL, -- Escrowed from the challenger when the duel opens
+    status TEXT NOT NULL DEFAULT 'open', -- open, done or refunded
+    winner_id INT8,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT NOW()
+);
\ No newline at end of file
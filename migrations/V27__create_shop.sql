@@ -0,0 +1,29 @@
+CREATE TABLE IF NOT EXISTS shop_items (
+    id SERIAL8 PRIMARY KEY,
+    server_id INT8 NOT NULL,
+    name TEXT NOT NULL,
+    kind TEXT NOT NULL, -- role, color, nickname or ping
+    price INT8 NOT NULL,
+    role_id INT8, -- granted role of role items
+    duration_hours INT8, -- how long granted roles last, NULL if they are permanent
+    created_at TIMESTAMPTZ NOT NULL DEFAULT NOW()
+);
+
+CREATE TABLE IF NOT EXISTS inventory_items (
+    id SERIAL8 PRIMARY KEY,
+    server_id INT8 NOT NULL,
+    user_id INT8 NOT NULL,
+    shop_item_id INT8 REFERENCES shop_items (id) ON DELETE SET NULL,
+    -- copied from the shop item, so removing it from the shop keeps bought items usable
+    name TEXT NOT NULL,
+    kind TEXT NOT NULL,
+    price INT8 NOT NULL,
+    duration_hours INT8,
+    role_id INT8, -- role granted on use, the created role of color items
+    used_at TIMESTAMPTZ,
+    expires_at TIMESTAMPTZ, -- when the granted role is removed again
+    expired BOOL NOT NULL DEFAULT FALSE,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT NOW()
+);
+
+CREATE INDEX IF NOT EXISTS inventory_items_user_idx ON inventory_items (server_id, user_id);
\ No newline at end of file
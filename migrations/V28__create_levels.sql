@@ -0,0 +1,10 @@
+CREATE TABLE IF NOT EXISTS levels (
+    id SERIAL8 PRIMARY KEY,
+    server_id INT8 NOT NULL,
+    user_id INT8 NOT NULL,
+    xp INT8 NOT NULL DEFAULT 0,
+    last_xp_at TIMESTAMPTZ NOT NULL DEFAULT NOW(), -- messages only give xp after a cooldown
+    UNIQUE (server_id, user_id)
+);
+
+CREATE INDEX IF NOT EXISTS levels_server_id_xp ON levels (server_id, xp DESC);
\ No newline at end of file
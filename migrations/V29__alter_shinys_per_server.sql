@@ -0,0 +1,15 @@
+-- Shinys used to be updated by user across all servers, keep one row per server and user
+DELETE FROM shinys a USING shinys b WHERE a.server_id = b.server_id AND a.user_id = b.user_id AND a.id < b.id;
+CREATE UNIQUE INDEX IF NOT EXISTS shinys_server_id_user_id ON shinys (server_id, user_id);
+
+-- Totals from before the history only live in shinys.amount
+CREATE TABLE IF NOT EXISTS shiny_contributions (
+    id SERIAL8 PRIMARY KEY,
+    server_id INT8 NOT NULL,
+    user_id INT8 NOT NULL,
+    amount INT8 NOT NULL, -- negative for corrections by mods
+    note TEXT,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT NOW()
+);
+
+CREATE INDEX IF NOT EXISTS shiny_contributions_server_id_created_at ON shiny_contributions (server_id, created_at);
\ No newline at end of file
@@ -0,0 +1,23 @@
+CREATE TABLE IF NOT EXISTS polls (
+    id SERIAL8 PRIMARY KEY,
+    server_id INT8 NOT NULL,
+    channel_id INT8 NOT NULL,
+    msg_id INT8, -- set once the poll message is posted
+    author_id INT8 NOT NULL,
+    question TEXT NOT NULL,
+    answers TEXT[] NOT NULL,
+    multiple BOOL NOT NULL, -- whether users may vote for several answers
+    anonymous BOOL NOT NULL, -- votes by buttons instead of reactions
+    end_time TIMESTAMPTZ NOT NULL,
+    closed BOOL NOT NULL DEFAULT FALSE,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT NOW()
+);
+
+CREATE INDEX IF NOT EXISTS polls_msg_id ON polls (msg_id);
+
+CREATE TABLE IF NOT EXISTS poll_votes (
+    poll_id INT8 NOT NULL REFERENCES polls (id) ON DELETE CASCADE,
+    user_id INT8 NOT NULL,
+    answer INT4 NOT NULL, -- index into polls.answers
+    PRIMARY KEY (poll_id, user_id, answer)
+);
\ No newline at end of file
@@ -0,0 +1,4 @@
+-- Lockdowns are stored without a duration too, they remember the @everyone overwrites of the
+-- channels they locked so unlocking puts them back
+ALTER TABLE lockdowns ALTER COLUMN end_time DROP NOT NULL;
+ALTER TABLE lockdowns ADD COLUMN IF NOT EXISTS overwrites JSONB NOT NULL DEFAULT '[]';
\ No newline at end of file
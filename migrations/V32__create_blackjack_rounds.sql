@@ -0,0 +1,8 @@
+CREATE TABLE IF NOT EXISTS blackjack_rounds (
+    id SERIAL8 PRIMARY KEY,
+    server_id INT8 NOT NULL,
+    user_id INT8 NOT NULL,
+    bet INT8 NOT NULL, -- Taken from the player when the round opens
+    status TEXT NOT NULL DEFAULT 'open', -- open, done or refunded
+    created_at TIMESTAMPTZ NOT NULL DEFAULT NOW()
+);
\ No newline at end of file
@@ -0,0 +1,606 @@
+use chrono::{DateTime, Duration, Utc};
+use once_cell::sync::Lazy;
+use regex::Regex;
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, VecDeque};
+
+static INVITE_REGEX: Lazy<Regex> = Lazy::new(|| {
+    Regex::new(r"(?i)(discord\.gg|discord(?:app)?\.com/invite)/[a-z0-9-]+")
+        .expect("Could not compile invite regex")
+});
+
+/// What the bot does once a rule is violated
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum Action {
+    Delete,
+    Warn,
+    Mute { secs: u64 },
+    Kick,
+    Lockdown { secs: Option<u64> },
+}
+
+impl Action {
+    /// Parses actions like `delete`, `mute 10m` or `lockdown 1h`
+    pub fn parse(args: &[&str]) -> Result<Self, String> {
+        let duration = |arg: Option<&&str>| {
+            arg.map(|d| {
+                crate::util::parse_duration(d)
+                    .map(|d| d.num_seconds() as u64)
+                    .ok_or_else(|| format!("Invalid duration: {}", d))
+            })
+            .transpose()
+        };
+
+        match args.first().copied() {
+            Some("delete") => Ok(Self::Delete),
+            Some("warn") => Ok(Self::Warn),
+            Some("mute") => Ok(Self::Mute {
+                secs: duration(args.get(1))?.unwrap_or(10 * 60),
+            }),
+            Some("kick") => Ok(Self::Kick),
+            Some("lockdown") => Ok(Self::Lockdown {
+                secs: duration(args.get(1))?,
+            }),
+            Some(other) => Err(format!("Unknown action: {}", other)),
+            None => Err("Missing action".to_string()),
+        }
+    }
+}
+
+impl std::fmt::Display for Action {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let humanize = |secs: u64| crate::util::humanize_duration(&Duration::seconds(secs as i64));
+
+        match self {
+            Self::Delete => write!(f, "delete"),
+            Self::Warn => write!(f, "warn"),
+            Self::Mute { secs } => write!(f, "mute for {}", humanize(*secs)),
+            Self::Kick => write!(f, "kick"),
+            Self::Lockdown { secs: Some(secs) } => write!(f, "lockdown for {}", humanize(*secs)),
+            Self::Lockdown { secs: None } => write!(f, "lockdown"),
+        }
+    }
+}
+
+/// A rule that triggers once more than `max` events happen within `per_secs`
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RateRule {
+    pub max: u32,
+    pub per_secs: u64,
+    pub action: Action,
+}
+
+/// A rule that triggers once a single message contains more than `max` of something
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+pub struct LimitRule {
+    pub max: u32,
+    pub action: Action,
+}
+
+// Keep every rule optional, a missing rule is simply not checked
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq, Eq)]
+#[serde(default)]
+pub struct Config {
+    pub enabled: bool,
+    pub message_rate: Option<RateRule>,
+    pub duplicates: Option<RateRule>,
+    pub mass_mentions: Option<LimitRule>,
+    pub invite_links: Option<Action>,
+    pub attachment_flood: Option<RateRule>,
+    pub join_raid: Option<RateRule>,
+}
+
+impl Config {
+    /// Applies a setting from the config command, e.g. `rate 5 10s mute 10m` or `invites off`
+    pub fn apply_setting(&mut self, rule: &str, args: &[&str]) -> Result<(), String> {
+        let off = args.first() == Some(&"off");
+
+        let rate_rule = || -> Result<RateRule, String> {
+            let max = args
+                .first()
+                .and_then(|m| m.parse::<u32>().ok())
+                .ok_or("Missing or invalid maximum")?;
+            let per_secs = args
+                .get(1)
+                .and_then(|d| crate::util::parse_duration(d))
+                .filter(|d| *d > Duration::zero())
+                .ok_or("Missing or invalid time window")?
+                .num_seconds() as u64;
+
+            Ok(RateRule {
+                max,
+                per_secs,
+                action: Action::parse(&args[2..])?,
+            })
+        };
+
+        match rule {
+            "on" => self.enabled = true,
+            "off" => self.enabled = false,
+            "rate" => self.message_rate = if off { None } else { Some(rate_rule()?) },
+            "duplicates" => self.duplicates = if off { None } else { Some(rate_rule()?) },
+            "attachments" => self.attachment_flood = if off { None } else { Some(rate_rule()?) },
+            "joins" => self.join_raid = if off { None } else { Some(rate_rule()?) },
+            "mentions" => {
+                self.mass_mentions = if off {
+                    None
+                } else {
+                    let max = args
+                        .first()
+                        .and_then(|m| m.parse::<u32>().ok())
+                        .ok_or("Missing or invalid maximum")?;
+                    Some(LimitRule {
+                        max,
+                        action: Action::parse(&args[1..])?,
+                    })
+                }
+            }
+            "invites" => {
+                self.invite_links = if off {
+                    None
+                } else {
+                    Some(Action::parse(args)?)
+                }
+            }
+            other => return Err(format!("Unknown rule: {}", other)),
+        }
+
+        Ok(())
+    }
+
+    fn longest_window(&self) -> Duration {
+        [self.message_rate, self.duplicates, self.attachment_flood]
+            .iter()
+            .flatten()
+            .map(|r| Duration::seconds(r.per_secs as i64))
+            .max()
+            .unwrap_or_else(Duration::zero)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Rule {
+    MessageRate,
+    Duplicates,
+    MassMentions,
+    InviteLinks,
+    AttachmentFlood,
+    JoinRaid,
+}
+
+impl Rule {
+    pub const fn describe(self) -> &'static str {
+        match self {
+            Self::MessageRate => "sending messages too fast",
+            Self::Duplicates => "sending duplicate messages",
+            Self::MassMentions => "mass mentions",
+            Self::InviteLinks => "posting invite links",
+            Self::AttachmentFlood => "flooding attachments",
+            Self::JoinRaid => "join raid",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Violation {
+    pub rule: Rule,
+    pub action: Action,
+}
+
+/// The parts of a guild message the engine looks at
+#[derive(Debug, Clone)]
+pub struct MessageEvent<'a> {
+    pub guild_id: u64,
+    pub user_id: u64,
+    pub at: DateTime<Utc>,
+    pub content: &'a str,
+    pub mentions: usize,
+    pub attachments: usize,
+}
+
+#[derive(Debug, Clone)]
+struct Seen {
+    at: DateTime<Utc>,
+    content: String,
+    attachments: usize,
+}
+
+#[derive(Debug, Default)]
+struct GuildState {
+    users: HashMap<u64, VecDeque<Seen>>,
+    joins: VecDeque<DateTime<Utc>>,
+    raid_until: Option<DateTime<Utc>>,
+}
+
+/// Keeps the recent history per guild and decides which rules are violated
+#[derive(Debug, Default)]
+pub struct Engine {
+    guilds: HashMap<u64, GuildState>,
+}
+
+impl Engine {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn on_message(&mut self, config: &Config, event: &MessageEvent<'_>) -> Vec<Violation> {
+        let mut violations = Vec::new();
+        if !config.enabled {
+            return violations;
+        }
+
+        if let Some(rule) = config.mass_mentions {
+            if event.mentions > rule.max as usize {
+                violations.push(Violation {
+                    rule: Rule::MassMentions,
+                    action: rule.action,
+                });
+            }
+        }
+
+        if let Some(action) = config.invite_links {
+            if INVITE_REGEX.is_match(event.content) {
+                violations.push(Violation {
+                    rule: Rule::InviteLinks,
+                    action,
+                });
+            }
+        }
+
+        // trim every history to the window and forget who stopped writing
+        let guild = self.guilds.entry(event.guild_id).or_default();
+        let oldest = event.at - config.longest_window();
+        guild.users.retain(|user_id, history| {
+            while history.front().map_or(false, |s| s.at < oldest) {
+                history.pop_front();
+            }
+            *user_id == event.user_id || !history.is_empty()
+        });
+        let history = guild.users.entry(event.user_id).or_default();
+
+        let content = normalize(event.content);
+        history.push_back(Seen {
+            at: event.at,
+            content: content.clone(),
+            attachments: event.attachments,
+        });
+
+        let within = |rule: &RateRule| {
+            let since = event.at - Duration::seconds(rule.per_secs as i64);
+            history.iter().filter(move |s| s.at >= since)
+        };
+
+        let mut history_violated = false;
+
+        if let Some(rule) = config.message_rate {
+            if within(&rule).count() > rule.max as usize {
+                history_violated = true;
+                violations.push(Violation {
+                    rule: Rule::MessageRate,
+                    action: rule.action,
+                });
+            }
+        }
+
+        if let Some(rule) = config.duplicates {
+            if !content.is_empty()
+                && within(&rule).filter(|s| s.content == content).count() > rule.max as usize
+            {
+                history_violated = true;
+                violations.push(Violation {
+                    rule: Rule::Duplicates,
+                    action: rule.action,
+                });
+            }
+        }
+
+        if let Some(rule) = config.attachment_flood {
+            if event.attachments > 0
+                && within(&rule).map(|s| s.attachments).sum::<usize>() > rule.max as usize
+            {
+                history_violated = true;
+                violations.push(Violation {
+                    rule: Rule::AttachmentFlood,
+                    action: rule.action,
+                });
+            }
+        }
+
+        // start counting from scratch so the same burst is not punished again
+        if history_violated {
+            history.clear();
+        }
+
+        violations
+    }
+
+    pub fn on_join(
+        &mut self,
+        config: &Config,
+        guild_id: u64,
+        at: DateTime<Utc>,
+    ) -> Option<Violation> {
+        let rule = config.join_raid.filter(|_| config.enabled)?;
+        let guild = self.guilds.entry(guild_id).or_default();
+        let window = Duration::seconds(rule.per_secs as i64);
+
+        if let Some(until) = guild.raid_until {
+            if at < until {
+                // only keep kicking newcomers, the lockdown is already in place
+                return matches!(rule.action, Action::Kick).then(|| Violation {
+                    rule: Rule::JoinRaid,
+                    action: rule.action,
+                });
+            }
+            guild.raid_until = None;
+        }
+
+        while guild.joins.front().map_or(false, |t| *t < at - window) {
+            guild.joins.pop_front();
+        }
+        guild.joins.push_back(at);
+
+        if guild.joins.len() > rule.max as usize {
+            guild.joins.clear();
+            guild.raid_until = Some(at + window);
+
+            Some(Violation {
+                rule: Rule::JoinRaid,
+                action: rule.action,
+            })
+        } else {
+            None
+        }
+    }
+}
+
+fn normalize(content: &str) -> String {
+    content
+        .split_whitespace()
+        .collect::<Vec<_>>()
+        .join(" ")
+        .to_lowercase()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::TimeZone;
+
+    fn config() -> Config {
+        Config {
+            enabled: true,
+            message_rate: Some(RateRule {
+                max: 4,
+                per_secs: 5,
+                action: Action::Mute { secs: 600 },
+            }),
+            duplicates: Some(RateRule {
+                max: 2,
+                per_secs: 30,
+                action: Action::Delete,
+            }),
+            mass_mentions: Some(LimitRule {
+                max: 5,
+                action: Action::Kick,
+            }),
+            invite_links: Some(Action::Delete),
+            attachment_flood: Some(RateRule {
+                max: 3,
+                per_secs: 10,
+                action: Action::Warn,
+            }),
+            join_raid: Some(RateRule {
+                max: 3,
+                per_secs: 10,
+                action: Action::Lockdown { secs: Some(900) },
+            }),
+        }
+    }
+
+    fn message(secs: i64, content: &str) -> MessageEvent<'_> {
+        MessageEvent {
+            guild_id: 1,
+            user_id: 2,
+            at: Utc.timestamp(1_600_000_000 + secs, 0),
+            content,
+            mentions: 0,
+            attachments: 0,
+        }
+    }
+
+    fn rules(violations: &[Violation]) -> Vec<Rule> {
+        violations.iter().map(|v| v.rule).collect()
+    }
+
+    #[test]
+    fn message_rate_triggers_once() {
+        let mut engine = Engine::new();
+        let config = config();
+
+        for i in 0..4 {
+            let msg = format!("message {}", i);
+            assert!(engine.on_message(&config, &message(i, &msg)).is_empty());
+        }
+
+        let violations = engine.on_message(&config, &message(4, "message 4"));
+        assert_eq!(rules(&violations), vec![Rule::MessageRate]);
+        assert_eq!(violations[0].action, Action::Mute { secs: 600 });
+
+        // history was reset after the violation
+        assert!(engine
+            .on_message(&config, &message(4, "message 5"))
+            .is_empty());
+    }
+
+    #[test]
+    fn slow_messages_are_fine() {
+        let mut engine = Engine::new();
+        let config = config();
+
+        for i in 0..20 {
+            let msg = format!("message {}", i);
+            assert!(engine.on_message(&config, &message(i * 2, &msg)).is_empty());
+        }
+    }
+
+    #[test]
+    fn quiet_users_are_forgotten() {
+        let mut engine = Engine::new();
+        let config = config();
+
+        engine.on_message(&config, &message(0, "hi"));
+        let other = MessageEvent {
+            user_id: 3,
+            ..message(10, "hello")
+        };
+        engine.on_message(&config, &other);
+        assert_eq!(engine.guilds[&1].users.len(), 2);
+
+        // the longest window is 30 seconds
+        let other = MessageEvent {
+            user_id: 3,
+            ..message(40, "still here")
+        };
+        engine.on_message(&config, &other);
+        assert_eq!(engine.guilds[&1].users.keys().collect::<Vec<_>>(), vec![&3]);
+    }
+
+    #[test]
+    fn duplicates_are_normalized() {
+        let mut engine = Engine::new();
+        let config = config();
+
+        assert!(engine
+            .on_message(&config, &message(0, "buy  now"))
+            .is_empty());
+        assert!(engine
+            .on_message(&config, &message(10, "BUY now"))
+            .is_empty());
+        assert_eq!(
+            rules(&engine.on_message(&config, &message(20, " buy now "))),
+            vec![Rule::Duplicates]
+        );
+    }
+
+    #[test]
+    fn mentions_and_invites() {
+        let mut engine = Engine::new();
+        let config = config();
+
+        let mut msg = message(0, "join https://discord.gg/abc123 now");
+        msg.mentions = 6;
+
+        assert_eq!(
+            rules(&engine.on_message(&config, &msg)),
+            vec![Rule::MassMentions, Rule::InviteLinks]
+        );
+        assert!(engine
+            .on_message(&config, &message(1, "discord.com/channels/1/2/3"))
+            .is_empty());
+    }
+
+    #[test]
+    fn attachment_flood() {
+        let mut engine = Engine::new();
+        let config = config();
+
+        let mut msg = message(0, "");
+        msg.attachments = 2;
+        assert!(engine.on_message(&config, &msg).is_empty());
+
+        let mut msg = message(1, "look");
+        msg.attachments = 2;
+        assert_eq!(
+            rules(&engine.on_message(&config, &msg)),
+            vec![Rule::AttachmentFlood]
+        );
+    }
+
+    #[test]
+    fn disabled_config_never_triggers() {
+        let mut engine = Engine::new();
+        let config = Config {
+            enabled: false,
+            ..config()
+        };
+
+        for i in 0..10 {
+            assert!(engine.on_message(&config, &message(0, "spam")).is_empty());
+            assert!(engine
+                .on_join(&config, 1, Utc.timestamp(1_600_000_000 + i, 0))
+                .is_none());
+        }
+    }
+
+    #[test]
+    fn join_raid_triggers_once_per_window() {
+        let mut engine = Engine::new();
+        let config = config();
+        let at = |secs: i64| Utc.timestamp(1_600_000_000 + secs, 0);
+
+        assert!(engine.on_join(&config, 1, at(0)).is_none());
+        assert!(engine.on_join(&config, 1, at(1)).is_none());
+        assert!(engine.on_join(&config, 1, at(2)).is_none());
+        assert_eq!(
+            engine.on_join(&config, 1, at(3)).map(|v| v.rule),
+            Some(Rule::JoinRaid)
+        );
+        // lockdown is already active
+        assert!(engine.on_join(&config, 1, at(4)).is_none());
+        // other guilds are not affected
+        assert!(engine.on_join(&config, 2, at(4)).is_none());
+        // after the window the counting starts again
+        assert!(engine.on_join(&config, 1, at(30)).is_none());
+    }
+
+    #[test]
+    fn raid_kicks_every_newcomer() {
+        let mut engine = Engine::new();
+        let config = Config {
+            join_raid: Some(RateRule {
+                max: 1,
+                per_secs: 60,
+                action: Action::Kick,
+            }),
+            ..config()
+        };
+        let at = |secs: i64| Utc.timestamp(1_600_000_000 + secs, 0);
+
+        assert!(engine.on_join(&config, 1, at(0)).is_none());
+        assert!(engine.on_join(&config, 1, at(1)).is_some());
+        assert!(engine.on_join(&config, 1, at(2)).is_some());
+    }
+
+    #[test]
+    fn settings() {
+        let mut config = Config::default();
+
+        config.apply_setting("on", &[]).unwrap();
+        config
+            .apply_setting("rate", &["5", "10s", "mute", "1h"])
+            .unwrap();
+        config.apply_setting("invites", &["warn"]).unwrap();
+        config.apply_setting("mentions", &["8", "kick"]).unwrap();
+
+        assert!(config.enabled);
+        assert_eq!(
+            config.message_rate,
+            Some(RateRule {
+                max: 5,
+                per_secs: 10,
+                action: Action::Mute { secs: 3600 }
+            })
+        );
+        assert_eq!(config.invite_links, Some(Action::Warn));
+        assert_eq!(config.mass_mentions.map(|r| r.max), Some(8));
+
+        config.apply_setting("invites", &["off"]).unwrap();
+        assert_eq!(config.invite_links, None);
+
+        assert!(config.apply_setting("rate", &["5"]).is_err());
+        assert!(config.apply_setting("nope", &[]).is_err());
+        assert!(config.apply_setting("joins", &["5", "1m", "ban"]).is_err());
+    }
+}
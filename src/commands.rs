@@ -1,14 +1,18 @@
 pub mod about;
 pub mod account;
+pub mod appeal;
 pub mod choose;
 pub mod config;
 pub mod copypasta;
 pub mod emoji;
 pub mod fav;
 pub mod fighting;
+pub mod filter;
 pub mod groups;
 pub mod lastfm;
+pub mod levels;
 pub mod moderation;
+pub mod modmail;
 pub mod optout;
 pub mod owner;
 pub mod poll;
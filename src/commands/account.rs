@@ -1,2 +1,219 @@
+pub mod blackjack;
+pub mod coinflip;
+pub mod duel;
 pub mod general;
+pub mod roulette;
+pub mod shop;
 pub mod slot;
+
+use crate::commands::config::{update_guild_config, Guild};
+use crate::models::bank::{Bank, Entry, LedgerError};
+use crate::models::bank_transaction::Kind;
+use crate::models::server_config::{DbError, ServerConfig};
+use crate::util::get_client;
+use serde::{Deserialize, Serialize};
+use serenity::framework::standard::CommandError;
+use serenity::model::{channel::Message, id::GuildId};
+use serenity::prelude::*;
+use sqlx::postgres::PgPool;
+
+/// The games that can be turned off per server
+pub const GAMES: [&str; 5] = ["slot", "blackjack", "coinflip", "roulette", "duel"];
+
+/// The economy of a server
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+#[serde(default)]
+pub struct Config {
+    /// Credits of a new bank
+    pub starting_balance: i64,
+    pub payday_amount: i64,
+    /// Hours between two paydays
+    pub payday_cooldown: i64,
+    /// Games from `GAMES` that are turned off
+    pub disabled_games: Vec<String>,
+    /// The slot machine in use
+    pub slot: slot::Machine,
+    /// A slot machine waiting for `acc slot activate`
+    pub slot_draft: Option<slot::Machine>,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            starting_balance: 1000,
+            payday_amount: 1000,
+            payday_cooldown: 24,
+            disabled_games: Vec::new(),
+            slot: slot::Machine::default(),
+            slot_draft: None,
+        }
+    }
+}
+
+impl Config {
+    pub fn apply_setting(&mut self, setting: &str, value: &str) -> Result<(), String> {
+        if GAMES.contains(&setting) {
+            let enabled = match value {
+                "on" => true,
+                "off" => false,
+                other => return Err(format!("Expected on or off, got: {}", other)),
+            };
+            self.disabled_games.retain(|g| g != setting);
+            if !enabled {
+                self.disabled_games.push(setting.to_string());
+            }
+            return Ok(());
+        }
+
+        let number = value
+            .parse::<i64>()
+            .ok()
+            .filter(|n| *n >= 0)
+            .ok_or_else(|| format!("Expected a positive number, got: {}", value))?;
+
+        match setting {
+            "starting_balance" => self.starting_balance = number,
+            "payday_amount" => self.payday_amount = number,
+            "payday_cooldown" => self.payday_cooldown = number,
+            other => return Err(format!("Unknown economy setting: {}", other)),
+        }
+
+        Ok(())
+    }
+
+    pub fn game_enabled(&self, game: &str) -> bool {
+        !self.disabled_games.iter().any(|g| g == game)
+    }
+}
+
+pub async fn config(pool: &PgPool, guild_id: GuildId) -> Config {
+    match ServerConfig::get(pool, *guild_id.as_u64() as i64).await {
+        Ok(mut config) => serde_json::from_value::<Guild>(config.config.take())
+            .map(|g_cfg| g_cfg.economy)
+            .unwrap_or_default(),
+        Err(_e) => Config::default(),
+    }
+}
+
+/// Replaces the economy in the config of the server
+pub async fn store_config(
+    pool: &PgPool,
+    guild_id: GuildId,
+    economy: Config,
+) -> Result<(), DbError> {
+    update_guild_config(pool, *guild_id.as_u64() as i64, |g| {
+        g.economy = economy;
+        Ok(())
+    })
+    .await
+    .map(drop)
+}
+
+/// Whether the game is turned on for the server of the message, replies if it is not
+pub async fn game_enabled(ctx: &Context, msg: &Message, game: &str) -> Result<bool, CommandError> {
+    let guild_id = msg.guild_id.ok_or("Games only exist on servers")?;
+    let pool = get_client(ctx).await?;
+
+    if config(&pool, guild_id).await.game_enabled(game) {
+        Ok(true)
+    } else {
+        msg.reply(ctx, format!("{} is turned off on this server", game))
+            .await?;
+        Ok(false)
+    }
+}
+
+/// Books the bet and the payout of a round at once. Replies and returns `None` if the bank is
+/// missing or too small, then nothing is booked.
+pub async fn play(
+    ctx: &Context,
+    msg: &Message,
+    bet: i64,
+    payout: i64,
+) -> Result<Option<Bank>, CommandError> {
+    play_as(ctx, msg, (Kind::Bet, Kind::Payout), bet, payout).await
+}
+
+/// Like `play`, booking the bet and the payout as the given kinds
+pub async fn play_as(
+    ctx: &Context,
+    msg: &Message,
+    (bet_kind, payout_kind): (Kind, Kind),
+    bet: i64,
+    payout: i64,
+) -> Result<Option<Bank>, CommandError> {
+    let server_id = *msg.guild_id.ok_or("Games only exist on servers")?.as_u64() as i64;
+    let user_id = *msg.author.id.as_u64() as i64;
+    let pool = get_client(ctx).await?;
+
+    let mut entries = vec![Entry::new(user_id, bet_kind, -bet)];
+    if payout > 0 {
+        entries.push(Entry::new(user_id, payout_kind, payout));
+    }
+
+    match Bank::apply(&pool, server_id, &entries).await {
+        Ok(mut banks) => Ok(banks.pop()),
+        Err(e) => {
+            refuse(ctx, msg, e).await?;
+            Ok(None)
+        }
+    }
+}
+
+/// Tells the user why a booking failed, database errors are passed on
+pub async fn refuse(ctx: &Context, msg: &Message, e: LedgerError) -> Result<(), CommandError> {
+    match e {
+        LedgerError::Insufficient { .. } => {
+            msg.channel_id
+                .say(
+                    &ctx,
+                    "You are missing the necessary credits for this action!",
+                )
+                .await?;
+        }
+        LedgerError::NoBank(user_id) if user_id == *msg.author.id.as_u64() as i64 => {
+            msg.channel_id
+                .say(&ctx, "Create your own bank first by running 'acc create'")
+                .await?;
+        }
+        LedgerError::NoBank(_) | LedgerError::Overflow(_) => {
+            msg.channel_id.say(&ctx, e.to_string()).await?;
+        }
+        LedgerError::Db(e) => return Err(e.into()),
+    }
+
+    Ok(())
+}
+
+/// Expected loss of the house per credit bet, from the payouts of many rounds with the bet
+#[cfg(test)]
+#[allow(clippy::cast_precision_loss)]
+pub fn house_edge(bet: i64, payouts: impl Iterator<Item = i64>) -> f64 {
+    let (rounds, paid) = payouts.fold((0_i64, 0_i64), |(n, sum), p| (n + 1, sum + p));
+    1.0 - paid as f64 / (rounds * bet) as f64
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Config;
+
+    #[test]
+    fn economy_settings() {
+        let mut config = Config::default();
+        config.apply_setting("payday_amount", "250").unwrap();
+        config.apply_setting("payday_cooldown", "12").unwrap();
+        assert_eq!(config.payday_amount, 250);
+        assert_eq!(config.payday_cooldown, 12);
+
+        assert!(config.apply_setting("starting_balance", "-5").is_err());
+        assert!(config.apply_setting("interest", "5").is_err());
+
+        config.apply_setting("duel", "off").unwrap();
+        config.apply_setting("duel", "off").unwrap();
+        assert!(!config.game_enabled("duel"));
+        assert_eq!(config.disabled_games.len(), 1);
+        config.apply_setting("duel", "on").unwrap();
+        assert!(config.game_enabled("duel"));
+        assert!(config.apply_setting("roulette", "maybe").is_err());
+    }
+}
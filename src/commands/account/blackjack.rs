@@ -0,0 +1,300 @@
+use crate::commands::account;
+use crate::models::bank::Bank;
+use crate::models::blackjack_round::BlackjackRound;
+use crate::util::get_client;
+use rand::prelude::*;
+use serenity::futures::stream::StreamExt;
+use serenity::model::channel::ReactionType;
+use serenity::prelude::*;
+use serenity::{
+    framework::standard::{macros::command, Args, CommandError, CommandResult},
+    model::channel::Message,
+};
+use std::time::Duration;
+
+const HIT: &str = "\u{1f44a}";
+const STAND: &str = "\u{270b}";
+
+/// A card of the deck from 0 to 51, the rank is `card / 4 + 1` with 1 as ace
+type Card = u8;
+
+const fn rank(card: Card) -> u8 {
+    card / 4 + 1
+}
+
+/// Aces count 11 while the hand stays at 21 or below
+fn value(hand: &[Card]) -> u8 {
+    let total: u8 = hand.iter().map(|c| rank(*c).min(10)).sum();
+    if hand.iter().any(|c| rank(*c) == 1) && total + 10 <= 21 {
+        total + 10
+    } else {
+        total
+    }
+}
+
+fn is_blackjack(hand: &[Card]) -> bool {
+    hand.len() == 2 && value(hand) == 21
+}
+
+fn show_card(card: Card) -> String {
+    let rank = match rank(card) {
+        1 => "A".to_string(),
+        11 => "J".to_string(),
+        12 => "Q".to_string(),
+        13 => "K".to_string(),
+        n => n.to_string(),
+    };
+    let suit = ["\u{2660}", "\u{2665}", "\u{2666}", "\u{2663}"][(card % 4) as usize];
+    format!("{}{}", rank, suit)
+}
+
+fn show_hand(hand: &[Card]) -> String {
+    let cards = hand.iter().map(|c| show_card(*c)).collect::<Vec<_>>();
+    format!("{} ({})", cards.join(" "), value(hand))
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Outcome {
+    Blackjack,
+    Win,
+    Push,
+    Lose,
+}
+
+impl Outcome {
+    /// What the round pays back including the bet, a blackjack pays 3 to 2. `None` if the bet
+    /// is too high to pay out.
+    fn payout(self, bet: i64) -> Option<i64> {
+        match self {
+            Self::Blackjack => bet.checked_mul(5).map(|b| b / 2),
+            Self::Win => bet.checked_mul(2),
+            Self::Push => Some(bet),
+            Self::Lose => Some(0),
+        }
+    }
+}
+
+/// One round from a fresh deck, the dealer stands on all 17s
+struct Game {
+    deck: Vec<Card>,
+    player: Vec<Card>,
+    dealer: Vec<Card>,
+}
+
+impl Game {
+    fn new(rng: &mut impl Rng) -> Self {
+        let mut deck = (0..52).collect::<Vec<Card>>();
+        deck.shuffle(rng);
+        let player = vec![deck.pop().unwrap(), deck.pop().unwrap()];
+        let dealer = vec![deck.pop().unwrap(), deck.pop().unwrap()];
+
+        Self {
+            deck,
+            player,
+            dealer,
+        }
+    }
+
+    fn hit(&mut self) {
+        // a round never uses up a deck
+        self.player.push(self.deck.pop().expect("Deck is empty"));
+    }
+
+    /// Whether the player has no choice left
+    fn over(&self) -> bool {
+        value(&self.player) >= 21 || is_blackjack(&self.dealer)
+    }
+
+    /// Plays the dealer hand and decides the round
+    fn finish(&mut self) -> Outcome {
+        if value(&self.player) > 21 {
+            return Outcome::Lose;
+        }
+        match (is_blackjack(&self.player), is_blackjack(&self.dealer)) {
+            (true, true) => return Outcome::Push,
+            (true, false) => return Outcome::Blackjack,
+            (false, true) => return Outcome::Lose,
+            (false, false) => {}
+        }
+
+        while value(&self.dealer) < 17 {
+            self.dealer.push(self.deck.pop().expect("Deck is empty"));
+        }
+
+        let (player, dealer) = (value(&self.player), value(&self.dealer));
+        if dealer > 21 || player > dealer {
+            Outcome::Win
+        } else if player == dealer {
+            Outcome::Push
+        } else {
+            Outcome::Lose
+        }
+    }
+
+    /// The table while the player decides, the second dealer card stays hidden
+    fn show_open(&self) -> String {
+        format!(
+            "Dealer: {} ??\nYou: {}\n\n{} hit | {} stand",
+            show_card(self.dealer[0]),
+            show_hand(&self.player),
+            HIT,
+            STAND
+        )
+    }
+
+    fn show_result(&self, outcome: Outcome, payout: i64, balance: i64) -> String {
+        format!(
+            "Dealer: {}\nYou: {}\n\n{:?}! Won: {}\nBank: {}",
+            show_hand(&self.dealer),
+            show_hand(&self.player),
+            outcome,
+            payout,
+            balance
+        )
+    }
+}
+
+/// Shows the table and lets the player hit until they stand, bust or the time is up
+async fn play_round(
+    ctx: &Context,
+    msg: &Message,
+    game: &mut Game,
+) -> Result<Message, CommandError> {
+    let mut bot_msg = msg
+        .channel_id
+        .send_message(&ctx, |m| {
+            m.embed(|e| e.description(game.show_open()).color((0, 120, 220)))
+        })
+        .await?;
+
+    if !game.over() {
+        for emoji in [HIT, STAND].iter() {
+            bot_msg
+                .react(ctx, ReactionType::Unicode(emoji.to_string()))
+                .await?;
+        }
+
+        // ignore add/remove reaction difference, the round ends with a stand or the timeout
+        let mut collector = bot_msg
+            .await_reactions(&ctx)
+            .timeout(Duration::from_secs(60))
+            .author_id(msg.author.id)
+            .removed(true)
+            .await;
+        while let Some(reaction) = collector.next().await {
+            match reaction.as_inner_ref().emoji {
+                ReactionType::Unicode(ref s) if s == HIT => game.hit(),
+                ReactionType::Unicode(ref s) if s == STAND => break,
+                _ => continue,
+            }
+            if game.over() {
+                break;
+            }
+            bot_msg
+                .edit(ctx, |m| {
+                    m.embed(|e| e.description(game.show_open()).color((0, 120, 220)))
+                })
+                .await?;
+        }
+    }
+
+    Ok(bot_msg)
+}
+
+#[command]
+#[description = "Play blackjack against the dealer. React with \u{1f44a} to hit and \u{270b} to stand, blackjack pays 3 to 2 and the dealer stands on 17"]
+#[num_args(1)]
+#[usage = "*bet_amount*"]
+#[example = "100"]
+#[only_in("guilds")]
+pub async fn blackjack(ctx: &Context, msg: &Message, mut args: Args) -> CommandResult {
+    if !account::game_enabled(ctx, msg, "blackjack").await? {
+        return Ok(());
+    }
+    // a blackjack pays the most, any outcome of the bet has to be payable
+    let bet = match args.single::<i64>() {
+        Ok(v) if v > 0 && Outcome::Blackjack.payout(v).is_some() => v,
+        _ => {
+            std::mem::drop(msg.channel_id.say(&ctx, "Invalid bet!").await);
+            return Ok(());
+        }
+    };
+    let pool = get_client(ctx).await?;
+    let server_id = *msg.guild_id.ok_or("Games only exist on servers")?.as_u64() as i64;
+    let user_id = *msg.author.id.as_u64() as i64;
+
+    // the bet is taken up front, the credits can not be spent elsewhere during the round
+    let round = match BlackjackRound::open(&pool, server_id, user_id, bet).await {
+        Ok(round) => round,
+        Err(e) => {
+            account::refuse(ctx, msg, e).await?;
+            return Ok(());
+        }
+    };
+
+    // a failed round returns the bet, rounds cut off by a restart are refunded on startup
+    let mut game = Game::new(&mut rand::thread_rng());
+    let mut bot_msg = match play_round(ctx, msg, &mut game).await {
+        Ok(bot_msg) => bot_msg,
+        Err(e) => {
+            BlackjackRound::refund(&pool, round.id).await?;
+            return Err(e);
+        }
+    };
+
+    let outcome = game.finish();
+    let payout = outcome.payout(bet).unwrap_or_default();
+    BlackjackRound::settle(&pool, round.id, payout).await?;
+    let bank = Bank::get(&pool, server_id, user_id).await?;
+
+    bot_msg
+        .edit(ctx, |m| {
+            m.embed(|e| {
+                e.description(game.show_result(outcome, payout, bank.amount))
+                    .color((0, 120, 220))
+            })
+        })
+        .await?;
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{value, Game, Outcome};
+    use crate::commands::account::house_edge;
+    use rand::{rngs::StdRng, SeedableRng};
+
+    #[test]
+    fn hand_values() {
+        // ranks are card / 4 + 1
+        let (ace, five, six, king) = (0, 16, 20, 48);
+        assert_eq!(value(&[ace, king]), 21);
+        assert_eq!(value(&[ace, five]), 16);
+        assert_eq!(value(&[ace, five, six]), 12);
+        assert_eq!(value(&[ace, ace + 1, king]), 12);
+        assert_eq!(value(&[king, king + 1, five]), 25);
+    }
+
+    #[test]
+    fn blackjack_house_edge() {
+        assert_eq!(Outcome::Blackjack.payout(10), Some(25));
+        assert_eq!(Outcome::Blackjack.payout(11), Some(27));
+        assert_eq!(Outcome::Push.payout(10), Some(10));
+        assert_eq!(Outcome::Win.payout(i64::MAX), None);
+
+        // hitting below 17 like the dealer gives the house about 5.5%
+        let mut rng = StdRng::seed_from_u64(21);
+        let edge = house_edge(
+            100,
+            (0..100_000).map(|_| {
+                let mut game = Game::new(&mut rng);
+                while !game.over() && value(&game.player) < 17 {
+                    game.hit();
+                }
+                game.finish().payout(100).unwrap()
+            }),
+        );
+        assert!((0.02..0.09).contains(&edge), "edge {}", edge);
+    }
+}
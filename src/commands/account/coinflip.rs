@@ -0,0 +1,118 @@
+use crate::commands::account;
+use rand::prelude::*;
+use serenity::prelude::*;
+use serenity::{
+    framework::standard::{macros::command, Args, CommandResult},
+    model::channel::Message,
+};
+use std::str::FromStr;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Side {
+    Heads,
+    Tails,
+}
+
+impl FromStr for Side {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "heads" | "head" | "kopf" => Ok(Self::Heads),
+            "tails" | "tail" | "zahl" => Ok(Self::Tails),
+            other => Err(format!("Call heads or tails, not {}", other)),
+        }
+    }
+}
+
+/// A won flip pays 1.95 times the bet, the house keeps 2.5%. `None` if the bet is too high
+/// to pay out.
+fn payout(bet: i64, call: Side, flip: Side) -> Option<i64> {
+    let won = bet.checked_mul(195)? / 100;
+    Some(if call == flip { won } else { 0 })
+}
+
+fn flip(rng: &mut impl Rng) -> Side {
+    if rng.gen_bool(0.5) {
+        Side::Heads
+    } else {
+        Side::Tails
+    }
+}
+
+#[command]
+#[description = "Flip a coin for credits, a right call pays 1.95 times the bet"]
+#[num_args(2)]
+#[usage = "*bet_amount* *heads/tails*"]
+#[example = "100 heads"]
+#[only_in("guilds")]
+pub async fn coinflip(ctx: &Context, msg: &Message, mut args: Args) -> CommandResult {
+    if !account::game_enabled(ctx, msg, "coinflip").await? {
+        return Ok(());
+    }
+    let bet = match args.single::<i64>() {
+        Ok(v) if v > 0 => v,
+        _ => {
+            std::mem::drop(msg.channel_id.say(&ctx, "Invalid bet!").await);
+            return Ok(());
+        }
+    };
+    let call = match args.single::<String>()?.parse::<Side>() {
+        Ok(call) => call,
+        Err(e) => {
+            msg.reply(ctx, e).await?;
+            return Ok(());
+        }
+    };
+
+    let flip = flip(&mut rand::thread_rng());
+    let payout = match payout(bet, call, flip) {
+        Some(payout) => payout,
+        None => {
+            std::mem::drop(msg.channel_id.say(&ctx, "Invalid bet!").await);
+            return Ok(());
+        }
+    };
+
+    if let Some(bank) = account::play(ctx, msg, bet, payout).await? {
+        msg.channel_id
+            .send_message(&ctx, |m| {
+                m.embed(|e| {
+                    e.description(format!(
+                        "{}\n\nWon: {}\nBank: {}",
+                        match flip {
+                            Side::Heads => "\u{1f5ff} Heads",
+                            Side::Tails => "\u{1fa99} Tails",
+                        },
+                        payout,
+                        bank.amount
+                    ))
+                    .color((0, 120, 220))
+                })
+            })
+            .await?;
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{flip, payout, Side};
+    use crate::commands::account::house_edge;
+    use rand::{rngs::StdRng, SeedableRng};
+
+    #[test]
+    fn coinflip_house_edge() {
+        assert_eq!(payout(100, Side::Heads, Side::Heads), Some(195));
+        assert_eq!(payout(100, Side::Heads, Side::Tails), Some(0));
+        assert_eq!(payout(i64::MAX / 100, Side::Heads, Side::Tails), None);
+
+        let mut rng = StdRng::seed_from_u64(43);
+        let edge = house_edge(
+            100,
+            (0..200_000).map(|_| payout(100, Side::Tails, flip(&mut rng)).unwrap()),
+        );
+        assert!((0.015..0.035).contains(&edge), "edge {}", edge);
+    }
+}
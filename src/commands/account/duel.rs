@@ -0,0 +1,157 @@
+use crate::commands::account;
+use crate::models::bank::LedgerError;
+use crate::models::duel::Duel;
+use crate::util::get_client;
+use rand::prelude::*;
+use serenity::model::channel::ReactionType;
+use serenity::prelude::*;
+use serenity::utils::parse_username;
+use serenity::{
+    framework::standard::{macros::command, Args, CommandResult},
+    model::{channel::Message, id::UserId},
+};
+use std::time::Duration;
+
+const ACCEPT: &str = "\u{2694}\u{fe0f}";
+
+/// Duels are fair, the winner takes both stakes without a house cut
+fn challenger_wins(rng: &mut impl Rng) -> bool {
+    rng.gen_bool(0.5)
+}
+
+#[command]
+#[description = "Challenge a user to a duel for credits, the winner takes both stakes. Your stake is held until the duel is decided or returned if it is not accepted within a minute."]
+#[num_args(2)]
+#[usage = "*@user* *stake*"]
+#[example = "@HansTrashy 500"]
+#[only_in("guilds")]
+pub async fn duel(ctx: &Context, msg: &Message, mut args: Args) -> CommandResult {
+    if !account::game_enabled(ctx, msg, "duel").await? {
+        return Ok(());
+    }
+    let opponent_id = match args
+        .single::<String>()
+        .ok()
+        .and_then(|u| parse_username(&u))
+    {
+        Some(id) if id != *msg.author.id.as_u64() => id,
+        _ => {
+            msg.reply(ctx, "Mention the user you want to duel").await?;
+            return Ok(());
+        }
+    };
+    let stake = match args.single::<i64>() {
+        Ok(v) if v > 0 => v,
+        _ => {
+            std::mem::drop(msg.channel_id.say(&ctx, "Invalid stake!").await);
+            return Ok(());
+        }
+    };
+    let pool = get_client(ctx).await?;
+    let server_id = *msg.guild_id.ok_or("Games only exist on servers")?.as_u64() as i64;
+
+    let duel = match Duel::open(
+        &pool,
+        server_id,
+        *msg.author.id.as_u64() as i64,
+        opponent_id as i64,
+        stake,
+    )
+    .await
+    {
+        Ok(duel) => duel,
+        Err(e) => {
+            account::refuse(ctx, msg, e).await?;
+            return Ok(());
+        }
+    };
+
+    let bot_msg = msg
+        .channel_id
+        .say(
+            ctx,
+            format!(
+                "<@{}>, <@{}> challenges you to a duel for {} credits! React with {} within a minute to accept.",
+                opponent_id, msg.author.id, stake, ACCEPT
+            ),
+        )
+        .await?;
+    bot_msg
+        .react(ctx, ReactionType::Unicode(ACCEPT.to_string()))
+        .await?;
+
+    let accepted = bot_msg
+        .await_reaction(&ctx)
+        .timeout(Duration::from_secs(60))
+        .author_id(UserId(opponent_id))
+        .filter(|reaction| {
+            matches!(reaction.emoji, ReactionType::Unicode(ref value) if value == ACCEPT)
+        })
+        .await
+        .is_some();
+
+    if !accepted {
+        Duel::refund(&pool, duel.id).await?;
+        msg.channel_id
+            .say(
+                ctx,
+                format!(
+                    "The duel was not accepted, <@{}> got the stake back",
+                    msg.author.id
+                ),
+            )
+            .await?;
+        return Ok(());
+    }
+
+    let challenger_wins = challenger_wins(&mut rand::thread_rng());
+    match Duel::settle(&pool, duel.id, challenger_wins).await {
+        Ok(Some(duel)) => {
+            msg.channel_id
+                .say(
+                    ctx,
+                    format!(
+                        "<@{}> wins the duel and takes {} credits!",
+                        duel.winner_id.unwrap_or_default(),
+                        2 * duel.stake
+                    ),
+                )
+                .await?;
+        }
+        Ok(None) => {}
+        Err(LedgerError::Db(e)) => {
+            Duel::refund(&pool, duel.id).await?;
+            return Err(e.into());
+        }
+        Err(e) => {
+            // the opponent could not cover the stake
+            Duel::refund(&pool, duel.id).await?;
+            msg.channel_id
+                .say(
+                    ctx,
+                    format!("{}, <@{}> got the stake back", e, msg.author.id),
+                )
+                .await?;
+        }
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::challenger_wins;
+    use crate::commands::account::house_edge;
+    use rand::{rngs::StdRng, SeedableRng};
+
+    #[test]
+    fn duel_is_fair() {
+        // from the view of the challenger, who pays the stake and wins both
+        let mut rng = StdRng::seed_from_u64(2);
+        let edge = house_edge(
+            50,
+            (0..200_000).map(|_| if challenger_wins(&mut rng) { 100 } else { 0 }),
+        );
+        assert!(edge.abs() < 0.01, "edge {}", edge);
+    }
+}
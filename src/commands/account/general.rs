@@ -1,36 +1,53 @@
-use crate::models::bank::Bank;
+use crate::commands::account;
+use crate::models::bank::{Bank, Entry, LedgerError};
+use crate::models::bank_transaction::{BankTransaction, Kind};
 use crate::util::get_client;
 use chrono::prelude::*;
 use serenity::prelude::*;
+use serenity::utils::parse_username;
 use serenity::{
     framework::standard::{macros::command, Args, CommandResult},
     model::channel::Message,
 };
 use tracing::debug;
 
+/// Leaderboard entries per page
+const PAGE_SIZE: i64 = 10;
+
 #[command]
 #[description = "Create a slot account"]
 #[num_args(0)]
+#[only_in("guilds")]
 pub async fn create(ctx: &Context, msg: &Message, _args: Args) -> CommandResult {
     let pool = get_client(ctx).await?;
+    let guild_id = msg.guild_id.ok_or("Banks only exist on servers")?;
+    let server_id = *guild_id.as_u64() as i64;
     // check if user already owns a bank
-    if let Ok(bank) = Bank::get(&pool, *msg.author.id.as_u64() as i64).await {
+    if let Ok(bank) = Bank::get(&pool, server_id, *msg.author.id.as_u64() as i64).await {
         std::mem::drop(
             msg.reply(ctx, &format!("Your bank balance: {}", bank.amount))
                 .await,
         );
     } else {
+        let economy = account::config(&pool, guild_id).await;
         let bank = Bank::create(
             &pool,
+            server_id,
             *msg.author.id.as_u64() as i64,
             msg.author.name.to_string(),
-            1000,
+            economy.starting_balance,
             Utc::now().naive_utc(),
         )
-        .await;
+        .await?;
         debug!("Created bank entry {:?}", bank);
 
-        std::mem::drop(msg.reply(ctx, "Created bank!").await);
+        std::mem::drop(
+            msg.reply(
+                ctx,
+                &format!("Created bank! Your bank balance: {}", bank.amount),
+            )
+            .await,
+        );
     }
     Ok(())
 }
@@ -39,67 +56,107 @@ pub async fn create(ctx: &Context, msg: &Message, _args: Args) -> CommandResult
 #[description = "Receive your daily allowance credits"]
 #[aliases("paydaddy")]
 #[num_args(0)]
+#[only_in("guilds")]
 pub async fn payday(ctx: &Context, msg: &Message, _args: Args) -> CommandResult {
     let pool = get_client(ctx).await?;
+    let guild_id = msg.guild_id.ok_or("Banks only exist on servers")?;
+    let server_id = *guild_id.as_u64() as i64;
+    let economy = account::config(&pool, guild_id).await;
     // check if user has a bank & last payday was over 24h ago
 
-    if let Ok(bank) = Bank::get(&pool, *msg.author.id.as_u64() as i64).await {
-        let hours_diff = Utc::now()
-            .naive_utc()
-            .signed_duration_since(bank.last_payday)
-            .num_hours();
-        if hours_diff > 23 {
-            let updated_amount = if bank.amount < 0 {
-                1000
-            } else {
-                bank.amount + 1000
-            };
-
-            Bank::update(
-                &pool,
-                *msg.author.id.as_u64() as i64,
-                updated_amount,
-                Utc::now().naive_utc(),
-            )
-            .await?;
-
+    match Bank::payday(
+        &pool,
+        server_id,
+        *msg.author.id.as_u64() as i64,
+        economy.payday_amount,
+        economy.payday_cooldown,
+    )
+    .await
+    {
+        Ok(Some(bank)) => {
             std::mem::drop(
-                msg.reply(ctx, &format!("Your new balance: {}", &updated_amount))
+                msg.reply(ctx, &format!("Your new balance: {}", &bank.amount))
                     .await,
             );
-        } else {
+        }
+        Ok(None) => {
+            let bank = Bank::get(&pool, server_id, *msg.author.id.as_u64() as i64).await?;
+            let hours_diff = Utc::now()
+                .naive_utc()
+                .signed_duration_since(bank.last_payday)
+                .num_hours();
             std::mem::drop(
                 msg.reply(
                     ctx,
-                    &format!("Wait {} hours for your next Payday!", (24 - &hours_diff)),
+                    &format!(
+                        "Wait {} hours for your next Payday!",
+                        (economy.payday_cooldown - &hours_diff)
+                    ),
                 )
                 .await,
             );
         }
-    } else {
-        std::mem::drop(
-            msg.reply(ctx, "Create your own bank first by running 'acc create'")
-                .await,
-        );
+        Err(LedgerError::NoBank(_)) => {
+            std::mem::drop(
+                msg.reply(ctx, "Create your own bank first by running 'acc create'")
+                    .await,
+            );
+        }
+        Err(e @ LedgerError::Overflow(_)) => {
+            std::mem::drop(msg.reply(ctx, e.to_string()).await);
+        }
+        Err(e) => return Err(e.into()),
     }
     Ok(())
 }
 
 #[command]
-#[description = "List the leading players"]
-#[num_args(0)]
-pub async fn leaderboard(ctx: &Context, msg: &Message, _args: Args) -> CommandResult {
+#[description = "List the leading players of this server"]
+#[max_args(1)]
+#[usage = "*page*"]
+#[example = "2"]
+#[only_in("guilds")]
+pub async fn leaderboard(ctx: &Context, msg: &Message, mut args: Args) -> CommandResult {
     let pool = get_client(ctx).await?;
-    let results = Bank::top10(&pool).await?;
+    let guild_id = msg.guild_id.ok_or("Banks only exist on servers")?;
+    let server_id = *guild_id.as_u64() as i64;
+    let page = if args.is_empty() {
+        1
+    } else {
+        match args.single::<i64>() {
+            Ok(page) if page > 0 => page,
+            _ => {
+                msg.reply(ctx, "Pages start at 1").await?;
+                return Ok(());
+            }
+        }
+    };
 
-    let mut rendered_leaderboard = String::from("Top Ten:\n");
+    let pages = (Bank::count_of_server(&pool, server_id).await? + PAGE_SIZE - 1) / PAGE_SIZE;
+    let results = Bank::top(&pool, server_id, (page - 1) * PAGE_SIZE, PAGE_SIZE).await?;
+
+    let mut rendered_leaderboard = String::from("Leaderboard:\n");
     for (i, r) in results.iter().enumerate() {
-        rendered_leaderboard.push_str(&format!("\n{} | {} | {}", i + 1, r.amount, r.user_name));
+        // names change, the one from creating the bank is only the fallback
+        let name = match guild_id.member(ctx, r.user_id as u64).await {
+            Ok(member) => member.display_name().to_string(),
+            Err(_e) => r.user_name.clone(),
+        };
+        rendered_leaderboard.push_str(&format!(
+            "\n{} | {} | {}",
+            (page - 1) * PAGE_SIZE + i as i64 + 1,
+            r.amount,
+            name
+        ));
     }
 
     msg.channel_id
         .send_message(&ctx, |m| {
-            m.embed(|e| e.description(&rendered_leaderboard).color((0, 120, 220)))
+            m.embed(|e| {
+                e.description(&rendered_leaderboard)
+                    .footer(|f| f.text(format!("Page {}/{}", page, pages.max(1))))
+                    .color((0, 120, 220))
+            })
         })
         .await?;
     Ok(())
@@ -109,8 +166,10 @@ pub async fn leaderboard(ctx: &Context, msg: &Message, _args: Args) -> CommandRe
 #[description = "Transfer credits from your bank to a list of other users"]
 #[usage = "*amount* *from_user_mention* *to_user_mention*"]
 #[example = "1000 @HansTrashy @ApoY2k"]
+#[only_in("guilds")]
 pub async fn transfer(ctx: &Context, msg: &Message, mut args: Args) -> CommandResult {
     let pool = get_client(ctx).await?;
+    let server_id = *msg.guild_id.ok_or("Banks only exist on servers")?.as_u64() as i64;
     let amount_to_transfer = match args.single::<i64>() {
         Ok(v) if v > 0 => v,
         Ok(_) => {
@@ -125,24 +184,16 @@ pub async fn transfer(ctx: &Context, msg: &Message, mut args: Args) -> CommandRe
         }
     };
 
-    let mentions_count = msg.mentions.len() as i64;
-
-    if let Ok(bank) = Bank::get(&pool, *msg.author.id.as_u64() as i64).await {
-        // check if user has enough balance
-        if mentions_count * amount_to_transfer <= bank.amount {
-            let updated_amount = bank.amount - mentions_count * amount_to_transfer;
-
-            // remove the needed money
-            Bank::update(&pool, bank.user_id, updated_amount, bank.last_payday).await?;
-
-            for mention in &msg.mentions {
-                if let Ok(bank) = Bank::get(&pool, *mention.id.as_u64() as i64).await {
-                    let mentioned_user_amount = bank.amount + amount_to_transfer;
-                    Bank::update(&pool, bank.user_id, mentioned_user_amount, bank.last_payday)
-                        .await?;
-                }
-            }
+    let from = *msg.author.id.as_u64() as i64;
+    let entries = msg
+        .mentions
+        .iter()
+        .flat_map(|mention| Entry::transfer(from, *mention.id.as_u64() as i64, amount_to_transfer))
+        .collect::<Vec<_>>();
 
+    // all credits move at once or none do
+    match Bank::apply(&pool, server_id, &entries).await {
+        Ok(_) => {
             let mentioned_user_names: Vec<String> =
                 msg.mentions.iter().map(|u| u.name.clone()).collect();
             msg.reply(
@@ -153,16 +204,214 @@ pub async fn transfer(ctx: &Context, msg: &Message, mut args: Args) -> CommandRe
                 ),
             )
             .await?;
-        } else {
+        }
+        Err(LedgerError::NoBank(user_id)) if user_id == from => {
+            msg.reply(ctx, "Create your own bank first by running 'acc create'")
+                .await?;
+        }
+        Err(LedgerError::NoBank(user_id)) => {
+            msg.reply(
+                ctx,
+                &format!("<@{}> has no bank yet, nothing was transferred", user_id),
+            )
+            .await?;
+        }
+        Err(LedgerError::Insufficient { .. }) => {
             msg.reply(
                 ctx,
                 "You cannot transfer more credits than you have in your bank!",
             )
             .await?;
         }
-    } else {
-        msg.reply(ctx, "Create your own bank first by running 'acc create'")
+        Err(e @ LedgerError::Overflow(_)) => {
+            msg.reply(ctx, e.to_string()).await?;
+        }
+        Err(e) => return Err(e.into()),
+    }
+    Ok(())
+}
+
+#[command]
+#[description = "Show the recent entries of your bank or the bank of another user"]
+#[max_args(1)]
+#[usage = "*@user*"]
+#[example = "@HansTrashy"]
+#[only_in("guilds")]
+pub async fn history(ctx: &Context, msg: &Message, args: Args) -> CommandResult {
+    let pool = get_client(ctx).await?;
+    let server_id = *msg.guild_id.ok_or("Banks only exist on servers")?.as_u64() as i64;
+    let user_id = match args.current() {
+        Some(user) => match parse_username(user) {
+            Some(user_id) => user_id,
+            None => {
+                msg.reply(ctx, "Mention the user whose history you want to see")
+                    .await?;
+                return Ok(());
+            }
+        },
+        None => *msg.author.id.as_u64(),
+    };
+
+    let entries = BankTransaction::recent_of_user(&pool, server_id, user_id as i64, 15).await?;
+    if entries.is_empty() {
+        msg.reply(ctx, "There is no bank history yet").await?;
+        return Ok(());
+    }
+    let balance = BankTransaction::balance_of_user(&pool, server_id, user_id as i64).await?;
+
+    let rendered_history = entries
+        .iter()
+        .map(|entry| {
+            let counterparty = entry
+                .counterparty_id
+                .map(|id| format!(" | <@{}>", id))
+                .unwrap_or_default();
+            format!(
+                "{} | {} | {:+} | {}{}",
+                entry.created_at.format("%d.%m.%Y %H:%M"),
+                entry.kind,
+                entry.amount,
+                entry.balance,
+                counterparty
+            )
+        })
+        .collect::<Vec<_>>()
+        .join("\n");
+
+    msg.channel_id
+        .send_message(&ctx, |m| {
+            m.embed(|e| {
+                e.description(format!("<@{}>\n\n{}", user_id, rendered_history))
+                    .footer(|f| f.text(format!("Balance: {}", balance)))
+                    .color((0, 120, 220))
+            })
+        })
+        .await?;
+    Ok(())
+}
+
+/// Parses `*@user* *amount*` of the admin commands
+async fn user_and_amount(
+    ctx: &Context,
+    msg: &Message,
+    args: &mut Args,
+) -> Result<Option<(i64, i64)>, serenity::Error> {
+    let user_id = args
+        .single::<String>()
+        .ok()
+        .and_then(|u| parse_username(&u));
+    let amount = args.single::<i64>().ok().filter(|a| *a > 0);
+
+    match (user_id, amount) {
+        (Some(user_id), Some(amount)) => Ok(Some((user_id as i64, amount))),
+        _ => {
+            msg.reply(ctx, "Mention a user and give a positive amount")
+                .await?;
+            Ok(None)
+        }
+    }
+}
+
+/// Books an adjustment by the mods and replies with the new balance
+async fn adjust(ctx: &Context, msg: &Message, user_id: i64, amount: i64) -> CommandResult {
+    let pool = get_client(ctx).await?;
+    let server_id = *msg.guild_id.ok_or("Banks only exist on servers")?.as_u64() as i64;
+
+    match Bank::apply(
+        &pool,
+        server_id,
+        &[Entry::new(user_id, Kind::Adjustment, amount)],
+    )
+    .await
+    {
+        Ok(banks) => {
+            msg.reply(
+                ctx,
+                &format!("New balance of <@{}>: {}", user_id, banks[0].amount),
+            )
+            .await?;
+        }
+        Err(e @ LedgerError::NoBank(_))
+        | Err(e @ LedgerError::Insufficient { .. })
+        | Err(e @ LedgerError::Overflow(_)) => {
+            msg.reply(ctx, e.to_string()).await?;
+        }
+        Err(e) => return Err(e.into()),
+    }
+    Ok(())
+}
+
+#[command]
+#[description = "Give credits to a user"]
+#[num_args(2)]
+#[usage = "*@user* *amount*"]
+#[example = "@HansTrashy 500"]
+#[only_in("guilds")]
+#[allowed_roles("Mods")]
+pub async fn grant(ctx: &Context, msg: &Message, mut args: Args) -> CommandResult {
+    if let Some((user_id, amount)) = user_and_amount(ctx, msg, &mut args).await? {
+        adjust(ctx, msg, user_id, amount).await?;
+    }
+    Ok(())
+}
+
+#[command]
+#[description = "Take credits from a user, not more than they have"]
+#[num_args(2)]
+#[usage = "*@user* *amount*"]
+#[example = "@HansTrashy 500"]
+#[only_in("guilds")]
+#[allowed_roles("Mods")]
+pub async fn take(ctx: &Context, msg: &Message, mut args: Args) -> CommandResult {
+    if let Some((user_id, amount)) = user_and_amount(ctx, msg, &mut args).await? {
+        adjust(ctx, msg, user_id, -amount).await?;
+    }
+    Ok(())
+}
+
+#[command]
+#[description = "Set the bank of a user back to the starting balance"]
+#[num_args(1)]
+#[usage = "*@user*"]
+#[example = "@HansTrashy"]
+#[only_in("guilds")]
+#[allowed_roles("Mods")]
+pub async fn reset(ctx: &Context, msg: &Message, mut args: Args) -> CommandResult {
+    let pool = get_client(ctx).await?;
+    let guild_id = msg.guild_id.ok_or("Banks only exist on servers")?;
+    let user_id = match args
+        .single::<String>()
+        .ok()
+        .and_then(|u| parse_username(&u))
+    {
+        Some(user_id) => user_id as i64,
+        None => {
+            msg.reply(ctx, "Mention the user whose bank to reset")
+                .await?;
+            return Ok(());
+        }
+    };
+    let economy = account::config(&pool, guild_id).await;
+
+    match Bank::reset(
+        &pool,
+        *guild_id.as_u64() as i64,
+        user_id,
+        economy.starting_balance,
+    )
+    .await
+    {
+        Ok(bank) => {
+            msg.reply(
+                ctx,
+                &format!("New balance of <@{}>: {}", user_id, bank.amount),
+            )
             .await?;
+        }
+        Err(e @ LedgerError::NoBank(_)) | Err(e @ LedgerError::Overflow(_)) => {
+            msg.reply(ctx, e.to_string()).await?;
+        }
+        Err(e) => return Err(e.into()),
     }
     Ok(())
 }
@@ -0,0 +1,206 @@
+use crate::commands::account;
+use rand::prelude::*;
+use serenity::prelude::*;
+use serenity::{
+    framework::standard::{macros::command, Args, CommandResult},
+    model::channel::Message,
+};
+use std::str::FromStr;
+
+const RED: [u8; 18] = [
+    1, 3, 5, 7, 9, 12, 14, 16, 18, 19, 21, 23, 25, 27, 30, 32, 34, 36,
+];
+
+/// Bets of a single zero wheel
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Bet {
+    Straight(u8),
+    Red,
+    Black,
+    Odd,
+    Even,
+    Low,
+    High,
+    /// 1-12, 13-24 or 25-36
+    Dozen(u8),
+    /// Every third number, the first column starts with 1
+    Column(u8),
+}
+
+impl FromStr for Bet {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let s = s.to_lowercase();
+        match s.as_str() {
+            "red" | "rot" => Ok(Self::Red),
+            "black" | "schwarz" => Ok(Self::Black),
+            "odd" => Ok(Self::Odd),
+            "even" => Ok(Self::Even),
+            "low" | "1-18" => Ok(Self::Low),
+            "high" | "19-36" => Ok(Self::High),
+            "dozen1" | "1-12" => Ok(Self::Dozen(1)),
+            "dozen2" | "13-24" => Ok(Self::Dozen(2)),
+            "dozen3" | "25-36" => Ok(Self::Dozen(3)),
+            "column1" => Ok(Self::Column(1)),
+            "column2" => Ok(Self::Column(2)),
+            "column3" => Ok(Self::Column(3)),
+            number => match number.parse::<u8>() {
+                Ok(n) if n <= 36 => Ok(Self::Straight(n)),
+                _ => Err(format!("Unknown bet: {}", number)),
+            },
+        }
+    }
+}
+
+impl Bet {
+    fn wins(self, pocket: u8) -> bool {
+        // zero only wins straight bets
+        if pocket == 0 {
+            return self == Self::Straight(0);
+        }
+
+        match self {
+            Self::Straight(n) => n == pocket,
+            Self::Red => RED.contains(&pocket),
+            Self::Black => !RED.contains(&pocket),
+            Self::Odd => pocket % 2 == 1,
+            Self::Even => pocket % 2 == 0,
+            Self::Low => pocket <= 18,
+            Self::High => pocket >= 19,
+            Self::Dozen(d) => (pocket - 1) / 12 + 1 == d,
+            Self::Column(c) => (pocket - 1) % 3 + 1 == c,
+        }
+    }
+
+    /// What a won bet pays back including the bet: 35 to 1, 2 to 1 or even money
+    const fn multiplier(self) -> i64 {
+        match self {
+            Self::Straight(_) => 36,
+            Self::Dozen(_) | Self::Column(_) => 3,
+            _ => 2,
+        }
+    }
+
+    /// `None` if the bet is too high to pay out
+    fn payout(self, bet: i64, pocket: u8) -> Option<i64> {
+        let won = bet.checked_mul(self.multiplier())?;
+        Some(if self.wins(pocket) { won } else { 0 })
+    }
+}
+
+fn spin(rng: &mut impl Rng) -> u8 {
+    rng.gen_range(0..=36)
+}
+
+fn pocket_emoji(pocket: u8) -> &'static str {
+    if pocket == 0 {
+        "\u{1f7e2}"
+    } else if RED.contains(&pocket) {
+        "\u{1f534}"
+    } else {
+        "\u{26ab}"
+    }
+}
+
+#[command]
+#[description = "Bet on the roulette wheel: a number (0-36), red, black, odd, even, low (1-18), high (19-36), dozen1-3 or column1-3"]
+#[num_args(2)]
+#[usage = "*bet_amount* *bet*"]
+#[example = "100 red"]
+#[example = "50 17"]
+#[example = "200 dozen2"]
+#[only_in("guilds")]
+pub async fn roulette(ctx: &Context, msg: &Message, mut args: Args) -> CommandResult {
+    if !account::game_enabled(ctx, msg, "roulette").await? {
+        return Ok(());
+    }
+    let amount = match args.single::<i64>() {
+        Ok(v) if v > 0 => v,
+        _ => {
+            std::mem::drop(msg.channel_id.say(&ctx, "Invalid bet!").await);
+            return Ok(());
+        }
+    };
+    let bet = match args.single::<String>()?.parse::<Bet>() {
+        Ok(bet) => bet,
+        Err(e) => {
+            msg.reply(ctx, e).await?;
+            return Ok(());
+        }
+    };
+
+    let pocket = spin(&mut rand::thread_rng());
+    let payout = match bet.payout(amount, pocket) {
+        Some(payout) => payout,
+        None => {
+            std::mem::drop(msg.channel_id.say(&ctx, "Invalid bet!").await);
+            return Ok(());
+        }
+    };
+
+    if let Some(bank) = account::play(ctx, msg, amount, payout).await? {
+        msg.channel_id
+            .send_message(&ctx, |m| {
+                m.embed(|e| {
+                    e.description(format!(
+                        "{} {}\n\nWon: {}\nBank: {}",
+                        pocket_emoji(pocket),
+                        pocket,
+                        payout,
+                        bank.amount
+                    ))
+                    .color((0, 120, 220))
+                })
+            })
+            .await?;
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{spin, Bet};
+    use crate::commands::account::house_edge;
+    use rand::{rngs::StdRng, SeedableRng};
+
+    const BETS: [Bet; 9] = [
+        Bet::Straight(0),
+        Bet::Red,
+        Bet::Black,
+        Bet::Odd,
+        Bet::Even,
+        Bet::Low,
+        Bet::High,
+        Bet::Dozen(3),
+        Bet::Column(2),
+    ];
+
+    #[test]
+    fn parse_bets() {
+        assert_eq!("RED".parse::<Bet>(), Ok(Bet::Red));
+        assert_eq!("13-24".parse::<Bet>(), Ok(Bet::Dozen(2)));
+        assert_eq!("36".parse::<Bet>(), Ok(Bet::Straight(36)));
+        assert!("37".parse::<Bet>().is_err());
+        assert!("green".parse::<Bet>().is_err());
+    }
+
+    #[test]
+    fn roulette_house_edge() {
+        // every bet pays 36 credits over the 37 pockets, so the edge is exactly 1/37
+        for bet in &BETS {
+            let edge = house_edge(1, (0..=36).map(|pocket| bet.payout(1, pocket).unwrap()));
+            assert!((edge - 1.0 / 37.0).abs() < 1e-9, "{:?}: {}", bet, edge);
+        }
+
+        let mut rng = StdRng::seed_from_u64(37);
+        let edge = house_edge(
+            10,
+            (0..200_000).map(|_| Bet::Red.payout(10, spin(&mut rng)).unwrap()),
+        );
+        assert!((0.015..0.04).contains(&edge), "edge {}", edge);
+
+        assert_eq!(Bet::Straight(7).payout(i64::MAX / 10, 0), None);
+    }
+}
@@ -0,0 +1,492 @@
+use crate::commands::account;
+use crate::models::inventory_item::InventoryItem;
+use crate::models::shop_item::{ItemKind, ShopItem};
+use crate::util::{self, get_client};
+use chrono::{Duration, Utc};
+use serenity::builder::ParseValue;
+use serenity::http::Http;
+use serenity::prelude::*;
+use serenity::utils::parse_role;
+use serenity::{
+    framework::standard::{macros::command, Args, CommandResult},
+    model::{channel::Message, id::GuildId, id::RoleId},
+};
+use sqlx::postgres::PgPool;
+use std::sync::Arc;
+use tokio::time::sleep;
+use tracing::error;
+
+const MAX_NICKNAME_LENGTH: usize = 32;
+
+/// `permanent` or a duration of at least an hour like `12h` or `7d`, in hours
+fn parse_hours(input: &str) -> Result<Option<i64>, String> {
+    if input == "permanent" {
+        return Ok(None);
+    }
+
+    match util::parse_duration(input).map(|d| d.num_hours()) {
+        Some(hours) if hours > 0 => Ok(Some(hours)),
+        _ => Err(format!(
+            "Expected permanent or a duration like 12h or 7d, got: {}",
+            input
+        )),
+    }
+}
+
+/// A hex color like `#ff8800`
+fn parse_color(input: &str) -> Option<u64> {
+    let hex = input.trim_start_matches('#');
+    if hex.len() != 6 {
+        return None;
+    }
+
+    u64::from_str_radix(hex, 16).ok()
+}
+
+/// The message of a ping perk, only the ping of the bot itself reaches everyone
+fn ping_content(user_id: u64, text: &str) -> String {
+    let text = text
+        .replace("@everyone", "@\u{200b}everyone")
+        .replace("@here", "@\u{200b}here");
+    format!("@here {}\n- <@{}>", text, user_id)
+}
+
+fn describe_duration(duration_hours: Option<i64>) -> String {
+    duration_hours
+        .map(|hours| format!(" for {}", util::humanize_duration(&Duration::hours(hours))))
+        .unwrap_or_default()
+}
+
+fn describe_item(item: &ShopItem) -> String {
+    let what = match (item.kind(), item.role_id) {
+        (Some(ItemKind::Role), Some(role_id)) => format!("<@&{}>", role_id),
+        (Some(ItemKind::Color), _) => "color role".to_string(),
+        (Some(ItemKind::Nickname), _) => "nickname change".to_string(),
+        (Some(ItemKind::Ping), _) => "channel ping".to_string(),
+        _ => item.kind.clone(),
+    };
+    format!(
+        "`{}` **{}** | {}{} | {} credits",
+        item.id,
+        item.name,
+        what,
+        describe_duration(item.duration_hours),
+        item.price
+    )
+}
+
+/// Removes a timed role once it expires, restarted on startup
+pub async fn expire_role(http: Arc<Http>, pool: PgPool, item: InventoryItem) {
+    let (role_id, expires_at) = match (item.role_id, item.expires_at) {
+        (Some(role_id), Some(expires_at)) => (RoleId(role_id as u64), expires_at),
+        _ => return,
+    };
+    if let Ok(remaining) = expires_at.signed_duration_since(Utc::now()).to_std() {
+        sleep(remaining).await;
+    }
+
+    match InventoryItem::expire(&pool, item.id).await {
+        Ok(0) => return,
+        Ok(_) => {}
+        Err(e) => {
+            error!(?e, "failed to expire inventory item");
+            return;
+        }
+    }
+
+    let guild_id = GuildId(item.server_id as u64);
+    let result = if item.kind() == Some(ItemKind::Color) {
+        // every color role belongs to a single user
+        guild_id.delete_role(&http, role_id).await
+    } else {
+        match InventoryItem::grants_role(
+            &pool,
+            item.server_id,
+            item.user_id,
+            *role_id.as_u64() as i64,
+        )
+        .await
+        {
+            // the user bought the role again in the meantime
+            Ok(true) => return,
+            Ok(false) => {}
+            Err(e) => error!(?e, "failed to check granted roles"),
+        }
+        match guild_id.member(&http, item.user_id as u64).await {
+            Ok(mut member) => member.remove_role(&http, role_id).await,
+            Err(e) => Err(e),
+        }
+    };
+    if let Err(e) = result {
+        error!(?e, "failed to remove expired role");
+    }
+}
+
+#[command]
+#[description = "Lists the items of the server shop, buy them with `acc buy`"]
+#[num_args(0)]
+#[only_in("guilds")]
+#[sub_commands(shop_add, shop_remove)]
+pub async fn shop(ctx: &Context, msg: &Message, _args: Args) -> CommandResult {
+    let pool = get_client(ctx).await?;
+    let server_id = *msg.guild_id.ok_or("Shops only exist on servers")?.as_u64() as i64;
+
+    let items = ShopItem::list(&pool, server_id).await?;
+    if items.is_empty() {
+        msg.reply(ctx, "The shop is empty").await?;
+        return Ok(());
+    }
+
+    let listing = items
+        .iter()
+        .map(describe_item)
+        .collect::<Vec<_>>()
+        .join("\n");
+    msg.channel_id
+        .send_message(&ctx, |m| {
+            m.embed(|e| e.title("Shop").description(listing).color((0, 120, 220)))
+        })
+        .await?;
+
+    Ok(())
+}
+
+#[command("add")]
+#[description = "Lists an item in the shop. Kinds are role (a role of the server), color (a role in a color of the buyer's choice), nickname (one nickname change) and ping (one @here ping in a channel). Roles last for a duration like 7d or are permanent."]
+#[min_args(3)]
+#[usage = "*kind* *price* *[@role]* *[duration]* *name*"]
+#[example = "role 5000 @VIP 7d VIP for a week"]
+#[example = "color 20000 permanent Custom color"]
+#[example = "nickname 1000 Nickname change"]
+#[only_in("guilds")]
+#[allowed_roles("Mods")]
+pub async fn shop_add(ctx: &Context, msg: &Message, mut args: Args) -> CommandResult {
+    let pool = get_client(ctx).await?;
+    let server_id = *msg.guild_id.ok_or("Shops only exist on servers")?.as_u64() as i64;
+
+    let kind = match ItemKind::parse(&args.single::<String>()?) {
+        Some(kind) => kind,
+        None => {
+            msg.reply(ctx, "Kinds are role, color, nickname and ping")
+                .await?;
+            return Ok(());
+        }
+    };
+    let price = match args.single::<i64>() {
+        Ok(price) if price >= 0 => price,
+        _ => {
+            msg.reply(ctx, "Invalid price!").await?;
+            return Ok(());
+        }
+    };
+    let role_id = if kind == ItemKind::Role {
+        match args.single::<String>().ok().and_then(|r| parse_role(&r)) {
+            Some(role_id) => Some(role_id as i64),
+            None => {
+                msg.reply(ctx, "Mention the role of the item").await?;
+                return Ok(());
+            }
+        }
+    } else {
+        None
+    };
+    let duration_hours = if kind == ItemKind::Role || kind == ItemKind::Color {
+        match parse_hours(&args.single::<String>().unwrap_or_default()) {
+            Ok(hours) => hours,
+            Err(e) => {
+                msg.reply(ctx, e).await?;
+                return Ok(());
+            }
+        }
+    } else {
+        None
+    };
+    let name = args.rest().trim();
+    if name.is_empty() {
+        msg.reply(ctx, "The item needs a name").await?;
+        return Ok(());
+    }
+
+    let item =
+        ShopItem::create(&pool, server_id, name, kind, price, role_id, duration_hours).await?;
+    msg.reply(ctx, format!("Added {}", describe_item(&item)))
+        .await?;
+
+    Ok(())
+}
+
+#[command("remove")]
+#[description = "Takes an item out of the shop, bought items can still be used"]
+#[num_args(1)]
+#[usage = "*item id*"]
+#[example = "3"]
+#[only_in("guilds")]
+#[allowed_roles("Mods")]
+pub async fn shop_remove(ctx: &Context, msg: &Message, mut args: Args) -> CommandResult {
+    let pool = get_client(ctx).await?;
+    let server_id = *msg.guild_id.ok_or("Shops only exist on servers")?.as_u64() as i64;
+    let id = args.single::<i64>()?;
+
+    if ShopItem::delete(&pool, server_id, id).await? == 0 {
+        msg.reply(ctx, "There is no such item in the shop").await?;
+    } else {
+        msg.reply(ctx, "Removed the item from the shop").await?;
+    }
+
+    Ok(())
+}
+
+#[command]
+#[description = "Buys an item from the shop, see the items with `acc shop`"]
+#[num_args(1)]
+#[usage = "*item id*"]
+#[example = "3"]
+#[only_in("guilds")]
+pub async fn buy(ctx: &Context, msg: &Message, mut args: Args) -> CommandResult {
+    let pool = get_client(ctx).await?;
+    let server_id = *msg.guild_id.ok_or("Shops only exist on servers")?.as_u64() as i64;
+    let id = args.single::<i64>()?;
+
+    let item = match ShopItem::get(&pool, server_id, id).await {
+        Ok(item) => item,
+        Err(_e) => {
+            msg.reply(ctx, "There is no such item in the shop").await?;
+            return Ok(());
+        }
+    };
+
+    match InventoryItem::buy(&pool, *msg.author.id.as_u64() as i64, &item).await {
+        Ok(bought) => {
+            msg.reply(
+                ctx,
+                format!(
+                    "You bought **{}**, use it with `acc use {}`",
+                    bought.name, bought.id
+                ),
+            )
+            .await?;
+        }
+        Err(e) => account::refuse(ctx, msg, e).await?,
+    }
+
+    Ok(())
+}
+
+#[command]
+#[description = "Lists your unused items and the roles you bought"]
+#[num_args(0)]
+#[only_in("guilds")]
+pub async fn inventory(ctx: &Context, msg: &Message, _args: Args) -> CommandResult {
+    let pool = get_client(ctx).await?;
+    let server_id = *msg.guild_id.ok_or("Shops only exist on servers")?.as_u64() as i64;
+
+    let items =
+        InventoryItem::list_of_user(&pool, server_id, *msg.author.id.as_u64() as i64).await?;
+    if items.is_empty() {
+        msg.reply(ctx, "Your inventory is empty").await?;
+        return Ok(());
+    }
+
+    let listing = items
+        .iter()
+        .map(|item| {
+            let state = match (item.used_at, item.expires_at) {
+                (None, _) => format!("unused{}", describe_duration(item.duration_hours)),
+                (Some(_), Some(expires_at)) => {
+                    format!("active until {}", expires_at.format("%d.%m.%Y %H:%M"))
+                }
+                (Some(_), None) => "active".to_string(),
+            };
+            format!("`{}` **{}** | {}", item.id, item.name, state)
+        })
+        .collect::<Vec<_>>()
+        .join("\n");
+    msg.channel_id
+        .send_message(&ctx, |m| {
+            m.embed(|e| {
+                e.title(format!("Inventory of {}", msg.author.name))
+                    .description(listing)
+                    .color((0, 120, 220))
+            })
+        })
+        .await?;
+
+    Ok(())
+}
+
+#[command("use")]
+#[description = "Uses an item of your inventory. Color items need a hex color, nickname items the new nickname and ping items the message."]
+#[min_args(1)]
+#[usage = "*inventory id* *[color/nickname/message]*"]
+#[example = "7"]
+#[example = "8 #ff8800"]
+#[example = "9 Movie night starts now!"]
+#[only_in("guilds")]
+pub async fn use_item(ctx: &Context, msg: &Message, mut args: Args) -> CommandResult {
+    let pool = get_client(ctx).await?;
+    let guild_id = msg.guild_id.ok_or("Shops only exist on servers")?;
+    let server_id = *guild_id.as_u64() as i64;
+    let id = args.single::<i64>()?;
+    let input = args.rest().trim();
+
+    let item = match InventoryItem::get_unused(&pool, server_id, *msg.author.id.as_u64() as i64, id)
+        .await
+    {
+        Ok(item) => item,
+        Err(_e) => {
+            msg.reply(ctx, "You have no such unused item").await?;
+            return Ok(());
+        }
+    };
+    let expires_at = item.duration_hours.map(|h| Utc::now() + Duration::hours(h));
+
+    // the item is marked as used before anything happens, so it can not be used twice
+    let result = match item.kind() {
+        Some(ItemKind::Role) => {
+            let role_id = item.role_id.ok_or("Role item without a role")?;
+            match InventoryItem::mark_used(&pool, item.id, Some(role_id), expires_at).await? {
+                Some(used) => {
+                    let granted = match guild_id.member(ctx, msg.author.id).await {
+                        Ok(mut member) => member.add_role(ctx, RoleId(role_id as u64)).await,
+                        Err(e) => Err(e),
+                    };
+                    granted.map(|_| Some(used))
+                }
+                None => Ok(None),
+            }
+        }
+        Some(ItemKind::Color) => {
+            let color = match parse_color(input) {
+                Some(color) => color,
+                None => {
+                    msg.reply(ctx, "Give a hex color like #ff8800").await?;
+                    return Ok(());
+                }
+            };
+            let role = guild_id
+                .create_role(ctx, |r| r.name(&msg.author.name).colour(color))
+                .await?;
+            match InventoryItem::mark_used(
+                &pool,
+                item.id,
+                Some(*role.id.as_u64() as i64),
+                expires_at,
+            )
+            .await?
+            {
+                Some(used) => {
+                    let granted = match guild_id.member(ctx, msg.author.id).await {
+                        Ok(mut member) => member.add_role(ctx, role.id).await,
+                        Err(e) => Err(e),
+                    };
+                    if granted.is_err() {
+                        std::mem::drop(guild_id.delete_role(ctx, role.id).await);
+                    }
+                    granted.map(|_| Some(used))
+                }
+                None => {
+                    std::mem::drop(guild_id.delete_role(ctx, role.id).await);
+                    Ok(None)
+                }
+            }
+        }
+        Some(ItemKind::Nickname) => {
+            if input.is_empty() || input.chars().count() > MAX_NICKNAME_LENGTH {
+                msg.reply(
+                    ctx,
+                    format!(
+                        "Give a nickname of up to {} characters",
+                        MAX_NICKNAME_LENGTH
+                    ),
+                )
+                .await?;
+                return Ok(());
+            }
+            match InventoryItem::mark_used(&pool, item.id, None, None).await? {
+                Some(used) => guild_id
+                    .edit_member(ctx, msg.author.id, |m| m.nickname(input))
+                    .await
+                    .map(|_| Some(used)),
+                None => Ok(None),
+            }
+        }
+        Some(ItemKind::Ping) => {
+            if input.is_empty() {
+                msg.reply(ctx, "Give the message of the ping").await?;
+                return Ok(());
+            }
+            match InventoryItem::mark_used(&pool, item.id, None, None).await? {
+                Some(used) => msg
+                    .channel_id
+                    .send_message(ctx, |m| {
+                        m.content(ping_content(*msg.author.id.as_u64(), input))
+                            .allowed_mentions(|am| am.parse(ParseValue::Everyone))
+                    })
+                    .await
+                    .map(|_| Some(used)),
+                None => Ok(None),
+            }
+        }
+        None => return Err(format!("Unknown item kind: {}", item.kind).into()),
+    };
+
+    match result {
+        Ok(Some(used)) => {
+            if used.expires_at.is_some() {
+                tokio::spawn(expire_role(ctx.http.clone(), pool.clone(), used.clone()));
+            }
+            if used.kind() != Some(ItemKind::Ping) {
+                msg.reply(
+                    ctx,
+                    format!(
+                        "Used **{}**{}",
+                        used.name,
+                        describe_duration(used.duration_hours)
+                    ),
+                )
+                .await?;
+            }
+        }
+        Ok(None) => {
+            msg.reply(ctx, "The item was already used").await?;
+        }
+        Err(e) => {
+            InventoryItem::mark_unused(&pool, item.id).await?;
+            error!(?e, "failed to use inventory item");
+            msg.reply(
+                ctx,
+                "Could not use the item, it stays in your inventory. The bot may be missing permissions.",
+            )
+            .await?;
+        }
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{parse_color, parse_hours, ping_content};
+
+    #[test]
+    fn item_arguments() {
+        assert_eq!(parse_hours("permanent"), Ok(None));
+        assert_eq!(parse_hours("12h"), Ok(Some(12)));
+        assert_eq!(parse_hours("7d"), Ok(Some(168)));
+        assert!(parse_hours("30m").is_err());
+        assert!(parse_hours("soon").is_err());
+
+        assert_eq!(parse_color("#ff8800"), Some(0x00ff_8800));
+        assert_eq!(parse_color("00ff00"), Some(0x0000_ff00));
+        assert_eq!(parse_color("#fff"), None);
+        assert_eq!(parse_color("#gg0000"), None);
+    }
+
+    #[test]
+    fn ping_only_from_the_bot() {
+        assert_eq!(
+            ping_content(1, "hi @everyone"),
+            "@here hi @\u{200b}everyone\n- <@1>"
+        );
+    }
+}
@@ -1,173 +1,510 @@
-use crate::models::bank::Bank;
+use crate::commands::account;
+use crate::models::bank_transaction::Kind;
 use crate::util::get_client;
+use rand::distributions::WeightedIndex;
 use rand::prelude::*;
+use serde::{Deserialize, Serialize};
 use serenity::prelude::*;
 use serenity::{
     framework::standard::{macros::command, Args, CommandResult},
     model::channel::Message,
 };
+use std::convert::TryFrom;
+
+/// Rows shown of every reel, the stop symbol is in the middle
+const ROWS: usize = 3;
+const MAX_SIMULATED_SPINS: u64 = 1_000_000;
+/// Highest multiplier of a symbol or a payline
+const MAX_MULTIPLIER: u32 = 1000;
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct Symbol {
+    /// Unicode or custom server emoji like `<:name:id>`
+    pub emoji: String,
+    /// Relative chance of a reel stopping on the symbol
+    #[serde(default = "one")]
+    pub weight: u32,
+    /// Factor on the payline multiplier when a line of the symbol wins
+    #[serde(default = "one")]
+    pub multiplier: u32,
+}
+
+const fn one() -> u32 {
+    1
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct Payline {
+    /// Row on every reel, 0 is the top row
+    pub rows: Vec<usize>,
+    /// Pays the bet this many times when all symbols on the line are the same
+    pub multiplier: u32,
+}
+
+/// A slot machine. The symbols form the strip of every reel in their order, so a reel shows
+/// the stop symbol with its neighbours on the strip above and below.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct Machine {
+    pub symbols: Vec<Symbol>,
+    pub reels: usize,
+    pub paylines: Vec<Payline>,
+}
+
+impl Default for Machine {
+    fn default() -> Self {
+        let symbols = [
+            "\u{1f9c0}",
+            "\u{1f349}",
+            "\u{1f352}",
+            "\u{1f95d}",
+            "\u{1f369}",
+            "\u{1f954}",
+            "\u{1f346}",
+        ];
+
+        Self {
+            symbols: symbols
+                .iter()
+                .map(|emoji| Symbol {
+                    emoji: emoji.to_string(),
+                    weight: 1,
+                    multiplier: 1,
+                })
+                .collect(),
+            reels: 3,
+            paylines: vec![
+                Payline {
+                    rows: vec![1, 1, 1],
+                    multiplier: 50,
+                },
+                Payline {
+                    rows: vec![0, 1, 2],
+                    multiplier: 40,
+                },
+                Payline {
+                    rows: vec![2, 1, 0],
+                    multiplier: 20,
+                },
+            ],
+        }
+    }
+}
+
+/// Outcome of simulated spins, in multiples of the bet
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Report {
+    pub spins: u64,
+    /// Return to player, above 1 the players win in the long run
+    pub rtp: f64,
+    pub variance: f64,
+    /// Share of spins that paid anything
+    pub hit_rate: f64,
+}
+
+impl Machine {
+    pub fn validate(&self) -> Result<(), String> {
+        if !(3..=20).contains(&self.symbols.len()) {
+            return Err("A machine needs 3 to 20 symbols".to_string());
+        }
+        if !(3..=5).contains(&self.reels) {
+            return Err("A machine needs 3 to 5 reels".to_string());
+        }
+        if let Some(symbol) = self
+            .symbols
+            .iter()
+            .find(|s| s.emoji.trim().is_empty() || s.weight == 0)
+        {
+            return Err(format!(
+                "Every symbol needs an emoji and a weight above 0: {:?}",
+                symbol
+            ));
+        }
+        if self
+            .symbols
+            .iter()
+            .map(|s| s.multiplier)
+            .chain(self.paylines.iter().map(|l| l.multiplier))
+            .any(|m| m > MAX_MULTIPLIER)
+        {
+            return Err(format!("Multipliers go up to {}", MAX_MULTIPLIER));
+        }
+        if self.paylines.is_empty() {
+            return Err("A machine needs at least one payline".to_string());
+        }
+        if let Some(line) = self
+            .paylines
+            .iter()
+            .find(|l| l.rows.len() != self.reels || l.rows.iter().any(|r| *r >= ROWS))
+        {
+            return Err(format!(
+                "Paylines need a row from 0 to {} for each of the {} reels: {:?}",
+                ROWS - 1,
+                self.reels,
+                line.rows
+            ));
+        }
+
+        Ok(())
+    }
+
+    /// The stop symbol of every reel
+    fn spin(&self, weights: &WeightedIndex<u32>, rng: &mut impl Rng) -> Vec<usize> {
+        (0..self.reels).map(|_| weights.sample(rng)).collect()
+    }
+
+    fn weights(&self) -> WeightedIndex<u32> {
+        WeightedIndex::new(self.symbols.iter().map(|s| s.weight)).expect("Validated weights")
+    }
+
+    /// The symbols shown, indexed by reel and then row
+    fn window(&self, stops: &[usize]) -> Vec<[usize; ROWS]> {
+        let n = self.symbols.len();
+        stops
+            .iter()
+            .map(|stop| [(stop + n - 1) % n, *stop, (stop + 1) % n])
+            .collect()
+    }
+
+    /// How many times the bet the window pays, summed over the winning paylines
+    fn multiplier(&self, window: &[[usize; ROWS]]) -> u64 {
+        self.paylines
+            .iter()
+            .filter_map(|line| {
+                let first = window[0][line.rows[0]];
+                line.rows
+                    .iter()
+                    .zip(window)
+                    .all(|(row, reel)| reel[*row] == first)
+                    .then(|| u64::from(line.multiplier) * u64::from(self.symbols[first].multiplier))
+            })
+            .sum()
+    }
+
+    /// The most a spin could pay, every payline winning with the symbol of the highest multiplier
+    fn max_multiplier(&self) -> u64 {
+        let symbol = self.symbols.iter().map(|s| s.multiplier).max().unwrap_or(0);
+        self.paylines
+            .iter()
+            .map(|line| u64::from(line.multiplier) * u64::from(symbol))
+            .sum()
+    }
+
+    /// What a spin with the multiplier pays for the bet, `None` if the bet is too high to pay out
+    fn payout(multiplier: u64, bet: i64) -> Option<i64> {
+        i64::try_from(multiplier).ok()?.checked_mul(bet)
+    }
+
+    fn render(&self, window: &[[usize; ROWS]]) -> String {
+        (0..ROWS)
+            .map(|row| {
+                window
+                    .iter()
+                    .map(|reel| self.symbols[reel[row]].emoji.as_str())
+                    .collect::<Vec<_>>()
+                    .join(" | ")
+            })
+            .collect::<Vec<_>>()
+            .join("\n")
+    }
+
+    // spins and multipliers stay far below where f64 gets imprecise
+    #[allow(clippy::cast_precision_loss)]
+    pub fn simulate(&self, spins: u64, rng: &mut impl Rng) -> Report {
+        let weights = self.weights();
+        let (mut sum, mut sum_squares, mut hits) = (0_f64, 0_f64, 0_u64);
+        for _ in 0..spins {
+            let multiplier = self.multiplier(&self.window(&self.spin(&weights, rng))) as f64;
+            sum += multiplier;
+            sum_squares += multiplier * multiplier;
+            hits += u64::from(multiplier > 0.0);
+        }
+
+        let rtp = sum / spins as f64;
+        Report {
+            spins,
+            rtp,
+            variance: rtp.mul_add(-rtp, sum_squares / spins as f64),
+            hit_rate: hits as f64 / spins as f64,
+        }
+    }
+}
+
+/// Reads a machine from JSON, optionally in a code block
+fn parse_machine(input: &str) -> Result<Machine, String> {
+    let json = input
+        .trim()
+        .trim_start_matches("```json")
+        .trim_matches('`')
+        .trim();
+    let machine: Machine =
+        serde_json::from_str(json).map_err(|e| format!("Invalid slot machine: {}", e))?;
+    machine.validate()?;
+
+    Ok(machine)
+}
+
+fn describe_report(report: &Report) -> String {
+    format!(
+        "{} spins\nRTP: {:.2}%\nVariance: {:.2}\nHit rate: {:.2}%",
+        report.spins,
+        report.rtp * 100.0,
+        report.variance,
+        report.hit_rate * 100.0
+    )
+}
 
 #[command]
 #[description = "Slot gamble game for worthless internet credits"]
 #[num_args(1)]
 #[usage = "*bet_amount*"]
 #[example = "1000"]
+#[only_in("guilds")]
+#[sub_commands(slot_define, slot_simulate, slot_activate, slot_show)]
 pub async fn slot(ctx: &Context, msg: &Message, mut args: Args) -> CommandResult {
+    if !account::game_enabled(ctx, msg, "slot").await? {
+        return Ok(());
+    }
     let pool = get_client(ctx).await?;
+    let guild_id = msg.guild_id.ok_or("Banks only exist on servers")?;
+    let machine = account::config(&pool, guild_id).await.slot;
+    // any spin of the machine has to be payable, like the best one
     let amount_to_bet = match args.single::<i64>() {
-        Ok(v) if v > 0 => v,
-        Ok(_) => {
-            // log
-            std::mem::drop(msg.channel_id.say(&ctx, "Invalid bet!").await);
-            return Ok(());
-        }
-        Err(_e) => {
-            // log
+        Ok(v) if v > 0 && Machine::payout(machine.max_multiplier(), v).is_some() => v,
+        _ => {
             std::mem::drop(msg.channel_id.say(&ctx, "Invalid bet!").await);
             return Ok(());
         }
     };
 
-    // check if user already owns a bank & has enough balance
-    if let Ok(bank) = Bank::get(&pool, *msg.author.id.as_u64() as i64).await {
-        if bank.amount >= amount_to_bet {
-            // roll
-            let full_reels: Vec<Vec<i64>> = (0..3)
-                .map(|_| {
-                    let roll = rand::thread_rng().gen_range(0..7);
-                    let prev;
-                    let next;
-                    if roll == 6 {
-                        prev = 5;
-                        next = 0;
-                    } else if roll == 0 {
-                        prev = 6;
-                        next = 1;
-                    } else {
-                        prev = roll - 1;
-                        next = roll + 1;
-                    }
-                    vec![prev, roll, next]
-                })
-                .collect();
+    // roll
+    let window = machine.window(&machine.spin(&machine.weights(), &mut rand::thread_rng()));
+    let payout = Machine::payout(machine.multiplier(&window), amount_to_bet).unwrap_or_default();
 
-            let payout = get_payout(&full_reels, amount_to_bet);
-            let delta = payout - amount_to_bet;
-            let updated_amount = bank.amount + delta;
+    if let Some(bank) = account::play_as(
+        ctx,
+        msg,
+        (Kind::SlotBet, Kind::SlotPayout),
+        amount_to_bet,
+        payout,
+    )
+    .await?
+    {
+        let slot_machine_output = format!(
+            "{}\n\n Won: {}\nBank: {}",
+            machine.render(&window),
+            payout,
+            bank.amount
+        );
+        msg.channel_id
+            .send_message(&ctx, |m| {
+                m.embed(|e| e.description(&slot_machine_output).color((0, 120, 220)))
+            })
+            .await?;
+    }
 
-            Bank::update(&pool, bank.user_id, updated_amount, bank.last_payday).await?;
+    Ok(())
+}
 
-            let slot_machine_output = display_reels(&full_reels, payout, updated_amount);
-            msg.channel_id
-                .send_message(&ctx, |m| {
-                    m.embed(|e| e.description(&slot_machine_output).color((0, 120, 220)))
-                })
-                .await?;
-        } else {
-            msg.channel_id
-                .say(
-                    &ctx,
-                    "You are missing the necessary credits for this action!",
-                )
-                .await?;
+#[command("define")]
+#[description = "Saves a draft slot machine from JSON, `default` drafts the classic machine. Check it with `acc slot simulate` and put it in use with `acc slot activate`."]
+#[min_args(1)]
+#[usage = "*machine json*"]
+#[example = "{\"symbols\": [{\"emoji\": \"🍒\", \"weight\": 3}, {\"emoji\": \"🍋\", \"weight\": 2}, {\"emoji\": \"<:pog:123>\", \"multiplier\": 5}], \"reels\": 3, \"paylines\": [{\"rows\": [1, 1, 1], \"multiplier\": 10}]}"]
+#[example = "default"]
+#[only_in("guilds")]
+#[allowed_roles("Mods")]
+pub async fn slot_define(ctx: &Context, msg: &Message, args: Args) -> CommandResult {
+    let pool = get_client(ctx).await?;
+    let guild_id = msg.guild_id.ok_or("Slot machines only exist on servers")?;
+
+    let machine = if args.rest().trim() == "default" {
+        Machine::default()
+    } else {
+        match parse_machine(args.rest()) {
+            Ok(machine) => machine,
+            Err(e) => {
+                msg.reply(ctx, e).await?;
+                return Ok(());
+            }
         }
+    };
+
+    let mut economy = account::config(&pool, guild_id).await;
+    economy.slot_draft = Some(machine);
+    account::store_config(&pool, guild_id, economy).await?;
+
+    msg.reply(
+        ctx,
+        "Saved the draft, check it with `acc slot simulate` and activate it with `acc slot activate`",
+    )
+    .await?;
+
+    Ok(())
+}
+
+#[command("simulate")]
+#[description = "Runs spins on the draft slot machine, or the active one without a draft, and reports the return to player and the variance"]
+#[max_args(1)]
+#[usage = "*spins*"]
+#[example = "100000"]
+#[only_in("guilds")]
+#[allowed_roles("Mods")]
+pub async fn slot_simulate(ctx: &Context, msg: &Message, mut args: Args) -> CommandResult {
+    let pool = get_client(ctx).await?;
+    let guild_id = msg.guild_id.ok_or("Slot machines only exist on servers")?;
+    let spins = if args.is_empty() {
+        100_000
     } else {
-        msg.channel_id
-            .say(&ctx, "Create your own bank first by running 'acc create'")
+        match args.single::<u64>() {
+            Ok(spins) if (1..=MAX_SIMULATED_SPINS).contains(&spins) => spins,
+            _ => {
+                msg.reply(ctx, format!("Simulate 1 to {} spins", MAX_SIMULATED_SPINS))
+                    .await?;
+                return Ok(());
+            }
+        }
+    };
+
+    let economy = account::config(&pool, guild_id).await;
+    let (name, machine) = match economy.slot_draft {
+        Some(draft) => ("draft", draft),
+        None => ("active", economy.slot),
+    };
+    let report =
+        tokio::task::spawn_blocking(move || machine.simulate(spins, &mut StdRng::from_entropy()))
             .await?;
-    }
+
+    msg.channel_id
+        .send_message(&ctx, |m| {
+            m.embed(|e| {
+                e.title(format!("Simulated {} slot machine", name))
+                    .description(describe_report(&report))
+                    .color((0, 120, 220))
+            })
+        })
+        .await?;
 
     Ok(())
 }
 
-fn get_payout(full_reels: &[Vec<i64>], betted_amount: i64) -> i64 {
-    if full_reels[0][1] == full_reels[1][1] && full_reels[1][1] == full_reels[2][1] {
-        // win 1
-        50 * betted_amount
-    } else if full_reels[0][0] == full_reels[1][1] && full_reels[1][1] == full_reels[2][2] {
-        // win 2
-        40 * betted_amount
-    } else if full_reels[0][2] == full_reels[1][1] && full_reels[1][1] == full_reels[2][0] {
-        // win 3
-        20 * betted_amount
-    } else {
-        0
+#[command("activate")]
+#[description = "Puts the draft slot machine in use"]
+#[num_args(0)]
+#[only_in("guilds")]
+#[allowed_roles("Mods")]
+pub async fn slot_activate(ctx: &Context, msg: &Message, _args: Args) -> CommandResult {
+    let pool = get_client(ctx).await?;
+    let guild_id = msg.guild_id.ok_or("Slot machines only exist on servers")?;
+
+    let mut economy = account::config(&pool, guild_id).await;
+    match economy.slot_draft.take() {
+        Some(draft) => {
+            economy.slot = draft;
+            account::store_config(&pool, guild_id, economy).await?;
+            msg.reply(ctx, "The new slot machine is active").await?;
+        }
+        None => {
+            msg.reply(ctx, "There is no draft, save one with `acc slot define`")
+                .await?;
+        }
     }
-}
 
-fn display_reels(full_reels: &[Vec<i64>], payout: i64, updated_amount: i64) -> String {
-    format!(
-        "{} | {} | {} \n{} | {} | {}\n {} | {} | {}\n\n Won: {}\nBank: {}",
-        number_to_emoji(full_reels[0][0]),
-        number_to_emoji(full_reels[1][0]),
-        number_to_emoji(full_reels[2][0]),
-        number_to_emoji(full_reels[0][1]),
-        number_to_emoji(full_reels[1][1]),
-        number_to_emoji(full_reels[2][1]),
-        number_to_emoji(full_reels[0][2]),
-        number_to_emoji(full_reels[1][2]),
-        number_to_emoji(full_reels[2][2]),
-        payout,
-        updated_amount
-    )
+    Ok(())
 }
 
-const fn number_to_emoji(n: i64) -> &'static str {
-    match n {
-        0 => "\u{1f9c0}",
-        1 => "\u{1f349}",
-        2 => "\u{1f352}",
-        3 => "\u{1f95d}",
-        4 => "\u{1f369}",
-        5 => "\u{1f954}",
-        _ => "\u{1f346}",
+#[command("show")]
+#[description = "Shows the active and the draft slot machine as JSON"]
+#[num_args(0)]
+#[only_in("guilds")]
+#[allowed_roles("Mods")]
+pub async fn slot_show(ctx: &Context, msg: &Message, _args: Args) -> CommandResult {
+    let pool = get_client(ctx).await?;
+    let guild_id = msg.guild_id.ok_or("Slot machines only exist on servers")?;
+    let economy = account::config(&pool, guild_id).await;
+
+    let mut shown = vec![("Active", serde_json::to_string(&economy.slot)?)];
+    if let Some(draft) = &economy.slot_draft {
+        shown.push(("Draft", serde_json::to_string(draft)?));
     }
+    for (name, json) in shown {
+        msg.channel_id
+            .say(ctx, format!("{}:\n```json\n{}\n```", name, json))
+            .await?;
+    }
+
+    Ok(())
 }
 
 #[cfg(test)]
 mod tests {
-    use super::get_payout;
-    use rand::Rng;
+    use super::{parse_machine, Machine};
+    use rand::{rngs::StdRng, SeedableRng};
 
     #[test]
     fn test_reels() {
-        let mut rng = rand::thread_rng();
-
-        let full_reels: Vec<Vec<i32>> = (0..3)
-            .map(|_| {
-                let roll = rng.gen_range(0..7);
-                let prev;
-                let next;
-                if roll == 6 {
-                    prev = 5;
-                    next = 0;
-                } else if roll == 0 {
-                    prev = 6;
-                    next = 1;
-                } else {
-                    prev = roll - 1;
-                    next = roll + 1;
-                }
-                vec![prev, roll, next]
-            })
-            .collect();
-
-        dbg!(full_reels);
+        let machine = Machine::default();
+        assert_eq!(
+            machine.window(&[0, 3, 6]),
+            vec![[6, 0, 1], [2, 3, 4], [5, 6, 0]]
+        );
+        machine.validate().unwrap();
     }
 
     #[test]
     fn test_payout() {
-        let full_reels_1 = vec![vec![0, 1, 2], vec![0, 1, 2], vec![0, 1, 2]];
-        let full_reels_2 = vec![vec![0, 1, 2], vec![6, 0, 1], vec![5, 6, 0]];
-        let full_reels_3 = vec![vec![0, 1, 2], vec![1, 2, 3], vec![2, 3, 4]];
+        let machine = Machine::default();
+        let full_reels_1 = vec![[0, 1, 2], [0, 1, 2], [0, 1, 2]];
+        let full_reels_2 = vec![[0, 1, 2], [6, 0, 1], [5, 6, 0]];
+        let full_reels_3 = vec![[0, 1, 2], [1, 2, 3], [2, 3, 4]];
+        let full_reels_4 = vec![[0, 1, 2], [1, 2, 3], [3, 4, 5]];
 
-        dbg!(&full_reels_1);
-        dbg!(get_payout(&full_reels_1, 10));
+        assert_eq!(machine.multiplier(&full_reels_1), 50);
+        assert_eq!(machine.multiplier(&full_reels_2), 40);
+        assert_eq!(machine.multiplier(&full_reels_3), 20);
+        assert_eq!(machine.multiplier(&full_reels_4), 0);
+
+        assert_eq!(machine.max_multiplier(), 110);
+        assert_eq!(Machine::payout(110, 10), Some(1100));
+        assert_eq!(Machine::payout(110, i64::MAX / 100), None);
+        assert_eq!(Machine::payout(u64::MAX, 1), None);
+    }
+
+    #[test]
+    fn simulated_rtp() {
+        // each line wins in 1 of 49 spins, so the classic machine returns (50 + 40 + 20) / 49
+        let report = Machine::default().simulate(500_000, &mut StdRng::seed_from_u64(44));
+        assert!((report.rtp - 110.0 / 49.0).abs() < 0.05, "{:?}", report);
+        assert!((report.hit_rate - 3.0 / 49.0).abs() < 0.002, "{:?}", report);
+        assert!(report.variance > 0.0);
+    }
+
+    #[test]
+    fn custom_machines() {
+        let machine = parse_machine(
+            r#"```json
+            {"symbols": [{"emoji": "a", "weight": 3}, {"emoji": "b"}, {"emoji": "<:c:1>", "multiplier": 5}],
+             "reels": 4, "paylines": [{"rows": [1, 1, 1, 1], "multiplier": 10}]}
+            ```"#,
+        )
+        .unwrap();
+        assert_eq!(machine.symbols[1].weight, 1);
+        assert_eq!(machine.multiplier(&[[1, 2, 0]; 4]), 50);
+        assert_eq!(machine.multiplier(&[[2, 0, 1]; 4]), 10);
 
-        dbg!(&full_reels_2);
-        dbg!(get_payout(&full_reels_2, 20));
+        let report = machine.simulate(200_000, &mut StdRng::seed_from_u64(4));
+        // the middle row shows a with 3/5, b with 1/5 and c with 1/5 on every reel,
+        // so a line pays 10 * 0.6^4 + 10 * 0.2^4 + 50 * 0.2^4 on average
+        let rtp = 1.392;
+        assert!((report.rtp - rtp).abs() < 0.1, "{:?} {}", report, rtp);
 
-        dbg!(&full_reels_3);
-        dbg!(get_payout(&full_reels_3, 30));
+        assert!(parse_machine(r#"{"symbols": [], "reels": 3, "paylines": []}"#).is_err());
+        assert!(parse_machine(
+            r#"{"symbols": [{"emoji": "a"}, {"emoji": "b"}, {"emoji": "c"}], "reels": 3, "paylines": [{"rows": [1, 3, 1], "multiplier": 2}]}"#
+        )
+        .is_err());
+        assert!(parse_machine(
+            r#"{"symbols": [{"emoji": "a", "multiplier": 4294967295}, {"emoji": "b"}, {"emoji": "c"}], "reels": 3, "paylines": [{"rows": [1, 1, 1], "multiplier": 2}]}"#
+        )
+        .is_err());
     }
 }
@@ -0,0 +1,140 @@
+use super::config::Guild;
+use crate::models::sanction::Sanction;
+use crate::models::server_config::ServerConfig;
+use crate::util;
+use crate::util::get_client;
+use serenity::prelude::*;
+use serenity::{
+    framework::standard::{macros::command, Args, CommandResult},
+    model::prelude::*,
+};
+use tracing::debug;
+
+/// Tells the user about the sanction and how to appeal it
+pub async fn notify_sanction(ctx: &Context, guild_id: GuildId, user: &User, sanction: &Sanction) {
+    let guild_name = guild_id
+        .name(ctx)
+        .await
+        .unwrap_or_else(|| "the server".to_string());
+    let duration = sanction.end_time.map_or_else(
+        || "permanent".to_string(),
+        |end| util::humanize_duration(&end.signed_duration_since(sanction.created_at)),
+    );
+    let kind = if sanction.kind == "ban" {
+        "banned from"
+    } else {
+        "muted on"
+    };
+
+    if let Err(e) = user
+        .dm(ctx, |m| {
+            m.embed(|e| {
+                e.title(format!("You have been {} {}", kind, guild_name))
+                    .field("Reason", &sanction.reason, false)
+                    .field("Duration", duration, false)
+                    .description(format!(
+                        "If you want to appeal, answer with `$appeal {} *your appeal*`",
+                        sanction.id
+                    ))
+                    .color((220, 0, 0))
+            })
+        })
+        .await
+    {
+        debug!(?e, "Could not notify sanctioned user");
+    }
+}
+
+#[command]
+#[only_in("dms")]
+#[description = "Appeal a mute or ban, the id is in the message you got"]
+#[usage = "*sanction_id* *appeal*"]
+#[example = "42 I am sorry, it was a misunderstanding"]
+#[min_args(2)]
+pub async fn appeal(ctx: &Context, msg: &Message, mut args: Args) -> CommandResult {
+    let sanction_id = args.single::<i64>()?;
+    let appeal = args.rest().trim();
+    let pool = get_client(ctx).await?;
+
+    let sanction = match Sanction::get(&pool, sanction_id).await {
+        Ok(sanction) if sanction.user_id == *msg.author.id.as_u64() as i64 => sanction,
+        _ => {
+            msg.reply(ctx, "I can not find this sanction").await?;
+            return Ok(());
+        }
+    };
+
+    if sanction.appeal_status != "none" {
+        msg.reply(ctx, "You already appealed this sanction").await?;
+        return Ok(());
+    }
+
+    let g_cfg: Guild = match ServerConfig::get(&pool, sanction.server_id).await {
+        Ok(mut config) => serde_json::from_value(config.config.take()).unwrap(),
+        Err(_e) => Guild::default(),
+    };
+
+    let appeals_channel = match g_cfg.appeals_channel {
+        Some(channel) => ChannelId(channel),
+        None => {
+            msg.reply(ctx, "This server does not accept appeals")
+                .await?;
+            return Ok(());
+        }
+    };
+
+    // claimed first, so the same sanction is never appealed twice
+    let sanction = match Sanction::appeal(&pool, sanction.id, appeal).await? {
+        Some(sanction) => sanction,
+        None => {
+            msg.reply(ctx, "You already appealed this sanction").await?;
+            return Ok(());
+        }
+    };
+
+    let appeal_msg = match appeals_channel
+        .send_message(&ctx, |m| {
+            m.embed(|e| {
+                e.author(|a| {
+                    a.name(format!("{}#{}", msg.author.name, msg.author.discriminator))
+                        .icon_url(msg.author.static_avatar_url().unwrap_or_default())
+                })
+                .title(format!("Appeal for {} #{}", sanction.kind, sanction.id))
+                .field("Reason", &sanction.reason, false)
+                .field(
+                    "Sanctioned by",
+                    format!("<@{}>", sanction.moderator_id),
+                    true,
+                )
+                .field("User", format!("<@{}>", sanction.user_id), true)
+                .description(appeal)
+                .footer(|f| f.text("React with ✅ to accept or ❌ to deny"))
+                .color((220, 160, 0))
+            })
+        })
+        .await
+    {
+        Ok(appeal_msg) => appeal_msg,
+        Err(e) => {
+            Sanction::withdraw_appeal(&pool, sanction.id).await?;
+            return Err(e.into());
+        }
+    };
+    Sanction::set_appeal_msg(&pool, sanction.id, *appeal_msg.id.as_u64() as i64).await?;
+
+    std::mem::drop(
+        appeal_msg
+            .react(ctx, ReactionType::Unicode("\u{2705}".to_string()))
+            .await,
+    );
+    std::mem::drop(
+        appeal_msg
+            .react(ctx, ReactionType::Unicode("\u{274c}".to_string()))
+            .await,
+    );
+
+    msg.reply(ctx, "Your appeal was sent to the moderators")
+        .await?;
+
+    Ok(())
+}
@@ -1,11 +1,46 @@
-use crate::models::server_config::ServerConfig;
+use crate::models::server_config::{DbError, ServerConfig};
 use crate::util::get_client;
+use once_cell::sync::Lazy;
 use serde::{Deserialize, Serialize};
 use serenity::prelude::*;
 use serenity::{
     framework::standard::{macros::command, Args, CommandResult},
     model::channel::Message,
 };
+use sqlx::postgres::PgPool;
+use std::collections::HashMap;
+use std::str::FromStr;
+use std::sync::{Arc, RwLock};
+use tracing::error;
+
+/// Parsed configs by server, `update_guild_config` replaces them on every change
+static GUILD_CONFIGS: Lazy<RwLock<HashMap<i64, Arc<Guild>>>> = Lazy::new(Default::default);
+
+/// Language of the messages the bot sends on its own, like DMs
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum Language {
+    De,
+    En,
+}
+
+impl Default for Language {
+    fn default() -> Self {
+        Self::De
+    }
+}
+
+impl FromStr for Language {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "de" => Ok(Self::De),
+            "en" => Ok(Self::En),
+            other => Err(format!("Unknown language {}, use de or en", other)),
+        }
+    }
+}
 
 // Keep every setting optional and use reasonable defaults
 #[derive(Serialize, Deserialize, Debug, Default)]
@@ -13,6 +48,25 @@ pub struct Guild {
     pub modlog_channel: Option<u64>,
     pub mute_role: Option<u64>,
     pub userlog_channel: Option<u64>,
+    pub appeals_channel: Option<u64>,
+    #[serde(default)]
+    pub automod: crate::automod::Config,
+    #[serde(default)]
+    pub filter: crate::filter::Config,
+    #[serde(default)]
+    pub modmail: super::modmail::Config,
+    #[serde(default)]
+    pub favs: super::fav::Config,
+    #[serde(default)]
+    pub language: Language,
+    #[serde(default)]
+    pub economy: super::account::Config,
+    #[serde(default)]
+    pub levels: super::levels::Config,
+    #[serde(default)]
+    pub shiny: super::shiny::Config,
+    #[serde(default)]
+    pub combo: super::fighting::Config,
 }
 
 #[command]
@@ -51,189 +105,351 @@ pub async fn show_config(ctx: &Context, msg: &Message, _args: Args) -> CommandRe
     Ok(())
 }
 
-#[command]
-#[num_args(1)]
-#[allowed_roles("Mods")]
-pub async fn set_modlog(ctx: &Context, msg: &Message, args: Args) -> CommandResult {
-    let modlog_channel = args.parse::<u64>()?;
-    let pool = get_client(ctx).await?;
+/// Loads the config of the server, parsed only once for every server
+///
+/// Servers without a config get the defaults, a config that can not be parsed is logged and
+/// replaced by the defaults too.
+pub async fn guild_config(pool: &PgPool, server_id: i64) -> Result<Arc<Guild>, DbError> {
+    let cached = GUILD_CONFIGS.read().unwrap().get(&server_id).cloned();
+    if let Some(g_cfg) = cached {
+        return Ok(g_cfg);
+    }
 
-    if let Some(server_id) = msg.guild_id {
-        match ServerConfig::get(&pool, *server_id.as_u64() as i64).await {
-            Ok(mut config) => {
-                let mut old_guild_config: Guild =
-                    serde_json::from_value(config.config.take()).unwrap();
-
-                old_guild_config.modlog_channel = Some(modlog_channel);
-
-                let updated_config = ServerConfig::update(
-                    &pool,
-                    *server_id.as_u64() as i64,
-                    serde_json::to_value(old_guild_config).unwrap(),
-                )
-                .await?;
-
-                std::mem::drop(
-                    msg.channel_id
-                        .send_message(&ctx.http, |m| {
-                            m.embed(|e| {
-                                e.description(format!("{:?}", &updated_config))
-                                    .color((0, 120, 220))
-                            })
-                        })
-                        .await,
-                );
-            }
-            Err(_e) => {
-                let guild_config = Guild {
-                    modlog_channel: Some(modlog_channel),
-                    ..Guild::default()
-                };
-
-                let inserted_config = ServerConfig::create(
-                    &pool,
-                    *server_id.as_u64() as i64,
-                    serde_json::to_value(guild_config).unwrap(),
-                )
-                .await?;
-
-                std::mem::drop(
-                    msg.channel_id
-                        .send_message(&ctx.http, |m| {
-                            m.embed(|e| {
-                                e.description(format!("{:?}", &inserted_config))
-                                    .color((0, 120, 220))
-                            })
-                        })
-                        .await,
-                );
-            }
-        }
+    let g_cfg = match ServerConfig::get(pool, server_id).await {
+        Ok(mut config) => serde_json::from_value(config.config.take()).unwrap_or_else(|e| {
+            error!(
+                ?e,
+                server_id, "Could not parse server config, using the defaults"
+            );
+            Guild::default()
+        }),
+        Err(DbError::RowNotFound) => Guild::default(),
+        Err(e) => return Err(e),
+    };
+
+    // a change stored while this one was loading wins
+    Ok(Arc::clone(
+        GUILD_CONFIGS
+            .write()
+            .unwrap()
+            .entry(server_id)
+            .or_insert_with(|| Arc::new(g_cfg)),
+    ))
+}
+
+/// Loads the config of the server, lets `f` change it and stores the result
+///
+/// Nothing is stored when `f` refuses the change, its message is returned instead.
+pub async fn update_guild_config<F>(
+    pool: &PgPool,
+    server_id: i64,
+    f: F,
+) -> Result<Result<Arc<Guild>, String>, DbError>
+where
+    F: FnOnce(&mut Guild) -> Result<(), String>,
+{
+    let (mut guild_config, exists): (Guild, bool) = match ServerConfig::get(pool, server_id).await {
+        Ok(mut config) => (
+            serde_json::from_value(config.config.take()).map_err(|e| DbError::Decode(e.into()))?,
+            true,
+        ),
+        Err(DbError::RowNotFound) => (Guild::default(), false),
+        Err(e) => return Err(e),
+    };
+
+    if let Err(e) = f(&mut guild_config) {
+        return Ok(Err(e));
     }
 
-    Ok(())
+    let value = serde_json::to_value(&guild_config).unwrap();
+    if exists {
+        ServerConfig::update(pool, server_id, value).await?;
+    } else {
+        ServerConfig::create(pool, server_id, value).await?;
+    }
+
+    let guild_config = Arc::new(guild_config);
+    GUILD_CONFIGS
+        .write()
+        .unwrap()
+        .insert(server_id, Arc::clone(&guild_config));
+    Ok(Ok(guild_config))
 }
 
-#[command]
-#[num_args(1)]
-#[allowed_roles("Mods")]
-pub async fn set_userlog(ctx: &Context, msg: &Message, args: Args) -> CommandResult {
-    let userlog_channel = args.parse::<u64>()?;
+/// Applies a change to the config of the server of the message, shows the changed part or
+/// replies why the change was refused
+async fn configure<F, D>(ctx: &Context, msg: &Message, f: F, show: D) -> CommandResult
+where
+    F: FnOnce(&mut Guild) -> Result<(), String>,
+    D: FnOnce(&Guild) -> String,
+{
+    let server_id = match msg.guild_id {
+        Some(server_id) => *server_id.as_u64() as i64,
+        None => return Ok(()),
+    };
     let pool = get_client(ctx).await?;
 
-    if let Some(server_id) = msg.guild_id {
-        match ServerConfig::get(&pool, *server_id.as_u64() as i64).await {
-            Ok(mut config) => {
-                let mut old_guild_config: Guild =
-                    serde_json::from_value(config.config.take()).unwrap();
-
-                old_guild_config.userlog_channel = Some(userlog_channel);
-
-                let inserted_config = ServerConfig::update(
-                    &pool,
-                    *server_id.as_u64() as i64,
-                    serde_json::to_value(old_guild_config).unwrap(),
-                )
-                .await?;
-
-                std::mem::drop(
-                    msg.channel_id
-                        .send_message(&ctx.http, |m| {
-                            m.embed(|e| {
-                                e.description(format!("{:?}", &inserted_config))
-                                    .color((0, 120, 220))
-                            })
-                        })
-                        .await,
-                );
-            }
-            Err(_e) => {
-                let guild_config = Guild {
-                    modlog_channel: Some(userlog_channel),
-                    ..Guild::default()
-                };
-
-                let inserted_config = ServerConfig::create(
-                    &pool,
-                    *server_id.as_u64() as i64,
-                    serde_json::to_value(guild_config).unwrap(),
-                )
-                .await?;
-
+    match update_guild_config(&pool, server_id, f).await? {
+        Ok(guild_config) => {
+            let description = show(&guild_config);
+            std::mem::drop(
                 msg.channel_id
                     .send_message(&ctx.http, |m| {
-                        m.embed(|e| {
-                            e.description(format!("{:?}", &inserted_config))
-                                .color((0, 120, 220))
-                        })
+                        m.embed(|e| e.description(description).color((0, 120, 220)))
                     })
-                    .await?;
-            }
+                    .await,
+            );
+        }
+        Err(e) => {
+            msg.reply(ctx, e).await?;
         }
     }
 
     Ok(())
 }
 
+#[command]
+#[num_args(1)]
+#[allowed_roles("Mods")]
+pub async fn set_modlog(ctx: &Context, msg: &Message, args: Args) -> CommandResult {
+    let modlog_channel = args.parse::<u64>()?;
+
+    configure(
+        ctx,
+        msg,
+        |g| {
+            g.modlog_channel = Some(modlog_channel);
+            Ok(())
+        },
+        |g| format!("{:?}", g),
+    )
+    .await
+}
+
+#[command]
+#[num_args(1)]
+#[allowed_roles("Mods")]
+pub async fn set_userlog(ctx: &Context, msg: &Message, args: Args) -> CommandResult {
+    let userlog_channel = args.parse::<u64>()?;
+
+    configure(
+        ctx,
+        msg,
+        |g| {
+            g.userlog_channel = Some(userlog_channel);
+            Ok(())
+        },
+        |g| format!("{:?}", g),
+    )
+    .await
+}
+
 #[command]
 #[num_args(1)]
 #[allowed_roles("Mods")]
 pub async fn set_muterole(ctx: &Context, msg: &Message, args: Args) -> CommandResult {
     let mute_role = args.parse::<u64>()?;
-    let pool = get_client(ctx).await?;
 
-    if let Some(server_id) = msg.guild_id {
-        match ServerConfig::get(&pool, *server_id.as_u64() as i64).await {
-            Ok(mut config) => {
-                let mut old_guild_config: Guild =
-                    serde_json::from_value(config.config.take()).unwrap();
-
-                old_guild_config.mute_role = Some(mute_role);
-
-                let inserted_config = ServerConfig::update(
-                    &pool,
-                    *server_id.as_u64() as i64,
-                    serde_json::to_value(old_guild_config).unwrap(),
-                )
-                .await?;
-
-                std::mem::drop(
-                    msg.channel_id
-                        .send_message(&ctx.http, |m| {
-                            m.embed(|e| {
-                                e.description(format!("{:?}", &inserted_config))
-                                    .color((0, 120, 220))
-                            })
-                        })
-                        .await,
-                );
-            }
-            Err(_e) => {
-                let guild_config = Guild {
-                    mute_role: Some(mute_role),
-                    ..Guild::default()
-                };
-
-                let inserted_config = ServerConfig::create(
-                    &pool,
-                    *server_id.as_u64() as i64,
-                    serde_json::to_value(guild_config).unwrap(),
-                )
-                .await?;
-
-                std::mem::drop(
-                    msg.channel_id
-                        .send_message(&ctx.http, |m| {
-                            m.embed(|e| {
-                                e.description(format!("{:?}", &inserted_config))
-                                    .color((0, 120, 220))
-                            })
-                        })
-                        .await,
-                );
-            }
-        }
-    }
+    configure(
+        ctx,
+        msg,
+        |g| {
+            g.mute_role = Some(mute_role);
+            Ok(())
+        },
+        |g| format!("{:?}", g),
+    )
+    .await
+}
 
-    Ok(())
+#[command]
+#[num_args(1)]
+#[description = "Set the channel where appeals of mutes and bans are posted"]
+#[allowed_roles("Mods")]
+pub async fn set_appeals(ctx: &Context, msg: &Message, args: Args) -> CommandResult {
+    let appeals_channel = args.parse::<u64>()?;
+
+    configure(
+        ctx,
+        msg,
+        |g| {
+            g.appeals_channel = Some(appeals_channel);
+            Ok(())
+        },
+        |g| format!("{:?}", g),
+    )
+    .await
+}
+
+#[command]
+#[min_args(1)]
+#[description = "Configure the automod rules. Rules: on, off, rate, duplicates, mentions, invites, attachments, joins. Actions: delete, warn, mute *duration*, kick, lockdown *duration*"]
+#[usage = "*rule* *settings*"]
+#[example = "rate 5 10s mute 10m"]
+#[example = "mentions 8 kick"]
+#[example = "invites off"]
+#[allowed_roles("Mods")]
+pub async fn set_automod(ctx: &Context, msg: &Message, mut args: Args) -> CommandResult {
+    let rule = args.single::<String>()?;
+    let settings = args.rest().split_whitespace().collect::<Vec<_>>();
+
+    configure(
+        ctx,
+        msg,
+        |g| g.automod.apply_setting(&rule, &settings),
+        |g| format!("{:?}", g.automod),
+    )
+    .await
+}
+
+#[command]
+#[min_args(1)]
+#[max_args(2)]
+#[description = "Enable modmail, new tickets are posted in the channel. Add `thread` to open a thread per ticket"]
+#[usage = "*channel_id* [thread]"]
+#[example = "123456789 thread"]
+#[allowed_roles("Mods")]
+pub async fn set_modmail(ctx: &Context, msg: &Message, mut args: Args) -> CommandResult {
+    let channel = args.single::<u64>()?;
+    let threads = args.single::<String>().map_or(false, |a| a == "thread");
+
+    configure(
+        ctx,
+        msg,
+        |g| {
+            g.modmail.channel = Some(channel);
+            g.modmail.threads = threads;
+            Ok(())
+        },
+        |g| format!("{:?}", g.modmail),
+    )
+    .await
+}
+
+#[command]
+#[description = "Set the greeting users get when they open a modmail ticket, without text the default is used"]
+#[usage = "*greeting*"]
+#[example = "Thanks for your message, a moderator will answer soon!"]
+#[allowed_roles("Mods")]
+pub async fn set_modmail_greeting(ctx: &Context, msg: &Message, args: Args) -> CommandResult {
+    let greeting = Some(args.rest().trim().to_string()).filter(|g| !g.is_empty());
+
+    configure(
+        ctx,
+        msg,
+        |g| {
+            g.modmail.greeting = greeting;
+            Ok(())
+        },
+        |g| format!("{:?}", g.modmail),
+    )
+    .await
+}
+
+#[command]
+#[min_args(2)]
+#[description = "Configure favs. Settings: prefer_live (on/off) shows the current version of a faved message instead of its snapshot, hall_of_fame (channel/off) reposts messages faved by hall_of_fame_threshold (1-100) users, block_notice (text/default) is the DM to users whose favs get blocked, `{count}` and `{links}` are filled in"]
+#[usage = "*setting* *value*"]
+#[example = "prefer_live on"]
+#[example = "hall_of_fame #hall-of-fame"]
+#[example = "hall_of_fame_threshold 7"]
+#[example = "block_notice {count} favs were blocked by the mods: {links}"]
+#[allowed_roles("Mods")]
+pub async fn set_favs(ctx: &Context, msg: &Message, mut args: Args) -> CommandResult {
+    let setting = args.single::<String>()?;
+    let value = args.rest().to_string();
+
+    configure(
+        ctx,
+        msg,
+        |g| g.favs.apply_setting(&setting, &value),
+        |g| format!("{:?}", g.favs),
+    )
+    .await
+}
+
+#[command]
+#[min_args(2)]
+#[description = "Configure xp and levels. Settings: enabled (on/off), announce (on/off) level ups, xp (min-max) per message, cooldown in seconds between messages that give xp, exclude (channel) toggles whether a channel gives xp, weight (channel percent) scales the xp of a channel, reward (level role/off) grants a role on reaching the level"]
+#[usage = "*setting* *value*"]
+#[example = "enabled on"]
+#[example = "xp 15-25"]
+#[example = "exclude #bot-spam"]
+#[example = "weight #off-topic 50"]
+#[example = "reward 10 @Regular"]
+#[allowed_roles("Mods")]
+pub async fn set_levels(ctx: &Context, msg: &Message, mut args: Args) -> CommandResult {
+    let setting = args.single::<String>()?;
+    let value = args.rest().to_string();
+
+    configure(
+        ctx,
+        msg,
+        |g| g.levels.apply_setting(&setting, &value),
+        |g| format!("{:?}", g.levels),
+    )
+    .await
+}
+
+#[command]
+#[min_args(2)]
+#[description = "Configure `combo`. Settings: game (gg/sf/bb/tekken) is used when a combo does not name one, emoji (notation emoji/off) shows a server emoji for a direction, motion, modifier or button"]
+#[usage = "*setting* *value*"]
+#[example = "game sf"]
+#[example = "emoji 236 <:qcf:123456789>"]
+#[example = "emoji P off"]
+#[allowed_roles("Mods")]
+pub async fn set_combo(ctx: &Context, msg: &Message, mut args: Args) -> CommandResult {
+    let setting = args.single::<String>()?;
+    let value = args.rest().to_string();
+
+    configure(
+        ctx,
+        msg,
+        |g| g.combo.apply_setting(&setting, &value),
+        |g| format!("{:?}", g.combo),
+    )
+    .await
+}
+
+#[command]
+#[num_args(2)]
+#[description = "Configure the credits of this server. Settings: starting_balance of new banks, payday_amount and payday_cooldown in hours, the games slot, blackjack, coinflip, roulette and duel can be turned on/off"]
+#[usage = "*setting* *value*"]
+#[example = "payday_amount 500"]
+#[example = "payday_cooldown 12"]
+#[example = "roulette off"]
+#[allowed_roles("Mods")]
+pub async fn set_economy(ctx: &Context, msg: &Message, mut args: Args) -> CommandResult {
+    let setting = args.single::<String>()?;
+    let value = args.single::<String>()?;
+
+    configure(
+        ctx,
+        msg,
+        |g| g.economy.apply_setting(&setting, &value),
+        |g| format!("{:?}", g.economy),
+    )
+    .await
+}
+
+#[command]
+#[num_args(1)]
+#[description = "Sets the language of messages the bot sends on its own (de/en)"]
+#[usage = "*language*"]
+#[example = "en"]
+#[allowed_roles("Mods")]
+pub async fn set_language(ctx: &Context, msg: &Message, mut args: Args) -> CommandResult {
+    let language = args.single::<String>()?.parse::<Language>();
+
+    configure(
+        ctx,
+        msg,
+        |g| {
+            g.language = language?;
+            Ok(())
+        },
+        |g| format!("{:?}", g.language),
+    )
+    .await
 }
@@ -1,26 +1,342 @@
-use crate::models::fav::Fav;
-use crate::models::fav_block::FavBlock;
-use crate::models::tag::Tag;
+use crate::commands::config::{Guild, Language};
+use crate::fav_index;
+use crate::models::collection::Collection;
+use crate::models::fav::export::{self, Format, Record};
+use crate::models::fav::query::{Atom, Query};
+use crate::models::fav::{Fav, Snapshot, SnapshotAttachment};
+use crate::models::fav_block::{FavBlock, Target};
+use crate::models::fav_post::FavPost;
+use crate::models::server_config::ServerConfig;
+use crate::models::tag::{normalize, suggest, Tag, TagChange};
 use crate::util;
 use crate::util::get_client;
 use crate::OptOut;
 use itertools::Itertools;
 use rand::prelude::*;
+use serde::{Deserialize, Serialize};
+use serenity::builder::CreateEmbed;
 use serenity::futures::stream::StreamExt;
-use serenity::model::{channel::Attachment, channel::ReactionType, id::ChannelId};
+use serenity::http::AttachmentType;
+use serenity::model::interactions::message_component::ButtonStyle;
+use serenity::model::{channel::ReactionType, id::ChannelId, id::GuildId, user::User};
 use serenity::prelude::*;
+use serenity::utils::{parse_channel, parse_username};
 use serenity::{
     framework::standard::{macros::command, Args, CommandResult},
     model::channel::Message,
     model::id::UserId,
 };
+use sqlx::postgres::PgPool;
+use std::borrow::Cow;
+use std::collections::HashMap;
 use std::time::Duration;
 use tracing::{debug, trace};
 
+pub const LABEL_REQUEST: &str =
+    "Send me your labels! Plain labels replace all tags, `+label` and `-label` add or remove single ones";
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+#[serde(default)]
+pub struct Config {
+    /// Show the current version of a faved message instead of its snapshot while it exists
+    pub prefer_live: bool,
+    /// Channel messages are reposted to once enough users faved them
+    pub hall_of_fame: Option<u64>,
+    pub hall_of_fame_threshold: u64,
+    /// Replaces the DM about blocked favs in the language of the server
+    pub block_notice: Option<String>,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            prefer_live: false,
+            hall_of_fame: None,
+            hall_of_fame_threshold: 5,
+            block_notice: None,
+        }
+    }
+}
+
+impl Config {
+    pub fn apply_setting(&mut self, setting: &str, value: &str) -> Result<(), String> {
+        match setting {
+            "prefer_live" => {
+                self.prefer_live = match value {
+                    "on" | "true" => true,
+                    "off" | "false" => false,
+                    other => return Err(format!("Expected on or off, got: {}", other)),
+                }
+            }
+            "hall_of_fame" => {
+                self.hall_of_fame = match value {
+                    "off" => None,
+                    channel => Some(
+                        parse_channel(channel)
+                            .or_else(|| channel.parse().ok())
+                            .ok_or_else(|| format!("Not a channel: {}", channel))?,
+                    ),
+                }
+            }
+            "hall_of_fame_threshold" => {
+                // the reacting users are fetched in one request of at most 100
+                self.hall_of_fame_threshold = value
+                    .parse()
+                    .ok()
+                    .filter(|threshold| (1..=100).contains(threshold))
+                    .ok_or_else(|| format!("Expected a number from 1 to 100, got: {}", value))?
+            }
+            "block_notice" => {
+                self.block_notice = match value.trim() {
+                    "default" => None,
+                    text => Some(text.to_string()),
+                }
+            }
+            other => return Err(format!("Unknown fav setting: {}", other)),
+        }
+
+        Ok(())
+    }
+}
+
+pub async fn prefers_live(pool: &PgPool, guild_id: Option<GuildId>) -> bool {
+    match guild_id {
+        Some(guild_id) => match ServerConfig::get(pool, *guild_id.as_u64() as i64).await {
+            Ok(mut config) => serde_json::from_value::<Guild>(config.config.take())
+                .map_or(false, |g_cfg| g_cfg.favs.prefer_live),
+            Err(_e) => false,
+        },
+        None => false,
+    }
+}
+
+/// Captures everything that is shown of the message, so the fav survives the original
+pub async fn snapshot_of(ctx: &Context, message: &Message) -> Snapshot {
+    Snapshot {
+        content: message.content.clone(),
+        author_name: message.author.name.clone(),
+        author_avatar: message.author.static_avatar_url().unwrap_or_default(),
+        timestamp: message.timestamp,
+        channel_name: message
+            .channel_id
+            .name(ctx)
+            .await
+            .unwrap_or_else(|| "-".to_string()),
+        attachments: message
+            .attachments
+            .iter()
+            .map(|a| SnapshotAttachment {
+                url: a.url.clone(),
+                filename: a.filename.clone(),
+                size: a.size,
+                width: a.width,
+                height: a.height,
+            })
+            .collect(),
+    }
+}
+
+/// What to show for the fav: its snapshot, or the live message if that is preferred (or
+/// there is no snapshot yet) and it is still available
+pub async fn fav_content(
+    ctx: &Context,
+    fav: &Fav,
+    prefer_live: bool,
+) -> Result<Snapshot, Box<dyn std::error::Error + Send + Sync>> {
+    let snapshot = fav.snapshot();
+
+    if prefer_live || snapshot.is_none() {
+        match ChannelId(fav.channel_id as u64)
+            .message(ctx, fav.msg_id as u64)
+            .await
+        {
+            Ok(message) => return Ok(snapshot_of(ctx, &message).await),
+            Err(e) => debug!(?e, fav = fav.id, "Original message of fav is not available"),
+        }
+    }
+
+    Ok(snapshot.ok_or("The original message is gone and the fav has no snapshot")?)
+}
+
+fn unknown_tag(known: &[String], label: &str) -> String {
+    let suggestions = suggest(known, label);
+    if suggestions.is_empty() {
+        format!("You have no tag {}", label)
+    } else {
+        format!(
+            "You have no tag {}, did you mean: {}?",
+            label,
+            suggestions.join(", ")
+        )
+    }
+}
+
+async fn reindex(ctx: &Context, pool: &PgPool, fav_ids: &[i64]) {
+    match Fav::by_ids(pool, fav_ids).await {
+        Ok(favs) => {
+            if let Err(e) = fav_index::index_favs(ctx, pool, &favs).await {
+                debug!(?e, "Could not index favs");
+            }
+        }
+        Err(e) => debug!(?e, "Could not get favs to index"),
+    }
+}
+
+/// Buttons on posted favs, the custom id carries the fav so they keep working after restarts
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FavButton {
+    Delete(i64),
+    Tags(i64),
+    Source(i64),
+    /// Post another fav with the same tags
+    Another(i64),
+}
+
+impl FavButton {
+    pub fn fav_id(self) -> i64 {
+        match self {
+            Self::Delete(id) | Self::Tags(id) | Self::Source(id) | Self::Another(id) => id,
+        }
+    }
+
+    pub fn custom_id(self) -> String {
+        let action = match self {
+            Self::Delete(_) => "delete",
+            Self::Tags(_) => "tags",
+            Self::Source(_) => "source",
+            Self::Another(_) => "another",
+        };
+
+        format!("fav:{}:{}", action, self.fav_id())
+    }
+
+    pub fn parse(custom_id: &str) -> Option<Self> {
+        let mut parts = custom_id.splitn(3, ':');
+        if parts.next()? != "fav" {
+            return None;
+        }
+        let action = parts.next()?;
+        let fav_id = parts.next()?.parse().ok()?;
+
+        match action {
+            "delete" => Some(Self::Delete(fav_id)),
+            "tags" => Some(Self::Tags(fav_id)),
+            "source" => Some(Self::Source(fav_id)),
+            "another" => Some(Self::Another(fav_id)),
+            _ => None,
+        }
+    }
+}
+
+/// Posts the fav with its buttons and records the post
+pub async fn send_fav(
+    ctx: &Context,
+    pool: &PgPool,
+    channel_id: ChannelId,
+    guild_id: Option<GuildId>,
+    poster: &User,
+    fav: &Fav,
+    shown: &Snapshot,
+) -> Result<Message, Box<dyn std::error::Error + Send + Sync>> {
+    let bot_msg = channel_id
+        .send_message(ctx, |m| {
+            m.embed(|e| {
+                let timestamp = shown.timestamp.format("%d.%m.%Y, %H:%M:%S");
+
+                let mut embed = e
+                    .author(|a| a.name(&shown.author_name).icon_url(&shown.author_avatar))
+                    .description(&shown.content)
+                    .color((0, 120, 220))
+                    .footer(|f| {
+                        f.text(&format!(
+                            "{} (UTC) | #{} | Fav by: {}",
+                            timestamp, shown.channel_name, &poster.name,
+                        ))
+                    });
+
+                if let Some(image) = shown.image() {
+                    embed = embed.image(image);
+                }
+
+                embed
+            })
+            .components(|c| {
+                c.create_action_row(|r| {
+                    r.create_button(|b| {
+                        b.style(ButtonStyle::Danger)
+                            .emoji(ReactionType::Unicode("🗑️".to_string()))
+                            .label("Delete")
+                            .custom_id(FavButton::Delete(fav.id).custom_id())
+                    })
+                    .create_button(|b| {
+                        b.style(ButtonStyle::Secondary)
+                            .emoji(ReactionType::Unicode("🏷️".to_string()))
+                            .label("Tags")
+                            .custom_id(FavButton::Tags(fav.id).custom_id())
+                    })
+                    .create_button(|b| {
+                        b.style(ButtonStyle::Secondary)
+                            .emoji(ReactionType::Unicode("\u{2139}\u{fe0f}".to_string()))
+                            .label("Source")
+                            .custom_id(FavButton::Source(fav.id).custom_id())
+                    })
+                    .create_button(|b| {
+                        b.style(ButtonStyle::Primary)
+                            .emoji(ReactionType::Unicode("🔀".to_string()))
+                            .label("Another")
+                            .custom_id(FavButton::Another(fav.id).custom_id())
+                    })
+                })
+            })
+        })
+        .await?;
+
+    if let Err(e) = FavPost::create(
+        pool,
+        fav.id,
+        guild_id.map(|g_id| *g_id.as_u64() as i64),
+        *channel_id.as_u64() as i64,
+        *poster.id.as_u64() as i64,
+    )
+    .await
+    {
+        debug!(?e, "Could not record fav post");
+    }
+
+    Ok(bot_msg)
+}
+
+/// Parses a fav query, resolving channel names on the server
+async fn parse_query(
+    ctx: &Context,
+    guild_id: Option<GuildId>,
+    text: &str,
+) -> Result<Query, String> {
+    let mut query = Query::parse(text)?;
+
+    let channels = match guild_id {
+        Some(guild_id) => guild_id
+            .channels(ctx)
+            .await
+            .map_err(|e| format!("Could not get the channels: {}", e))?,
+        None => HashMap::new(),
+    };
+    query.resolve_channels(|name| {
+        channels
+            .values()
+            .find(|c| c.name == name)
+            .map(|c| *c.id.as_u64())
+    })?;
+
+    Ok(query)
+}
+
 #[command]
-#[description = "Post a fav"]
-#[usage = "*tag*"]
+#[description = "Post a fav. Tags separated by spaces must all match, `|` means or, `-tag` excludes a tag. Filter with `author:@user` and `channel:#channel`. Start with `@user collection` to pick from a collection the user shared"]
+#[usage = "*query*"]
 #[example = "taishi wichsen"]
+#[example = "haus|turm -fenster author:@user"]
+#[example = "@user memes"]
 #[bucket = "fav"]
 pub async fn post(ctx: &Context, msg: &Message, mut args: Args) -> CommandResult {
     let pool = get_client(ctx).await?;
@@ -42,33 +358,72 @@ pub async fn post(ctx: &Context, msg: &Message, mut args: Args) -> CommandResult
         return Ok(());
     }
 
-    let labels: Vec<String> = args.iter::<String>().filter_map(Result::ok).collect();
+    let mut owner_id = *msg.author.id.as_u64();
+    let mut collection_id = None;
+    if let Some(user_id) = args.current().and_then(parse_username) {
+        args.advance();
+        let name = args.single::<String>()?;
+        let guild_id = msg.guild_id.ok_or("Collections only exist on servers")?;
+
+        let collection =
+            match Collection::get(&pool, *guild_id.as_u64() as i64, user_id as i64, &name).await {
+                Ok(collection) if collection.public || user_id == owner_id => collection,
+                _ => {
+                    msg.reply(ctx, format!("There is no shared collection named {}", name))
+                        .await?;
+                    return Ok(());
+                }
+            };
 
-    let results = if labels.is_empty() {
-        Fav::list(
-            &pool,
-            *msg.author.id.as_u64() as i64,
-            msg.guild_id.map(|g_id| *g_id.as_u64() as i64),
-        )
-        .await?
-    } else {
-        Fav::tagged_with(
-            &pool,
-            *msg.author.id.as_u64() as i64,
-            msg.guild_id.map(|g_id| *g_id.as_u64() as i64),
-            labels,
-        )
-        .await?
+        if opt_out.lock().await.set.contains(&user_id) {
+            msg.reply(ctx, "The user does not want to be quoted")
+                .await?;
+            return Ok(());
+        }
+
+        owner_id = user_id;
+        collection_id = Some(collection.id);
+    }
+
+    let query = match parse_query(ctx, msg.guild_id, args.rest()).await {
+        Ok(query) => query,
+        Err(e) => {
+            msg.reply(ctx, e).await?;
+            return Ok(());
+        }
     };
 
-    let chosen_fav = results
-        .into_iter()
-        .choose(&mut rand::thread_rng())
-        .ok_or("possible favs empty")?;
+    let results = Fav::query(
+        &pool,
+        owner_id as i64,
+        msg.guild_id.map(|g_id| *g_id.as_u64() as i64),
+        collection_id,
+        &query,
+    )
+    .await?;
 
-    let fav_msg = ChannelId(chosen_fav.channel_id as u64)
-        .message(&ctx.http, chosen_fav.msg_id as u64)
-        .await?;
+    let chosen_fav = match results.into_iter().choose(&mut rand::thread_rng()) {
+        Some(fav) => fav,
+        None => {
+            let known = Tag::labels_of_user(&pool, owner_id as i64).await?;
+            let hint = query
+                .groups
+                .iter()
+                .flatten()
+                .filter_map(|term| match term.atom {
+                    Atom::Tag(ref label) if !term.negated && !known.contains(label) => {
+                        Some(unknown_tag(&known, label))
+                    }
+                    _ => None,
+                })
+                .next()
+                .unwrap_or_else(|| "No fav matches".to_string());
+            msg.reply(ctx, hint).await?;
+            return Ok(());
+        }
+    };
+
+    let shown = fav_content(ctx, &chosen_fav, prefers_live(&pool, msg.guild_id).await).await?;
 
     if msg.delete(ctx).await.is_err() {
         debug!("Deletion is not supported in DMs");
@@ -78,7 +433,7 @@ pub async fn post(ctx: &Context, msg: &Message, mut args: Args) -> CommandResult
         .lock()
         .await
         .set
-        .contains(fav_msg.author.id.as_u64())
+        .contains(&(chosen_fav.author_id as u64))
     {
         std::mem::drop(
             msg.channel_id
@@ -90,130 +445,16 @@ pub async fn post(ctx: &Context, msg: &Message, mut args: Args) -> CommandResult
         return Ok(());
     }
 
-    let channel_name = fav_msg
-        .channel_id
-        .name(&ctx)
-        .await
-        .unwrap_or_else(|| "-".to_string());
-
-    let bot_msg = msg
-        .channel_id
-        .send_message(&ctx.http, |m| {
-            m.embed(|e| {
-                let timestamp = fav_msg.timestamp.format("%d.%m.%Y, %H:%M:%S");
-
-                let mut embed = e
-                    .author(|a| {
-                        a.name(&fav_msg.author.name)
-                            .icon_url(&fav_msg.author.static_avatar_url().unwrap_or_default())
-                    })
-                    .description(&fav_msg.content)
-                    .color((0, 120, 220))
-                    .footer(|f| {
-                        f.text(&format!(
-                            "{} (UTC) | #{} | Fav by: {}",
-                            timestamp, channel_name, &msg.author.name,
-                        ))
-                    });
-
-                if let Some(image) = fav_msg
-                    .attachments
-                    .iter()
-                    .cloned()
-                    .filter(|a| a.width.is_some())
-                    .collect::<Vec<Attachment>>()
-                    .first()
-                {
-                    embed = embed.image(&image.url);
-                }
-
-                embed
-            })
-        })
-        .await?;
-
-    let collector_delete = bot_msg
-        .await_reactions(&ctx)
-        .timeout(Duration::from_secs(120))
-        .author_id(msg.author.id)
-        .filter(|reaction| matches!(reaction.emoji, ReactionType::Unicode(ref value) if value.starts_with('🗑')))
-        .await;
-
-    let collector_label = bot_msg
-        .await_reactions(&ctx)
-        .timeout(Duration::from_secs(120))
-        .author_id(msg.author.id)
-        .filter(|reaction| matches!(reaction.emoji, ReactionType::Unicode(ref value) if value.starts_with('🏷')))
-        .await;
-
-    let collector_info = bot_msg
-        .await_reactions(&ctx)
-        .timeout(Duration::from_secs(5 * 60_u64))
-        .filter(|reaction| matches!(reaction.emoji, ReactionType::Unicode(ref value) if value == "\u{2139}\u{fe0f}"))
-        .await;
-
-    let c1 = collector_delete.for_each(|_reaction| {
-        let chosen_fav_id = chosen_fav.id;
-        let pool = pool.clone();
-        async move {
-            std::mem::drop(Fav::delete(&pool, chosen_fav_id).await);
-        }
-    });
-    let c2 = collector_label.for_each(|reaction| {
-        let ctx = ctx.clone();
-        let chosen_fav_id = chosen_fav.id;
-        let pool = pool.clone();
-        async move {
-            let reaction = reaction.as_inner_ref();
-            if let Ok(dm_channel) = reaction.user_id.unwrap().create_dm_channel(&ctx).await {
-                trace!(user = ?reaction.user_id, "Requesting labels from user");
-                std::mem::drop(dm_channel.say(&ctx, "Send me your labels!").await);
-
-                if let Some(label_reply) = dm_channel
-                    .id
-                    .await_reply(&ctx)
-                    .author_id(reaction.user_id.unwrap())
-                    .timeout(Duration::from_secs(120))
-                    .await
-                {
-                    // clear old tags for this fav
-                    std::mem::drop(Tag::delete(&pool, chosen_fav_id).await);
-
-                    // TODO: make this a single statement
-                    for tag in label_reply.content.split(' ') {
-                        std::mem::drop(Tag::create(&pool, chosen_fav_id, tag).await);
-                    }
-
-                    std::mem::drop(label_reply.reply(&ctx, "added the tags!").await);
-                }
-            }
-        }
-    });
-
-    let c3 = collector_info.for_each(|reaction| {
-        let ctx = ctx.clone();
-        let chosen_fav = chosen_fav.clone();
-        async move {
-            // ignore add/remove reaction difference
-            let reaction = reaction.as_inner_ref();
-            if let Ok(dm_channel) = reaction.user_id.unwrap().create_dm_channel(&ctx).await {
-                trace!(user = ?reaction.user_id, "sending info source for quote");
-                std::mem::drop(
-                    dm_channel
-                        .say(
-                            &ctx,
-                            format!(
-                                "https://discordapp.com/channels/{}/{}/{}",
-                                &chosen_fav.server_id, &chosen_fav.channel_id, &chosen_fav.msg_id,
-                            ),
-                        )
-                        .await,
-                );
-            }
-        }
-    });
-
-    futures::future::join3(c1, c2, c3).await;
+    send_fav(
+        ctx,
+        &pool,
+        msg.channel_id,
+        msg.guild_id,
+        &msg.author,
+        &chosen_fav,
+        &shown,
+    )
+    .await?;
 
     Ok(())
 }
@@ -238,17 +479,9 @@ pub async fn untagged(ctx: &Context, msg: &Message, _args: Args) -> CommandResul
         std::mem::drop(msg.reply(ctx, "Du hat keine untagged Favs!").await);
     } else {
         let fav = results.first().unwrap();
-        let fav_msg = ChannelId(fav.channel_id as u64)
-            .message(&ctx, fav.msg_id as u64)
-            .await
-            .unwrap();
+        let shown = fav_content(ctx, fav, false).await?;
 
-        if opt_out
-            .lock()
-            .await
-            .set
-            .contains(fav_msg.author.id.as_u64())
-        {
+        if opt_out.lock().await.set.contains(&(fav.author_id as u64)) {
             std::mem::drop(
                 msg.channel_id
                     .send_message(&ctx.http, |m| {
@@ -264,29 +497,19 @@ pub async fn untagged(ctx: &Context, msg: &Message, _args: Args) -> CommandResul
             .send_message(&ctx, |m| {
                 m.embed(|e| {
                     let mut embed = e
-                        .author(|a| {
-                            a.name(&fav_msg.author.name)
-                                .icon_url(&fav_msg.author.static_avatar_url().unwrap_or_default())
-                        })
-                        .description(&fav_msg.content)
+                        .author(|a| a.name(&shown.author_name).icon_url(&shown.author_avatar))
+                        .description(&shown.content)
                         .color((0, 120, 220))
                         .footer(|f| {
                             f.text(&format!(
                                 "{} | Zitiert von: {}",
-                                &fav_msg.timestamp.format("%d.%m.%Y, %H:%M:%S"),
+                                &shown.timestamp.format("%d.%m.%Y, %H:%M:%S"),
                                 &msg.author.name
                             ))
                         });
 
-                    if let Some(image) = fav_msg
-                        .attachments
-                        .iter()
-                        .cloned()
-                        .filter(|a| a.width.is_some())
-                        .collect::<Vec<Attachment>>()
-                        .first()
-                    {
-                        embed = embed.image(&image.url);
+                    if let Some(image) = shown.image() {
+                        embed = embed.image(image);
                     }
 
                     embed
@@ -325,35 +548,32 @@ pub async fn untagged(ctx: &Context, msg: &Message, _args: Args) -> CommandResul
             async move {
                 trace!(fav = fav_id, "Delete Tag for fav");
                 std::mem::drop(Fav::delete(&pool, fav_id).await);
+                std::mem::drop(fav_index::remove_favs(&[fav_id]).await);
             }
         });
         let c2 = collector_label.for_each(|reaction| {
             let ctx = ctx.clone();
+            let fav = fav.clone();
             let fav_id = fav.id;
             let pool = pool.clone();
             async move {
                 let reaction = reaction.as_inner_ref();
                 if let Ok(dm_channel) = reaction.user_id.unwrap().create_dm_channel(&ctx).await {
                     trace!(user = ?reaction.user_id, "Requesting labels from user");
-                    std::mem::drop(dm_channel.say(&ctx, "Send me your labels!").await);
-
-                    if let Some(label_reply) = dm_channel
-                        .id
-                        .await_reply(&ctx)
-                        .author_id(reaction.user_id.unwrap())
-                        .timeout(Duration::from_secs(120))
-                        .await
+                    std::mem::drop(dm_channel.say(&ctx, LABEL_REQUEST).await);
+
+                    if let Some(label_reply) = util::await_reply(
+                        &ctx,
+                        dm_channel.id,
+                        reaction.user_id.unwrap(),
+                        Duration::from_secs(120),
+                    )
+                    .await
                     {
-                        // clear old tags for this fav
-                        let r = Tag::delete(&pool, fav_id).await;
-                        trace!(tag_deletion = ?r, "Tags deleted");
-
-                        // TODO: make this a single statement
-                        for tag in label_reply.content.split(' ') {
-                            let r = Tag::create(&pool, fav_id, tag).await;
-
-                            trace!(tag_creation = ?r, "Tag created");
-                        }
+                        let change = TagChange::parse(&label_reply.content);
+                        let r = Tag::apply(&pool, fav_id, &change).await;
+                        trace!(tag_change = ?r, "Tags changed");
+                        std::mem::drop(fav_index::index_fav(&ctx, &pool, &fav).await);
 
                         std::mem::drop(label_reply.reply(&ctx, "added the tags!").await);
                     }
@@ -366,6 +586,135 @@ pub async fn untagged(ctx: &Context, msg: &Message, _args: Args) -> CommandResul
     Ok(())
 }
 
+#[command]
+#[only_in("guilds")]
+#[min_args(1)]
+#[description = "Manage your fav collections on this server. Actions: list, create, delete, add *query*, remove *message link*, share, unshare. `list @user` shows the collections the user shared"]
+#[usage = "*action* *name* *arguments*"]
+#[example = "create memes"]
+#[example = "add memes meme|shitpost -cringe"]
+#[example = "share memes"]
+#[example = "list @user"]
+pub async fn collection(ctx: &Context, msg: &Message, mut args: Args) -> CommandResult {
+    let action = args.single::<String>()?;
+    let pool = get_client(ctx).await?;
+    let guild_id = msg.guild_id.ok_or("Collections only exist on servers")?;
+    let server_id = *guild_id.as_u64() as i64;
+    let user_id = *msg.author.id.as_u64() as i64;
+
+    if action == "list" {
+        let (owner_id, only_public) = match args.current().and_then(parse_username) {
+            Some(other) if other as i64 != user_id => (other as i64, true),
+            _ => (user_id, false),
+        };
+
+        let mut lines = Vec::new();
+        for collection in Collection::of_user(&pool, server_id, owner_id).await? {
+            if only_public && !collection.public {
+                continue;
+            }
+            lines.push(format!(
+                "**{}**: {} favs{}",
+                collection.name,
+                Collection::size(&pool, collection.id).await?,
+                if collection.public { " (shared)" } else { "" }
+            ));
+        }
+
+        let description = if lines.is_empty() {
+            "No collections".to_string()
+        } else {
+            lines.join("\n")
+        };
+        msg.channel_id
+            .send_message(ctx, |m| {
+                m.embed(|e| {
+                    e.title("Collections")
+                        .description(description)
+                        .color((0, 120, 220))
+                })
+            })
+            .await?;
+
+        return Ok(());
+    }
+
+    let name = args.single::<String>()?;
+    if action == "create" {
+        let response = match Collection::create(&pool, server_id, user_id, &name).await {
+            Ok(_) => format!("Created the collection {}", name),
+            Err(e) => {
+                debug!(?e, "Could not create collection");
+                format!("You already have a collection named {}", name)
+            }
+        };
+        msg.reply(ctx, response).await?;
+
+        return Ok(());
+    }
+
+    let collection = match Collection::get(&pool, server_id, user_id, &name).await {
+        Ok(collection) => collection,
+        Err(_e) => {
+            msg.reply(ctx, format!("You have no collection named {}", name))
+                .await?;
+            return Ok(());
+        }
+    };
+
+    let response = match action.as_str() {
+        "delete" => {
+            Collection::delete(&pool, collection.id).await?;
+            format!("Deleted the collection {}", name)
+        }
+        "share" => {
+            Collection::set_public(&pool, collection.id, true).await?;
+            format!("Everyone on this server can now post from {}", name)
+        }
+        "unshare" => {
+            Collection::set_public(&pool, collection.id, false).await?;
+            format!("{} is private again", name)
+        }
+        "add" => {
+            let query = match parse_query(ctx, msg.guild_id, args.rest()).await {
+                Ok(query) => query,
+                Err(e) => {
+                    msg.reply(ctx, e).await?;
+                    return Ok(());
+                }
+            };
+
+            // only messages of this server, a shared collection must not leak other servers
+            let fav_ids = Fav::query(&pool, user_id, Some(server_id), None, &query)
+                .await?
+                .into_iter()
+                .filter(|fav| fav.server_id == server_id)
+                .map(|fav| fav.id)
+                .collect::<Vec<_>>();
+            let added = Collection::add_favs(&pool, collection.id, &fav_ids).await?;
+
+            format!("Added {} favs to {}", added, name)
+        }
+        "remove" => {
+            let regex = crate::MESSAGE_REGEX.get().expect("regex not init");
+            let (_, channel_id, msg_id) = util::parse_message_link(regex, args.rest())?;
+            let fav_ids = Fav::list_by_channel_msg(&pool, channel_id as i64, msg_id as i64)
+                .await?
+                .into_iter()
+                .filter(|fav| fav.user_id == user_id)
+                .map(|fav| fav.id)
+                .collect::<Vec<_>>();
+            let removed = Collection::remove_favs(&pool, collection.id, &fav_ids).await?;
+
+            format!("Removed {} favs from {}", removed, name)
+        }
+        other => format!("Unknown action: {}", other),
+    };
+    msg.reply(ctx, response).await?;
+
+    Ok(())
+}
+
 #[command]
 #[only_in("dms")]
 #[description = "Add a fav per link to the message"]
@@ -381,22 +730,35 @@ pub async fn add(ctx: &Context, msg: &Message, args: Args) -> CommandResult {
 
     let pool = get_client(ctx).await?;
 
-    if FavBlock::check_blocked(&pool, fav_channel_id as i64, fav_msg_id as i64).await {
+    if FavBlock::check_blocked(
+        &pool,
+        fav_server_id as i64,
+        fav_channel_id as i64,
+        fav_msg_id as i64,
+        *fav_msg.author.id.as_u64() as i64,
+    )
+    .await
+    {
         // is blocked
         msg.author
             .dm(ctx, |m| m.content("This fav is blocked"))
             .await?;
     } else {
-        Fav::create(
+        let fav = Fav::create(
             &pool,
             fav_server_id as i64,
             fav_channel_id as i64,
             fav_msg_id as i64,
             *msg.author.id.as_u64() as i64,
             *fav_msg.author.id.as_u64() as i64,
+            &snapshot_of(ctx, &fav_msg).await,
         )
         .await?;
 
+        if let Err(e) = fav_index::index_fav(ctx, &pool, &fav).await {
+            debug!(?e, "Could not index fav");
+        }
+
         if let Err(why) = msg.author.dm(ctx, |m| m.content("Fav saved!")).await {
             debug!("Error sending message: {:?}", why);
         }
@@ -405,15 +767,91 @@ pub async fn add(ctx: &Context, msg: &Message, args: Args) -> CommandResult {
     Ok(())
 }
 
+#[command]
+#[min_args(2)]
+#[description = "Manage your tags. `rename old new` renames a tag on all your favs, `merge from into` moves all favs from one tag to another, `add` and `remove` change single tags of a fav"]
+#[usage = "*action* *arguments*"]
+#[example = "rename hous haus"]
+#[example = "merge car auto"]
+#[example = "add https://discord.com/channels/1/2/3 haus turm"]
+#[example = "remove https://discord.com/channels/1/2/3 turm"]
+pub async fn tag(ctx: &Context, msg: &Message, mut args: Args) -> CommandResult {
+    let action = args.single::<String>()?;
+    let pool = get_client(ctx).await?;
+    let user_id = *msg.author.id.as_u64() as i64;
+
+    let response = match action.as_str() {
+        "rename" | "merge" => {
+            let old = args.single::<String>()?.to_lowercase();
+            let new = args.single::<String>()?.to_lowercase();
+            let known = Tag::labels_of_user(&pool, user_id).await?;
+
+            if !known.contains(&old) {
+                unknown_tag(&known, &old)
+            } else if old == new {
+                format!("{} is already called {}", old, new)
+            } else if action == "rename" && known.contains(&new) {
+                format!("You already use {}, merge the tags instead", new)
+            } else {
+                let fav_ids = Tag::relabel(&pool, user_id, &old, &new).await?;
+                reindex(ctx, &pool, &fav_ids).await;
+                format!("Changed {} favs from {} to {}", fav_ids.len(), old, new)
+            }
+        }
+        "add" | "remove" => {
+            let regex = crate::MESSAGE_REGEX.get().expect("regex not init");
+            let (_, channel_id, msg_id) =
+                util::parse_message_link(regex, &args.single::<String>()?)?;
+            let labels = normalize(args.rest());
+            let change = if action == "add" {
+                TagChange::Edit {
+                    add: labels,
+                    remove: Vec::new(),
+                }
+            } else {
+                TagChange::Edit {
+                    add: Vec::new(),
+                    remove: labels,
+                }
+            };
+
+            let fav_ids = Fav::list_by_channel_msg(&pool, channel_id as i64, msg_id as i64)
+                .await?
+                .into_iter()
+                .filter(|fav| fav.user_id == user_id)
+                .map(|fav| fav.id)
+                .collect::<Vec<_>>();
+            for fav_id in &fav_ids {
+                Tag::apply(&pool, *fav_id, &change).await?;
+            }
+            reindex(ctx, &pool, &fav_ids).await;
+
+            if fav_ids.is_empty() {
+                "You have not faved this message".to_string()
+            } else {
+                "Tags changed!".to_string()
+            }
+        }
+        other => format!("Unknown action: {}", other),
+    };
+    msg.reply(ctx, response).await?;
+
+    Ok(())
+}
+
 #[command]
 #[only_in("dms")]
-#[description = "Shows your used tags so you do not have to remember them all"]
-#[num_args(0)]
-pub async fn tags(ctx: &Context, msg: &Message, _args: Args) -> CommandResult {
+#[description = "Shows your used tags so you do not have to remember them all, optionally only the ones starting with the given text"]
+#[usage = "*start*"]
+#[example = "ha"]
+#[max_args(1)]
+pub async fn tags(ctx: &Context, msg: &Message, args: Args) -> CommandResult {
     let pool = get_client(ctx).await?;
+    let start = args.rest().trim().to_lowercase();
     let mut messages = Vec::new();
     {
         let mut fav_tags = Tag::of_user(&pool, *msg.author.id.as_u64() as i64).await?;
+        fav_tags.retain(|t| t.label.starts_with(&start));
 
         fav_tags.sort_unstable_by(|a, b| a.label.partial_cmp(&b.label).unwrap());
         let mut message_content = String::new();
@@ -439,102 +877,690 @@ pub async fn tags(ctx: &Context, msg: &Message, _args: Args) -> CommandResult {
     Ok(())
 }
 
+fn search_page(
+    e: &mut CreateEmbed,
+    text: &str,
+    hits: &[fav_index::Hit],
+    page: usize,
+    pages: usize,
+) -> &mut CreateEmbed {
+    let description = hits
+        .iter()
+        .map(|hit| {
+            let content = hit.content.chars().take(120).collect::<String>();
+            format!(
+                "**{}** {} | {} in #{} [jump]({})\n*{}*",
+                hit.fav_id, content, hit.author, hit.channel, hit.link, hit.tags
+            )
+        })
+        .collect::<Vec<_>>()
+        .join("\n\n");
+
+    e.title(format!("Favs matching: {}", text))
+        .description(description)
+        .footer(|f| f.text(format!("Page {} of {}", page + 1, pages)))
+        .color((0, 120, 220))
+}
+
+#[command]
+#[description = "Search your favs by their content, author, channel and tags"]
+#[usage = "*text*"]
+#[example = "haus"]
+#[min_args(1)]
+#[bucket = "fav"]
+pub async fn search(ctx: &Context, msg: &Message, args: Args) -> CommandResult {
+    let text = args.rest().trim();
+    let user_id = *msg.author.id.as_u64();
+    let opted_out = match ctx.data.read().await.get::<OptOut>() {
+        Some(v) => v.lock().await.set.iter().copied().collect::<Vec<_>>(),
+        None => Vec::new(),
+    };
+
+    let (total, hits) = fav_index::search(user_id, text, &opted_out, 0)?;
+    if total == 0 {
+        msg.reply(ctx, "Nothing found").await?;
+        return Ok(());
+    }
+
+    let pages = (total + fav_index::PAGE_SIZE - 1) / fav_index::PAGE_SIZE;
+    let mut bot_msg = msg
+        .channel_id
+        .send_message(ctx, |m| m.embed(|e| search_page(e, text, &hits, 0, pages)))
+        .await?;
+
+    if pages > 1 {
+        std::mem::drop(
+            bot_msg
+                .react(ctx, ReactionType::Unicode("\u{25c0}".to_string()))
+                .await,
+        );
+        std::mem::drop(
+            bot_msg
+                .react(ctx, ReactionType::Unicode("\u{25b6}".to_string()))
+                .await,
+        );
+
+        // ignore add/remove reaction difference, both turn the page
+        let mut collector = bot_msg
+            .await_reactions(&ctx)
+            .timeout(Duration::from_secs(120))
+            .author_id(msg.author.id)
+            .removed(true)
+            .await;
+
+        let mut page = 0;
+        while let Some(reaction) = collector.next().await {
+            page = match reaction.as_inner_ref().emoji {
+                ReactionType::Unicode(ref s) if s == "\u{25c0}" => page.saturating_sub(1),
+                ReactionType::Unicode(ref s) if s == "\u{25b6}" => (page + 1).min(pages - 1),
+                _ => continue,
+            };
+
+            let (_, hits) = fav_index::search(user_id, text, &opted_out, page)?;
+            bot_msg
+                .edit(ctx, |m| {
+                    m.embed(|e| search_page(e, text, &hits, page, pages))
+                })
+                .await?;
+        }
+    }
+
+    Ok(())
+}
+
+/// Parses `*message link*`, `channel #channel` or `author @user`
+fn block_target(input: &str) -> Result<Target, String> {
+    let mut words = input.split_whitespace();
+
+    match (words.next(), words.next(), words.next()) {
+        (Some("channel"), Some(channel), None) => parse_channel(channel)
+            .or_else(|| channel.parse().ok())
+            .map(|id| Target::Channel(id as i64))
+            .ok_or_else(|| format!("Not a channel: {}", channel)),
+        (Some("author"), Some(user), None) => parse_username(user)
+            .or_else(|| user.parse().ok())
+            .map(|id| Target::Author(id as i64))
+            .ok_or_else(|| format!("Not a user: {}", user)),
+        (Some(link), None, None) => {
+            let regex = crate::MESSAGE_REGEX.get().expect("regex not init");
+            let (_, channel_id, msg_id) = util::parse_message_link(regex, link)?;
+            Ok(Target::Message {
+                channel_id: channel_id as i64,
+                msg_id: msg_id as i64,
+            })
+        }
+        _ => Err("Expected a message link, `channel #channel` or `author @user`".to_string()),
+    }
+}
+
+fn describe_block(block: &FavBlock) -> String {
+    match block.target() {
+        Some(Target::Message { channel_id, msg_id }) => format!(
+            "message https://discord.com/channels/{}/{}/{}",
+            block.server_id, channel_id, msg_id
+        ),
+        Some(Target::Channel(channel_id)) => format!("channel <#{}>", channel_id),
+        Some(Target::Author(author_id)) => format!("author <@{}>", author_id),
+        None => format!("unknown {}", block.kind),
+    }
+}
+
+fn default_block_notice(language: Language) -> &'static str {
+    match language {
+        Language::De => "Es wurden gerade {count} Favs von dir geblockt:\n{links}",
+        Language::En => "{count} of your favs were just blocked:\n{links}",
+    }
+}
+
+/// The DM for a user whose favs were blocked, `{count}` and `{links}` in the text are replaced
+pub fn block_notice(g_cfg: &Guild, links: &[String]) -> String {
+    const SHOWN_LINKS: usize = 10;
+
+    let mut shown = links.iter().take(SHOWN_LINKS).cloned().collect::<Vec<_>>();
+    if links.len() > SHOWN_LINKS {
+        shown.push(format!("+{}", links.len() - SHOWN_LINKS));
+    }
+
+    g_cfg
+        .favs
+        .block_notice
+        .as_deref()
+        .unwrap_or_else(|| default_block_notice(g_cfg.language))
+        .replace("{count}", &links.len().to_string())
+        .replace("{links}", &shown.join("\n"))
+}
+
 #[command]
 #[only_in("guilds")]
-#[description = "Adds a fav to the blocklist"]
+#[min_args(1)]
+#[description = "Blocks favs of a message, of a whole channel or of an author on this server. Everyone with an affected fav gets a DM"]
+#[usage = "*message link* | channel *#channel* | author *@user*"]
+#[example = "https://discord.com/channels/1/2/3"]
+#[example = "channel #staff"]
+#[example = "author @user"]
 #[allowed_roles("Mods")]
 pub async fn block(ctx: &Context, msg: &Message, args: Args) -> CommandResult {
-    let regex = crate::MESSAGE_REGEX.get().expect("regex not init");
-    let (_, block_channel_id, block_msg_id) = util::parse_message_link(regex, args.rest())?;
+    let target = match block_target(args.rest()) {
+        Ok(target) => target,
+        Err(e) => {
+            msg.reply(ctx, e).await?;
+            return Ok(());
+        }
+    };
+    let guild_id = msg.guild_id.ok_or("Blocks only exist on servers")?;
     let pool = get_client(ctx).await?;
 
-    // add to blocklist
-    let fav_block = FavBlock::create(
-        &pool,
-        *msg.guild_id.unwrap().as_u64() as i64,
-        block_channel_id as i64,
-        block_msg_id as i64,
-    )
-    .await?;
+    let fav_block = FavBlock::create(&pool, *guild_id.as_u64() as i64, target).await?;
 
     // check who used it as fav
-    let favs_now_blocked =
-        Fav::list_by_channel_msg(&pool, fav_block.channel_id, fav_block.msg_id).await?;
-
-    // send messages to those using the fav
-    futures::stream::iter(favs_now_blocked)
-        .map(|blocked_fav| async move {
-            if let Ok(dm_channel) = UserId(blocked_fav.user_id as u64)
-                .create_dm_channel(&ctx)
-                .await
-            {
-                std::mem::drop(dm_channel.say(ctx, format!("Es wurde gerade ein fav von dir geblockt. https://discordapp.com/channels/{}/{}/{}", blocked_fav.server_id as u64, blocked_fav.channel_id as u64, blocked_fav.msg_id as u64)).await);
+    let favs_now_blocked = fav_block.favs(&pool).await?;
+    fav_index::remove_favs(&favs_now_blocked.iter().map(|f| f.id).collect::<Vec<_>>()).await?;
+
+    let mut links: HashMap<i64, Vec<String>> = HashMap::new();
+    for fav in favs_now_blocked {
+        links.entry(fav.user_id).or_default().push(format!(
+            "https://discord.com/channels/{}/{}/{}",
+            fav.server_id, fav.channel_id, fav.msg_id
+        ));
+    }
+
+    // one message to everyone using the favs
+    let g_cfg: Guild = match ServerConfig::get(&pool, *guild_id.as_u64() as i64).await {
+        Ok(mut config) => serde_json::from_value(config.config.take()).unwrap_or_default(),
+        Err(_e) => Guild::default(),
+    };
+    futures::stream::iter(links)
+        .map(|(user_id, links)| {
+            let notice = block_notice(&g_cfg, &links);
+            async move {
+                if let Ok(dm_channel) = UserId(user_id as u64).create_dm_channel(&ctx).await {
+                    std::mem::drop(dm_channel.say(ctx, notice).await);
+                }
             }
         })
+        .buffer_unordered(5)
         .collect::<Vec<_>>()
         .await;
 
-    std::mem::drop(
-        msg.react(ctx, ReactionType::Unicode("✅".to_string()))
-            .await,
-    );
+    msg.reply(
+        ctx,
+        format!(
+            "Blocked {} as #{}",
+            describe_block(&fav_block),
+            fav_block.id
+        ),
+    )
+    .await?;
 
     Ok(())
 }
 
 #[command]
 #[only_in("guilds")]
-#[description = "Creates a list of all favs on the server"]
+#[num_args(1)]
+#[description = "Removes a block, the number is shown by `fav blocks`"]
+#[usage = "*number*"]
+#[example = "12"]
 #[allowed_roles("Mods")]
-pub async fn create_fav_list(ctx: &Context, msg: &Message) -> CommandResult {
-    use tokio::io::{AsyncWriteExt, BufWriter};
+pub async fn unblock(ctx: &Context, msg: &Message, mut args: Args) -> CommandResult {
+    let id = args.single::<String>()?;
+    let id = id.trim_start_matches('#').parse::<i64>()?;
+    let server_id = *msg.guild_id.ok_or("Blocks only exist on servers")?.as_u64() as i64;
     let pool = get_client(ctx).await?;
 
-    let favs = Fav::list_all_from_server(
-        &pool,
-        *msg.guild_id
-            .ok_or("this command is only supposed to be called in a server channel")?
-            .as_u64() as i64,
+    let fav_block = match FavBlock::delete(&pool, server_id, id).await? {
+        Some(fav_block) => fav_block,
+        None => {
+            msg.reply(ctx, format!("There is no block #{}", id)).await?;
+            return Ok(());
+        }
+    };
+
+    // make the favs searchable again, unless another block still applies
+    let mut unblocked = Vec::new();
+    for fav in fav_block.favs(&pool).await? {
+        if !FavBlock::check_blocked(
+            &pool,
+            fav.server_id,
+            fav.channel_id,
+            fav.msg_id,
+            fav.author_id,
+        )
+        .await
+        {
+            unblocked.push(fav);
+        }
+    }
+    if let Err(e) = fav_index::index_favs(ctx, &pool, &unblocked).await {
+        debug!(?e, "Could not index favs");
+    }
+
+    msg.reply(ctx, format!("Unblocked {}", describe_block(&fav_block)))
+        .await?;
+
+    Ok(())
+}
+
+#[command]
+#[only_in("guilds")]
+#[num_args(0)]
+#[description = "Lists the fav blocks of this server"]
+#[allowed_roles("Mods")]
+pub async fn blocks(ctx: &Context, msg: &Message, _args: Args) -> CommandResult {
+    let server_id = *msg.guild_id.ok_or("Blocks only exist on servers")?.as_u64() as i64;
+    let pool = get_client(ctx).await?;
+
+    let lines = FavBlock::list(&pool, server_id)
+        .await?
+        .iter()
+        .map(|block| format!("#{} {}", block.id, describe_block(block)))
+        .collect::<Vec<_>>();
+    if lines.is_empty() {
+        msg.reply(ctx, "Nothing is blocked").await?;
+        return Ok(());
+    }
+
+    for chunk in lines.chunks(30) {
+        msg.channel_id
+            .send_message(ctx, |m| {
+                m.embed(|e| {
+                    e.title("Fav blocks")
+                        .description(chunk.join("\n"))
+                        .color((0, 120, 220))
+                })
+            })
+            .await?;
+    }
+
+    Ok(())
+}
+
+const MAX_IMPORT_SIZE: u64 = 8 * 1024 * 1024;
+
+fn export_format(args: &Args) -> Result<Format, String> {
+    match args.rest().trim() {
+        "" => Ok(Format::Json),
+        format => format.parse(),
+    }
+}
+
+/// Exports the stored snapshots, favs without one are left for `backfill` to fetch
+fn export_records(favs: &[Fav], tags: Vec<Tag>) -> Vec<Record> {
+    let mut labels: HashMap<i64, Vec<String>> = HashMap::new();
+    for tag in tags {
+        labels.entry(tag.fav_id).or_default().push(tag.label);
+    }
+
+    favs.iter()
+        .map(|fav| {
+            Record::new(
+                fav,
+                fav.snapshot().as_ref(),
+                labels.remove(&fav.id).unwrap_or_default(),
+            )
+        })
+        .collect()
+}
+
+#[command]
+#[description = "Sends you all your favs with their tags and links as a file, to keep or to import again"]
+#[usage = "*json|csv*"]
+#[example = "csv"]
+#[max_args(1)]
+pub async fn export(ctx: &Context, msg: &Message, args: Args) -> CommandResult {
+    let format = match export_format(&args) {
+        Ok(format) => format,
+        Err(e) => {
+            msg.reply(ctx, e).await?;
+            return Ok(());
+        }
+    };
+    let pool = get_client(ctx).await?;
+    let user_id = *msg.author.id.as_u64() as i64;
+
+    let favs = Fav::of_user(&pool, user_id).await?;
+    let records = export_records(&favs, Tag::of_user(&pool, user_id).await?);
+    let data = export::write(&records, format)?;
+
+    msg.author
+        .dm(ctx, |m| {
+            m.content(format!("Your {} favs", records.len()))
+                .add_file(AttachmentType::Bytes {
+                    data: Cow::from(data),
+                    filename: format!("favs.{}", format.extension()),
+                })
+        })
+        .await?;
+    if !msg.is_private() {
+        msg.reply(ctx, "Sent you your favs").await?;
+    }
+
+    Ok(())
+}
+
+#[command("export-server")]
+#[only_in("guilds")]
+#[description = "Exports all favs saved on the server with their tags and links as a file"]
+#[usage = "*json|csv*"]
+#[example = "csv"]
+#[max_args(1)]
+#[allowed_roles("Mods")]
+pub async fn export_server(ctx: &Context, msg: &Message, args: Args) -> CommandResult {
+    let format = match export_format(&args) {
+        Ok(format) => format,
+        Err(e) => {
+            msg.reply(ctx, e).await?;
+            return Ok(());
+        }
+    };
+    let pool = get_client(ctx).await?;
+    let server_id = *msg
+        .guild_id
+        .ok_or("this command is only supposed to be called in a server channel")?
+        .as_u64() as i64;
+
+    let favs = Fav::list_all_from_server(&pool, server_id).await?;
+    let records = export_records(&favs, Tag::of_server(&pool, server_id).await?);
+    let data = export::write(&records, format)?;
+
+    msg.channel_id
+        .send_message(ctx, |m| {
+            m.content(format!("{} favs of this server", records.len()))
+                .add_file(AttachmentType::Bytes {
+                    data: Cow::from(data),
+                    filename: format!("server_favs.{}", format.extension()),
+                })
+        })
+        .await?;
+
+    Ok(())
+}
+
+#[command]
+#[description = "Imports favs from a file made by `fav export`, attach it to the command. Favs you already have, blocked ones and messages that can not be read anymore (unless someone else saved them) are skipped"]
+#[num_args(0)]
+pub async fn import(ctx: &Context, msg: &Message, _args: Args) -> CommandResult {
+    let attachment = match msg.attachments.first() {
+        Some(attachment) => attachment,
+        None => {
+            msg.reply(ctx, "Attach a file made by `fav export`").await?;
+            return Ok(());
+        }
+    };
+    let format = match Format::of_file(&attachment.filename) {
+        Some(format) if attachment.size <= MAX_IMPORT_SIZE => format,
+        Some(_) => {
+            msg.reply(ctx, "The file is too large").await?;
+            return Ok(());
+        }
+        None => {
+            msg.reply(ctx, "Only .json and .csv files can be imported")
+                .await?;
+            return Ok(());
+        }
+    };
+    let records = match export::read(&attachment.download().await?, format) {
+        Ok(records) => records,
+        Err(e) => {
+            msg.reply(ctx, e).await?;
+            return Ok(());
+        }
+    };
+
+    let pool = get_client(ctx).await?;
+    let regex = crate::MESSAGE_REGEX.get().expect("regex not init");
+    let user_id = *msg.author.id.as_u64() as i64;
+    let (mut imported, mut skipped) = (Vec::new(), 0);
+
+    for record in records {
+        let (server_id, channel_id, msg_id) = match util::parse_message_link(regex, &record.link) {
+            Ok(ids) => ids,
+            Err(_) => {
+                skipped += 1;
+                continue;
+            }
+        };
+
+        let stored = Fav::list_by_channel_msg(&pool, channel_id as i64, msg_id as i64).await?;
+        if stored.iter().any(|fav| fav.user_id == user_id) {
+            skipped += 1;
+            continue;
+        }
+
+        // the file is never trusted for the content, favs of deleted messages can only be
+        // imported if someone else saved the message before
+        let (author_id, snapshot) = match ChannelId(channel_id).message(ctx, msg_id).await {
+            Ok(message) => (
+                *message.author.id.as_u64(),
+                snapshot_of(ctx, &message).await,
+            ),
+            Err(_) => match stored
+                .iter()
+                .find_map(|fav| Some((fav.author_id as u64, fav.snapshot()?)))
+            {
+                Some(stored) => stored,
+                None => {
+                    skipped += 1;
+                    continue;
+                }
+            },
+        };
+
+        if FavBlock::check_blocked(
+            &pool,
+            server_id as i64,
+            channel_id as i64,
+            msg_id as i64,
+            author_id as i64,
+        )
+        .await
+        {
+            skipped += 1;
+            continue;
+        }
+
+        let fav = Fav::create(
+            &pool,
+            server_id as i64,
+            channel_id as i64,
+            msg_id as i64,
+            user_id,
+            author_id as i64,
+            &snapshot,
+        )
+        .await?;
+        Tag::apply(
+            &pool,
+            fav.id,
+            &TagChange::Replace(normalize(&record.tags.join(" "))),
+        )
+        .await?;
+
+        imported.push(fav);
+    }
+
+    if let Err(e) = fav_index::index_favs(ctx, &pool, &imported).await {
+        debug!(?e, "Could not index favs");
+    }
+
+    msg.reply(
+        ctx,
+        format!(
+            "Imported {} favs, skipped {} that were blocked, already saved or unavailable",
+            imported.len(),
+            skipped
+        ),
     )
     .await?;
 
-    let outfile = tokio::fs::OpenOptions::new()
-        .create_new(true)
-        .write(true)
-        .open("./fav_list.txt")
+    Ok(())
+}
+
+fn top_favs(favs: &[Fav], counts: &[(i64, i64)]) -> String {
+    let lines = counts
+        .iter()
+        .filter_map(|(fav_id, count)| {
+            let fav = favs.iter().find(|fav| fav.id == *fav_id)?;
+            let content = match fav.content.as_deref() {
+                Some(content) if !content.trim().is_empty() => content.chars().take(60).collect(),
+                _ => "jump".to_string(),
+            };
+            Some(format!(
+                "{}x [{}](https://discord.com/channels/{}/{}/{}) by <@{}>",
+                count, content, fav.server_id, fav.channel_id, fav.msg_id, fav.author_id
+            ))
+        })
+        .collect::<Vec<_>>();
+
+    if lines.is_empty() {
+        "-".to_string()
+    } else {
+        lines.join("\n")
+    }
+}
+
+fn top_authors(counts: &[(i64, i64)]) -> String {
+    if counts.is_empty() {
+        return "-".to_string();
+    }
+
+    counts
+        .iter()
+        .map(|(author_id, count)| format!("<@{}>: {}x", author_id, count))
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+async fn opted_out_authors(ctx: &Context) -> Vec<i64> {
+    match ctx.data.read().await.get::<OptOut>() {
+        Some(v) => v.lock().await.set.iter().map(|id| *id as i64).collect(),
+        None => Vec::new(),
+    }
+}
+
+#[command]
+#[description = "Shows statistics about your favs and how often they were posted"]
+#[num_args(0)]
+#[sub_commands(server_stats)]
+pub async fn stats(ctx: &Context, msg: &Message, _args: Args) -> CommandResult {
+    let pool = get_client(ctx).await?;
+    let user_id = *msg.author.id.as_u64() as i64;
+    let excluded = opted_out_authors(ctx).await;
+
+    let total = Fav::count_of_user(&pool, user_id).await?;
+    let untagged = Fav::count_untagged(&pool, user_id).await?;
+    let tags = Tag::top_of_user(&pool, user_id, 5)
+        .await?
+        .into_iter()
+        .map(|(label, count)| format!("{} ({})", label, count))
+        .collect::<Vec<_>>();
+    let top = FavPost::top_favs_of_user(&pool, user_id, &excluded).await?;
+    let favs = Fav::by_ids(&pool, &top.iter().map(|(id, _)| *id).collect::<Vec<_>>()).await?;
+    let authors = FavPost::top_authors_of_user(&pool, user_id, &excluded).await?;
+
+    msg.channel_id
+        .send_message(ctx, |m| {
+            m.embed(|e| {
+                e.title(format!("Fav stats of {}", msg.author.name))
+                    .color((0, 120, 220))
+                    .field("Favs", total, true)
+                    .field("Untagged", untagged, true)
+                    .field(
+                        "Top tags",
+                        if tags.is_empty() {
+                            "-".to_string()
+                        } else {
+                            tags.join(", ")
+                        },
+                        false,
+                    )
+                    .field("Most posted", top_favs(&favs, &top), false)
+                    .field("Most quoted", top_authors(&authors), false)
+            })
+        })
+        .await?;
+
+    Ok(())
+}
+
+#[command("server")]
+#[only_in("guilds")]
+#[description = "Shows the favs posted most on this server and the most quoted authors"]
+#[num_args(0)]
+#[allowed_roles("Mods")]
+pub async fn server_stats(ctx: &Context, msg: &Message, _args: Args) -> CommandResult {
+    let pool = get_client(ctx).await?;
+    let guild_id = msg.guild_id.ok_or("Server stats only exist on servers")?;
+    let server_id = *guild_id.as_u64() as i64;
+    let excluded = opted_out_authors(ctx).await;
+
+    let posts = FavPost::count_of_server(&pool, server_id).await?;
+    let top = FavPost::top_favs_of_server(&pool, server_id, &excluded).await?;
+    let favs = Fav::by_ids(&pool, &top.iter().map(|(id, _)| *id).collect::<Vec<_>>()).await?;
+    let authors = FavPost::top_authors_of_server(&pool, server_id, &excluded).await?;
+
+    msg.channel_id
+        .send_message(ctx, |m| {
+            m.embed(|e| {
+                e.title("Fav stats of this server")
+                    .color((0, 120, 220))
+                    .field("Favs posted", posts, true)
+                    .field("Most posted", top_favs(&favs, &top), false)
+                    .field("Most quoted", top_authors(&authors), false)
+            })
+        })
         .await?;
 
-    let mut out_buf = BufWriter::new(outfile);
+    Ok(())
+}
+
+#[command]
+#[owners_only]
+#[description = "Stores a snapshot for all favs saved before snapshots existed"]
+#[num_args(0)]
+pub async fn backfill(ctx: &Context, msg: &Message, _args: Args) -> CommandResult {
+    let pool = get_client(ctx).await?;
+    let favs = Fav::list_without_snapshot(&pool).await?;
+    let (mut stored, mut missing) = (Vec::new(), 0);
 
-    for fav in favs {
-        if let Ok(fav_msg) = ChannelId(fav.channel_id as u64)
-            .message(&ctx, fav.msg_id as u64)
+    for fav in &favs {
+        match ChannelId(fav.channel_id as u64)
+            .message(ctx, fav.msg_id as u64)
             .await
         {
-            let line = format!(
-                "https://discord.com/channels/{}/{}/{};{};{}\n",
-                fav.server_id as u64,
-                fav.channel_id as u64,
-                fav.msg_id as u64,
-                fav_msg.content,
-                fav_msg
-                    .attachments
-                    .first()
-                    .map_or_else(|| "No image".to_string(), |a| a.url.clone()),
-            );
-
-            std::mem::drop(out_buf.write(line.as_bytes()).await);
+            Ok(message) => {
+                Fav::set_snapshot(&pool, fav.id, &snapshot_of(ctx, &message).await).await?;
+                stored.push(fav.id);
+            }
+            Err(e) => {
+                debug!(?e, fav = fav.id, "Could not backfill fav");
+                missing += 1;
+            }
         }
     }
 
-    out_buf.flush().await?;
+    // the index gets the content from the snapshots now
+    reindex(ctx, &pool, &stored).await;
+
+    msg.reply(
+        ctx,
+        format!(
+            "Stored {} snapshots, {} messages are not available anymore",
+            stored.len(),
+            missing
+        ),
+    )
+    .await?;
 
     Ok(())
 }
 
 #[cfg(test)]
 mod tests {
+    use super::{block_notice, block_target, Config, FavButton};
+    use crate::commands::config::{Guild, Language};
     use crate::models::fav::Fav;
+    use crate::models::fav_block::Target;
     use crate::models::tag::Tag;
 
     #[test]
@@ -548,6 +1574,12 @@ mod tests {
                     msg_id: 1,
                     user_id: 1,
                     author_id: 1,
+                    content: None,
+                    author_name: None,
+                    author_avatar: None,
+                    msg_timestamp: None,
+                    channel_name: None,
+                    attachments: serde_json::Value::Null,
                 },
                 vec![
                     Tag {
@@ -570,6 +1602,12 @@ mod tests {
                     msg_id: 2,
                     user_id: 2,
                     author_id: 2,
+                    content: None,
+                    author_name: None,
+                    author_avatar: None,
+                    msg_timestamp: None,
+                    channel_name: None,
+                    attachments: serde_json::Value::Null,
                 },
                 vec![
                     Tag {
@@ -592,6 +1630,12 @@ mod tests {
                     msg_id: 1,
                     user_id: 1,
                     author_id: 1,
+                    content: None,
+                    author_name: None,
+                    author_avatar: None,
+                    msg_timestamp: None,
+                    channel_name: None,
+                    attachments: serde_json::Value::Null,
                 },
                 vec![
                     Tag {
@@ -614,6 +1658,12 @@ mod tests {
                     msg_id: 1,
                     user_id: 1,
                     author_id: 1,
+                    content: None,
+                    author_name: None,
+                    author_avatar: None,
+                    msg_timestamp: None,
+                    channel_name: None,
+                    attachments: serde_json::Value::Null,
                 },
                 vec![
                     Tag {
@@ -650,4 +1700,61 @@ mod tests {
 
         dbg!(&possible_favs);
     }
+
+    #[test]
+    fn fav_settings() {
+        let mut config = Config::default();
+
+        config.apply_setting("hall_of_fame", "<#123>").unwrap();
+        config.apply_setting("hall_of_fame_threshold", "3").unwrap();
+        config.apply_setting("prefer_live", "on").unwrap();
+        assert_eq!(config.hall_of_fame, Some(123));
+        assert_eq!(config.hall_of_fame_threshold, 3);
+        assert!(config.prefer_live);
+
+        assert!(config.apply_setting("hall_of_fame_threshold", "0").is_err());
+        assert!(config.apply_setting("prefer_live", "maybe").is_err());
+        config.apply_setting("hall_of_fame", "off").unwrap();
+        assert_eq!(config.hall_of_fame, None);
+    }
+
+    #[test]
+    fn button_ids() {
+        for button in [
+            FavButton::Delete(1),
+            FavButton::Tags(22),
+            FavButton::Source(333),
+            FavButton::Another(4444),
+        ]
+        .iter()
+        {
+            assert_eq!(FavButton::parse(&button.custom_id()), Some(*button));
+        }
+        assert_eq!(FavButton::parse("fav:delete:x"), None);
+        assert_eq!(FavButton::parse("poll:vote:1"), None);
+    }
+
+    #[test]
+    fn block_targets() {
+        assert_eq!(block_target("channel <#12>"), Ok(Target::Channel(12)));
+        assert_eq!(block_target("author <@!34>"), Ok(Target::Author(34)));
+        assert!(block_target("channel").is_err());
+        assert!(block_target("author @nobody").is_err());
+    }
+
+    #[test]
+    fn block_notices() {
+        let links = (0..12).map(|i| i.to_string()).collect::<Vec<_>>();
+        let mut g_cfg = Guild::default();
+        assert_eq!(
+            block_notice(&g_cfg, &links[..2]),
+            "Es wurden gerade 2 Favs von dir geblockt:\n0\n1"
+        );
+
+        g_cfg.language = Language::En;
+        assert!(block_notice(&g_cfg, &links).ends_with("\n9\n+2"));
+
+        g_cfg.favs.block_notice = Some("{count} blocked".to_string());
+        assert_eq!(block_notice(&g_cfg, &links), "12 blocked");
+    }
 }
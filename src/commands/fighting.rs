@@ -1,159 +1,790 @@
-use std::str::FromStr;
-
-use nom::{
-    bytes::complete::tag,
-    character::complete::{alpha1, digit1},
-    combinator::map_res,
-    multi::many1,
-    multi::separated_list1,
-    sequence::tuple,
-    IResult,
-};
+use crate::commands::config::Guild;
+use crate::models::server_config::ServerConfig;
+use crate::util::get_client;
+use serde::{Deserialize, Serialize};
 use serenity::prelude::*;
 use serenity::{
     framework::standard::{macros::command, Args, CommandResult},
-    model::channel::Message,
+    model::{channel::Message, id::GuildId},
 };
-use tracing::error;
+use sqlx::postgres::PgPool;
+use std::collections::BTreeMap;
 
-#[command]
-#[description = "Show a combo moveset with easy to understand symbols"]
-#[usage = "*move instructions*"]
-#[example = "632146P"]
-pub async fn combo(ctx: &Context, msg: &Message, args: Args) -> CommandResult {
-    let combo_str = args.rest().trim();
+/// Embed descriptions can not be longer
+const MAX_RENDERED_LENGTH: usize = 4096;
+
+/// How a game writes directions
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Notation {
+    /// Digits like on a numpad, 6 is forward and 2 is down
+    Numpad,
+    /// Letters like `f`, `d/f` and `qcf`, uppercase is held
+    Tekken,
+}
+
+/// The inputs of a game
+#[derive(Debug)]
+pub struct Game {
+    pub name: &'static str,
+    pub aliases: &'static [&'static str],
+    pub title: &'static str,
+    pub notation: Notation,
+    pub buttons: &'static [&'static str],
+}
 
-    match parse_combo_str(combo_str) {
-        Ok((_, moves)) => {
-            msg.reply(ctx, format!("{:?}", moves)).await?;
+pub const GAMES: &[Game] = &[
+    Game {
+        name: "gg",
+        aliases: &["ggst", "strive", "xrd"],
+        title: "Guilty Gear",
+        notation: Notation::Numpad,
+        buttons: &["P", "K", "S", "HS", "H", "D"],
+    },
+    Game {
+        name: "sf",
+        aliases: &["sf6", "sfv", "sf5"],
+        title: "Street Fighter",
+        notation: Notation::Numpad,
+        buttons: &[
+            "LP", "MP", "HP", "LK", "MK", "HK", "PPP", "KKK", "PP", "KK", "P", "K",
+        ],
+    },
+    Game {
+        name: "bb",
+        aliases: &["blazblue"],
+        title: "BlazBlue",
+        notation: Notation::Numpad,
+        buttons: &["A", "B", "C", "D"],
+    },
+    Game {
+        name: "tekken",
+        aliases: &["t7", "t8"],
+        title: "Tekken",
+        notation: Notation::Tekken,
+        buttons: &["1", "2", "3", "4"],
+    },
+];
+
+pub fn find_game(name: &str) -> Option<&'static Game> {
+    let name = name.to_lowercase();
+    GAMES
+        .iter()
+        .find(|game| game.name == name || game.aliases.iter().any(|alias| *alias == name))
+}
+
+/// Motions in numpad notation with their names
+const MOTIONS: &[(&str, &str)] = &[
+    ("236", "quarter circle forward"),
+    ("214", "quarter circle back"),
+    ("623", "dragon punch"),
+    ("421", "reverse dragon punch"),
+    ("41236", "half circle forward"),
+    ("63214", "half circle back"),
+    ("412364", "half circle forward back"),
+    ("632146", "half circle back forward"),
+    ("236236", "double quarter circle forward"),
+    ("214214", "double quarter circle back"),
+    ("632147896", "full circle"),
+    ("22", "down down"),
+    ("66", "dash"),
+    ("44", "backdash"),
+];
+
+/// Tekken directions written as numpad digits, longer ones first
+const TEKKEN_DIRECTIONS: &[(&str, &str)] = &[
+    ("qcf", "236"),
+    ("qcb", "214"),
+    ("hcf", "41236"),
+    ("hcb", "63214"),
+    ("d/f", "3"),
+    ("d/b", "1"),
+    ("u/f", "9"),
+    ("u/b", "7"),
+    ("f", "6"),
+    ("b", "4"),
+    ("u", "8"),
+    ("d", "2"),
+    ("n", "5"),
+];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Modifier {
+    Jump,
+    Close,
+    Far,
+    WhileStanding,
+    Sidestep,
+    CrouchDash,
+    FullCrouch,
+}
+
+impl Modifier {
+    const fn of(notation: Notation) -> &'static [Self] {
+        match notation {
+            Notation::Numpad => &[Self::Jump, Self::Close, Self::Far],
+            Notation::Tekken => &[
+                Self::WhileStanding,
+                Self::Sidestep,
+                Self::CrouchDash,
+                Self::FullCrouch,
+            ],
         }
-        Err(e) => {
-            error!(?e, "Failed parsing input");
-            msg.reply(ctx, "Sorry that is not a valid combo sequence!")
-                .await?;
+    }
+
+    pub const fn notation(self) -> &'static str {
+        match self {
+            Self::Jump => "j.",
+            Self::Close => "c.",
+            Self::Far => "f.",
+            Self::WhileStanding => "ws",
+            Self::Sidestep => "ss",
+            Self::CrouchDash => "cd",
+            Self::FullCrouch => "fc",
         }
     }
 
-    Ok(())
+    const fn name(self) -> &'static str {
+        match self {
+            Self::Jump => "jump",
+            Self::Close => "close",
+            Self::Far => "far",
+            Self::WhileStanding => "while standing",
+            Self::Sidestep => "sidestep",
+            Self::CrouchDash => "crouch dash",
+            Self::FullCrouch => "full crouch",
+        }
+    }
 }
 
-#[derive(Debug)]
-struct Combo {
-    moves: Vec<Move>,
+/// How two moves are connected
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Link {
+    /// `>`, the next move follows
+    Followup,
+    /// `~`, the next move comes right away like in a rekka
+    Chain,
+    /// `xx`, the move is cancelled into the next one
+    Cancel,
+    /// `,`, the next move is linked after this one recovers
+    Sequence,
 }
 
-#[derive(Debug)]
-struct Move {
-    directionals: Vec<Directional>,
-    buttons: Vec<Button>,
+impl Link {
+    const ALL: [Self; 4] = [Self::Followup, Self::Chain, Self::Cancel, Self::Sequence];
+
+    pub const fn notation(self) -> &'static str {
+        match self {
+            Self::Followup => ">",
+            Self::Chain => "~",
+            Self::Cancel => "xx",
+            Self::Sequence => ",",
+        }
+    }
+
+    const fn emoji(self) -> &'static str {
+        match self {
+            Self::Followup => "\u{25b6}\u{fe0f}",
+            Self::Chain => "\u{3030}\u{fe0f}",
+            Self::Cancel => "\u{2716}\u{fe0f}",
+            Self::Sequence => ",",
+        }
+    }
 }
 
-#[derive(Debug)]
-enum Directional {
-    Neutral,
-    Left,
-    Right,
-    Up,
-    Down,
-    DownLeft,
-    DownRight,
-    UpLeft,
-    UpRight,
-    QuarterCircleRight,
-    QuarterCircleLeft,
-    DragonPunchRight,
-    DragonPunchLeft,
-    HalfCircleRight,
-    HalfCircleLeft,
-    FullCircle,
-}
-
-impl FromStr for Directional {
-    type Err = Box<dyn std::error::Error>;
-
-    fn from_str(s: &str) -> Result<Self, Self::Err> {
-        Ok(match s {
-            "1" => Self::DownLeft,
-            "2" => Self::Down,
-            "3" => Self::DownRight,
-            "4" => Self::Left,
-            "5" => Self::Neutral,
-            "6" => Self::Right,
-            "7" => Self::UpLeft,
-            "8" => Self::Up,
-            "9" => Self::UpRight,
-            "214" => Self::QuarterCircleLeft,
-            "236" => Self::QuarterCircleRight,
-            "623" => Self::DragonPunchRight,
-            "421" => Self::DragonPunchLeft,
-            "412364" => Self::HalfCircleLeft,
-            "632146" => Self::HalfCircleRight,
-            "632147896" => Self::FullCircle,
-            _ => return Err(String::from("failed to parse directional").into()),
-        })
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Directional {
+    /// A numpad direction, 5 is neutral
+    Direction(u8),
+    /// A known motion in numpad notation like `236`
+    Motion(&'static str),
+    /// A direction held down to charge, written `[4]`
+    Charge(u8),
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Move {
+    pub modifier: Option<Modifier>,
+    pub directionals: Vec<Directional>,
+    /// Pressed together
+    pub buttons: Vec<&'static str>,
+}
+
+impl Move {
+    fn is_empty(&self) -> bool {
+        self.modifier.is_none() && self.directionals.is_empty() && self.buttons.is_empty()
     }
 }
 
-#[derive(Debug)]
-enum Button {
-    Punch,
-    Kick,
-    Slash,
-    HeavySlash,
-    Dust,
-}
-
-impl FromStr for Button {
-    type Err = Box<dyn std::error::Error>;
-
-    fn from_str(s: &str) -> Result<Self, Self::Err> {
-        Ok(match s {
-            "P" => Self::Punch,
-            "K" => Self::Kick,
-            "S" => Self::Slash,
-            "H" => Self::HeavySlash,
-            "D" => Self::Dust,
-            _ => return Err(String::from("failed to parse button").into()),
-        })
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Step {
+    Move(Move),
+    Link(Link),
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Combo {
+    pub steps: Vec<Step>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Token {
+    Space,
+    Plus,
+    Link(Link),
+    Modifier(Modifier),
+    Directionals(Vec<Directional>),
+    Button(&'static str),
+}
+
+fn starts_with_ignore_case(input: &str, prefix: &str) -> bool {
+    input
+        .get(..prefix.len())
+        .map_or(false, |start| start.eq_ignore_ascii_case(prefix))
+}
+
+/// Splits numpad digits into as few known motions and single directions as possible, so
+/// `6623` is a forward and a dragon punch instead of a dash, down and down forward
+fn split_motions(digits: &str) -> Vec<Directional> {
+    let n = digits.len();
+    // fewest pieces for the digits from i on, with the motion starting at i
+    let mut best: Vec<(usize, Option<&'static str>)> = vec![(0, None); n + 1];
+    for i in (0..n).rev() {
+        best[i] = (best[i + 1].0 + 1, None);
+        for (motion, _) in MOTIONS {
+            if digits[i..].starts_with(motion) && best[i + motion.len()].0 + 1 < best[i].0 {
+                best[i] = (best[i + motion.len()].0 + 1, Some(*motion));
+            }
+        }
     }
+
+    let mut directionals = Vec::new();
+    let mut i = 0;
+    while i < n {
+        if let Some(motion) = best[i].1 {
+            directionals.push(Directional::Motion(motion));
+            i += motion.len();
+        } else {
+            directionals.push(Directional::Direction(digits.as_bytes()[i] - b'0'));
+            i += 1;
+        }
+    }
+
+    directionals
 }
 
-fn parse_combo_str(combo_str: &str) -> IResult<&str, Combo> {
-    let directional = separated_list1(
-        tag(","),
-        map_res(digit1, |s: &str| s.parse::<Directional>()),
-    );
+/// The next token and its length
+fn next_token(game: &Game, input: &str) -> Option<(Token, usize)> {
+    if let Some(link) = Link::ALL
+        .iter()
+        .find(|link| starts_with_ignore_case(input, link.notation()))
+    {
+        return Some((Token::Link(*link), link.notation().len()));
+    }
+    if let Some(modifier) = Modifier::of(game.notation)
+        .iter()
+        .find(|modifier| starts_with_ignore_case(input, modifier.notation()))
+    {
+        return Some((Token::Modifier(*modifier), modifier.notation().len()));
+    }
+    if input.starts_with('+') {
+        return Some((Token::Plus, 1));
+    }
 
-    let directionals = many1(directional);
-    let button = map_res(alpha1, |s: &str| s.parse::<Button>());
-    let buttons = many1(button);
+    match game.notation {
+        Notation::Numpad => {
+            let bytes = input.as_bytes();
+            if bytes.len() >= 3
+                && bytes[0] == b'['
+                && (b'1'..=b'9').contains(&bytes[1])
+                && bytes[2] == b']'
+            {
+                return Some((
+                    Token::Directionals(vec![Directional::Charge(bytes[1] - b'0')]),
+                    3,
+                ));
+            }
+            let digits = input
+                .find(|c: char| !('1'..='9').contains(&c))
+                .unwrap_or(input.len());
+            if digits > 0 {
+                return Some((Token::Directionals(split_motions(&input[..digits])), digits));
+            }
+        }
+        Notation::Tekken => {
+            for (direction, digits) in TEKKEN_DIRECTIONS {
+                if input.starts_with(direction) {
+                    return Some((Token::Directionals(split_motions(digits)), direction.len()));
+                }
+                // uppercase directions are held
+                if digits.len() == 1 && input.starts_with(&direction.to_uppercase()) {
+                    let held = Directional::Charge(digits.as_bytes()[0] - b'0');
+                    return Some((Token::Directionals(vec![held]), direction.len()));
+                }
+            }
+        }
+    }
 
-    let (input, moves) = separated_list1(tag(" "), tuple((directionals, buttons)))(combo_str)?;
+    game.buttons
+        .iter()
+        .filter(|button| starts_with_ignore_case(input, button))
+        .max_by_key(|button| button.len())
+        .map(|button| (Token::Button(button), button.len()))
+}
+
+fn tokenize(game: &Game, input: &str) -> Result<Vec<Token>, String> {
+    let mut tokens = Vec::new();
+    let mut rest = input;
+    while let Some(c) = rest.chars().next() {
+        if c.is_whitespace() {
+            tokens.push(Token::Space);
+            rest = rest.trim_start();
+            continue;
+        }
+
+        let (token, len) = next_token(game, rest).ok_or_else(|| {
+            format!(
+                "`{}` is not an input of {}, the buttons are {}",
+                c,
+                game.title,
+                game.buttons.join(" ")
+            )
+        })?;
+        tokens.push(token);
+        rest = &rest[len..];
+    }
 
-    Ok((
-        input,
-        Combo {
-            moves: moves
-                .into_iter()
-                .map(|(directionals, buttons)| Move {
-                    directionals: directionals.into_iter().flatten().collect(),
-                    buttons,
+    Ok(tokens)
+}
+
+pub fn parse_combo(game: &Game, input: &str) -> Result<Combo, String> {
+    let mut steps = Vec::new();
+    let mut current = Move::default();
+    let finish = |current: &mut Move, steps: &mut Vec<Step>| {
+        if !current.is_empty() {
+            steps.push(Step::Move(std::mem::take(current)));
+        }
+    };
+
+    for token in tokenize(game, input)? {
+        match token {
+            Token::Space => finish(&mut current, &mut steps),
+            Token::Plus => (),
+            Token::Link(link) => {
+                finish(&mut current, &mut steps);
+                steps.push(Step::Link(link));
+            }
+            Token::Modifier(modifier) => {
+                finish(&mut current, &mut steps);
+                current.modifier = Some(modifier);
+            }
+            Token::Directionals(directionals) => {
+                // a direction after a button starts the next move
+                if !current.buttons.is_empty() {
+                    finish(&mut current, &mut steps);
+                }
+                current.directionals.extend(directionals);
+            }
+            Token::Button(button) => current.buttons.push(button),
+        }
+    }
+    finish(&mut current, &mut steps);
+
+    if !steps.iter().any(|step| matches!(step, Step::Move(_))) {
+        return Err("There are no moves in the combo".to_string());
+    }
+
+    Ok(Combo { steps })
+}
+
+/// Whether guild emojis can be set for the notation, like `236`, `[4]`, `j.`, `xx` or a button
+pub fn is_notation_key(key: &str) -> bool {
+    let charge = key.len() == 3
+        && key.starts_with('[')
+        && key.ends_with(']')
+        && ('1'..='9').contains(&key.chars().nth(1).unwrap_or_default());
+    let direction = key.len() == 1 && ('1'..='9').contains(&key.chars().next().unwrap_or_default());
+
+    charge
+        || direction
+        || MOTIONS.iter().any(|(motion, _)| *motion == key)
+        || Link::ALL.iter().any(|link| link.notation() == key)
+        || [Notation::Numpad, Notation::Tekken]
+            .iter()
+            .flat_map(|notation| Modifier::of(*notation))
+            .any(|modifier| modifier.notation() == key)
+        || GAMES.iter().any(|game| game.buttons.contains(&key))
+}
+
+const fn arrow(direction: u8) -> &'static str {
+    match direction {
+        1 => "\u{2199}\u{fe0f}",
+        2 => "\u{2b07}\u{fe0f}",
+        3 => "\u{2198}\u{fe0f}",
+        4 => "\u{2b05}\u{fe0f}",
+        6 => "\u{27a1}\u{fe0f}",
+        7 => "\u{2196}\u{fe0f}",
+        8 => "\u{2b06}\u{fe0f}",
+        9 => "\u{2197}\u{fe0f}",
+        _ => "\u{23fa}\u{fe0f}",
+    }
+}
+
+impl Combo {
+    /// Arrows for directions and bold buttons, guild emojis replace any notation they are set for
+    pub fn render(&self, emojis: &BTreeMap<String, String>) -> String {
+        let emoji = |key: &str, default: String| emojis.get(key).cloned().unwrap_or(default);
+        let direction = |d: u8| emoji(&d.to_string(), arrow(d).to_string());
+
+        let mut out = String::new();
+        for step in &self.steps {
+            let part = match step {
+                Step::Link(link) => emoji(link.notation(), link.emoji().to_string()),
+                Step::Move(m) => {
+                    let modifier = m
+                        .modifier
+                        .map(|modifier| {
+                            emoji(modifier.notation(), format!("*{}*", modifier.name()))
+                        })
+                        .unwrap_or_default();
+                    let directionals = m
+                        .directionals
+                        .iter()
+                        .map(|directional| match directional {
+                            Directional::Direction(d) => direction(*d),
+                            Directional::Motion(motion) => emoji(
+                                motion,
+                                motion.bytes().map(|d| direction(d - b'0')).collect(),
+                            ),
+                            Directional::Charge(d) => {
+                                emoji(&format!("[{}]", d), format!("[{}]", direction(*d)))
+                            }
+                        })
+                        .collect::<String>();
+                    let buttons = m
+                        .buttons
+                        .iter()
+                        .map(|button| emoji(button, format!("**{}**", button)))
+                        .collect::<Vec<_>>()
+                        .join("+");
+
+                    [modifier, directionals, buttons]
+                        .iter()
+                        .filter(|part| !part.is_empty())
+                        .cloned()
+                        .collect::<Vec<_>>()
+                        .join(" ")
+                }
+            };
+
+            if !out.is_empty() && *step != Step::Link(Link::Sequence) {
+                out.push(' ');
+            }
+            out.push_str(&part);
+        }
+
+        out
+    }
+}
+
+/// The game and the guild emojis for combos
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+#[serde(default)]
+pub struct Config {
+    /// Used when a combo does not name its game
+    pub game: String,
+    /// Guild emojis by the notation they replace
+    pub emojis: BTreeMap<String, String>,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            game: GAMES[0].name.to_string(),
+            emojis: BTreeMap::new(),
+        }
+    }
+}
+
+impl Config {
+    pub fn apply_setting(&mut self, setting: &str, value: &str) -> Result<(), String> {
+        let mut values = value.split_whitespace();
+        let (first, second) = (values.next().unwrap_or_default(), values.next());
+
+        match (setting, second) {
+            ("game", None) => {
+                self.game = find_game(first)
+                    .ok_or_else(|| format!("Unknown game: {}", first))?
+                    .name
+                    .to_string();
+            }
+            ("emoji", Some(emoji)) => {
+                if !is_notation_key(first) {
+                    return Err(format!("Not a direction, motion or button: {}", first));
+                }
+                if emoji == "off" {
+                    self.emojis.remove(first);
+                } else {
+                    self.emojis.insert(first.to_string(), emoji.to_string());
+                }
+            }
+            _ => return Err(format!("Unknown setting: {} {}", setting, value)),
+        }
+
+        Ok(())
+    }
+}
+
+pub async fn config(pool: &PgPool, guild_id: GuildId) -> Config {
+    match ServerConfig::get(pool, *guild_id.as_u64() as i64).await {
+        Ok(mut config) => serde_json::from_value::<Guild>(config.config.take())
+            .map(|g_cfg| g_cfg.combo)
+            .unwrap_or_default(),
+        Err(_e) => Config::default(),
+    }
+}
+
+#[command]
+#[description = "Show a combo with easy to understand symbols. Directions are numpad digits like `236` or `[4]6` for charge, `j.` `c.` `f.` jump, close and far, `>` `~` `xx` `,` connect the moves. The game can be named first, see `combo games`."]
+#[usage = "*[game]* *move instructions*"]
+#[example = "632146P"]
+#[example = "c.S > 2D ~ 236P xx 632146H"]
+#[example = "sf [4]6HP xx 236236PPP"]
+#[example = "tekken d/f+1, 2 > ws1+2"]
+#[sub_commands(combo_games)]
+pub async fn combo(ctx: &Context, msg: &Message, args: Args) -> CommandResult {
+    let config = match msg.guild_id {
+        Some(guild_id) => config(&get_client(ctx).await?, guild_id).await,
+        None => Config::default(),
+    };
+
+    let mut input = args.rest().trim();
+    let game = match input.split_whitespace().next().and_then(find_game) {
+        Some(game) => {
+            input = input
+                .split_once(char::is_whitespace)
+                .map_or("", |(_, rest)| rest);
+            game
+        }
+        None => find_game(&config.game).unwrap_or(&GAMES[0]),
+    };
+
+    match parse_combo(game, input) {
+        Ok(combo) => {
+            let rendered = combo.render(&config.emojis);
+            if rendered.chars().count() > MAX_RENDERED_LENGTH {
+                msg.reply(ctx, "Sorry that combo is too long to show")
+                    .await?;
+                return Ok(());
+            }
+            msg.channel_id
+                .send_message(ctx, |m| {
+                    m.embed(|e| {
+                        e.title(format!("{} combo", game.title))
+                            .description(rendered)
+                            .footer(|f| {
+                                f.text(format!(
+                                    "{} | {} followed by | {} right into | {} cancel",
+                                    input,
+                                    Link::Followup.emoji(),
+                                    Link::Chain.emoji(),
+                                    Link::Cancel.emoji()
+                                ))
+                            })
+                            .color((0, 120, 220))
+                    })
                 })
-                .collect(),
-        },
-    ))
+                .await?;
+        }
+        Err(e) => {
+            msg.reply(ctx, format!("Sorry that is not a valid combo: {}", e))
+                .await?;
+        }
+    }
+
+    Ok(())
+}
+
+#[command("games")]
+#[description = "Lists the games combos can be written for"]
+pub async fn combo_games(ctx: &Context, msg: &Message, _args: Args) -> CommandResult {
+    let default = match msg.guild_id {
+        Some(guild_id) => config(&get_client(ctx).await?, guild_id).await.game,
+        None => Config::default().game,
+    };
+
+    let games = GAMES
+        .iter()
+        .map(|game| {
+            format!(
+                "**{}** {}{}: {} ({})",
+                game.name,
+                game.title,
+                if game.name == default {
+                    ", default"
+                } else {
+                    ""
+                },
+                game.buttons.join(" "),
+                match game.notation {
+                    Notation::Numpad => "numpad directions",
+                    Notation::Tekken => "directions like f, d/f, qcf",
+                }
+            )
+        })
+        .collect::<Vec<_>>()
+        .join("\n");
+
+    msg.channel_id
+        .send_message(ctx, |m| {
+            m.embed(|e| {
+                e.title("Combo games")
+                    .description(games)
+                    .color((0, 120, 220))
+            })
+        })
+        .await?;
+
+    Ok(())
 }
 
 #[cfg(test)]
 mod tests {
-    use crate::commands::fighting::parse_combo_str;
+    use super::*;
+
+    fn gg() -> &'static Game {
+        find_game("gg").unwrap()
+    }
+
+    #[test]
+    fn motions() {
+        use Directional::*;
+
+        let combo = parse_combo(gg(), "623,623P 5P 5P").unwrap();
+        assert_eq!(combo.steps.len(), 5);
+        assert_eq!(
+            combo.steps[2],
+            Step::Move(Move {
+                modifier: None,
+                directionals: vec![Motion("623")],
+                buttons: vec!["P"],
+            })
+        );
+
+        assert_eq!(split_motions("236"), vec![Motion("236")]);
+        assert_eq!(split_motions("41236"), vec![Motion("41236")]);
+        assert_eq!(split_motions("632146"), vec![Motion("632146")]);
+        assert_eq!(split_motions("6623"), vec![Direction(6), Motion("623")]);
+        assert_eq!(split_motions("2365"), vec![Motion("236"), Direction(5)]);
+
+        let combo = parse_combo(gg(), "j.[2]8S").unwrap();
+        assert_eq!(
+            combo.steps,
+            vec![Step::Move(Move {
+                modifier: Some(Modifier::Jump),
+                directionals: vec![Charge(2), Direction(8)],
+                buttons: vec!["S"],
+            })]
+        );
+    }
 
     #[test]
-    fn test_nom_parser() {
-        let combo_str = "623,623P 5P 5P";
+    fn links_and_buttons() {
+        let combo = parse_combo(gg(), "c.S > 2D ~ 236p xx 632146HS").unwrap();
+        let links = combo
+            .steps
+            .iter()
+            .filter_map(|step| match step {
+                Step::Link(link) => Some(*link),
+                Step::Move(_) => None,
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(links, vec![Link::Followup, Link::Chain, Link::Cancel]);
+        assert_eq!(
+            combo.steps.last(),
+            Some(&Step::Move(Move {
+                modifier: None,
+                directionals: vec![Directional::Motion("632146")],
+                buttons: vec!["HS"],
+            }))
+        );
 
-        let (_, combo) = parse_combo_str(combo_str).unwrap();
+        let sf = find_game("sf6").unwrap();
+        let combo = parse_combo(sf, "[4]6HP xx 2MK+MP").unwrap();
+        assert_eq!(combo.steps.len(), 3);
+        assert_eq!(
+            combo.steps[2],
+            Step::Move(Move {
+                modifier: None,
+                directionals: vec![Directional::Direction(2)],
+                buttons: vec!["MK", "MP"],
+            })
+        );
 
-        println!("{:?}", combo);
+        let tekken = find_game("tekken").unwrap();
+        let combo = parse_combo(tekken, "d/f+1, 2 > ws1+2 F+4").unwrap();
+        assert_eq!(
+            combo.steps,
+            vec![
+                Step::Move(Move {
+                    modifier: None,
+                    directionals: vec![Directional::Direction(3)],
+                    buttons: vec!["1"],
+                }),
+                Step::Link(Link::Sequence),
+                Step::Move(Move {
+                    modifier: None,
+                    directionals: vec![],
+                    buttons: vec!["2"],
+                }),
+                Step::Link(Link::Followup),
+                Step::Move(Move {
+                    modifier: Some(Modifier::WhileStanding),
+                    directionals: vec![],
+                    buttons: vec!["1", "2"],
+                }),
+                Step::Move(Move {
+                    modifier: None,
+                    directionals: vec![Directional::Charge(6)],
+                    buttons: vec!["4"],
+                }),
+            ]
+        );
+
+        assert!(parse_combo(gg(), "5X").is_err());
+        assert!(parse_combo(gg(), "0P").is_err());
+        assert!(parse_combo(gg(), " > ").is_err());
+    }
+
+    #[test]
+    fn rendering() {
+        let combo = parse_combo(gg(), "j.K > 236P,5S xx [4]6H").unwrap();
+        assert_eq!(
+            combo.render(&BTreeMap::new()),
+            "*jump* **K** \u{25b6}\u{fe0f} \u{2b07}\u{fe0f}\u{2198}\u{fe0f}\u{27a1}\u{fe0f} **P**, \u{23fa}\u{fe0f} **S** \u{2716}\u{fe0f} [\u{2b05}\u{fe0f}]\u{27a1}\u{fe0f} **H**"
+        );
+
+        let mut emojis = BTreeMap::new();
+        emojis.insert("236".to_string(), "<:qcf:1>".to_string());
+        emojis.insert("6".to_string(), "<:f:2>".to_string());
+        emojis.insert("P".to_string(), "<:p:3>".to_string());
+        let combo = parse_combo(gg(), "236P 6P 41236P").unwrap();
+        assert_eq!(
+            combo.render(&emojis),
+            "<:qcf:1> <:p:3> <:f:2> <:p:3> \u{2b05}\u{fe0f}\u{2199}\u{fe0f}\u{2b07}\u{fe0f}\u{2198}\u{fe0f}<:f:2> <:p:3>"
+        );
+    }
+
+    #[test]
+    fn settings() {
+        let mut config = Config::default();
+        config.apply_setting("game", "SF6").unwrap();
+        assert_eq!(config.game, "sf");
+        config.apply_setting("emoji", "236 <:qcf:1>").unwrap();
+        config.apply_setting("emoji", "[4] <:charge:2>").unwrap();
+        assert_eq!(config.emojis.len(), 2);
+        config.apply_setting("emoji", "236 off").unwrap();
+        assert_eq!(config.emojis.len(), 1);
+        assert!(config.apply_setting("emoji", "5X <:x:3>").is_err());
+        assert!(config.apply_setting("game", "smash").is_err());
     }
 }
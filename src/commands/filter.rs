@@ -0,0 +1,299 @@
+use super::config::{update_guild_config, Guild};
+use crate::automod::Action;
+use crate::filter::{Entry, FilterSet, PatternKind};
+use crate::models::server_config::ServerConfig;
+use crate::models::word_filter::WordFilter;
+use crate::util::get_client;
+use crate::WordFilterCache;
+use serenity::prelude::*;
+use serenity::{
+    framework::standard::{macros::command, Args, CommandResult},
+    model::channel::Message,
+    model::id::GuildId,
+    utils::MessageBuilder,
+};
+use std::sync::Arc;
+use tracing::{error, warn};
+
+/// Returns the compiled filter of the guild, compiling it only if the list changed
+pub async fn filter_set(
+    ctx: &Context,
+    guild_id: GuildId,
+) -> Result<Arc<FilterSet>, Box<dyn std::error::Error + Send + Sync>> {
+    let cache = ctx
+        .data
+        .read()
+        .await
+        .get::<WordFilterCache>()
+        .ok_or("Failed to get word filter cache")?
+        .clone();
+
+    if let Some(set) = cache.lock().await.get(guild_id.as_u64()) {
+        return Ok(Arc::clone(set));
+    }
+
+    // loaded without holding the cache, the other guilds are filtered in the meantime. A list
+    // that does not compile is not retried on every message, only once it changes.
+    let set = match FilterSet::compile(entries(ctx, guild_id).await?) {
+        Ok(set) => set,
+        Err(e) => {
+            error!(
+                ?e,
+                "Could not compile word filter, filtering nothing until it changes"
+            );
+            FilterSet::empty()
+        }
+    };
+
+    // a refresh in the meantime stored the newer list
+    Ok(Arc::clone(
+        cache
+            .lock()
+            .await
+            .entry(*guild_id.as_u64())
+            .or_insert_with(|| Arc::new(set)),
+    ))
+}
+
+async fn entries(
+    ctx: &Context,
+    guild_id: GuildId,
+) -> Result<Vec<Entry>, Box<dyn std::error::Error + Send + Sync>> {
+    let pool = get_client(ctx).await?;
+    let entries = WordFilter::list(&pool, *guild_id.as_u64() as i64)
+        .await?
+        .into_iter()
+        .filter_map(
+            |f| match (f.kind.parse(), serde_json::from_value(f.action)) {
+                (Ok(kind), Ok(action)) => Some(Entry {
+                    id: f.id,
+                    kind,
+                    pattern: f.pattern,
+                    action,
+                }),
+                _ => {
+                    warn!(id = f.id, "Skipping malformed word filter");
+                    None
+                }
+            },
+        )
+        .collect();
+
+    Ok(entries)
+}
+
+/// Compiles the changed list of the guild. If that fails the last filter that compiled stays
+/// in use, returns whether the change is active.
+async fn refresh(ctx: &Context, guild_id: GuildId) -> bool {
+    let set = match entries(ctx, guild_id)
+        .await
+        .and_then(|entries| FilterSet::compile(entries).map_err(Into::into))
+    {
+        Ok(set) => set,
+        Err(e) => {
+            error!(?e, "Could not compile word filter, keeping the last one");
+            return false;
+        }
+    };
+
+    if let Some(cache) = ctx.data.read().await.get::<WordFilterCache>() {
+        cache.lock().await.insert(*guild_id.as_u64(), Arc::new(set));
+    }
+
+    true
+}
+
+#[command]
+#[only_in("guilds")]
+#[allowed_roles("Mods")]
+#[description = "Add a filter pattern. Kinds: word, wildcard, regex. Actions: delete, warn, mute *duration*"]
+#[usage = "*kind* *action* *pattern*"]
+#[example = "word delete badword"]
+#[example = "wildcard warn scam*"]
+#[example = "regex mute 10m (?i)free\\s+nitro"]
+#[min_args(3)]
+pub async fn add(ctx: &Context, msg: &Message, mut args: Args) -> CommandResult {
+    let guild_id = msg.guild_id.ok_or("only in guilds")?;
+    let kind = args.single::<String>()?.parse::<PatternKind>()?;
+
+    let mut action_args = vec![args.single::<String>()?];
+    if action_args[0] == "mute" {
+        action_args.push(args.single::<String>()?);
+    }
+    let action = Action::parse(&action_args.iter().map(String::as_str).collect::<Vec<_>>())?;
+    if !matches!(action, Action::Delete | Action::Warn | Action::Mute { .. }) {
+        msg.reply(ctx, "Only delete, warn and mute are allowed for filters")
+            .await?;
+        return Ok(());
+    }
+
+    let pattern = args.rest().trim();
+    if let Err(e) = crate::filter::to_regex(kind, pattern) {
+        msg.reply(ctx, e).await?;
+        return Ok(());
+    }
+
+    let pool = get_client(ctx).await?;
+    let filter = WordFilter::create(
+        &pool,
+        *guild_id.as_u64() as i64,
+        kind.as_str(),
+        pattern,
+        serde_json::to_value(action)?,
+    )
+    .await?;
+
+    let reply = if refresh(ctx, guild_id).await {
+        format!("Added filter {} ({})", filter.id, action)
+    } else {
+        format!(
+            "Added filter {} ({}), but the filters are too big together. The previous filters stay active until some are removed.",
+            filter.id, action
+        )
+    };
+    msg.reply(ctx, reply).await?;
+
+    Ok(())
+}
+
+#[command]
+#[only_in("guilds")]
+#[allowed_roles("Mods")]
+#[description = "Remove a filter pattern by its id"]
+#[usage = "*filter_id*"]
+#[example = "12"]
+#[num_args(1)]
+pub async fn remove(ctx: &Context, msg: &Message, mut args: Args) -> CommandResult {
+    let guild_id = msg.guild_id.ok_or("only in guilds")?;
+    let id = args.single::<i64>()?;
+    let pool = get_client(ctx).await?;
+
+    let removed = WordFilter::delete(&pool, *guild_id.as_u64() as i64, id).await?;
+    refresh(ctx, guild_id).await;
+
+    msg.reply(ctx, format!("Removed {} filter(s)", removed))
+        .await?;
+
+    Ok(())
+}
+
+#[command]
+#[only_in("guilds")]
+#[allowed_roles("Mods")]
+#[description = "List the filter patterns and exemptions of this server"]
+#[num_args(0)]
+pub async fn list(ctx: &Context, msg: &Message, _args: Args) -> CommandResult {
+    let guild_id = msg.guild_id.ok_or("only in guilds")?;
+    let pool = get_client(ctx).await?;
+    let filters = WordFilter::list(&pool, *guild_id.as_u64() as i64).await?;
+
+    let g_cfg: Guild = match ServerConfig::get(&pool, *guild_id.as_u64() as i64).await {
+        Ok(mut config) => serde_json::from_value(config.config.take()).unwrap(),
+        Err(_e) => Guild::default(),
+    };
+
+    let mut content = MessageBuilder::new();
+    for f in &filters {
+        let action = serde_json::from_value::<Action>(f.action.clone())
+            .map_or_else(|_| "-".to_string(), |a| a.to_string());
+        content
+            .push(format!("{} | {} | {} | ", f.id, f.kind, action))
+            .push_mono_line_safe(&f.pattern);
+    }
+    if filters.is_empty() {
+        content.push_line("No filters configured");
+    }
+
+    content.push_line("").push_bold_line("Exempt:");
+    for role in &g_cfg.filter.exempt_roles {
+        content.role(*role).push(" ");
+    }
+    for channel in &g_cfg.filter.exempt_channels {
+        content.channel(*channel).push(" ");
+    }
+
+    msg.channel_id
+        .send_message(&ctx, |m| {
+            m.embed(|e| e.description(content.build()).color((0, 120, 220)))
+        })
+        .await?;
+
+    Ok(())
+}
+
+#[command]
+#[only_in("guilds")]
+#[allowed_roles("Mods")]
+#[description = "Test the filter of this server against some text"]
+#[usage = "*text*"]
+#[example = "get your free nitro here"]
+#[min_args(1)]
+pub async fn test(ctx: &Context, msg: &Message, args: Args) -> CommandResult {
+    let guild_id = msg.guild_id.ok_or("only in guilds")?;
+    let set = filter_set(ctx, guild_id).await?;
+    let matched = set.matches(args.rest());
+
+    let response = if matched.is_empty() {
+        "No filter matches".to_string()
+    } else {
+        matched
+            .iter()
+            .map(|e| {
+                format!(
+                    "{} | {} | {} | `{}`",
+                    e.id,
+                    e.kind.as_str(),
+                    e.action,
+                    e.pattern
+                )
+            })
+            .collect::<Vec<_>>()
+            .join("\n")
+    };
+
+    msg.reply(ctx, response).await?;
+
+    Ok(())
+}
+
+#[command]
+#[only_in("guilds")]
+#[allowed_roles("Mods")]
+#[description = "Toggle the filter exemption of the mentioned roles and channels"]
+#[usage = "*role_or_channel_mention*"]
+#[example = "@Mods #staff"]
+#[min_args(1)]
+pub async fn exempt(ctx: &Context, msg: &Message, _args: Args) -> CommandResult {
+    let guild_id = msg.guild_id.ok_or("only in guilds")?;
+    let pool = get_client(ctx).await?;
+
+    let toggle = |list: &mut Vec<u64>, id: u64| {
+        if let Some(pos) = list.iter().position(|x| *x == id) {
+            list.remove(pos);
+        } else {
+            list.push(id);
+        }
+    };
+
+    let g_cfg = update_guild_config(&pool, *guild_id.as_u64() as i64, |g_cfg| {
+        for role in &msg.mention_roles {
+            toggle(&mut g_cfg.filter.exempt_roles, *role.as_u64());
+        }
+        for channel in crate::util::channel_mentions(&msg.content) {
+            toggle(&mut g_cfg.filter.exempt_channels, channel);
+        }
+        Ok(())
+    })
+    .await??;
+
+    msg.reply(
+        ctx,
+        format!(
+            "Exempt roles: {:?}, exempt channels: {:?}",
+            g_cfg.filter.exempt_roles, g_cfg.filter.exempt_channels
+        ),
+    )
+    .await?;
+
+    Ok(())
+}
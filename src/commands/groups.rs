@@ -1,18 +1,28 @@
 pub mod general {
     use crate::commands::{
-        about::*, choose::*, emoji::*, fighting::*, owner::*, poll::*, quote::*, remindme::*,
-        roll::*, selfmute::*, spongebob::*, userinfo::*, uwuify::*, xkcd::*,
+        about::*, appeal::*, choose::*, emoji::*, fighting::*, owner::*, poll::*, quote::*,
+        remindme::*, roll::*, selfmute::*, spongebob::*, userinfo::*, uwuify::*, xkcd::*,
     };
     use serenity::framework::standard::macros::group;
 
     #[group]
     #[commands(
         about, roll, choose, xkcd, quote, userinfo, remindme, spongebob, selfmute, katzer, poll,
-        leave, index_xkcd, combo, uwuify
+        leave, index_xkcd, combo, uwuify, appeal
     )]
     pub struct General;
 }
 
+pub mod levels {
+    use crate::commands::levels::*;
+    use serenity::framework::standard::macros::group;
+
+    #[group]
+    #[only_in("guilds")]
+    #[commands(rank, levels, setxp, resetxp)]
+    pub struct Levels;
+}
+
 pub mod lastfm {
     use crate::commands::lastfm::*;
     use serenity::framework::standard::macros::group;
@@ -39,7 +49,21 @@ pub mod config {
 
     #[group]
     #[prefix("cfg")]
-    #[commands(show_config, set_modlog, set_muterole, set_userlog)]
+    #[commands(
+        show_config,
+        set_modlog,
+        set_muterole,
+        set_userlog,
+        set_automod,
+        set_appeals,
+        set_modmail,
+        set_modmail_greeting,
+        set_favs,
+        set_language,
+        set_economy,
+        set_levels,
+        set_combo
+    )]
     pub struct Config;
 }
 
@@ -48,17 +72,58 @@ pub mod moderation {
     use serenity::framework::standard::macros::group;
     #[group]
     #[prefix("mod")]
-    #[commands(mute, unmute, kick, ban)]
+    #[commands(mute, unmute, kick, ban, purge, slowmode, lock_cmd, unlock_cmd)]
     pub struct Moderation;
 }
 
+pub mod modmail {
+    use crate::commands::modmail::*;
+    use serenity::framework::standard::macros::group;
+
+    #[group]
+    #[only_in("guilds")]
+    #[allowed_roles("Mods")]
+    #[commands(reply, areply, close, tickets, transcript)]
+    pub struct Modmail;
+}
+
+pub mod filter {
+    use crate::commands::filter::*;
+    use serenity::framework::standard::macros::group;
+
+    #[group]
+    #[prefix("filter")]
+    #[commands(add, remove, list, test, exempt)]
+    pub struct Filter;
+}
+
 pub mod account {
-    use crate::commands::account::{general::*, slot::*};
+    use crate::commands::account::{
+        blackjack::*, coinflip::*, duel::*, general::*, roulette::*, shop::*, slot::*,
+    };
     use serenity::framework::standard::macros::group;
 
     #[group]
     #[prefix("acc")]
-    #[commands(create, payday, leaderboard, transfer, slot)]
+    #[commands(
+        create,
+        payday,
+        leaderboard,
+        transfer,
+        slot,
+        blackjack,
+        coinflip,
+        roulette,
+        duel,
+        shop,
+        buy,
+        inventory,
+        use_item,
+        history,
+        grant,
+        take,
+        reset
+    )]
     pub struct Account;
 }
 
@@ -69,6 +134,22 @@ pub mod greenbook {
     #[group]
     #[prefix("fav")]
     #[default_command(post)]
-    #[commands(post, untagged, add, tags, block, create_fav_list)]
+    #[commands(
+        post,
+        untagged,
+        add,
+        tag,
+        tags,
+        collection,
+        search,
+        block,
+        unblock,
+        blocks,
+        export,
+        export_server,
+        import,
+        stats,
+        backfill
+    )]
     pub struct Greenbook;
 }
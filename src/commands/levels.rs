@@ -0,0 +1,493 @@
+use crate::commands::config::Guild;
+use crate::models::level::Level;
+use crate::models::server_config::ServerConfig;
+use crate::util::get_client;
+use crate::OptOut;
+use rand::Rng;
+use serde::{Deserialize, Serialize};
+use serenity::prelude::*;
+use serenity::utils::{parse_channel, parse_role, parse_username};
+use serenity::{
+    framework::standard::{macros::command, Args, CommandResult},
+    model::{channel::Message, id::GuildId, id::RoleId, id::UserId},
+};
+use sqlx::postgres::PgPool;
+use std::collections::BTreeMap;
+use tracing::error;
+
+const PAGE_SIZE: i64 = 10;
+
+/// Xp for messages, off until mods turn it on
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+#[serde(default)]
+pub struct Config {
+    pub enabled: bool,
+    /// Congratulate in the channel of the message on a level up
+    pub announce: bool,
+    pub min_xp: i64,
+    pub max_xp: i64,
+    /// Seconds after a message until the next one gives xp
+    pub cooldown_secs: i64,
+    pub excluded_channels: Vec<u64>,
+    /// Xp of messages in a channel in percent of the normal xp
+    pub channel_weights: BTreeMap<u64, u32>,
+    /// Role granted when reaching a level
+    pub role_rewards: BTreeMap<u32, u64>,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            announce: true,
+            min_xp: 15,
+            max_xp: 25,
+            cooldown_secs: 60,
+            excluded_channels: Vec::new(),
+            channel_weights: BTreeMap::new(),
+            role_rewards: BTreeMap::new(),
+        }
+    }
+}
+
+fn parse_switch(value: &str) -> Result<bool, String> {
+    match value {
+        "on" | "true" => Ok(true),
+        "off" | "false" => Ok(false),
+        other => Err(format!("Expected on or off, got: {}", other)),
+    }
+}
+
+fn parse_channel_arg(value: &str) -> Result<u64, String> {
+    parse_channel(value)
+        .or_else(|| value.parse().ok())
+        .ok_or_else(|| format!("Not a channel: {}", value))
+}
+
+impl Config {
+    pub fn apply_setting(&mut self, setting: &str, value: &str) -> Result<(), String> {
+        let mut values = value.split_whitespace();
+        let (first, second) = (values.next().unwrap_or_default(), values.next());
+
+        match (setting, second) {
+            ("enabled", None) => self.enabled = parse_switch(first)?,
+            ("announce", None) => self.announce = parse_switch(first)?,
+            ("xp", None) => {
+                let (min_xp, max_xp) = first
+                    .split_once('-')
+                    .and_then(|(min, max)| Some((min.parse().ok()?, max.parse().ok()?)))
+                    .filter(|(min, max)| 0 < *min && min <= max && *max <= 1000)
+                    .ok_or_else(|| format!("Expected a range like 15-25, got: {}", first))?;
+                self.min_xp = min_xp;
+                self.max_xp = max_xp;
+            }
+            ("cooldown", None) => {
+                self.cooldown_secs = first
+                    .parse()
+                    .ok()
+                    .filter(|secs| *secs >= 0)
+                    .ok_or_else(|| format!("Expected seconds, got: {}", first))?;
+            }
+            ("exclude", None) => {
+                let channel_id = parse_channel_arg(first)?;
+                if self.excluded_channels.contains(&channel_id) {
+                    self.excluded_channels.retain(|c| *c != channel_id);
+                } else {
+                    self.excluded_channels.push(channel_id);
+                }
+            }
+            ("weight", Some(percent)) => {
+                let channel_id = parse_channel_arg(first)?;
+                let percent = percent
+                    .trim_end_matches('%')
+                    .parse::<u32>()
+                    .ok()
+                    .filter(|p| *p <= 1000)
+                    .ok_or_else(|| format!("Expected a percentage up to 1000, got: {}", percent))?;
+                if percent == 100 {
+                    self.channel_weights.remove(&channel_id);
+                } else {
+                    self.channel_weights.insert(channel_id, percent);
+                }
+            }
+            ("reward", Some(role)) => {
+                let level = first
+                    .parse::<u32>()
+                    .ok()
+                    .filter(|l| *l > 0)
+                    .ok_or_else(|| format!("Not a level: {}", first))?;
+                if role == "off" {
+                    self.role_rewards.remove(&level);
+                } else {
+                    let role_id = parse_role(role)
+                        .or_else(|| role.parse().ok())
+                        .ok_or_else(|| format!("Not a role: {}", role))?;
+                    self.role_rewards.insert(level, role_id);
+                }
+            }
+            ("enabled" | "announce" | "xp" | "cooldown" | "exclude", _) => {
+                return Err(format!("{} takes a single value", setting))
+            }
+            ("weight" | "reward", None) => return Err(format!("{} takes two values", setting)),
+            (other, _) => return Err(format!("Unknown level setting: {}", other)),
+        }
+
+        Ok(())
+    }
+
+    /// Xp for a message in the channel, `None` if it gives none
+    pub fn xp_for(&self, channel_id: u64, rng: &mut impl Rng) -> Option<i64> {
+        if !self.enabled || self.excluded_channels.contains(&channel_id) {
+            return None;
+        }
+
+        let weight = self
+            .channel_weights
+            .get(&channel_id)
+            .copied()
+            .unwrap_or(100);
+        Some(rng.gen_range(self.min_xp..=self.max_xp) * i64::from(weight) / 100)
+            .filter(|xp| *xp > 0)
+    }
+
+    /// Roles of the levels after `from` up to and including `to`
+    pub fn rewards_between(&self, from: u32, to: u32) -> Vec<u64> {
+        if to <= from {
+            return Vec::new();
+        }
+
+        self.role_rewards
+            .range(from + 1..=to)
+            .map(|(_, role_id)| *role_id)
+            .collect()
+    }
+}
+
+/// Most xp a user can be given, about level 840. The level curve can not overflow below it.
+const MAX_XP: i64 = 1_000_000_000;
+
+/// Xp from one level to the next, it grows with every level
+fn xp_to_next(level: u32) -> i64 {
+    let level = i64::from(level);
+    5 * level * level + 50 * level + 100
+}
+
+/// Total xp needed for the level
+pub fn xp_for_level(level: u32) -> i64 {
+    (0..level).map(xp_to_next).sum()
+}
+
+pub fn level_of(xp: i64) -> u32 {
+    let mut level = 0;
+    let mut needed = xp_to_next(0);
+    let mut left = xp;
+    while left >= needed {
+        left -= needed;
+        level += 1;
+        needed = xp_to_next(level);
+    }
+    level
+}
+
+/// Users on the opt-out list do not show up on leaderboards
+async fn hidden_users(ctx: &Context) -> Vec<i64> {
+    match ctx.data.read().await.get::<OptOut>() {
+        Some(opt_out) => opt_out
+            .lock()
+            .await
+            .set
+            .iter()
+            .map(|id| *id as i64)
+            .collect(),
+        None => Vec::new(),
+    }
+}
+
+/// Grants the rewards of the levels reached between the two levels
+pub async fn grant_rewards(
+    ctx: &Context,
+    guild_id: GuildId,
+    user_id: UserId,
+    config: &Config,
+    from: u32,
+    to: u32,
+) {
+    let rewards = config.rewards_between(from, to);
+    if rewards.is_empty() {
+        return;
+    }
+
+    match guild_id.member(ctx, user_id).await {
+        Ok(mut member) => {
+            for role_id in rewards {
+                if let Err(e) = member.add_role(ctx, RoleId(role_id)).await {
+                    error!(?e, "failed to grant level reward");
+                }
+            }
+        }
+        Err(e) => error!(?e, "failed to get member for level rewards"),
+    }
+}
+
+pub async fn config(pool: &PgPool, guild_id: GuildId) -> Config {
+    match ServerConfig::get(pool, *guild_id.as_u64() as i64).await {
+        Ok(mut config) => serde_json::from_value::<Guild>(config.config.take())
+            .map(|g_cfg| g_cfg.levels)
+            .unwrap_or_default(),
+        Err(_e) => Config::default(),
+    }
+}
+
+#[command]
+#[description = "Shows your level or the one of another user"]
+#[max_args(1)]
+#[usage = "*[@user]*"]
+#[example = "@HansTrashy"]
+#[only_in("guilds")]
+pub async fn rank(ctx: &Context, msg: &Message, mut args: Args) -> CommandResult {
+    let pool = get_client(ctx).await?;
+    let server_id = *msg.guild_id.ok_or("Levels only exist on servers")?.as_u64() as i64;
+    let user_id = match args.single::<String>() {
+        Ok(user) => match parse_username(&user) {
+            Some(user_id) => user_id,
+            None => {
+                msg.reply(ctx, "Mention the user whose rank you want to see")
+                    .await?;
+                return Ok(());
+            }
+        },
+        Err(_e) => *msg.author.id.as_u64(),
+    };
+
+    let level = match Level::get(&pool, server_id, user_id as i64).await {
+        Ok(level) => level,
+        Err(_e) => {
+            msg.reply(ctx, "There is no xp yet").await?;
+            return Ok(());
+        }
+    };
+    let hidden = hidden_users(ctx).await;
+    let position = if hidden.contains(&(user_id as i64)) {
+        "-".to_string()
+    } else {
+        level.position(&pool, &hidden).await?.to_string()
+    };
+
+    let current = level_of(level.xp);
+    let (start, end) = (xp_for_level(current), xp_for_level(current + 1));
+    msg.channel_id
+        .send_message(&ctx, |m| {
+            m.embed(|e| {
+                e.description(format!(
+                    "<@{}>\n\nLevel: {}\nXp: {}\nNext level: {}/{}\nRank: #{}",
+                    user_id,
+                    current,
+                    level.xp,
+                    level.xp - start,
+                    end - start,
+                    position
+                ))
+                .color((0, 120, 220))
+            })
+        })
+        .await?;
+
+    Ok(())
+}
+
+#[command]
+#[description = "Shows the users with the most xp on this server"]
+#[max_args(1)]
+#[usage = "*[page]*"]
+#[example = "2"]
+#[only_in("guilds")]
+pub async fn levels(ctx: &Context, msg: &Message, mut args: Args) -> CommandResult {
+    let pool = get_client(ctx).await?;
+    let guild_id = msg.guild_id.ok_or("Levels only exist on servers")?;
+    let server_id = *guild_id.as_u64() as i64;
+    let page = if args.is_empty() {
+        1
+    } else {
+        match args.single::<i64>() {
+            Ok(page) if page > 0 => page,
+            _ => {
+                msg.reply(ctx, "Pages start at 1").await?;
+                return Ok(());
+            }
+        }
+    };
+
+    let hidden = hidden_users(ctx).await;
+    let pages =
+        (Level::count_of_server(&pool, server_id, &hidden).await? + PAGE_SIZE - 1) / PAGE_SIZE;
+    let results = Level::top(&pool, server_id, &hidden, (page - 1) * PAGE_SIZE, PAGE_SIZE).await?;
+
+    let mut rendered_leaderboard = String::from("Levels:\n");
+    for (i, r) in results.iter().enumerate() {
+        let name = match guild_id.member(ctx, r.user_id as u64).await {
+            Ok(member) => member.display_name().to_string(),
+            Err(_e) => format!("<@{}>", r.user_id),
+        };
+        rendered_leaderboard.push_str(&format!(
+            "\n{} | Level {} | {} xp | {}",
+            (page - 1) * PAGE_SIZE + i as i64 + 1,
+            level_of(r.xp),
+            r.xp,
+            name
+        ));
+    }
+
+    msg.channel_id
+        .send_message(&ctx, |m| {
+            m.embed(|e| {
+                e.description(&rendered_leaderboard)
+                    .footer(|f| f.text(format!("Page {}/{}", page, pages.max(1))))
+                    .color((0, 120, 220))
+            })
+        })
+        .await?;
+
+    Ok(())
+}
+
+#[command]
+#[description = "Sets the xp of a user, reached role rewards are granted"]
+#[num_args(2)]
+#[usage = "*@user* *xp*"]
+#[example = "@HansTrashy 1500"]
+#[only_in("guilds")]
+#[allowed_roles("Mods")]
+pub async fn setxp(ctx: &Context, msg: &Message, mut args: Args) -> CommandResult {
+    let pool = get_client(ctx).await?;
+    let guild_id = msg.guild_id.ok_or("Levels only exist on servers")?;
+    let server_id = *guild_id.as_u64() as i64;
+    let user_id = args
+        .single::<String>()
+        .ok()
+        .and_then(|u| parse_username(&u));
+    let xp = args
+        .single::<i64>()
+        .ok()
+        .filter(|xp| (0..=MAX_XP).contains(xp));
+    let (user_id, xp) = match (user_id, xp) {
+        (Some(user_id), Some(xp)) => (user_id, xp),
+        _ => {
+            msg.reply(
+                ctx,
+                format!("Mention a user and give the xp, up to {}", MAX_XP),
+            )
+            .await?;
+            return Ok(());
+        }
+    };
+
+    let before = Level::get(&pool, server_id, user_id as i64)
+        .await
+        .map_or(0, |l| level_of(l.xp));
+    let level = level_of(Level::set(&pool, server_id, user_id as i64, xp).await?.xp);
+    grant_rewards(
+        ctx,
+        guild_id,
+        UserId(user_id),
+        &config(&pool, guild_id).await,
+        before,
+        level,
+    )
+    .await;
+
+    msg.reply(
+        ctx,
+        format!("<@{}> now has {} xp, level {}", user_id, xp, level),
+    )
+    .await?;
+
+    Ok(())
+}
+
+#[command]
+#[description = "Resets the xp of a user, or of everyone with `all`. Granted roles are kept."]
+#[num_args(1)]
+#[usage = "*@user/all*"]
+#[example = "@HansTrashy"]
+#[example = "all"]
+#[only_in("guilds")]
+#[allowed_roles("Mods")]
+pub async fn resetxp(ctx: &Context, msg: &Message, mut args: Args) -> CommandResult {
+    let pool = get_client(ctx).await?;
+    let server_id = *msg.guild_id.ok_or("Levels only exist on servers")?.as_u64() as i64;
+    let target = args.single::<String>()?;
+
+    let user_id = if target == "all" {
+        None
+    } else {
+        match parse_username(&target) {
+            Some(user_id) => Some(user_id as i64),
+            None => {
+                msg.reply(ctx, "Mention a user or use all").await?;
+                return Ok(());
+            }
+        }
+    };
+
+    let reset = Level::reset(&pool, server_id, user_id).await?;
+    msg.reply(ctx, format!("Reset the xp of {} users", reset))
+        .await?;
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{level_of, xp_for_level, Config, MAX_XP};
+    use rand::{rngs::StdRng, SeedableRng};
+
+    #[test]
+    fn level_curve() {
+        assert_eq!(xp_for_level(0), 0);
+        assert_eq!(xp_for_level(1), 100);
+        assert_eq!(xp_for_level(2), 255);
+        assert_eq!(level_of(0), 0);
+        assert_eq!(level_of(99), 0);
+        assert_eq!(level_of(100), 1);
+        assert_eq!(level_of(254), 1);
+        assert_eq!(level_of(255), 2);
+        for level in 0..100 {
+            assert_eq!(level_of(xp_for_level(level)), level);
+        }
+        assert!(xp_for_level(level_of(MAX_XP) + 1) > MAX_XP);
+    }
+
+    #[test]
+    fn level_settings() {
+        let mut config = Config::default();
+        let mut rng = StdRng::seed_from_u64(46);
+        assert_eq!(config.xp_for(1, &mut rng), None);
+
+        config.apply_setting("enabled", "on").unwrap();
+        config.apply_setting("xp", "10-10").unwrap();
+        config.apply_setting("exclude", "<#2>").unwrap();
+        config.apply_setting("weight", "<#3> 250").unwrap();
+        assert_eq!(config.xp_for(1, &mut rng), Some(10));
+        assert_eq!(config.xp_for(2, &mut rng), None);
+        assert_eq!(config.xp_for(3, &mut rng), Some(25));
+
+        config.apply_setting("exclude", "2").unwrap();
+        config.apply_setting("weight", "<#3> 100").unwrap();
+        assert!(config.excluded_channels.is_empty());
+        assert!(config.channel_weights.is_empty());
+
+        config.apply_setting("reward", "5 <@&50>").unwrap();
+        config.apply_setting("reward", "10 <@&100>").unwrap();
+        assert_eq!(config.rewards_between(0, 4), Vec::<u64>::new());
+        assert_eq!(config.rewards_between(4, 10), vec![50, 100]);
+        assert_eq!(config.rewards_between(5, 9), Vec::<u64>::new());
+        assert_eq!(config.rewards_between(10, 2), Vec::<u64>::new());
+        config.apply_setting("reward", "5 off").unwrap();
+        assert_eq!(config.rewards_between(0, 10), vec![100]);
+
+        assert!(config.apply_setting("xp", "25-15").is_err());
+        assert!(config.apply_setting("weight", "<#3>").is_err());
+        assert!(config.apply_setting("reward", "0 <@&1>").is_err());
+        assert!(config.apply_setting("bonus", "on").is_err());
+    }
+}
@@ -1,11 +1,15 @@
+use super::appeal::notify_sanction;
 use super::config::Guild;
+use crate::models::lockdown::{Lockdown, SavedOverwrite};
 use crate::models::mute::Mute;
+use crate::models::sanction::Sanction;
 use crate::models::server_config::ServerConfig;
 use crate::util;
 use crate::util::get_client;
 use chrono::{Duration, Utc};
 use futures::future::join_all;
 use futures::{stream, StreamExt};
+use serenity::http::Http;
 use serenity::prelude::*;
 use serenity::{
     framework::standard::{macros::command, Args, CommandResult},
@@ -14,7 +18,10 @@ use serenity::{
     model::id::ChannelId,
     model::id::RoleId,
     model::prelude::*,
+    utils::MessageBuilder,
 };
+use sqlx::postgres::PgPool;
+use std::sync::Arc;
 use tokio::time::sleep;
 use tracing::{debug, error};
 
@@ -60,6 +67,23 @@ pub async fn mute(ctx: &Context, msg: &Message, mut args: Args) -> CommandResult
                                 Ok(_) => (),
                                 Err(e) => debug!(error = ?e, "failed to create mute"),
                             }
+
+                            match Sanction::create(
+                                &pool,
+                                *guild_id.as_u64() as i64,
+                                *user.id.as_u64() as i64,
+                                *msg.author.id.as_u64() as i64,
+                                "mute",
+                                mute_message,
+                                Some(end_time),
+                            )
+                            .await
+                            {
+                                Ok(sanction) => {
+                                    notify_sanction(ctx, guild_id, user, &sanction).await
+                                }
+                                Err(e) => debug!(error = ?e, "failed to record sanction"),
+                            }
                         }
 
                         let mutes = msg
@@ -106,7 +130,7 @@ pub async fn mute(ctx: &Context, msg: &Message, mut args: Args) -> CommandResult
     Ok(())
 }
 
-async fn remove_mute(
+pub async fn remove_mute(
     ctx: Context,
     guild_id: GuildId,
     user_id: u64,
@@ -129,6 +153,221 @@ async fn remove_mute(
     Ok(())
 }
 
+/// Removes the mute role and the stored mute, returns the member if they are still on the server
+pub async fn lift_mute(
+    ctx: &Context,
+    pool: &PgPool,
+    guild_id: GuildId,
+    user_id: UserId,
+    mute_role: u64,
+) -> Result<Option<Member>, Box<dyn std::error::Error + Send + Sync>> {
+    let member = match guild_id.member(ctx, user_id).await {
+        Ok(mut member) => {
+            std::mem::drop(member.remove_role(&ctx, RoleId(mute_role)).await);
+            Some(member)
+        }
+        Err(e) => {
+            error!("Could not get member: {:?}", e);
+            None
+        }
+    };
+
+    Mute::delete(pool, *guild_id.as_u64() as i64, *user_id.as_u64() as i64).await?;
+
+    Ok(member)
+}
+
+/// Posts the description as an embed into the modlog channel, if one is configured
+pub async fn send_modlog(ctx: &Context, modlog_channel: Option<u64>, description: &str) {
+    if let Some(modlog_channel) = modlog_channel {
+        std::mem::drop(
+            ChannelId(modlog_channel)
+                .send_message(&ctx, |m| {
+                    m.embed(|e| e.description(description).color((0, 120, 220)))
+                })
+                .await,
+        );
+    }
+}
+
+/// Denies sending messages for @everyone, keeping all other overwrites. Returns the overwrite
+/// from before, `None` if the channel did not allow sending messages anyway.
+pub async fn lock_channel(
+    http: &Http,
+    guild_id: GuildId,
+    channel: &GuildChannel,
+) -> serenity::Result<Option<SavedOverwrite>> {
+    let everyone = PermissionOverwriteType::Role(RoleId(*guild_id.as_u64()));
+    let previous = channel
+        .permission_overwrites
+        .iter()
+        .find(|o| o.kind == everyone)
+        .cloned();
+    if previous
+        .as_ref()
+        .map_or(false, |o| o.deny.contains(Permissions::SEND_MESSAGES))
+    {
+        return Ok(None);
+    }
+
+    let saved = SavedOverwrite {
+        channel_id: *channel.id.as_u64(),
+        existed: previous.is_some(),
+        allow: previous.as_ref().map_or(0, |o| o.allow.bits()),
+        deny: previous.as_ref().map_or(0, |o| o.deny.bits()),
+    };
+    let mut overwrite = previous.unwrap_or(PermissionOverwrite {
+        allow: Permissions::empty(),
+        deny: Permissions::empty(),
+        kind: everyone,
+    });
+    overwrite.allow.remove(Permissions::SEND_MESSAGES);
+    overwrite.deny.insert(Permissions::SEND_MESSAGES);
+    channel.id.create_permission(http, &overwrite).await?;
+
+    Ok(Some(saved))
+}
+
+/// Puts back the @everyone overwrite a channel had before it was locked
+pub async fn restore_channel(
+    http: &Http,
+    guild_id: GuildId,
+    saved: &SavedOverwrite,
+) -> serenity::Result<()> {
+    let everyone = PermissionOverwriteType::Role(RoleId(*guild_id.as_u64()));
+    let channel_id = ChannelId(saved.channel_id);
+    if saved.existed {
+        channel_id
+            .create_permission(
+                http,
+                &PermissionOverwrite {
+                    allow: Permissions::from_bits_truncate(saved.allow),
+                    deny: Permissions::from_bits_truncate(saved.deny),
+                    kind: everyone,
+                },
+            )
+            .await
+    } else {
+        channel_id.delete_permission(http, everyone).await
+    }
+}
+
+/// Locks every text channel of the guild that is not locked yet
+pub async fn lock_guild(http: &Http, guild_id: GuildId) -> serenity::Result<Vec<SavedOverwrite>> {
+    let channels = guild_id.channels(http).await?;
+
+    let mut saved = Vec::new();
+    for channel in channels.values().filter(|c| c.kind == ChannelType::Text) {
+        match lock_channel(http, guild_id, channel).await {
+            Ok(Some(overwrite)) => saved.push(overwrite),
+            Ok(None) => (),
+            Err(e) => error!(?e, channel = ?channel.id, "failed to lock channel"),
+        }
+    }
+
+    Ok(saved)
+}
+
+/// Locks a single channel or the whole guild (`channel_id` is `None`), returns the number of locked channels.
+/// The overwrites from before are stored for the unlock, with a duration the unlock survives restarts.
+pub async fn lock(
+    ctx: &Context,
+    guild_id: GuildId,
+    channel_id: Option<ChannelId>,
+    duration: Option<Duration>,
+) -> Result<usize, Box<dyn std::error::Error + Send + Sync>> {
+    let saved = match channel_id {
+        Some(channel_id) => {
+            let channel = channel_id
+                .to_channel(ctx)
+                .await?
+                .guild()
+                .ok_or("not a guild channel")?;
+            lock_channel(&ctx.http, guild_id, &channel)
+                .await?
+                .into_iter()
+                .collect()
+        }
+        None => lock_guild(&ctx.http, guild_id).await?,
+    };
+    // everything was locked already, there is nothing to restore later
+    if saved.is_empty() {
+        return Ok(0);
+    }
+
+    let pool = get_client(ctx).await?;
+    let lockdown = Lockdown::create(
+        &pool,
+        *guild_id.as_u64() as i64,
+        channel_id.map(|c| *c.as_u64() as i64),
+        duration.map(|duration| Utc::now() + duration),
+        &saved,
+    )
+    .await?;
+
+    if duration.is_some() {
+        tokio::spawn(lift_lockdown(Arc::clone(&ctx.http), pool, lockdown));
+    }
+
+    Ok(saved.len())
+}
+
+/// Restores the channels the lockdown locked, returns the number of unlocked channels
+async fn restore_lockdown(http: &Http, lockdown: &Lockdown) -> usize {
+    let guild_id = GuildId(lockdown.server_id as u64);
+    let mut restored = 0;
+    for saved in lockdown.saved_overwrites() {
+        match restore_channel(http, guild_id, &saved).await {
+            Ok(()) => restored += 1,
+            Err(e) => error!(?e, channel = saved.channel_id, "failed to unlock channel"),
+        }
+    }
+
+    restored
+}
+
+/// Unlocks a single channel or the whole guild, only channels locked by the bot are changed.
+/// Returns the number of unlocked channels.
+pub async fn unlock(
+    http: &Http,
+    pool: &PgPool,
+    guild_id: GuildId,
+    channel_id: Option<ChannelId>,
+) -> Result<usize, Box<dyn std::error::Error + Send + Sync>> {
+    let lockdowns = Lockdown::delete_for(
+        pool,
+        *guild_id.as_u64() as i64,
+        channel_id.map(|c| *c.as_u64() as i64),
+    )
+    .await?;
+
+    let mut unlocked = 0;
+    for lockdown in &lockdowns {
+        unlocked += restore_lockdown(http, lockdown).await;
+    }
+
+    Ok(unlocked)
+}
+
+/// Waits for the end of the lockdown and unlocks again
+pub async fn lift_lockdown(http: Arc<Http>, pool: PgPool, lockdown: Lockdown) {
+    if let Some(remaining) = lockdown
+        .end_time
+        .and_then(|end_time| end_time.signed_duration_since(Utc::now()).to_std().ok())
+    {
+        sleep(remaining).await;
+    }
+
+    // a manual unlock in the meantime already removed the lockdown
+    match Lockdown::delete(&pool, lockdown.id).await {
+        Ok(Some(lockdown)) => {
+            restore_lockdown(&http, &lockdown).await;
+        }
+        Ok(None) => (),
+        Err(e) => error!(?e, "failed to lift lockdown"),
+    }
+}
+
 async fn create_mute_message(users: &[Member], duration: &Duration, mute_message: &str) -> String {
     let intro = if users.len() > 1 {
         "Muted users:"
@@ -261,23 +500,11 @@ pub async fn unmute(ctx: &Context, msg: &Message, _args: Args) -> CommandResult
                 if let Some(mute_role) = &guild_config.mute_role {
                     let mut found_members = Vec::new();
                     for user in &msg.mentions {
-                        match guild_id.member(ctx, user).await {
-                            Ok(mut member) => {
-                                std::mem::drop(member.remove_role(&ctx, RoleId(*mute_role)).await);
-                                found_members.push(member);
-                            }
-                            Err(e) => error!("Could not get member: {:?}", e),
-                        };
-                    }
-
-                    for user in &msg.mentions {
-                        //TODO: this should be done in a single statement
-                        let _ = Mute::delete(
-                            &pool,
-                            *guild_id.as_u64() as i64,
-                            *user.id.as_u64() as i64,
-                        )
-                        .await?;
+                        if let Some(member) =
+                            lift_mute(ctx, &pool, guild_id, user.id, *mute_role).await?
+                        {
+                            found_members.push(member);
+                        }
                     }
 
                     if let Some(modlog_channel) = &guild_config.modlog_channel {
@@ -369,6 +596,23 @@ pub async fn ban(ctx: &Context, msg: &Message, args: Args) -> CommandResult {
                 let mut found_members = Vec::new();
                 for user in &msg.mentions {
                     let member = guild_id.member(ctx, user).await?;
+
+                    // notify before banning, afterwards there is no shared server anymore
+                    match Sanction::create(
+                        &pool,
+                        *guild_id.as_u64() as i64,
+                        *user.id.as_u64() as i64,
+                        *msg.author.id.as_u64() as i64,
+                        "ban",
+                        ban_msg,
+                        None,
+                    )
+                    .await
+                    {
+                        Ok(sanction) => notify_sanction(ctx, guild_id, user, &sanction).await,
+                        Err(e) => debug!(error = ?e, "failed to record sanction"),
+                    }
+
                     std::mem::drop(member.ban_with_reason(&ctx, 0, ban_msg).await);
                     found_members.push(member);
                 }
@@ -399,3 +643,327 @@ pub async fn ban(ctx: &Context, msg: &Message, args: Args) -> CommandResult {
 
     Ok(())
 }
+
+/// Which messages are removed by `purge`
+#[derive(Debug, PartialEq, Eq)]
+enum PurgeFilter {
+    All,
+    User(u64),
+    Bots,
+    Contains(String),
+    Attachments,
+}
+
+impl PurgeFilter {
+    fn parse(filter: &str) -> Result<Self, String> {
+        let filter = filter.trim();
+        if filter.is_empty() {
+            return Ok(Self::All);
+        }
+
+        if let Some(text) = filter.strip_prefix("contains:") {
+            return Ok(Self::Contains(text.to_lowercase()));
+        }
+
+        match filter {
+            "bots" => Ok(Self::Bots),
+            "attachments" => Ok(Self::Attachments),
+            mention => mention
+                .trim_start_matches("<@")
+                .trim_start_matches('!')
+                .trim_end_matches('>')
+                .parse::<u64>()
+                .map(Self::User)
+                .map_err(|_| format!("Unknown purge filter: {}", mention)),
+        }
+    }
+
+    fn matches(&self, msg: &Message) -> bool {
+        match self {
+            Self::All => true,
+            Self::User(id) => msg.author.id == *id,
+            Self::Bots => msg.author.bot,
+            Self::Contains(text) => msg.content.to_lowercase().contains(text),
+            Self::Attachments => !msg.attachments.is_empty(),
+        }
+    }
+}
+
+/// Discord refuses to bulk delete messages older than 14 days
+fn bulk_deletable(timestamp: chrono::DateTime<Utc>, now: chrono::DateTime<Utc>) -> bool {
+    now.signed_duration_since(timestamp) < Duration::days(14) - Duration::minutes(1)
+}
+
+#[command]
+#[only_in("guilds")]
+#[allowed_roles("Mods")]
+#[description = "Bulk delete the last messages of this channel, optionally filtered. Messages older than 14 days are skipped"]
+#[usage = "*amount* (*user_mention*|bots|contains:*text*|attachments)"]
+#[example = "50"]
+#[example = "20 @HansTrashy"]
+#[example = "100 contains:nitro"]
+#[min_args(1)]
+pub async fn purge(ctx: &Context, msg: &Message, mut args: Args) -> CommandResult {
+    let amount = args.single::<usize>()?;
+    if amount == 0 || amount > 1000 {
+        msg.reply(ctx, "You can purge between 1 and 1000 messages")
+            .await?;
+        return Ok(());
+    }
+    let filter = match PurgeFilter::parse(args.rest()) {
+        Ok(filter) => filter,
+        Err(e) => {
+            msg.reply(ctx, e).await?;
+            return Ok(());
+        }
+    };
+
+    let now = Utc::now();
+    let mut to_delete = Vec::new();
+    let mut before = msg.id;
+
+    // page backwards through the channel until enough messages matched
+    'pages: while to_delete.len() < amount {
+        let page = msg
+            .channel_id
+            .messages(&ctx, |r| r.before(before).limit(100))
+            .await?;
+
+        for m in &page {
+            if !bulk_deletable(m.timestamp, now) {
+                break 'pages;
+            }
+            if filter.matches(m) {
+                to_delete.push(m.id);
+                if to_delete.len() == amount {
+                    break 'pages;
+                }
+            }
+        }
+
+        match page.last() {
+            Some(last) if page.len() == 100 => before = last.id,
+            _ => break,
+        }
+    }
+
+    for chunk in to_delete.chunks(100) {
+        if let [single] = chunk {
+            msg.channel_id.delete_message(&ctx, *single).await?;
+        } else {
+            msg.channel_id.delete_messages(&ctx, chunk).await?;
+        }
+    }
+
+    std::mem::drop(msg.delete(ctx).await);
+
+    if let Some(guild_id) = msg.guild_id {
+        let modlog_channel = modlog_channel(ctx, guild_id).await;
+        send_modlog(
+            ctx,
+            modlog_channel,
+            &MessageBuilder::new()
+                .mention(&msg.author)
+                .push(format!(" purged **{}** messages in ", to_delete.len()))
+                .mention(&msg.channel_id)
+                .push(format!(" ({:?})", filter))
+                .build(),
+        )
+        .await;
+    }
+
+    Ok(())
+}
+
+#[command]
+#[only_in("guilds")]
+#[allowed_roles("Mods")]
+#[description = "Set the slowmode of this channel (max 6h), use `off` to disable it"]
+#[usage = "(*duration*|off)"]
+#[example = "30s"]
+#[example = "off"]
+#[num_args(1)]
+pub async fn slowmode(ctx: &Context, msg: &Message, mut args: Args) -> CommandResult {
+    let arg = args.single::<String>()?;
+    let duration = if arg == "off" {
+        Duration::zero()
+    } else {
+        match util::parse_duration(&arg) {
+            Some(d) if d >= Duration::zero() && d <= Duration::hours(6) => d,
+            _ => {
+                msg.reply(ctx, "Invalid duration, allowed are 0s up to 6h")
+                    .await?;
+                return Ok(());
+            }
+        }
+    };
+
+    msg.channel_id
+        .edit(&ctx, |c| c.slow_mode_rate(duration.num_seconds() as u64))
+        .await?;
+
+    if let Some(guild_id) = msg.guild_id {
+        let modlog_channel = modlog_channel(ctx, guild_id).await;
+        let setting = if duration.is_zero() {
+            "off".to_string()
+        } else {
+            format!("{}s", duration.num_seconds())
+        };
+        send_modlog(
+            ctx,
+            modlog_channel,
+            &MessageBuilder::new()
+                .mention(&msg.author)
+                .push(format!(" set the slowmode to **{}** in ", setting))
+                .mention(&msg.channel_id)
+                .build(),
+        )
+        .await;
+    }
+
+    std::mem::drop(
+        msg.react(ctx, ReactionType::Unicode("\u{2705}".to_string()))
+            .await,
+    );
+
+    Ok(())
+}
+
+#[command("lock")]
+#[only_in("guilds")]
+#[allowed_roles("Mods")]
+#[description = "Lock this channel or the whole server, optionally unlocking it again after the duration"]
+#[usage = "(server) (*duration*)"]
+#[example = "30m"]
+#[example = "server 1h"]
+#[max_args(2)]
+pub async fn lock_cmd(ctx: &Context, msg: &Message, args: Args) -> CommandResult {
+    let guild_id = msg.guild_id.ok_or("only in guilds")?;
+    let (server_wide, duration) = parse_lock_args(&args)?;
+    let channel_id = if server_wide {
+        None
+    } else {
+        Some(msg.channel_id)
+    };
+
+    let locked = lock(ctx, guild_id, channel_id, duration).await?;
+
+    let modlog_channel = modlog_channel(ctx, guild_id).await;
+    let mut description = MessageBuilder::new();
+    description.mention(&msg.author);
+    if server_wide {
+        description.push(format!(" locked the server (**{}** channels)", locked));
+    } else {
+        description.push(" locked ").mention(&msg.channel_id);
+    }
+    if let Some(duration) = duration {
+        description.push(format!(" for {}", util::humanize_duration(&duration)));
+    }
+    send_modlog(ctx, modlog_channel, &description.build()).await;
+
+    std::mem::drop(
+        msg.react(ctx, ReactionType::Unicode("\u{1f512}".to_string()))
+            .await,
+    );
+
+    Ok(())
+}
+
+#[command("unlock")]
+#[only_in("guilds")]
+#[allowed_roles("Mods")]
+#[description = "Unlock this channel or the whole server"]
+#[usage = "(server)"]
+#[example = "server"]
+#[max_args(1)]
+pub async fn unlock_cmd(ctx: &Context, msg: &Message, args: Args) -> CommandResult {
+    let guild_id = msg.guild_id.ok_or("only in guilds")?;
+    let server_wide = args.rest().trim() == "server";
+    let channel_id = if server_wide {
+        None
+    } else {
+        Some(msg.channel_id)
+    };
+
+    let pool = get_client(ctx).await?;
+    let unlocked = unlock(&ctx.http, &pool, guild_id, channel_id).await?;
+
+    let modlog_channel = modlog_channel(ctx, guild_id).await;
+    let mut description = MessageBuilder::new();
+    description.mention(&msg.author);
+    if server_wide {
+        description.push(format!(" unlocked the server (**{}** channels)", unlocked));
+    } else {
+        description.push(" unlocked ").mention(&msg.channel_id);
+    }
+    send_modlog(ctx, modlog_channel, &description.build()).await;
+
+    std::mem::drop(
+        msg.react(ctx, ReactionType::Unicode("\u{1f513}".to_string()))
+            .await,
+    );
+
+    Ok(())
+}
+
+fn parse_lock_args(args: &Args) -> Result<(bool, Option<Duration>), String> {
+    let mut server_wide = false;
+    let mut duration = None;
+
+    for arg in args.raw() {
+        if arg == "server" {
+            server_wide = true;
+        } else {
+            duration = Some(
+                util::parse_duration(arg)
+                    .filter(|d| *d > Duration::zero())
+                    .ok_or_else(|| format!("Invalid duration: {}", arg))?,
+            );
+        }
+    }
+
+    Ok((server_wide, duration))
+}
+
+async fn modlog_channel(ctx: &Context, guild_id: GuildId) -> Option<u64> {
+    let pool = get_client(ctx).await.ok()?;
+    let mut config = ServerConfig::get(&pool, *guild_id.as_u64() as i64)
+        .await
+        .ok()?;
+    serde_json::from_value::<Guild>(config.config.take())
+        .ok()?
+        .modlog_channel
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{bulk_deletable, PurgeFilter};
+    use chrono::{Duration, Utc};
+
+    #[test]
+    fn parse_purge_filter() {
+        assert_eq!(PurgeFilter::parse(""), Ok(PurgeFilter::All));
+        assert_eq!(PurgeFilter::parse("bots"), Ok(PurgeFilter::Bots));
+        assert_eq!(
+            PurgeFilter::parse("attachments"),
+            Ok(PurgeFilter::Attachments)
+        );
+        assert_eq!(
+            PurgeFilter::parse("contains:Free Nitro"),
+            Ok(PurgeFilter::Contains("free nitro".to_string()))
+        );
+        assert_eq!(
+            PurgeFilter::parse("<@!200009451292459011>"),
+            Ok(PurgeFilter::User(200_009_451_292_459_011))
+        );
+        assert!(PurgeFilter::parse("everything").is_err());
+    }
+
+    #[test]
+    fn fourteen_day_limit() {
+        let now = Utc::now();
+
+        assert!(bulk_deletable(now - Duration::days(13), now));
+        assert!(!bulk_deletable(now - Duration::days(14), now));
+    }
+}
@@ -0,0 +1,448 @@
+use super::config::Guild;
+use crate::models::server_config::ServerConfig;
+use crate::models::ticket::Ticket;
+use crate::models::ticket_message::TicketMessage;
+use crate::util;
+use crate::util::get_client;
+use serde::{Deserialize, Serialize};
+use serenity::http::AttachmentType;
+use serenity::prelude::*;
+use serenity::{
+    framework::standard::{macros::command, Args, CommandResult},
+    model::prelude::*,
+};
+use sqlx::postgres::PgPool;
+use std::borrow::Cow;
+use std::collections::HashMap;
+use std::time::Duration;
+use tracing::{debug, error};
+
+#[derive(Serialize, Deserialize, Debug, Default, Clone, PartialEq, Eq)]
+#[serde(default)]
+pub struct Config {
+    /// Staff channel new tickets are posted in, modmail is disabled without one
+    pub channel: Option<u64>,
+    /// Open a thread per ticket in the staff channel
+    pub threads: bool,
+    /// Sent to the user when a ticket is opened
+    pub greeting: Option<String>,
+}
+
+/// Relays a dm that is not a command to the staff, opening a ticket if needed
+pub async fn handle_dm(ctx: &Context, msg: &Message) {
+    // answers for collectors like the fav tag prompts are not meant for the staff
+    if msg.author.bot || util::is_awaited_reply(ctx, msg).await {
+        return;
+    }
+
+    let pool = match get_client(ctx).await {
+        Ok(pool) => pool,
+        Err(_) => return,
+    };
+
+    // the most recent ticket wins if the user has open tickets on several servers
+    let ticket = match Ticket::open_of_user(&pool, *msg.author.id.as_u64() as i64).await {
+        Ok(tickets) if !tickets.is_empty() => tickets[0].clone(),
+        Ok(_) => match open_ticket(ctx, &pool, msg).await {
+            Some(ticket) => ticket,
+            None => return,
+        },
+        Err(e) => {
+            error!(?e, "failed to load tickets");
+            return;
+        }
+    };
+
+    let mut content = msg.content.clone();
+    for attachment in &msg.attachments {
+        content.push('\n');
+        content.push_str(&attachment.url);
+    }
+
+    let relayed = ChannelId(ticket.channel_id as u64)
+        .send_message(ctx, |m| {
+            m.embed(|e| {
+                e.author(|a| {
+                    a.name(msg.author.tag())
+                        .icon_url(msg.author.static_avatar_url().unwrap_or_default())
+                })
+                .description(&content)
+                .footer(|f| f.text(format!("Ticket #{}", ticket.id)))
+                .color((0, 120, 220))
+            })
+        })
+        .await;
+
+    match relayed {
+        Ok(_) => {
+            if let Err(e) = TicketMessage::create(
+                &pool,
+                ticket.id,
+                *msg.author.id.as_u64() as i64,
+                false,
+                false,
+                &content,
+            )
+            .await
+            {
+                error!(?e, "failed to store ticket message");
+            }
+
+            std::mem::drop(
+                msg.react(ctx, ReactionType::Unicode("\u{2705}".to_string()))
+                    .await,
+            );
+        }
+        Err(e) => {
+            error!(?e, "failed to relay message to staff");
+            std::mem::drop(
+                msg.reply(
+                    ctx,
+                    "Could not forward your message, please try again later",
+                )
+                .await,
+            );
+        }
+    }
+}
+
+/// Opens a ticket on the server the user picks from the ones with modmail enabled
+async fn open_ticket(ctx: &Context, pool: &PgPool, msg: &Message) -> Option<Ticket> {
+    let mut servers = Vec::new();
+    for mut config in ServerConfig::list(pool).await.ok()? {
+        let g_cfg: Guild = match serde_json::from_value(config.config.take()) {
+            Ok(g_cfg) => g_cfg,
+            Err(_) => continue,
+        };
+        let guild_id = GuildId(config.server_id as u64);
+
+        if let Some(channel) = g_cfg.modmail.channel {
+            if guild_id.member(ctx, msg.author.id).await.is_ok() {
+                servers.push((guild_id, channel, g_cfg.modmail));
+            }
+        }
+    }
+
+    let (guild_id, channel, config) = match servers.len() {
+        0 => return None,
+        1 => servers.remove(0),
+        _ => {
+            let mut question = "Which server do you want to contact?\n".to_string();
+            for (i, (guild_id, _, _)) in servers.iter().enumerate() {
+                let name = guild_id.name(ctx).await.unwrap_or_else(|| "-".to_string());
+                question.push_str(&format!("{}: {}\n", i + 1, name));
+            }
+            std::mem::drop(msg.reply(ctx, question).await);
+
+            let answer =
+                util::await_reply(ctx, msg.channel_id, msg.author.id, Duration::from_secs(60))
+                    .await?;
+            match answer.content.trim().parse::<usize>() {
+                Ok(i) if i > 0 && i <= servers.len() => servers.remove(i - 1),
+                _ => {
+                    std::mem::drop(answer.reply(ctx, "Unknown server, message not sent").await);
+                    return None;
+                }
+            }
+        }
+    };
+
+    let mut ticket = Ticket::create(
+        pool,
+        *guild_id.as_u64() as i64,
+        *msg.author.id.as_u64() as i64,
+        channel as i64,
+    )
+    .await
+    .ok()?;
+
+    let opening = ChannelId(channel)
+        .send_message(ctx, |m| {
+            m.embed(|e| {
+                e.title(format!("Ticket #{} opened", ticket.id))
+                    .description(format!(
+                        "by <@{}>, answer with `$reply` or `$areply` to stay anonymous",
+                        msg.author.id
+                    ))
+                    .color((0, 220, 0))
+            })
+        })
+        .await
+        .ok()?;
+
+    if config.threads {
+        match ChannelId(channel)
+            .create_public_thread(ctx, opening.id, |t| {
+                t.name(format!("Ticket #{} {}", ticket.id, msg.author.name))
+            })
+            .await
+        {
+            Ok(thread) => {
+                if let Ok(moved) =
+                    Ticket::set_channel(pool, ticket.id, *thread.id.as_u64() as i64).await
+                {
+                    ticket = moved;
+                }
+            }
+            Err(e) => debug!(?e, "Could not create ticket thread"),
+        }
+    }
+
+    let guild_name = guild_id
+        .name(ctx)
+        .await
+        .unwrap_or_else(|| "the server".to_string());
+    let greeting = config.greeting.unwrap_or_else(|| {
+        format!(
+            "Your message was forwarded to the staff of {}, their answers will arrive here.",
+            guild_name
+        )
+    });
+    std::mem::drop(msg.channel_id.say(ctx, greeting).await);
+
+    Some(ticket)
+}
+
+/// Finds the ticket of the channel or thread, the first argument picks one by id if the
+/// channel has several
+async fn find_ticket(
+    pool: &PgPool,
+    channel_id: ChannelId,
+    args: &mut Args,
+) -> Result<Ticket, Box<dyn std::error::Error + Send + Sync>> {
+    let mut in_channel = Ticket::in_channel(pool, *channel_id.as_u64() as i64).await?;
+
+    if let Ok(id) = args.parse::<i64>() {
+        if let Some(pos) = in_channel.iter().position(|t| t.id == id) {
+            args.advance();
+            return Ok(in_channel.remove(pos));
+        }
+    }
+
+    if in_channel.len() == 1 {
+        Ok(in_channel.remove(0))
+    } else {
+        Err("Use this in a ticket thread or pass the id of a ticket of this channel".into())
+    }
+}
+
+async fn relay_reply(
+    ctx: &Context,
+    msg: &Message,
+    mut args: Args,
+    anonymous: bool,
+) -> CommandResult {
+    let guild_id = msg.guild_id.ok_or("only in guilds")?;
+    let pool = get_client(ctx).await?;
+    let ticket = find_ticket(&pool, msg.channel_id, &mut args).await?;
+
+    if !ticket.open {
+        msg.reply(ctx, "This ticket is closed").await?;
+        return Ok(());
+    }
+
+    let content = args.rest().trim();
+    if content.is_empty() {
+        msg.reply(ctx, "Nothing to send").await?;
+        return Ok(());
+    }
+
+    let guild_name = guild_id
+        .name(ctx)
+        .await
+        .unwrap_or_else(|| "the server".to_string());
+    let author_name = if anonymous {
+        format!("Staff of {}", guild_name)
+    } else {
+        msg.author_nick(ctx)
+            .await
+            .unwrap_or_else(|| msg.author.name.clone())
+    };
+
+    UserId(ticket.user_id as u64)
+        .create_dm_channel(ctx)
+        .await?
+        .send_message(ctx, |m| {
+            m.embed(|e| {
+                e.author(|a| {
+                    if !anonymous {
+                        a.icon_url(msg.author.static_avatar_url().unwrap_or_default());
+                    }
+                    a.name(&author_name)
+                })
+                .description(content)
+                .color((0, 120, 220))
+            })
+        })
+        .await?;
+
+    TicketMessage::create(
+        &pool,
+        ticket.id,
+        *msg.author.id.as_u64() as i64,
+        true,
+        anonymous,
+        content,
+    )
+    .await?;
+
+    msg.react(ctx, ReactionType::Unicode("\u{2705}".to_string()))
+        .await?;
+
+    Ok(())
+}
+
+#[command]
+#[description = "Answer a modmail ticket, the user sees your name"]
+#[usage = "*ticket_id (only in the staff channel)* *message*"]
+#[example = "12 We will look into it"]
+#[min_args(1)]
+pub async fn reply(ctx: &Context, msg: &Message, args: Args) -> CommandResult {
+    relay_reply(ctx, msg, args, false).await
+}
+
+#[command]
+#[description = "Answer a modmail ticket anonymously"]
+#[usage = "*ticket_id (only in the staff channel)* *message*"]
+#[example = "12 We will look into it"]
+#[min_args(1)]
+pub async fn areply(ctx: &Context, msg: &Message, args: Args) -> CommandResult {
+    relay_reply(ctx, msg, args, true).await
+}
+
+#[command]
+#[description = "Close a modmail ticket"]
+#[usage = "*ticket_id (only in the staff channel)*"]
+#[example = "12"]
+pub async fn close(ctx: &Context, msg: &Message, mut args: Args) -> CommandResult {
+    let guild_id = msg.guild_id.ok_or("only in guilds")?;
+    let pool = get_client(ctx).await?;
+    let ticket = find_ticket(&pool, msg.channel_id, &mut args).await?;
+
+    if Ticket::close(&pool, ticket.id, *msg.author.id.as_u64() as i64)
+        .await
+        .is_err()
+    {
+        msg.reply(ctx, "This ticket is already closed").await?;
+        return Ok(());
+    }
+
+    let guild_name = guild_id
+        .name(ctx)
+        .await
+        .unwrap_or_else(|| "the server".to_string());
+    if let Ok(dm_channel) = UserId(ticket.user_id as u64).create_dm_channel(ctx).await {
+        std::mem::drop(
+            dm_channel
+                .say(
+                    ctx,
+                    format!(
+                        "Your ticket on {} was closed. Write again to open a new one.",
+                        guild_name
+                    ),
+                )
+                .await,
+        );
+    }
+
+    msg.reply(ctx, format!("Closed ticket #{}", ticket.id))
+        .await?;
+
+    Ok(())
+}
+
+#[command]
+#[description = "List the open modmail tickets"]
+#[num_args(0)]
+pub async fn tickets(ctx: &Context, msg: &Message, _args: Args) -> CommandResult {
+    let guild_id = msg.guild_id.ok_or("only in guilds")?;
+    let pool = get_client(ctx).await?;
+    let tickets = Ticket::list_open(&pool, *guild_id.as_u64() as i64).await?;
+
+    let description = if tickets.is_empty() {
+        "No open tickets".to_string()
+    } else {
+        tickets
+            .iter()
+            .map(|t| {
+                format!(
+                    "#{} | <@{}> | <#{}> | {}",
+                    t.id,
+                    t.user_id,
+                    t.channel_id,
+                    t.created_at.format("%d.%m.%Y %H:%M")
+                )
+            })
+            .collect::<Vec<_>>()
+            .join("\n")
+    };
+
+    msg.channel_id
+        .send_message(ctx, |m| {
+            m.embed(|e| {
+                e.title("Open tickets")
+                    .description(description)
+                    .color((0, 120, 220))
+            })
+        })
+        .await?;
+
+    Ok(())
+}
+
+#[command]
+#[description = "Get the transcript of a modmail ticket as file"]
+#[usage = "*ticket_id (only in the staff channel)*"]
+#[example = "12"]
+pub async fn transcript(ctx: &Context, msg: &Message, mut args: Args) -> CommandResult {
+    let pool = get_client(ctx).await?;
+    let ticket = find_ticket(&pool, msg.channel_id, &mut args).await?;
+    let messages = TicketMessage::of_ticket(&pool, ticket.id).await?;
+
+    let mut names: HashMap<i64, String> = HashMap::new();
+    let mut content = format!(
+        "Ticket #{} opened {}\n\n",
+        ticket.id,
+        ticket.created_at.format("%d.%m.%Y %H:%M:%S")
+    );
+    for message in &messages {
+        if !names.contains_key(&message.author_id) {
+            let name = UserId(message.author_id as u64)
+                .to_user(ctx)
+                .await
+                .map_or_else(|_| message.author_id.to_string(), |u| u.tag());
+            names.insert(message.author_id, name);
+        }
+
+        content.push_str(&format!(
+            "[{}] {}{}{}: {}\n",
+            message.created_at.format("%d.%m.%Y %H:%M:%S"),
+            names[&message.author_id],
+            if message.from_staff { " (staff)" } else { "" },
+            if message.anonymous {
+                " (anonymous)"
+            } else {
+                ""
+            },
+            message.content
+        ));
+    }
+    if let Some(closed_at) = ticket.closed_at {
+        content.push_str(&format!(
+            "\nClosed {}\n",
+            closed_at.format("%d.%m.%Y %H:%M:%S")
+        ));
+    }
+
+    msg.channel_id
+        .send_message(ctx, |m| {
+            m.content(format!("Transcript of ticket #{}", ticket.id))
+                .add_file(AttachmentType::Bytes {
+                    data: Cow::from(content.into_bytes()),
+                    filename: format!("ticket_{}.txt", ticket.id),
+                })
+        })
+        .await?;
+
+    Ok(())
+}
@@ -1,111 +1,495 @@
-use serenity::collector::ReactionAction;
+use crate::models::poll::{NewPoll, Poll};
+use crate::util::{self, get_client};
+use chrono::{DateTime, Duration, Utc};
+use serenity::builder::{CreateComponents, CreateEmbed};
+use serenity::http::Http;
 use serenity::model::channel::ReactionType;
+use serenity::model::interactions::message_component::ButtonStyle;
 use serenity::prelude::*;
 use serenity::{
     framework::standard::{macros::command, Args, CommandResult},
-    futures::stream::StreamExt,
     model::channel::Message,
-    model::user::User,
+    model::id::ChannelId,
 };
-use std::collections::HashMap;
+use sqlx::postgres::PgPool;
+use std::convert::TryFrom;
 use std::sync::Arc;
-use std::time::Duration;
+use tokio::time::sleep;
+use tracing::error;
+
+/// Discord allows 20 different reactions on a message
+const MAX_ANSWERS: usize = 20;
+const MAX_LABEL_LENGTH: usize = 80;
+const MAX_TITLE_LENGTH: usize = 256;
+/// Keeps the results of all answers below the 4096 characters of an embed description
+const MAX_ANSWER_LENGTH: usize = 150;
+const BAR_WIDTH: usize = 10;
+
+fn default_duration() -> Duration {
+    Duration::hours(1)
+}
+
+fn max_duration() -> Duration {
+    Duration::days(30)
+}
+
+/// Keycaps for the first ten answers, then letters
+pub fn answer_emoji(answer: usize) -> String {
+    match answer {
+        0..=8 => format!("{}\u{fe0f}\u{20e3}", answer + 1),
+        9 => "\u{1f51f}".to_string(),
+        _ => u32::try_from(answer - 10)
+            .ok()
+            .and_then(|letter| letter.checked_add(0x1f1e6))
+            .and_then(std::char::from_u32)
+            .map(String::from)
+            .unwrap_or_default(),
+    }
+}
+
+pub fn emoji_answer(emoji: &str) -> Option<usize> {
+    (0..MAX_ANSWERS).find(|answer| answer_emoji(*answer) == emoji)
+}
+
+/// Votes on anonymous polls, the custom id carries the poll so they keep working after restarts
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PollButton {
+    pub poll_id: i64,
+    pub answer: usize,
+}
+
+impl PollButton {
+    pub fn custom_id(self) -> String {
+        format!("poll:vote:{}:{}", self.poll_id, self.answer)
+    }
+
+    pub fn parse(custom_id: &str) -> Option<Self> {
+        let mut parts = custom_id.splitn(4, ':');
+        if parts.next()? != "poll" || parts.next()? != "vote" {
+            return None;
+        }
+
+        Some(Self {
+            poll_id: parts.next()?.parse().ok()?,
+            answer: parts.next()?.parse().ok()?,
+        })
+    }
+}
+
+/// Reads `[--multi] [--anon] [--duration 2h] "question" "answer 1" "answer 2" ...`
+fn parse_poll(tokens: &[String], now: DateTime<Utc>) -> Result<NewPoll, String> {
+    let (mut multiple, mut anonymous, mut duration) = (false, false, default_duration());
+    let mut tokens = tokens.iter();
+    let question = loop {
+        match tokens.next().map(String::as_str) {
+            Some("--multi") => multiple = true,
+            Some("--anon") => anonymous = true,
+            Some("--duration") => {
+                let value = tokens.next().map(String::as_str).unwrap_or_default();
+                duration = util::parse_duration(value)
+                    .filter(|d| *d >= Duration::minutes(1) && *d <= max_duration())
+                    .ok_or_else(|| {
+                        format!("Expected a duration from 1m to 30d like 2h, got: {}", value)
+                    })?;
+            }
+            Some(question) => break question.to_string(),
+            None => return Err("The poll needs a question".to_string()),
+        }
+    };
+    let answers = tokens.cloned().collect::<Vec<_>>();
+
+    if !(2..=MAX_ANSWERS).contains(&answers.len()) {
+        return Err(format!("A poll needs 2 to {} answers", MAX_ANSWERS));
+    }
+    if question.chars().count() > MAX_TITLE_LENGTH {
+        return Err(format!(
+            "The question can be up to {} characters long",
+            MAX_TITLE_LENGTH
+        ));
+    }
+    if answers
+        .iter()
+        .any(|a| a.chars().count() > MAX_ANSWER_LENGTH)
+    {
+        return Err(format!(
+            "Answers can be up to {} characters long",
+            MAX_ANSWER_LENGTH
+        ));
+    }
+
+    Ok(NewPoll {
+        question,
+        answers,
+        multiple,
+        anonymous,
+        end_time: now + duration,
+    })
+}
+
+fn bar(votes: i64, total: i64) -> String {
+    let filled = if total > 0 {
+        // in i128 the product can not overflow
+        i128::from(votes) * BAR_WIDTH as i128 / i128::from(total)
+    } else {
+        0
+    };
+    let filled = usize::try_from(filled).unwrap_or_default().min(BAR_WIDTH);
+    format!(
+        "{}{}",
+        "\u{2588}".repeat(filled),
+        "\u{2591}".repeat(BAR_WIDTH - filled)
+    )
+}
+
+fn render_results(answers: &[String], counts: &[i64]) -> String {
+    let total = counts.iter().sum::<i64>();
+    answers
+        .iter()
+        .zip(counts)
+        .enumerate()
+        .map(|(i, (answer, votes))| {
+            format!(
+                "{} {}\n`{}` {} ({}%)",
+                answer_emoji(i),
+                answer,
+                bar(*votes, total),
+                votes,
+                if total > 0 { votes * 100 / total } else { 0 }
+            )
+        })
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+/// The answers with the most votes, empty without votes
+fn winners<'a>(answers: &'a [String], counts: &[i64]) -> Vec<&'a str> {
+    let most = counts.iter().copied().max().unwrap_or(0);
+    if most == 0 {
+        return Vec::new();
+    }
+
+    answers
+        .iter()
+        .zip(counts)
+        .filter(|(_, votes)| **votes == most)
+        .map(|(answer, _)| answer.as_str())
+        .collect()
+}
+
+fn poll_embed<'a>(e: &'a mut CreateEmbed, poll: &Poll, counts: &[i64]) -> &'a mut CreateEmbed {
+    let mode = match (poll.multiple, poll.anonymous) {
+        (false, false) => "Single choice",
+        (true, false) => "Multiple choice",
+        (false, true) => "Single choice, anonymous",
+        (true, true) => "Multiple choice, anonymous",
+    };
+    let state = if poll.closed {
+        "Closed".to_string()
+    } else {
+        format!("Closes {}", poll.end_time.format("%d.%m.%Y %H:%M"))
+    };
+
+    e.title(
+        poll.question
+            .chars()
+            .take(MAX_TITLE_LENGTH)
+            .collect::<String>(),
+    )
+    .description(format!(
+        "<@{}> asks\n\n{}",
+        poll.author_id,
+        render_results(&poll.answers, counts)
+    ))
+    .footer(|f| f.text(format!("Poll {} | {} | {}", poll.id, mode, state)))
+    .color((0, 120, 220))
+}
+
+fn vote_buttons<'a>(c: &'a mut CreateComponents, poll: &Poll) -> &'a mut CreateComponents {
+    for (row, answers) in poll.answers.chunks(5).enumerate() {
+        c.create_action_row(|r| {
+            for (i, answer) in answers.iter().enumerate() {
+                let answer_id = row * 5 + i;
+                r.create_button(|b| {
+                    b.style(ButtonStyle::Secondary)
+                        .emoji(ReactionType::Unicode(answer_emoji(answer_id)))
+                        .label(answer.chars().take(MAX_LABEL_LENGTH).collect::<String>())
+                        .custom_id(
+                            PollButton {
+                                poll_id: poll.id,
+                                answer: answer_id,
+                            }
+                            .custom_id(),
+                        )
+                });
+            }
+            r
+        });
+    }
+    c
+}
+
+/// Shows the current votes in the poll message, closed polls lose their buttons
+pub async fn update_poll_message(http: &Http, pool: &PgPool, poll: &Poll) {
+    let msg_id = match poll.msg_id {
+        Some(msg_id) => msg_id as u64,
+        None => return,
+    };
+    let counts = match poll.counts(pool).await {
+        Ok(counts) => counts,
+        Err(e) => {
+            error!(?e, "failed to count poll votes");
+            return;
+        }
+    };
+
+    if let Err(e) = ChannelId(poll.channel_id as u64)
+        .edit_message(http, msg_id, |m| {
+            m.embed(|e| poll_embed(e, poll, &counts));
+            if poll.closed {
+                m.components(|c| c);
+            }
+            m
+        })
+        .await
+    {
+        error!(?e, "failed to update poll message");
+    }
+}
+
+/// Closes the poll at its end time, restarted on startup
+pub async fn close_poll(http: Arc<Http>, pool: PgPool, poll: Poll) {
+    if let Ok(remaining) = poll.end_time.signed_duration_since(Utc::now()).to_std() {
+        sleep(remaining).await;
+    }
+
+    finish_poll(&http, &pool, poll.id).await;
+}
+
+/// Closes the poll and announces the result, false if it was closed already
+async fn finish_poll(http: &Http, pool: &PgPool, poll_id: i64) -> bool {
+    let poll = match Poll::close(pool, poll_id).await {
+        Ok(Some(poll)) => poll,
+        Ok(None) => return false,
+        Err(e) => {
+            error!(?e, "failed to close poll");
+            return false;
+        }
+    };
+    update_poll_message(http, pool, &poll).await;
+
+    let counts = poll.counts(pool).await.unwrap_or_default();
+    let result = match winners(&poll.answers, &counts)[..] {
+        [] => "nobody voted".to_string(),
+        [winner] => format!("**{}** won", winner),
+        ref tied => format!("**{}** tied", tied.join("**, **")),
+    };
+    // the answers are user input, they must not ping anyone
+    std::mem::drop(
+        ChannelId(poll.channel_id as u64)
+            .send_message(http, |m| {
+                m.content(format!("Poll {} closed: {}", poll.id, result))
+                    .allowed_mentions(|am| am.empty_parse())
+            })
+            .await,
+    );
+
+    true
+}
 
 #[command]
-#[description = "Create an adhoc poll"]
-#[usage = "*\"question\"* *\"answer_1\"* *\"answer_2\"*"]
+#[description = "Create a poll, single choice and open for an hour by default. `--multi` allows votes for several answers, `--anon` votes with buttons instead of reactions and `--duration` sets how long the poll is open (up to 30d)."]
+#[usage = "*[--multi]* *[--anon]* *[--duration 2h]* *\"question\"* *\"answer_1\"* *\"answer_2\"*"]
 #[example = "\"Do you freeze bread?\" \"Yes\" \"No\""]
+#[example = "--multi --duration 1d \"Which evenings work for you?\" \"Monday\" \"Tuesday\" \"Friday\""]
 #[only_in("guilds")]
+#[sub_commands(poll_close)]
 async fn poll(ctx: &Context, msg: &Message, mut args: Args) -> CommandResult {
-    let question = args.single::<String>()?;
-    let answers = args
-        .iter()
-        .filter_map(|a| a.map(Some).unwrap_or(None))
-        .collect::<Vec<String>>();
+    let server_id = *msg.guild_id.ok_or("Polls only exist on servers")?.as_u64() as i64;
+    let tokens = args
+        .iter::<String>()
+        .quoted()
+        .filter_map(Result::ok)
+        .collect::<Vec<_>>();
+    let new = match parse_poll(&tokens, Utc::now()) {
+        Ok(new) => new,
+        Err(e) => {
+            msg.reply(ctx, e).await?;
+            return Ok(());
+        }
+    };
+    let pool = get_client(ctx).await?;
 
-    if answers.len() > 9 {
-        return Err("Only up to 9 answers are allowed".into());
-    }
-
-    //TODO: react with all possible answer reactions
-    let question_msg = msg
+    let poll = Poll::create(
+        &pool,
+        server_id,
+        *msg.channel_id.as_u64() as i64,
+        *msg.author.id.as_u64() as i64,
+        &new,
+    )
+    .await?;
+    let counts = vec![0; poll.answers.len()];
+    let poll_msg = match msg
         .channel_id
-        .say(&ctx, ask_question(&msg.author, &question, &answers).await)
-        .await?;
+        .send_message(&ctx, |m| {
+            m.embed(|e| poll_embed(e, &poll, &counts));
+            if poll.anonymous {
+                m.components(|c| vote_buttons(c, &poll));
+            }
+            m
+        })
+        .await
+    {
+        Ok(poll_msg) => poll_msg,
+        Err(e) => {
+            // nobody can vote on a poll that was never posted
+            Poll::delete(&pool, poll.id).await?;
+            return Err(e.into());
+        }
+    };
+    let poll = Poll::set_msg_id(&pool, poll.id, *poll_msg.id.as_u64() as i64).await?;
 
     std::mem::drop(msg.delete(ctx).await);
 
-    let collector = question_msg
-        .await_reactions(&ctx)
-        .timeout(Duration::from_secs(60))
-        .await;
+    if !poll.anonymous {
+        for answer in 0..poll.answers.len() {
+            poll_msg
+                .react(ctx, ReactionType::Unicode(answer_emoji(answer)))
+                .await?;
+        }
+    }
+
+    tokio::spawn(close_poll(ctx.http.clone(), pool, poll));
 
-    let reactions: Vec<Arc<ReactionAction>> = collector.collect().await;
+    Ok(())
+}
 
-    let num_reactions = reactions
-        .iter()
-        .filter_map(reaction_to_num)
-        .filter(|&num| num <= answers.len())
-        .map(|num| num - 1) // reduce num to prevent index out of bounds
-        .fold(HashMap::new(), |mut acc, x| {
-            let entry = acc.entry(x).or_insert(0_usize);
-            *entry += 1;
-
-            acc
-        });
+#[command("close")]
+#[description = "Closes a poll before its end, only who asked and the mods can"]
+#[num_args(1)]
+#[usage = "*poll id*"]
+#[example = "12"]
+#[only_in("guilds")]
+async fn poll_close(ctx: &Context, msg: &Message, mut args: Args) -> CommandResult {
+    let guild_id = msg.guild_id.ok_or("Polls only exist on servers")?;
+    let id = args.single::<i64>()?;
+    let pool = get_client(ctx).await?;
 
-    let poll_results = num_reactions
-        .into_iter()
-        .map(|(key, val)| (answers[key].clone(), val))
-        .collect::<HashMap<_, _>>();
+    let poll = match Poll::get(&pool, *guild_id.as_u64() as i64, id).await {
+        Ok(poll) => poll,
+        Err(_e) => {
+            msg.reply(ctx, "There is no such poll").await?;
+            return Ok(());
+        }
+    };
 
-    msg.channel_id
-        .say(&ctx, render_poll_results(&question, &poll_results))
-        .await?;
+    if poll.author_id != *msg.author.id.as_u64() as i64 {
+        let member = msg.member(ctx).await?;
+        let roles = guild_id.roles(ctx).await?;
+        let is_mod = member
+            .roles
+            .iter()
+            .any(|r| roles.get(r).map_or(false, |role| role.name == "Mods"));
+        if !is_mod {
+            msg.reply(ctx, "Only who asked and the mods can close the poll")
+                .await?;
+            return Ok(());
+        }
+    }
+
+    if !finish_poll(&ctx.http, &pool, poll.id).await {
+        msg.reply(ctx, "The poll is closed already").await?;
+    }
 
     Ok(())
 }
 
-async fn ask_question(user: &User, question: &str, answers: &[String]) -> String {
-    let mut rendered = format!("{} asks: {}\nPossible answers:\n", user.name, question);
-    for (i, a) in answers.iter().enumerate() {
-        rendered.push_str(&format!("{} | {}\n", i + 1, a));
-    }
-    rendered
-}
-
-fn reaction_to_num(reaction: &Arc<ReactionAction>) -> Option<usize> {
-    let reaction = reaction.as_inner_ref();
-    match &reaction.emoji {
-        ReactionType::Unicode(s) => match s.as_ref() {
-            "1\u{fe0f}\u{20e3}" => Some(1),
-            "2\u{fe0f}\u{20e3}" => Some(2),
-            "3\u{fe0f}\u{20e3}" => Some(3),
-            "4\u{fe0f}\u{20e3}" => Some(4),
-            "5\u{fe0f}\u{20e3}" => Some(5),
-            "6\u{fe0f}\u{20e3}" => Some(6),
-            "7\u{fe0f}\u{20e3}" => Some(7),
-            "8\u{fe0f}\u{20e3}" => Some(8),
-            "9\u{fe0f}\u{20e3}" => Some(9),
-            _ => None,
-        },
-        _ => None,
-    }
-}
-
-fn render_poll_results(question: &str, poll_results: &HashMap<String, usize>) -> String {
-    let mut rendered = String::new();
-    rendered.push_str(&format!("On the question of: {}\n", question));
-    rendered.push_str("people answered: \n");
-    for (answer, votes) in poll_results {
-        rendered.push_str(&format!("{} with {} vote(s)!\n", answer, votes));
-    }
-    if let Some(winner) = poll_results.iter().max_by_key(|&(_, v)| v) {
-        rendered.push_str(&format!(
-            "**{}** won with **{}** vote(s)!",
-            winner.0, winner.1
-        ));
+#[cfg(test)]
+mod tests {
+    use super::{
+        answer_emoji, emoji_answer, parse_poll, render_results, winners, PollButton, MAX_ANSWERS,
+        MAX_ANSWER_LENGTH,
+    };
+    use chrono::{Duration, TimeZone, Utc};
+
+    fn tokens(input: &[&str]) -> Vec<String> {
+        input.iter().map(ToString::to_string).collect()
+    }
+
+    #[test]
+    fn poll_arguments() {
+        let now = Utc.ymd(2021, 5, 1).and_hms(12, 0, 0);
+        let poll = parse_poll(&tokens(&["Bread?", "Yes", "No"]), now).unwrap();
+        assert_eq!(poll.question, "Bread?");
+        assert_eq!(poll.answers, tokens(&["Yes", "No"]));
+        assert!(!poll.multiple && !poll.anonymous);
+        assert_eq!(poll.end_time, now + Duration::hours(1));
+
+        let poll = parse_poll(
+            &tokens(&[
+                "--anon",
+                "--duration",
+                "2d",
+                "--multi",
+                "Days?",
+                "Mo",
+                "Tu",
+                "Fr",
+            ]),
+            now,
+        )
+        .unwrap();
+        assert!(poll.multiple && poll.anonymous);
+        assert_eq!(poll.end_time, now + Duration::days(2));
+        assert_eq!(poll.answers.len(), 3);
+
+        assert!(parse_poll(&tokens(&["Bread?", "Yes"]), now).is_err());
+        assert!(parse_poll(&tokens(&["--duration", "60d", "Q", "A", "B"]), now).is_err());
+        assert!(parse_poll(&tokens(&["--multi"]), now).is_err());
+        let many = (0..21).map(|i| i.to_string()).collect::<Vec<_>>();
+        assert!(parse_poll(&[vec!["Q".to_string()], many].concat(), now).is_err());
+        let long = "a".repeat(MAX_ANSWER_LENGTH + 1);
+        assert!(parse_poll(&tokens(&["Q", "A", &long]), now).is_err());
+        assert!(parse_poll(&tokens(&[&long.repeat(2), "A", "B"]), now).is_err());
+
+        // the longest answers still fit in the embed
+        let answers = vec!["a".repeat(MAX_ANSWER_LENGTH); MAX_ANSWERS];
+        let counts = vec![1_000_000; MAX_ANSWERS];
+        assert!(render_results(&answers, &counts).chars().count() < 4000);
     }
 
-    rendered
+    #[test]
+    fn answer_emojis() {
+        assert_eq!(answer_emoji(0), "1\u{fe0f}\u{20e3}");
+        assert_eq!(answer_emoji(9), "\u{1f51f}");
+        assert_eq!(answer_emoji(10), "\u{1f1e6}");
+        for answer in 0..20 {
+            assert_eq!(emoji_answer(&answer_emoji(answer)), Some(answer));
+        }
+        assert_eq!(emoji_answer("\u{1f4d7}"), None);
+
+        let button = PollButton {
+            poll_id: 12,
+            answer: 3,
+        };
+        assert_eq!(PollButton::parse(&button.custom_id()), Some(button));
+        assert_eq!(PollButton::parse("fav:delete:12"), None);
+    }
+
+    #[test]
+    fn results() {
+        let answers = tokens(&["Yes", "No", "Maybe"]);
+        assert_eq!(
+            render_results(&answers[..2], &[3, 1]),
+            format!(
+                "1\u{fe0f}\u{20e3} Yes\n`{}{}` 3 (75%)\n2\u{fe0f}\u{20e3} No\n`{}{}` 1 (25%)",
+                "\u{2588}".repeat(7),
+                "\u{2591}".repeat(3),
+                "\u{2588}".repeat(2),
+                "\u{2591}".repeat(8)
+            )
+        );
+        assert_eq!(winners(&answers, &[0, 0, 0]), Vec::<&str>::new());
+        assert_eq!(winners(&answers, &[2, 5, 1]), vec!["No"]);
+        assert_eq!(winners(&answers, &[4, 1, 4]), vec!["Yes", "Maybe"]);
+    }
 }
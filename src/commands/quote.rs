@@ -1,8 +1,10 @@
 use crate::util;
 use crate::OptOut;
+use regex::Regex;
+use serenity::builder::CreateEmbed;
 use serenity::futures::stream::StreamExt;
-use serenity::model::channel::Attachment;
 use serenity::model::id::ChannelId;
+use serenity::model::interactions::message_component::ButtonStyle;
 use serenity::prelude::*;
 use serenity::{
     framework::standard::{macros::command, Args, CommandResult},
@@ -11,18 +13,135 @@ use serenity::{
 use std::time::Duration;
 use tracing::{debug, trace};
 
+/// How many following messages `+count` can add to a quote
+const MAX_FOLLOWING: usize = 9;
+/// Discord allows this many embeds per message
+const MAX_EMBEDS: usize = 10;
+const MAX_DESCRIPTION: usize = 4096;
+/// Discord limits the text of all embeds of a message together
+const MAX_EMBEDS_LENGTH: usize = 6000;
+
+/// The message a quote starts with
+#[derive(Debug, PartialEq, Eq)]
+enum Target {
+    Link {
+        server_id: u64,
+        channel_id: u64,
+        msg_id: u64,
+    },
+    /// Message id in the current channel
+    Id(u64),
+    /// The message the command replies to
+    Reply,
+}
+
+/// Parses `[message link or id] [+count]`
+fn parse_args(regex: &Regex, input: &str) -> Result<(Target, usize), String> {
+    let (mut target, mut following) = (Target::Reply, 0);
+
+    for word in input.split_whitespace() {
+        if let Some(count) = word.strip_prefix('+') {
+            following = count
+                .parse::<usize>()
+                .map_err(|_| format!("Not a number of messages: {}", word))?
+                .min(MAX_FOLLOWING);
+        } else if target != Target::Reply {
+            return Err("Only one message can start a quote".to_string());
+        } else if let Ok(msg_id) = word.parse::<u64>() {
+            target = Target::Id(msg_id);
+        } else {
+            let (server_id, channel_id, msg_id) = util::parse_message_link(regex, word)?;
+            target = Target::Link {
+                server_id,
+                channel_id,
+                msg_id,
+            };
+        }
+    }
+
+    Ok((target, following))
+}
+
+/// Cuts the text to at most `max` chars, marking the cut
+fn truncate(text: &str, max: usize) -> String {
+    if text.chars().count() <= max {
+        text.to_string()
+    } else if max == 0 {
+        String::new()
+    } else {
+        let mut cut = text.chars().take(max - 1).collect::<String>();
+        cut.push('…');
+        cut
+    }
+}
+
+/// The characters of the embed that count towards `MAX_EMBEDS_LENGTH`
+fn embed_length(embed: &Embed) -> usize {
+    let count = |text: &Option<String>| text.as_ref().map_or(0, |t| t.chars().count());
+    count(&embed.title)
+        + count(&embed.description)
+        + embed.author.as_ref().map_or(0, |a| a.name.chars().count())
+        + embed.footer.as_ref().map_or(0, |f| f.text.chars().count())
+        + embed
+            .fields
+            .iter()
+            .map(|f| f.name.chars().count() + f.value.chars().count())
+            .sum::<usize>()
+}
+
+fn link(server_id: u64, quoted: &Message) -> String {
+    format!(
+        "https://discord.com/channels/{}/{}/{}",
+        server_id, quoted.channel_id, quoted.id
+    )
+}
+
 #[command]
-#[description = "Quote a message"]
-#[usage = "*message_link*"]
+#[description = "Quote a message, a message id is looked up in the current channel. `+count` adds up to 9 following messages of the same author. Without a message the message you reply to is quoted."]
+#[usage = "*message_link or id* *+count*"]
 #[example = "https://discord.com/channels/_/_/_"]
+#[example = "https://discord.com/channels/_/_/_ +3"]
 #[only_in("guilds")]
 pub async fn quote(ctx: &Context, msg: &Message, args: Args) -> CommandResult {
     check_optout(ctx, msg, *msg.author.id.as_u64()).await?;
 
     let regex = crate::MESSAGE_REGEX.get().expect("Regex not initialized");
+    let current_server_id = *msg.guild_id.ok_or("Quotes only work on servers")?.as_u64();
 
-    let (quote_server_id, quote_channel_id, quote_msg_id) =
-        util::parse_message_link(regex, args.rest())?;
+    let (quote_server_id, quote_channel_id, quote_msg_id, following) =
+        match parse_args(regex, args.rest())? {
+            (
+                Target::Link {
+                    server_id,
+                    channel_id,
+                    msg_id,
+                },
+                following,
+            ) => (server_id, channel_id, msg_id, following),
+            (Target::Id(msg_id), following) => (
+                current_server_id,
+                *msg.channel_id.as_u64(),
+                msg_id,
+                following,
+            ),
+            (Target::Reply, following) => match msg
+                .message_reference
+                .as_ref()
+                .and_then(|r| Some((r.channel_id, r.message_id?)))
+            {
+                Some((channel_id, msg_id)) => (
+                    current_server_id,
+                    *channel_id.as_u64(),
+                    *msg_id.as_u64(),
+                    following,
+                ),
+                None => {
+                    msg.reply(ctx, "Link a message or reply to the message to quote")
+                        .await?;
+                    return Ok(());
+                }
+            },
+        };
 
     if let Ok(quoted_msg) = ChannelId(quote_channel_id)
         .message(&ctx.http, quote_msg_id)
@@ -30,43 +149,136 @@ pub async fn quote(ctx: &Context, msg: &Message, args: Args) -> CommandResult {
     {
         check_optout(ctx, msg, *quoted_msg.author.id.as_u64()).await?;
 
-        let channel_name = quoted_msg
+        // consecutive messages of the same author
+        let mut quoted = vec![quoted_msg];
+        if following > 0 {
+            let mut next = ChannelId(quote_channel_id)
+                .messages(&ctx.http, |r| r.after(quote_msg_id).limit(following as u64))
+                .await?;
+            next.sort_by_key(|m| m.id);
+            let author_id = quoted[0].author.id;
+            quoted.extend(next.into_iter().take_while(|m| m.author.id == author_id));
+        }
+        let first = &quoted[0];
+
+        // the message the quote replies to, unless its author opted out
+        let replied_to = match (&first.referenced_message, &first.message_reference) {
+            (Some(replied_to), _) => Some(*replied_to.clone()),
+            (
+                None,
+                Some(MessageReference {
+                    channel_id,
+                    message_id: Some(message_id),
+                    ..
+                }),
+            ) => channel_id.message(&ctx.http, *message_id).await.ok(),
+            _ => None,
+        };
+        let hidden = match &replied_to {
+            Some(replied_to) => is_opted_out(ctx, *replied_to.author.id.as_u64()).await,
+            None => false,
+        };
+        let replied_to = if hidden { None } else { replied_to };
+
+        let channel_name = first
             .channel_id
             .name(&ctx)
             .await
             .unwrap_or_else(|| "-".to_string());
+
+        // the quote gets the text it needs first, the reply and the copied embeds the rest
+        let footer = format!(
+            "{} (UTC) | #{} | Quoted by: {}",
+            &first.timestamp.format("%d.%m.%Y, %H:%M:%S"),
+            channel_name,
+            &msg.author.name
+        );
+        let mut budget = MAX_EMBEDS_LENGTH
+            .saturating_sub(first.author.name.chars().count() + footer.chars().count());
+        let description = truncate(
+            &quoted
+                .iter()
+                .map(|m| m.content.as_str())
+                .filter(|c| !c.is_empty())
+                .collect::<Vec<_>>()
+                .join("\n"),
+            budget.min(MAX_DESCRIPTION),
+        );
+        budget = budget.saturating_sub(description.chars().count());
+
+        let mut embeds = Vec::new();
+        if let Some(replied_to) = &replied_to {
+            let name = format!("↪ {}", &replied_to.author.name);
+            budget = budget.saturating_sub(name.chars().count());
+            let reply = truncate(&replied_to.content, budget.min(MAX_DESCRIPTION));
+            budget = budget.saturating_sub(reply.chars().count());
+
+            let mut embed = CreateEmbed::default();
+            embed
+                .author(|a| {
+                    a.name(name)
+                        .icon_url(&replied_to.author.static_avatar_url().unwrap_or_default())
+                })
+                .color((150, 150, 150))
+                .description(reply);
+            embeds.push(embed);
+        }
+
+        let mut embed = CreateEmbed::default();
+        embed
+            .author(|a| {
+                a.name(&first.author.name)
+                    .icon_url(&first.author.static_avatar_url().unwrap_or_default())
+            })
+            .color((0, 120, 220))
+            .description(description)
+            .footer(|f| f.text(footer));
+
+        // stickers only show when there is no image
+        let image = quoted
+            .iter()
+            .flat_map(|m| m.attachments.iter())
+            .find(|a| a.width.is_some())
+            .map(|a| a.url.clone())
+            .or_else(|| {
+                quoted
+                    .iter()
+                    .flat_map(|m| m.sticker_items.iter())
+                    .find_map(|s| s.image_url())
+            });
+        if let Some(image) = image {
+            embed.image(image);
+        }
+        embeds.push(embed);
+
+        // the embeds of the original, like link previews, as long as they fit
+        for copied in quoted.iter().flat_map(|m| m.embeds.iter()) {
+            let length = embed_length(copied);
+            if embeds.len() < MAX_EMBEDS && length <= budget {
+                budget -= length;
+                embeds.push(CreateEmbed::from(copied.clone()));
+            }
+        }
+
         let bot_msg = msg
             .channel_id
             .send_message(&ctx.http, |m| {
-                m.embed(|e| {
-                    let mut embed = e
-                        .author(|a| {
-                            a.name(&quoted_msg.author.name).icon_url(
-                                &quoted_msg.author.static_avatar_url().unwrap_or_default(),
-                            )
-                        })
-                        .color((0, 120, 220))
-                        .description(&quoted_msg.content)
-                        .footer(|f| {
-                            f.text(&format!(
-                                "{} (UTC) | #{} | Quoted by: {}",
-                                &quoted_msg.timestamp.format("%d.%m.%Y, %H:%M:%S"),
-                                channel_name,
-                                &msg.author.name
-                            ))
+                m.add_embeds(embeds).components(|c| {
+                    c.create_action_row(|r| {
+                        r.create_button(|b| {
+                            b.style(ButtonStyle::Link)
+                                .label("Jump to message")
+                                .url(link(quote_server_id, first))
                         });
-
-                    if let Some(image) = quoted_msg
-                        .attachments
-                        .iter()
-                        .cloned()
-                        .filter(|a| a.width.is_some())
-                        .collect::<Vec<Attachment>>()
-                        .first()
-                    {
-                        embed = embed.image(&image.url);
-                    }
-                    embed
+                        if let Some(replied_to) = &replied_to {
+                            r.create_button(|b| {
+                                b.style(ButtonStyle::Link)
+                                    .label("Jump to reply")
+                                    .url(link(quote_server_id, replied_to))
+                            });
+                        }
+                        r
+                    })
                 })
             })
             .await?;
@@ -116,9 +328,8 @@ pub async fn quote(ctx: &Context, msg: &Message, args: Args) -> CommandResult {
     Ok(())
 }
 
-async fn check_optout(ctx: &Context, msg: &Message, id: u64) -> CommandResult {
-    if ctx
-        .data
+async fn is_opted_out(ctx: &Context, id: u64) -> bool {
+    ctx.data
         .read()
         .await
         .get::<OptOut>()
@@ -127,7 +338,10 @@ async fn check_optout(ctx: &Context, msg: &Message, id: u64) -> CommandResult {
         .await
         .set
         .contains(&id)
-    {
+}
+
+async fn check_optout(ctx: &Context, msg: &Message, id: u64) -> CommandResult {
+    if is_opted_out(ctx, id).await {
         msg.channel_id
             .send_message(&ctx.http, |m| {
                 m.content("OptOut is used by you or the quoted")
@@ -141,3 +355,41 @@ async fn check_optout(ctx: &Context, msg: &Message, id: u64) -> CommandResult {
         Ok(())
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::{parse_args, truncate, Target, MAX_FOLLOWING};
+    use regex::Regex;
+
+    #[test]
+    fn quote_args() {
+        let regex =
+            Regex::new(r"https://(?:discord.com|discordapp.com)/channels/(\d+)/(\d+)/(\d+)")
+                .unwrap();
+
+        assert_eq!(parse_args(&regex, ""), Ok((Target::Reply, 0)));
+        assert_eq!(parse_args(&regex, "+2"), Ok((Target::Reply, 2)));
+        assert_eq!(parse_args(&regex, "123"), Ok((Target::Id(123), 0)));
+        assert_eq!(
+            parse_args(&regex, "https://discord.com/channels/1/2/3 +50"),
+            Ok((
+                Target::Link {
+                    server_id: 1,
+                    channel_id: 2,
+                    msg_id: 3
+                },
+                MAX_FOLLOWING
+            ))
+        );
+        assert!(parse_args(&regex, "1 2").is_err());
+        assert!(parse_args(&regex, "+x").is_err());
+        assert!(parse_args(&regex, "hello").is_err());
+    }
+
+    #[test]
+    fn truncated() {
+        assert_eq!(truncate("äöü", 3), "äöü");
+        assert_eq!(truncate("äöüß", 3), "äö…");
+        assert_eq!(truncate("äöüß", 0), "");
+    }
+}
@@ -0,0 +1,895 @@
+//! Dice notation for `roll`
+//!
+//! `NdS` rolls N dice with S sides, `d%` is a d100 and `dF` a fate die showing -1, 0 or +1.
+//! Modifiers follow the dice in this order: `r`/`ro` rerolls (once), `!` explodes, `kh`/`kl`
+//! keep and `dh`/`dl` drop the highest or lowest dice, and a comparison like `>=7` counts
+//! successes instead of summing. A comparison right after `!` or `r` decides which faces
+//! explode or get rerolled. Rolls combine with `+ - * /` and parentheses.
+use nom::{
+    branch::alt,
+    bytes::complete::tag,
+    character::complete::{char, digit1, multispace0, multispace1, one_of},
+    combinator::{all_consuming, map, map_res, opt, recognize, value},
+    multi::{many0, separated_list1},
+    sequence::{delimited, pair, preceded},
+    IResult,
+};
+use rand::Rng;
+use std::fmt;
+
+pub const MAX_INPUT_LENGTH: usize = 200;
+/// Dice of all rolls in one expression
+pub const MAX_DICE: u32 = 100;
+pub const MAX_SIDES: u32 = 1000;
+pub const MAX_NUMBER: i64 = 1_000_000;
+/// Nested parentheses, deeper rolls are not parsed at all
+const MAX_DEPTH: usize = 20;
+/// Rerolls and explosions of a single die
+const MAX_EXTRA_ROLLS: u32 = 10;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Sides {
+    Number(u32),
+    Percent,
+    Fate,
+}
+
+impl Sides {
+    fn range(self) -> (i64, i64) {
+        match self {
+            Self::Number(sides) => (1, i64::from(sides)),
+            Self::Percent => (1, 100),
+            Self::Fate => (-1, 1),
+        }
+    }
+
+    fn roll<R: Rng + ?Sized>(self, rng: &mut R) -> i64 {
+        let (min, max) = self.range();
+        rng.gen_range(min..=max)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Compare {
+    Eq,
+    Lt,
+    Le,
+    Gt,
+    Ge,
+}
+
+impl fmt::Display for Compare {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Self::Eq => "=",
+            Self::Lt => "<",
+            Self::Le => "<=",
+            Self::Gt => ">",
+            Self::Ge => ">=",
+        })
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Condition {
+    pub compare: Compare,
+    pub value: i64,
+}
+
+impl Condition {
+    pub const fn matches(self, roll: i64) -> bool {
+        match self.compare {
+            Compare::Eq => roll == self.value,
+            Compare::Lt => roll < self.value,
+            Compare::Le => roll <= self.value,
+            Compare::Gt => roll > self.value,
+            Compare::Ge => roll >= self.value,
+        }
+    }
+
+    /// Written without `=` after `!` and `r`
+    fn fmt_point(self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.compare == Compare::Eq && self.value >= 0 {
+            write!(f, "{}", self.value)
+        } else {
+            write!(f, "{}{}", self.compare, self.value)
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Keep {
+    Highest(u32),
+    Lowest(u32),
+    DropHighest(u32),
+    DropLowest(u32),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Reroll {
+    pub condition: Condition,
+    pub once: bool,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Dice {
+    pub count: u32,
+    pub sides: Sides,
+    pub reroll: Option<Reroll>,
+    pub explode: Option<Condition>,
+    pub keep: Option<Keep>,
+    pub success: Option<Condition>,
+}
+
+/// A single rolled die
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct Die {
+    pub value: i64,
+    pub rerolled: bool,
+    pub exploded: bool,
+    pub dropped: bool,
+}
+
+impl Die {
+    pub const fn counted(self) -> bool {
+        !self.rerolled && !self.dropped
+    }
+}
+
+impl Dice {
+    /// `!` without a comparison explodes on the highest face
+    fn default_explode(sides: Sides) -> Condition {
+        Condition {
+            compare: Compare::Eq,
+            value: sides.range().1,
+        }
+    }
+
+    fn validate(&self) -> Result<(), String> {
+        if self.count == 0 {
+            return Err("Roll at least one die".to_string());
+        }
+        if let Sides::Number(sides) = self.sides {
+            if sides == 0 {
+                return Err("Dice need at least one side".to_string());
+            }
+            if sides > MAX_SIDES {
+                return Err(format!("Dice can have at most {} sides", MAX_SIDES));
+            }
+        }
+
+        let (min, max) = self.sides.range();
+        let every_face = |condition: Condition| (min..=max).all(|face| condition.matches(face));
+        if self.explode.map_or(false, every_face) {
+            return Err("Exploding on every face would never stop".to_string());
+        }
+        if self
+            .reroll
+            .map_or(false, |r| !r.once && every_face(r.condition))
+        {
+            return Err("Rerolling every face would never stop".to_string());
+        }
+
+        Ok(())
+    }
+
+    /// The value is the sum of the counted dice, or the number of successes among them
+    pub fn roll<R: Rng + ?Sized>(&self, rng: &mut R) -> (i64, Vec<Die>) {
+        let mut rolls = Vec::new();
+        for _ in 0..self.count {
+            let mut extra = 0;
+            loop {
+                let mut value = self.sides.roll(rng);
+                if let Some(reroll) = self.reroll {
+                    while reroll.condition.matches(value) && extra < MAX_EXTRA_ROLLS {
+                        rolls.push(Die {
+                            value,
+                            rerolled: true,
+                            ..Die::default()
+                        });
+                        value = self.sides.roll(rng);
+                        extra += 1;
+                        if reroll.once {
+                            break;
+                        }
+                    }
+                }
+
+                let exploded =
+                    extra < MAX_EXTRA_ROLLS && self.explode.map_or(false, |c| c.matches(value));
+                rolls.push(Die {
+                    value,
+                    exploded,
+                    ..Die::default()
+                });
+                if !exploded {
+                    break;
+                }
+                extra += 1;
+            }
+        }
+
+        if let Some(keep) = self.keep {
+            let mut live = (0..rolls.len())
+                .filter(|i| !rolls[*i].rerolled)
+                .collect::<Vec<_>>();
+            live.sort_by_key(|i| rolls[*i].value);
+            let n = live.len();
+            let dropped = match keep {
+                Keep::Highest(k) => &live[..n.saturating_sub(k as usize)],
+                Keep::Lowest(k) => &live[n.min(k as usize)..],
+                Keep::DropHighest(k) => &live[n - n.min(k as usize)..],
+                Keep::DropLowest(k) => &live[..n.min(k as usize)],
+            };
+            for i in dropped {
+                rolls[*i].dropped = true;
+            }
+        }
+
+        let counted = rolls.iter().filter(|d| d.counted());
+        let value = match self.success {
+            Some(success) => counted.filter(|d| success.matches(d.value)).count() as i64,
+            None => counted.map(|d| d.value).sum(),
+        };
+
+        (value, rolls)
+    }
+
+    /// Dropped and rerolled dice are struck through, successes are bold
+    fn render_rolls(&self, rolls: &[Die]) -> String {
+        rolls
+            .iter()
+            .map(|die| {
+                let mut face = match (self.sides, die.value) {
+                    (Sides::Fate, 1) => "+".to_string(),
+                    (Sides::Fate, -1) => "-".to_string(),
+                    (_, value) => value.to_string(),
+                };
+                if die.exploded {
+                    face.push('!');
+                }
+                if !die.counted() {
+                    format!("~~{}~~", face)
+                } else if self.success.map_or(false, |s| s.matches(die.value)) {
+                    format!("**{}**", face)
+                } else {
+                    face
+                }
+            })
+            .collect::<Vec<_>>()
+            .join(", ")
+    }
+}
+
+impl fmt::Display for Dice {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.count != 1 {
+            write!(f, "{}", self.count)?;
+        }
+        match self.sides {
+            Sides::Number(sides) => write!(f, "d{}", sides)?,
+            Sides::Percent => f.write_str("d%")?,
+            Sides::Fate => f.write_str("dF")?,
+        }
+        if let Some(reroll) = self.reroll {
+            f.write_str(if reroll.once { "ro" } else { "r" })?;
+            reroll.condition.fmt_point(f)?;
+        }
+        if let Some(explode) = self.explode {
+            f.write_str("!")?;
+            // a success right after a bare `!` would be read as the explosion
+            if explode != Self::default_explode(self.sides)
+                || (self.keep.is_none() && self.success.is_some())
+            {
+                explode.fmt_point(f)?;
+            }
+        }
+        match self.keep {
+            Some(Keep::Highest(k)) => write!(f, "kh{}", k)?,
+            Some(Keep::Lowest(k)) => write!(f, "kl{}", k)?,
+            Some(Keep::DropHighest(k)) => write!(f, "dh{}", k)?,
+            Some(Keep::DropLowest(k)) => write!(f, "dl{}", k)?,
+            None => (),
+        }
+        if let Some(success) = self.success {
+            write!(f, "{}{}", success.compare, success.value)?;
+        }
+
+        Ok(())
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Op {
+    Add,
+    Sub,
+    Mul,
+    Div,
+}
+
+impl Op {
+    const fn precedence(self) -> u8 {
+        match self {
+            Self::Add | Self::Sub => 1,
+            Self::Mul | Self::Div => 2,
+        }
+    }
+
+    const fn symbol(self) -> &'static str {
+        match self {
+            Self::Add => "+",
+            Self::Sub => "-",
+            Self::Mul => "*",
+            Self::Div => "/",
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Expr {
+    Number(i64),
+    Dice(Dice),
+    Neg(Box<Self>),
+    Binary(Box<Self>, Op, Box<Self>),
+}
+
+/// The result of a roll, the breakdown shows the expression with every rolled die
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Roll {
+    pub value: i64,
+    pub breakdown: String,
+}
+
+impl Expr {
+    /// Parses a single expression without checking the limits
+    pub fn parse(input: &str) -> Result<Self, String> {
+        all_consuming(delimited(multispace0, expr, multispace0))(input)
+            .map(|(_, expr)| expr)
+            .map_err(|e| format!("Invalid roll: {}", e))
+    }
+
+    const fn precedence(&self) -> u8 {
+        match self {
+            Self::Binary(_, op, _) => op.precedence(),
+            Self::Neg(_) => 3,
+            Self::Number(_) | Self::Dice(_) => 4,
+        }
+    }
+
+    /// Calls `f` for all dice from left to right
+    fn for_each_dice<'a>(&'a self, f: &mut impl FnMut(&'a Dice)) {
+        match self {
+            Self::Number(_) => (),
+            Self::Dice(dice) => f(dice),
+            Self::Neg(expr) => expr.for_each_dice(f),
+            Self::Binary(left, _, right) => {
+                left.for_each_dice(f);
+                right.for_each_dice(f);
+            }
+        }
+    }
+
+    pub fn validate(&self) -> Result<(), String> {
+        let mut result = Ok(());
+        let mut count = 0u32;
+        self.for_each_dice(&mut |dice| {
+            count = count.saturating_add(dice.count);
+            if result.is_ok() {
+                result = dice.validate();
+            }
+        });
+        result?;
+        if count > MAX_DICE {
+            return Err(format!("At most {} dice can be rolled at once", MAX_DICE));
+        }
+
+        self.validate_numbers()
+    }
+
+    fn validate_numbers(&self) -> Result<(), String> {
+        match self {
+            Self::Number(n) if *n > MAX_NUMBER => {
+                Err(format!("Numbers can be at most {}", MAX_NUMBER))
+            }
+            Self::Number(_) | Self::Dice(_) => Ok(()),
+            Self::Neg(expr) => expr.validate_numbers(),
+            Self::Binary(left, _, right) => {
+                left.validate_numbers()?;
+                right.validate_numbers()
+            }
+        }
+    }
+
+    /// Writes the expression with the minimal parentheses, dice are written by `dice`
+    fn render(&self, out: &mut String, dice: &mut dyn FnMut(&Dice) -> String) {
+        match self {
+            Self::Number(n) => out.push_str(&n.to_string()),
+            Self::Dice(d) => out.push_str(&dice(d)),
+            Self::Neg(expr) => {
+                out.push('-');
+                expr.render_operand(out, dice, 3);
+            }
+            Self::Binary(left, op, right) => {
+                left.render_operand(out, dice, op.precedence());
+                out.push(' ');
+                out.push_str(op.symbol());
+                out.push(' ');
+                // operators are left associative
+                right.render_operand(out, dice, op.precedence() + 1);
+            }
+        }
+    }
+
+    fn render_operand(&self, out: &mut String, dice: &mut dyn FnMut(&Dice) -> String, min: u8) {
+        if self.precedence() < min {
+            out.push('(');
+            self.render(out, dice);
+            out.push(')');
+        } else {
+            self.render(out, dice);
+        }
+    }
+
+    /// Takes the values of the dice in the order of `for_each_dice`
+    fn eval(&self, dice: &mut impl Iterator<Item = i64>) -> Result<i64, String> {
+        let too_large = || "The result is too large".to_string();
+        match self {
+            Self::Number(n) => Ok(*n),
+            Self::Dice(_) => Ok(dice.next().unwrap_or_default()),
+            Self::Neg(expr) => expr.eval(dice)?.checked_neg().ok_or_else(too_large),
+            Self::Binary(left, op, right) => {
+                let (left, right) = (left.eval(dice)?, right.eval(dice)?);
+                match op {
+                    Op::Add => left.checked_add(right).ok_or_else(too_large),
+                    Op::Sub => left.checked_sub(right).ok_or_else(too_large),
+                    Op::Mul => left.checked_mul(right).ok_or_else(too_large),
+                    Op::Div if right == 0 => Err("Division by zero".to_string()),
+                    // rounds towards zero
+                    Op::Div => left.checked_div(right).ok_or_else(too_large),
+                }
+            }
+        }
+    }
+
+    pub fn roll<R: Rng + ?Sized>(&self, rng: &mut R) -> Result<Roll, String> {
+        let mut values = Vec::new();
+        let mut breakdown = String::new();
+        self.render(&mut breakdown, &mut |dice| {
+            let (value, rolls) = dice.roll(rng);
+            values.push(value);
+            format!("{} [{}]", dice, dice.render_rolls(&rolls))
+        });
+        let value = self.eval(&mut values.into_iter())?;
+
+        Ok(Roll { value, breakdown })
+    }
+}
+
+impl fmt::Display for Expr {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut out = String::new();
+        self.render(&mut out, &mut |dice| dice.to_string());
+        f.write_str(&out)
+    }
+}
+
+/// Parses rolls separated by whitespace like `1d20+5 2d6`
+pub fn parse_rolls(input: &str) -> Result<Vec<Expr>, String> {
+    if input.chars().count() > MAX_INPUT_LENGTH {
+        return Err(format!(
+            "Rolls can be at most {} characters long",
+            MAX_INPUT_LENGTH
+        ));
+    }
+    let mut depth = 0usize;
+    for c in input.chars() {
+        match c {
+            '(' => depth += 1,
+            ')' => depth = depth.saturating_sub(1),
+            _ => continue,
+        }
+        if depth > MAX_DEPTH {
+            return Err(format!("Rolls can nest at most {} parentheses", MAX_DEPTH));
+        }
+    }
+
+    let (_, rolls) = all_consuming(delimited(
+        multispace0,
+        separated_list1(multispace1, expr),
+        multispace0,
+    ))(input)
+    .map_err(|_| "That is not a valid roll, try something like `4d6kh3 + 2`".to_string())?;
+    for roll in &rolls {
+        roll.validate()?;
+    }
+
+    Ok(rolls)
+}
+
+fn unsigned<T: std::str::FromStr>(input: &str) -> IResult<&str, T> {
+    map_res(digit1, str::parse)(input)
+}
+
+fn signed(input: &str) -> IResult<&str, i64> {
+    map_res(recognize(pair(opt(char('-')), digit1)), str::parse)(input)
+}
+
+fn compare(input: &str) -> IResult<&str, Compare> {
+    alt((
+        value(Compare::Ge, tag(">=")),
+        value(Compare::Le, tag("<=")),
+        value(Compare::Gt, char('>')),
+        value(Compare::Lt, char('<')),
+        value(Compare::Eq, char('=')),
+    ))(input)
+}
+
+fn condition(input: &str) -> IResult<&str, Condition> {
+    map(pair(compare, signed), |(compare, value)| Condition {
+        compare,
+        value,
+    })(input)
+}
+
+/// A condition after `!` or `r`, a plain number means equal
+fn point(input: &str) -> IResult<&str, Condition> {
+    alt((
+        condition,
+        map(unsigned, |value| Condition {
+            compare: Compare::Eq,
+            value,
+        }),
+    ))(input)
+}
+
+fn sides(input: &str) -> IResult<&str, Sides> {
+    alt((
+        value(Sides::Percent, char('%')),
+        value(Sides::Fate, one_of("Ff")),
+        map(unsigned, Sides::Number),
+    ))(input)
+}
+
+fn keep(input: &str) -> IResult<&str, Keep> {
+    let (input, kind) = alt((tag("kh"), tag("kl"), tag("dh"), tag("dl"), tag("k")))(input)?;
+    let (input, n) = opt(unsigned)(input)?;
+    let n = n.unwrap_or(1);
+    let keep = match kind {
+        "kl" => Keep::Lowest(n),
+        "dh" => Keep::DropHighest(n),
+        "dl" => Keep::DropLowest(n),
+        _ => Keep::Highest(n),
+    };
+
+    Ok((input, keep))
+}
+
+fn dice(input: &str) -> IResult<&str, Dice> {
+    let (input, count) = opt(unsigned)(input)?;
+    let (input, _) = one_of("dD")(input)?;
+    let (input, sides) = sides(input)?;
+    let (input, reroll) = opt(pair(alt((tag("ro"), tag("r"))), point))(input)?;
+    let (input, explode) = opt(preceded(char('!'), opt(point)))(input)?;
+    let (input, keep) = opt(keep)(input)?;
+    let (input, success) = opt(condition)(input)?;
+
+    Ok((
+        input,
+        Dice {
+            count: count.unwrap_or(1),
+            sides,
+            reroll: reroll.map(|(kind, condition)| Reroll {
+                condition,
+                once: kind == "ro",
+            }),
+            explode: explode.map(|point| point.unwrap_or_else(|| Dice::default_explode(sides))),
+            keep,
+            success,
+        },
+    ))
+}
+
+fn atom(input: &str) -> IResult<&str, Expr> {
+    alt((
+        map(dice, Expr::Dice),
+        map(unsigned, Expr::Number),
+        delimited(
+            pair(char('('), multispace0),
+            expr,
+            pair(multispace0, char(')')),
+        ),
+    ))(input)
+}
+
+fn factor(input: &str) -> IResult<&str, Expr> {
+    alt((
+        map(preceded(pair(char('-'), multispace0), factor), |expr| {
+            Expr::Neg(Box::new(expr))
+        }),
+        atom,
+    ))(input)
+}
+
+fn binary<'a>(
+    operand: fn(&'a str) -> IResult<&'a str, Expr>,
+    ops: &'static str,
+) -> impl FnMut(&'a str) -> IResult<&'a str, Expr> {
+    move |input| {
+        let (input, first) = operand(input)?;
+        let (input, rest) = many0(pair(
+            delimited(multispace0, one_of(ops), multispace0),
+            operand,
+        ))(input)?;
+        let expr = rest.into_iter().fold(first, |left, (op, right)| {
+            let op = match op {
+                '+' => Op::Add,
+                '-' => Op::Sub,
+                '*' => Op::Mul,
+                _ => Op::Div,
+            };
+            Expr::Binary(Box::new(left), op, Box::new(right))
+        });
+
+        Ok((input, expr))
+    }
+}
+
+fn term(input: &str) -> IResult<&str, Expr> {
+    binary(factor, "*/")(input)
+}
+
+fn expr(input: &str) -> IResult<&str, Expr> {
+    binary(term, "+-")(input)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use proptest::prelude::*;
+    use rand::{rngs::StdRng, SeedableRng};
+
+    fn roll(input: &str) -> i64 {
+        Expr::parse(input)
+            .unwrap()
+            .roll(&mut StdRng::seed_from_u64(7))
+            .unwrap()
+            .value
+    }
+
+    #[test]
+    fn notation() {
+        let dice = |input: &str| match Expr::parse(input).unwrap() {
+            Expr::Dice(dice) => dice,
+            expr => panic!("not a single dice: {:?}", expr),
+        };
+
+        let d = dice("4d6kh3");
+        assert_eq!(
+            (d.count, d.sides, d.keep),
+            (4, Sides::Number(6), Some(Keep::Highest(3)))
+        );
+        assert_eq!(dice("d%").sides, Sides::Percent);
+        assert_eq!(dice("4dF").sides, Sides::Fate);
+        assert_eq!(
+            dice("d6!").explode,
+            Some(Condition {
+                compare: Compare::Eq,
+                value: 6
+            })
+        );
+        assert_eq!(
+            dice("10d10>=7").success,
+            Some(Condition {
+                compare: Compare::Ge,
+                value: 7
+            })
+        );
+        assert_eq!(
+            dice("2d6ro<3").reroll,
+            Some(Reroll {
+                condition: Condition {
+                    compare: Compare::Lt,
+                    value: 3
+                },
+                once: true
+            })
+        );
+        assert_eq!(dice("3d8r1!dl1").keep, Some(Keep::DropLowest(1)));
+        assert_eq!(dice("6d6!>=3").success, None);
+        assert_eq!(dice("6d6!6>=3").to_string(), "6d6!6>=3");
+
+        assert_eq!(
+            Expr::parse("1d6+2").unwrap().to_string(),
+            "d6 + 2".to_string()
+        );
+        assert_eq!(parse_rolls("1d6+2 2d20-3").unwrap().len(), 2);
+        assert_eq!(
+            Expr::parse("2 * (1 + d4) - -3").unwrap().to_string(),
+            "2 * (1 + d4) - -3"
+        );
+        assert!(Expr::parse("d").is_err());
+        assert!(Expr::parse("2d6kh3 +").is_err());
+        assert!(Expr::parse("(1 + 2").is_err());
+    }
+
+    #[test]
+    fn arithmetic() {
+        assert_eq!(roll("2 + 3 * 4"), 14);
+        assert_eq!(roll("(2 + 3) * 4"), 20);
+        assert_eq!(roll("10 - 4 - 3"), 3);
+        assert_eq!(roll("-7 / 2"), -3);
+        assert_eq!(roll("d1 + 5d1 * 2"), 11);
+        assert!(Expr::parse("1 / (2 - 2)")
+            .unwrap()
+            .roll(&mut StdRng::seed_from_u64(7))
+            .is_err());
+    }
+
+    #[test]
+    fn limits() {
+        assert!(parse_rolls("1000000d1000000").is_err());
+        assert!(parse_rolls("60d6 + 60d6").is_err());
+        assert!(parse_rolls("2d0").is_err());
+        assert!(parse_rolls("0d6").is_err());
+        assert!(parse_rolls("d1!").is_err());
+        assert!(parse_rolls("d6!>0").is_err());
+        assert!(parse_rolls("d6r<7").is_err());
+        assert!(parse_rolls("d6ro<7").is_ok());
+        assert!(parse_rolls("2000000 + d6").is_err());
+        assert!(parse_rolls(&"(".repeat(300)).is_err());
+        assert!(parse_rolls(&format!("{}1{}", "(".repeat(30), ")".repeat(30))).is_err());
+        assert!(parse_rolls(&format!("{}1{}", "(".repeat(20), ")".repeat(20))).is_ok());
+        assert!(parse_rolls("99999999999999999999d6").is_err());
+    }
+
+    #[test]
+    fn breakdown() {
+        let dice = Dice {
+            count: 4,
+            sides: Sides::Number(10),
+            reroll: None,
+            explode: None,
+            keep: Some(Keep::Highest(3)),
+            success: Some(Condition {
+                compare: Compare::Ge,
+                value: 7,
+            }),
+        };
+        let rolls = [8, 2, 10, 5]
+            .iter()
+            .map(|value| Die {
+                value: *value,
+                dropped: *value == 2,
+                ..Die::default()
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(dice.render_rolls(&rolls), "**8**, ~~2~~, **10**, 5");
+
+        let roll = Expr::parse("2d1! + 1")
+            .unwrap()
+            .roll(&mut StdRng::seed_from_u64(7));
+        assert!(roll.is_ok());
+        let roll = Expr::parse("3d1 + 1")
+            .unwrap()
+            .roll(&mut StdRng::seed_from_u64(7))
+            .unwrap();
+        assert_eq!(roll.breakdown, "3d1 [1, 1, 1] + 1");
+        assert_eq!(roll.value, 4);
+    }
+
+    fn arb_sides() -> impl Strategy<Value = Sides> {
+        prop_oneof![
+            (0..30u32).prop_map(Sides::Number),
+            Just(Sides::Percent),
+            Just(Sides::Fate),
+        ]
+    }
+
+    fn arb_condition() -> impl Strategy<Value = Condition> {
+        let compare = prop_oneof![
+            Just(Compare::Eq),
+            Just(Compare::Lt),
+            Just(Compare::Le),
+            Just(Compare::Gt),
+            Just(Compare::Ge),
+        ];
+        (compare, -2..30i64).prop_map(|(compare, value)| Condition { compare, value })
+    }
+
+    fn arb_keep() -> impl Strategy<Value = Keep> {
+        prop_oneof![
+            (0..8u32).prop_map(Keep::Highest),
+            (0..8u32).prop_map(Keep::Lowest),
+            (0..8u32).prop_map(Keep::DropHighest),
+            (0..8u32).prop_map(Keep::DropLowest),
+        ]
+    }
+
+    fn arb_dice() -> impl Strategy<Value = Dice> {
+        (
+            0..8u32,
+            arb_sides(),
+            proptest::option::of((arb_condition(), any::<bool>())),
+            proptest::option::of(arb_condition()),
+            proptest::option::of(arb_keep()),
+            proptest::option::of(arb_condition()),
+        )
+            .prop_map(|(count, sides, reroll, explode, keep, success)| Dice {
+                count,
+                sides,
+                reroll: reroll.map(|(condition, once)| Reroll { condition, once }),
+                explode,
+                keep,
+                success,
+            })
+    }
+
+    fn arb_expr() -> impl Strategy<Value = Expr> {
+        let leaf = prop_oneof![
+            (0..2_000_000i64).prop_map(Expr::Number),
+            arb_dice().prop_map(Expr::Dice),
+        ];
+        leaf.prop_recursive(4, 24, 2, |inner| {
+            let op = prop_oneof![Just(Op::Add), Just(Op::Sub), Just(Op::Mul), Just(Op::Div)];
+            prop_oneof![
+                inner.clone().prop_map(|expr| Expr::Neg(Box::new(expr))),
+                (inner.clone(), op, inner).prop_map(|(left, op, right)| Expr::Binary(
+                    Box::new(left),
+                    op,
+                    Box::new(right)
+                )),
+            ]
+        })
+    }
+
+    proptest! {
+        #[test]
+        fn written_rolls_parse_back(expr in arb_expr()) {
+            prop_assert_eq!(Expr::parse(&expr.to_string()), Ok(expr));
+        }
+
+        #[test]
+        fn parsing_never_panics(input in "[0-9dDFkhlro!%<>=()+*/ -]{0,40}") {
+            let _ = parse_rolls(&input);
+        }
+
+        #[test]
+        fn parsing_any_text_never_panics(input in "\\PC{0,60}") {
+            let _ = parse_rolls(&input);
+        }
+
+        #[test]
+        fn valid_rolls_evaluate(expr in arb_expr(), seed in any::<u64>()) {
+            if expr.validate().is_ok() {
+                let mut rng = StdRng::seed_from_u64(seed);
+                let first = expr.roll(&mut rng);
+                let mut rng = StdRng::seed_from_u64(seed);
+                prop_assert_eq!(first, expr.roll(&mut rng));
+            }
+        }
+
+        #[test]
+        fn dice_stay_in_range(dice in arb_dice(), seed in any::<u64>()) {
+            prop_assume!(dice.validate().is_ok());
+            let (value, rolls) = dice.roll(&mut StdRng::seed_from_u64(seed));
+            let (min, max) = dice.sides.range();
+            let counted = rolls.iter().filter(|d| d.counted()).collect::<Vec<_>>();
+
+            prop_assert!(rolls.iter().all(|d| (min..=max).contains(&d.value)));
+            prop_assert!(rolls.len() <= (dice.count * (MAX_EXTRA_ROLLS + 1)) as usize);
+            if dice.explode.is_none() && dice.reroll.is_none() {
+                let kept = match dice.keep {
+                    Some(Keep::Highest(k) | Keep::Lowest(k)) => k.min(dice.count),
+                    Some(Keep::DropHighest(k) | Keep::DropLowest(k)) => dice.count.saturating_sub(k),
+                    None => dice.count,
+                };
+                prop_assert_eq!(counted.len(), kept as usize);
+            }
+            if dice.success.is_some() {
+                prop_assert!(value >= 0 && value <= counted.len() as i64);
+            } else {
+                prop_assert_eq!(value, counted.iter().map(|d| d.value).sum::<i64>());
+            }
+        }
+    }
+}
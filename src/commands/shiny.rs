@@ -1,13 +1,104 @@
+use crate::commands::config::{update_guild_config, Guild};
+use crate::models::server_config::ServerConfig;
 use crate::models::shiny::Shiny;
+use crate::models::shiny_contribution::ShinyContribution;
 use crate::util::get_client;
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
 use serenity::{
     framework::standard::{macros::command, Args, CommandResult},
     model::prelude::*,
     prelude::*,
-    utils::MessageBuilder,
+    utils::{parse_username, MessageBuilder},
 };
+use sqlx::postgres::PgPool;
+use std::str::FromStr;
 
-//TODO: this need some rework too, shinies are updated by their user_ids across servers, so every server creates probably a new entry and then updates all of them
+const HISTORY_SIZE: i64 = 15;
+const PERIODS_SHOWN: i64 = 12;
+const BAR_WIDTH: i64 = 20;
+
+/// Shinys the server collects for, counted from the time it was set
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct Goal {
+    pub amount: i64,
+    pub title: Option<String>,
+    pub since: DateTime<Utc>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq, Eq)]
+#[serde(default)]
+pub struct Config {
+    pub goal: Option<Goal>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Period {
+    Day,
+    Week,
+    Month,
+    Year,
+}
+
+impl FromStr for Period {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "day" | "daily" => Ok(Self::Day),
+            "week" | "weekly" => Ok(Self::Week),
+            "month" | "monthly" => Ok(Self::Month),
+            "year" | "yearly" => Ok(Self::Year),
+            other => Err(format!(
+                "Unknown period {}, use day, week, month or year",
+                other
+            )),
+        }
+    }
+}
+
+impl Period {
+    /// The unit of `date_trunc`
+    const fn as_str(self) -> &'static str {
+        match self {
+            Self::Day => "day",
+            Self::Week => "week",
+            Self::Month => "month",
+            Self::Year => "year",
+        }
+    }
+
+    fn format(self, start: DateTime<Utc>) -> String {
+        match self {
+            Self::Day | Self::Week => start.format("%d.%m.%Y").to_string(),
+            Self::Month => start.format("%m.%Y").to_string(),
+            Self::Year => start.format("%Y").to_string(),
+        }
+    }
+}
+
+/// A bar of `BAR_WIDTH` blocks with the percentage, it stays full above the goal
+fn progress_bar(current: i64, goal: i64) -> String {
+    // in i128 the products can not overflow, whatever the totals are
+    let (current, goal) = (i128::from(current), i128::from(goal.max(1)));
+    let width = i128::from(BAR_WIDTH);
+    let filled = (current * width / goal).clamp(0, width);
+    format!(
+        "`{}{}` {}%",
+        "\u{2588}".repeat(filled as usize),
+        "\u{2591}".repeat((width - filled) as usize),
+        current * 100 / goal
+    )
+}
+
+async fn config(pool: &PgPool, server_id: i64) -> Config {
+    match ServerConfig::get(pool, server_id).await {
+        Ok(mut config) => serde_json::from_value::<Guild>(config.config.take())
+            .map(|g_cfg| g_cfg.shiny)
+            .unwrap_or_default(),
+        Err(_e) => Config::default(),
+    }
+}
 
 #[command]
 #[description = "List shiny counts"]
@@ -36,42 +127,270 @@ async fn list(ctx: &Context, msg: &Message, _args: Args) -> CommandResult {
 }
 
 #[command]
-#[description = "Increase your shiny charity count"]
+#[description = "Increase your shiny charity count, optionally with a note"]
 #[example("1000")]
+#[example("500 Stream donation")]
+#[min_args(1)]
 #[only_in("guilds")]
-#[usage("*amount*")]
+#[usage("*amount* *[note]*")]
+#[sub_commands(shiny_history, shiny_totals, shiny_goal, shiny_setgoal, shiny_undo)]
 async fn shiny(ctx: &Context, msg: &Message, mut args: Args) -> CommandResult {
-    let amount = args.single::<i64>()?;
+    let server_id = msg.guild_id.ok_or("Shinys only exist on servers")?;
+    let amount = match args.single::<i64>() {
+        Ok(amount) if amount > 0 => amount,
+        _ => {
+            msg.reply(ctx, "Invalid amount!").await?;
+            return Ok(());
+        }
+    };
+    let note = Some(args.rest().trim()).filter(|n| !n.is_empty());
     let pool = get_client(ctx).await?;
 
-    if let Ok(user_shiny) = Shiny::get(&pool, *msg.author.id.as_u64() as i64).await {
-        let updated_shiny = Shiny::update(
-            &pool,
-            *msg.author.id.as_u64() as i64,
-            user_shiny.amount + amount,
-        )
-        .await?;
+    let (shiny, contribution) = Shiny::contribute(
+        &pool,
+        *server_id.as_u64() as i64,
+        *msg.author.id.as_u64() as i64,
+        &msg.author.name,
+        amount,
+        note,
+    )
+    .await?;
 
-        respond(ctx, msg, updated_shiny).await;
-    } else if let Some(server_id) = msg.guild_id {
-        let new_shiny = Shiny::create(
-            &pool,
-            *server_id.as_u64() as i64,
-            *msg.author.id.as_u64() as i64,
-            msg.author.name.to_string(),
-            amount,
-        )
+    respond(ctx, msg, shiny, contribution.id).await;
+
+    Ok(())
+}
+
+async fn respond(ctx: &Context, msg: &Message, shiny: Shiny, entry_id: i64) {
+    msg.reply(
+        ctx,
+        format!("Shiny value: {} (entry {})", shiny.amount, entry_id),
+    )
+    .await
+    .expect("Could not answer");
+}
+
+#[command("history")]
+#[description = "Shows the latest shiny contributions on this server or of a user"]
+#[max_args(1)]
+#[usage("*[@user]*")]
+#[example("@HansTrashy")]
+#[only_in("guilds")]
+async fn shiny_history(ctx: &Context, msg: &Message, mut args: Args) -> CommandResult {
+    let server_id = *msg.guild_id.ok_or("Shinys only exist on servers")?.as_u64() as i64;
+    let user_id = match args.single::<String>() {
+        Ok(user) => match parse_username(&user) {
+            Some(user_id) => Some(user_id as i64),
+            None => {
+                msg.reply(ctx, "Mention the user whose history you want to see")
+                    .await?;
+                return Ok(());
+            }
+        },
+        Err(_e) => None,
+    };
+    let pool = get_client(ctx).await?;
+
+    let entries = ShinyContribution::recent(&pool, server_id, user_id, HISTORY_SIZE).await?;
+    if entries.is_empty() {
+        msg.reply(ctx, "There are no shiny contributions yet")
+            .await?;
+        return Ok(());
+    }
+
+    let rendered_history = entries
+        .iter()
+        .map(|entry| {
+            format!(
+                "`{}` | {} | {:+} | <@{}>{}",
+                entry.id,
+                entry.created_at.format("%d.%m.%Y %H:%M"),
+                entry.amount,
+                entry.user_id,
+                entry
+                    .note
+                    .as_ref()
+                    .map(|note| format!(" | {}", note))
+                    .unwrap_or_default()
+            )
+        })
+        .collect::<Vec<_>>()
+        .join("\n");
+
+    msg.channel_id
+        .send_message(&ctx, |m| {
+            m.embed(|e| {
+                e.title("Shiny history")
+                    .description(rendered_history)
+                    .color((0, 120, 220))
+            })
+        })
         .await?;
-        respond(ctx, msg, new_shiny).await;
+
+    Ok(())
+}
+
+#[command("totals")]
+#[description = "Shows the shinys collected on this server per day, week, month or year"]
+#[max_args(1)]
+#[usage("*[period]*")]
+#[example("month")]
+#[only_in("guilds")]
+async fn shiny_totals(ctx: &Context, msg: &Message, mut args: Args) -> CommandResult {
+    let server_id = *msg.guild_id.ok_or("Shinys only exist on servers")?.as_u64() as i64;
+    let period = match args
+        .single::<String>()
+        .map_or(Ok(Period::Week), |p| p.parse::<Period>())
+    {
+        Ok(period) => period,
+        Err(e) => {
+            msg.reply(ctx, e).await?;
+            return Ok(());
+        }
+    };
+    let pool = get_client(ctx).await?;
+
+    let totals =
+        ShinyContribution::totals(&pool, server_id, period.as_str(), PERIODS_SHOWN).await?;
+    if totals.is_empty() {
+        msg.reply(ctx, "There are no shiny contributions yet")
+            .await?;
+        return Ok(());
     }
 
+    let rendered_totals = totals
+        .iter()
+        .map(|(start, sum)| format!("{} | {}", period.format(*start), sum))
+        .collect::<Vec<_>>()
+        .join("\n");
+
+    msg.channel_id
+        .send_message(&ctx, |m| {
+            m.embed(|e| {
+                e.title(format!("Shinys per {}", period.as_str()))
+                    .description(rendered_totals)
+                    .color((0, 120, 220))
+            })
+        })
+        .await?;
+
     Ok(())
 }
 
-async fn respond(ctx: &Context, msg: &Message, shiny: Shiny) {
-    msg.reply(ctx, format!("Shiny value: {}", shiny.amount))
-        .await
-        .expect("Could not answer");
+#[command("goal")]
+#[description = "Shows the progress towards the shiny goal of this server"]
+#[num_args(0)]
+#[only_in("guilds")]
+async fn shiny_goal(ctx: &Context, msg: &Message, _args: Args) -> CommandResult {
+    let server_id = *msg.guild_id.ok_or("Shinys only exist on servers")?.as_u64() as i64;
+    let pool = get_client(ctx).await?;
+
+    let goal = match config(&pool, server_id).await.goal {
+        Some(goal) => goal,
+        None => {
+            msg.reply(ctx, "There is no shiny goal").await?;
+            return Ok(());
+        }
+    };
+    let collected = ShinyContribution::sum_since(&pool, server_id, goal.since).await?;
+
+    msg.channel_id
+        .send_message(&ctx, |m| {
+            m.embed(|e| {
+                e.title(goal.title.as_deref().unwrap_or("Shiny goal"))
+                    .description(format!(
+                        "{}\n\n{} of {} shinys",
+                        progress_bar(collected, goal.amount),
+                        collected,
+                        goal.amount
+                    ))
+                    .footer(|f| f.text(format!("Since {}", goal.since.format("%d.%m.%Y"))))
+                    .color((0, 120, 220))
+            })
+        })
+        .await?;
+
+    Ok(())
+}
+
+#[command("setgoal")]
+#[description = "Sets the shiny goal of this server, counting from now on, or removes it with `off`"]
+#[min_args(1)]
+#[usage("*amount/off* *[title]*")]
+#[example("100000 New server banner")]
+#[example("off")]
+#[only_in("guilds")]
+#[allowed_roles("Mods")]
+async fn shiny_setgoal(ctx: &Context, msg: &Message, mut args: Args) -> CommandResult {
+    let server_id = *msg.guild_id.ok_or("Shinys only exist on servers")?.as_u64() as i64;
+    let amount = args.single::<String>()?;
+    let goal = if amount == "off" {
+        None
+    } else {
+        match amount.parse::<i64>() {
+            Ok(amount) if amount > 0 => Some(Goal {
+                amount,
+                title: Some(args.rest().trim().to_string()).filter(|t| !t.is_empty()),
+                since: Utc::now(),
+            }),
+            _ => {
+                msg.reply(ctx, "Invalid amount!").await?;
+                return Ok(());
+            }
+        }
+    };
+    let pool = get_client(ctx).await?;
+
+    let description = match update_guild_config(&pool, server_id, |g| {
+        g.shiny.goal = goal;
+        Ok(())
+    })
+    .await?
+    {
+        Ok(g_cfg) => format!("{:?}", g_cfg.shiny),
+        Err(e) => e,
+    };
+
+    std::mem::drop(
+        msg.channel_id
+            .send_message(&ctx.http, |m| {
+                m.embed(|e| e.description(description).color((0, 120, 220)))
+            })
+            .await,
+    );
+
+    Ok(())
+}
+
+#[command("undo")]
+#[description = "Takes back a shiny contribution, the entry ids are in `misc shiny history`"]
+#[num_args(1)]
+#[usage("*entry id*")]
+#[example("42")]
+#[only_in("guilds")]
+#[allowed_roles("Mods")]
+async fn shiny_undo(ctx: &Context, msg: &Message, mut args: Args) -> CommandResult {
+    let server_id = *msg.guild_id.ok_or("Shinys only exist on servers")?.as_u64() as i64;
+    let id = args.single::<i64>()?;
+    let pool = get_client(ctx).await?;
+
+    match ShinyContribution::undo(&pool, server_id, id).await? {
+        Some(entry) => {
+            msg.reply(
+                ctx,
+                format!(
+                    "Took back {:+} shinys of <@{}>",
+                    entry.amount, entry.user_id
+                ),
+            )
+            .await?;
+        }
+        None => {
+            msg.reply(ctx, "There is no such entry").await?;
+        }
+    }
+
+    Ok(())
 }
 
 #[command]
@@ -81,33 +400,32 @@ async fn respond(ctx: &Context, msg: &Message, shiny: Shiny) {
 #[allowed_roles("Mods")]
 #[usage("*amount* *user_mention*")]
 async fn setshiny(ctx: &Context, msg: &Message, mut args: Args) -> CommandResult {
-    let amount = args.single::<i64>()?;
+    let server_id = *msg.guild_id.ok_or("Shinys only exist on servers")?.as_u64() as i64;
+    let amount = match args.single::<i64>() {
+        Ok(amount) if amount >= 0 => amount,
+        _ => {
+            msg.reply(ctx, "Invalid amount!").await?;
+            return Ok(());
+        }
+    };
     let pool = get_client(ctx).await?;
+    let note = format!("Set by {}", msg.author.name);
 
     let mut response = Vec::new();
 
     for user in &msg.mentions {
-        // check if user has an entry already
-        if let Ok(_user_shiny) = Shiny::get(&pool, *user.id.as_u64() as i64).await {
-            let updated_shiny = Shiny::update(&pool, *user.id.as_u64() as i64, amount).await?;
-
-            response.push(format!("{}: {}", user.name, updated_shiny.amount));
-        } else {
-            // insert new entry
-
-            if let Some(server_id) = msg.guild_id {
-                let new_shiny = Shiny::create(
-                    &pool,
-                    *server_id.as_u64() as i64,
-                    *user.id.as_u64() as i64,
-                    user.name.to_string(),
-                    amount,
-                )
-                .await?;
-
-                response.push(format!("{}: {}", user.name, new_shiny.amount));
-            }
-        }
+        // the difference to the current amount goes into the history
+        let updated_shiny = Shiny::set(
+            &pool,
+            server_id,
+            *user.id.as_u64() as i64,
+            &user.name,
+            amount,
+            &note,
+        )
+        .await?;
+
+        response.push(format!("{}: {}", user.name, updated_shiny.amount));
     }
 
     msg.reply(ctx, response.join("\n"))
@@ -124,12 +442,12 @@ async fn setshiny(ctx: &Context, msg: &Message, mut args: Args) -> CommandResult
 #[allowed_roles("Mods")]
 #[usage("*user_mention_1* *user_mention_2*")]
 async fn removeshiny(ctx: &Context, msg: &Message, _args: Args) -> CommandResult {
+    let server_id = *msg.guild_id.ok_or("Shinys only exist on servers")?.as_u64() as i64;
     let mut response = Vec::new();
     let pool = get_client(ctx).await?;
 
     for user in &msg.mentions {
-        // check if user has an entry already
-        let _updated_shiny = Shiny::delete(&pool, *user.id.as_u64() as i64).await?;
+        Shiny::delete(&pool, server_id, *user.id.as_u64() as i64).await?;
 
         response.push(format!("Removed shinys for {}", user.name));
     }
@@ -140,3 +458,39 @@ async fn removeshiny(ctx: &Context, msg: &Message, _args: Args) -> CommandResult
 
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::{progress_bar, Period};
+
+    #[test]
+    fn goal_progress() {
+        assert_eq!(
+            progress_bar(0, 100),
+            format!("`{}` 0%", "\u{2591}".repeat(20))
+        );
+        assert_eq!(
+            progress_bar(25, 100),
+            format!("`{}{}` 25%", "\u{2588}".repeat(5), "\u{2591}".repeat(15))
+        );
+        assert_eq!(
+            progress_bar(150, 100),
+            format!("`{}` 150%", "\u{2588}".repeat(20))
+        );
+        assert_eq!(
+            progress_bar(-10, 100),
+            format!("`{}` -10%", "\u{2591}".repeat(20))
+        );
+        assert_eq!(
+            progress_bar(i64::MAX, 1),
+            format!("`{}` {}00%", "\u{2588}".repeat(20), i64::MAX)
+        );
+    }
+
+    #[test]
+    fn periods() {
+        assert_eq!("Month".parse::<Period>(), Ok(Period::Month));
+        assert_eq!("weekly".parse::<Period>(), Ok(Period::Week));
+        assert!("decade".parse::<Period>().is_err());
+    }
+}
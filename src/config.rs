@@ -9,6 +9,7 @@ pub struct Config {
     pub application_id: u64,
     pub lastfm_api_key: String,
     pub xkcd_index: String,
+    pub fav_index: String,
     pub db_url: String,
     pub db_pool_max_size: u32,
     pub buckets: Vec<Bucket>,
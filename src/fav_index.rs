@@ -0,0 +1,196 @@
+//! Full text index over the content of favs, shared by all users but always searched per user
+use crate::models::fav::Fav;
+use crate::models::tag::Tag;
+use crate::{FAV_INDEX, FAV_INDEX_READER, FAV_INDEX_SCHEMA, FAV_INDEX_WRITER};
+use serenity::prelude::*;
+use sqlx::postgres::PgPool;
+use tantivy::collector::{Count, TopDocs};
+use tantivy::query::{BooleanQuery, Occur, Query, QueryParser, TermQuery};
+use tantivy::schema::{Field, IndexRecordOption, Schema};
+use tantivy::{Document, Term};
+use tracing::debug;
+
+pub const PAGE_SIZE: usize = 10;
+
+type IndexResult<T> = Result<T, Box<dyn std::error::Error + Send + Sync>>;
+
+#[derive(Debug)]
+pub struct Hit {
+    pub fav_id: u64,
+    pub content: String,
+    pub author: String,
+    pub channel: String,
+    pub tags: String,
+    pub link: String,
+}
+
+fn schema() -> IndexResult<&'static Schema> {
+    Ok(FAV_INDEX_SCHEMA
+        .get()
+        .ok_or("Fav index schema not initialized")?)
+}
+
+fn field(schema: &Schema, name: &str) -> Field {
+    schema
+        .get_field(name)
+        .unwrap_or_else(|| panic!("fav index has no field {}", name))
+}
+
+async fn document(ctx: &Context, pool: &PgPool, fav: &Fav) -> IndexResult<Document> {
+    let content = crate::commands::fav::fav_content(ctx, fav, false).await?;
+    let tags = Tag::of_fav(pool, fav.id)
+        .await?
+        .into_iter()
+        .map(|t| t.label)
+        .collect::<Vec<_>>()
+        .join(" ");
+
+    let schema = schema()?;
+    let mut document = Document::default();
+    document.add_u64(field(schema, "fav_id"), fav.id as u64);
+    document.add_u64(field(schema, "user_id"), fav.user_id as u64);
+    document.add_u64(field(schema, "author_id"), fav.author_id as u64);
+    document.add_text(field(schema, "content"), &content.content);
+    document.add_text(field(schema, "author"), &content.author_name);
+    document.add_text(field(schema, "channel"), &content.channel_name);
+    document.add_text(field(schema, "tags"), &tags);
+    document.add_text(
+        field(schema, "link"),
+        &format!(
+            "https://discord.com/channels/{}/{}/{}",
+            fav.server_id, fav.channel_id, fav.msg_id
+        ),
+    );
+
+    Ok(document)
+}
+
+/// Removes the favs and adds the documents with a single commit. The writer blocks, so this
+/// runs outside of the async runtime.
+async fn write(fav_ids: Vec<i64>, documents: Vec<Document>) -> IndexResult<()> {
+    if fav_ids.is_empty() && documents.is_empty() {
+        return Ok(());
+    }
+    let schema = schema()?;
+    let writer = FAV_INDEX_WRITER
+        .get()
+        .ok_or("Fav index writer not initialized")?;
+
+    tokio::task::spawn_blocking(move || -> IndexResult<()> {
+        let mut writer = writer.lock().map_err(|_| "Fav index writer poisoned")?;
+        for id in fav_ids {
+            writer.delete_term(Term::from_field_u64(field(schema, "fav_id"), id as u64));
+        }
+        for document in documents {
+            writer.add_document(document);
+        }
+        writer.commit()?;
+
+        Ok(())
+    })
+    .await?
+}
+
+/// Adds the fav to the index, replacing an older version of it (e.g. before it was tagged)
+pub async fn index_fav(ctx: &Context, pool: &PgPool, fav: &Fav) -> IndexResult<()> {
+    let document = document(ctx, pool, fav).await?;
+    write(vec![fav.id], vec![document]).await
+}
+
+/// Like `index_fav` for many favs at once, favs whose content can not be read are skipped
+pub async fn index_favs(ctx: &Context, pool: &PgPool, favs: &[Fav]) -> IndexResult<()> {
+    let mut documents = Vec::with_capacity(favs.len());
+    for fav in favs {
+        match document(ctx, pool, fav).await {
+            Ok(document) => documents.push(document),
+            Err(e) => debug!(?e, fav = fav.id, "Could not index fav"),
+        }
+    }
+
+    write(favs.iter().map(|f| f.id).collect(), documents).await
+}
+
+pub async fn remove_favs(fav_ids: &[i64]) -> IndexResult<()> {
+    write(fav_ids.to_vec(), Vec::new()).await
+}
+
+/// Searches the favs of the user, returns the total number of hits and the requested page
+pub fn search(
+    user_id: u64,
+    text: &str,
+    excluded_authors: &[u64],
+    page: usize,
+) -> IndexResult<(usize, Vec<Hit>)> {
+    let index = FAV_INDEX.get().ok_or("Fav index not initialized")?;
+    let searcher = FAV_INDEX_READER
+        .get()
+        .ok_or("Fav index reader not initialized")?
+        .searcher();
+    let schema = schema()?;
+
+    let text_query = QueryParser::for_index(
+        index,
+        vec![
+            field(schema, "content"),
+            field(schema, "author"),
+            field(schema, "channel"),
+            field(schema, "tags"),
+        ],
+    )
+    .parse_query(text)
+    .map_err(|e| format!("Failed to parse query: {:?}", e))?;
+
+    let mut clauses: Vec<(Occur, Box<dyn Query>)> = vec![
+        (
+            Occur::Must,
+            Box::new(TermQuery::new(
+                Term::from_field_u64(field(schema, "user_id"), user_id),
+                IndexRecordOption::Basic,
+            )),
+        ),
+        (Occur::Must, text_query),
+    ];
+    for author in excluded_authors {
+        clauses.push((
+            Occur::MustNot,
+            Box::new(TermQuery::new(
+                Term::from_field_u64(field(schema, "author_id"), *author),
+                IndexRecordOption::Basic,
+            )),
+        ));
+    }
+    let query = BooleanQuery::from(clauses);
+
+    let (top_docs, total) = searcher.search(
+        &query,
+        &(
+            TopDocs::with_limit(PAGE_SIZE).and_offset(page * PAGE_SIZE),
+            Count,
+        ),
+    )?;
+
+    let text_of = |doc: &Document, name: &str| {
+        doc.get_first(field(schema, name))
+            .and_then(|v| v.text())
+            .unwrap_or_default()
+            .to_string()
+    };
+
+    let mut hits = Vec::with_capacity(top_docs.len());
+    for (_score, address) in top_docs {
+        let doc = searcher.doc(address)?;
+        hits.push(Hit {
+            fav_id: doc
+                .get_first(field(schema, "fav_id"))
+                .and_then(|v| v.u64_value())
+                .unwrap_or_default(),
+            content: text_of(&doc, "content"),
+            author: text_of(&doc, "author"),
+            channel: text_of(&doc, "channel"),
+            tags: text_of(&doc, "tags"),
+            link: text_of(&doc, "link"),
+        });
+    }
+
+    Ok((total, hits))
+}
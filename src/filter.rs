@@ -0,0 +1,226 @@
+use crate::automod::Action;
+use regex::{Regex, RegexSet};
+use serde::{Deserialize, Serialize};
+use std::str::FromStr;
+use tracing::warn;
+
+#[derive(Serialize, Deserialize, Debug, Default, Clone, PartialEq, Eq)]
+#[serde(default)]
+pub struct Config {
+    pub exempt_roles: Vec<u64>,
+    pub exempt_channels: Vec<u64>,
+}
+
+impl Config {
+    pub fn is_exempt(&self, channel_id: u64, roles: &[u64]) -> bool {
+        self.exempt_channels.contains(&channel_id)
+            || roles.iter().any(|r| self.exempt_roles.contains(r))
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PatternKind {
+    /// A literal word, matched case insensitive on word boundaries
+    Word,
+    /// A word where `*` matches any non whitespace and `?` a single character
+    Wildcard,
+    /// A regular expression as understood by the `regex` crate
+    Regex,
+}
+
+impl FromStr for PatternKind {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "word" => Ok(Self::Word),
+            "wildcard" => Ok(Self::Wildcard),
+            "regex" => Ok(Self::Regex),
+            other => Err(format!("Unknown pattern kind: {}", other)),
+        }
+    }
+}
+
+impl PatternKind {
+    pub const fn as_str(self) -> &'static str {
+        match self {
+            Self::Word => "word",
+            Self::Wildcard => "wildcard",
+            Self::Regex => "regex",
+        }
+    }
+}
+
+/// Translates a filter pattern into a regex, validating it on the way
+pub fn to_regex(kind: PatternKind, pattern: &str) -> Result<String, String> {
+    let pattern = pattern.trim();
+    if pattern.is_empty() {
+        return Err("Empty pattern".to_string());
+    }
+
+    let regex = match kind {
+        PatternKind::Regex => pattern.to_string(),
+        PatternKind::Word | PatternKind::Wildcard => {
+            let mut body = regex::escape(pattern);
+            if kind == PatternKind::Wildcard {
+                body = body.replace(r"\*", r"\S*").replace(r"\?", r"\S");
+            }
+
+            // only anchor on word boundaries where the pattern itself has a word character
+            let boundary = |c: Option<char>| {
+                if c.map_or(false, char::is_alphanumeric) {
+                    r"\b"
+                } else {
+                    ""
+                }
+            };
+
+            format!(
+                "(?i){}{}{}",
+                boundary(pattern.chars().next()),
+                body,
+                boundary(pattern.chars().last()),
+            )
+        }
+    };
+
+    Regex::new(&regex).map_err(|e| format!("Invalid pattern: {}", e))?;
+
+    Ok(regex)
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Entry {
+    pub id: i64,
+    pub kind: PatternKind,
+    pub pattern: String,
+    pub action: Action,
+}
+
+/// All patterns of a guild compiled into a single `RegexSet`
+#[derive(Debug)]
+pub struct FilterSet {
+    set: RegexSet,
+    entries: Vec<Entry>,
+}
+
+impl FilterSet {
+    /// Fails if the patterns are too big together, each of them is valid on its own
+    pub fn compile(entries: Vec<Entry>) -> Result<Self, regex::Error> {
+        let (regexes, entries): (Vec<_>, Vec<_>) = entries
+            .into_iter()
+            .filter_map(|e| match to_regex(e.kind, &e.pattern) {
+                Ok(regex) => Some((regex, e)),
+                Err(why) => {
+                    warn!(?e, %why, "Skipping invalid filter pattern");
+                    None
+                }
+            })
+            .unzip();
+
+        Ok(Self {
+            set: RegexSet::new(regexes)?,
+            entries,
+        })
+    }
+
+    /// Matches nothing, used while the patterns of a guild do not compile
+    pub fn empty() -> Self {
+        Self {
+            set: RegexSet::empty(),
+            entries: Vec::new(),
+        }
+    }
+
+    pub fn matches(&self, text: &str) -> Vec<&Entry> {
+        self.set
+            .matches(text)
+            .into_iter()
+            .map(|i| &self.entries[i])
+            .collect()
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn entry(id: i64, kind: PatternKind, pattern: &str) -> Entry {
+        Entry {
+            id,
+            kind,
+            pattern: pattern.to_string(),
+            action: Action::Delete,
+        }
+    }
+
+    #[test]
+    fn words_match_whole_words_only() {
+        let set = FilterSet::compile(vec![entry(1, PatternKind::Word, "ass")]).unwrap();
+
+        assert_eq!(set.matches("you ASS!").len(), 1);
+        assert!(set.matches("a classic").is_empty());
+    }
+
+    #[test]
+    fn literal_words_are_escaped() {
+        let set = FilterSet::compile(vec![entry(1, PatternKind::Word, "c++")]).unwrap();
+
+        assert_eq!(set.matches("i love c++").len(), 1);
+        assert!(set.matches("i love c").is_empty());
+    }
+
+    #[test]
+    fn wildcards() {
+        let set = FilterSet::compile(vec![
+            entry(1, PatternKind::Wildcard, "scam*"),
+            entry(2, PatternKind::Wildcard, "fr?e nitro"),
+        ])
+        .unwrap();
+
+        assert_eq!(set.matches("what a scammer").len(), 1);
+        assert!(set.matches("no scum here").is_empty());
+        assert_eq!(set.matches("FREE nitro here").len(), 1);
+        assert_eq!(
+            set.matches("scam: free nitro")
+                .iter()
+                .map(|e| e.id)
+                .collect::<Vec<_>>(),
+            vec![1, 2]
+        );
+    }
+
+    #[test]
+    fn regexes_and_invalid_patterns() {
+        assert!(to_regex(PatternKind::Regex, "(unclosed").is_err());
+        assert!(to_regex(PatternKind::Word, "  ").is_err());
+
+        let set = FilterSet::compile(vec![
+            entry(1, PatternKind::Regex, r"steam\w*\.(ru|xyz)"),
+            entry(2, PatternKind::Regex, "(unclosed"),
+        ])
+        .unwrap();
+
+        assert_eq!(set.len(), 1);
+        assert_eq!(set.matches("visit steamcommunlty.ru").len(), 1);
+        assert!(FilterSet::empty()
+            .matches("visit steamcommunlty.ru")
+            .is_empty());
+    }
+
+    #[test]
+    fn exemptions() {
+        let config = Config {
+            exempt_roles: vec![10],
+            exempt_channels: vec![20],
+        };
+
+        assert!(config.is_exempt(20, &[]));
+        assert!(config.is_exempt(1, &[5, 10]));
+        assert!(!config.is_exempt(1, &[5]));
+    }
+}
@@ -1,9 +1,15 @@
+mod appeal;
+mod automod;
 mod fav;
+mod filter;
+mod levels;
+mod poll;
 
-use crate::commands::config::Guild;
+use crate::commands::config::{guild_config, Guild};
+use crate::commands::fav::FavButton;
+use crate::commands::poll::PollButton;
 use crate::commands::userinfo::UserInfo;
 use crate::models::mute::Mute;
-use crate::models::server_config::ServerConfig;
 use crate::util::get_client;
 use chrono::Utc;
 use serenity::{
@@ -11,16 +17,19 @@ use serenity::{
     model::{
         channel::Reaction,
         channel::{ChannelType, Message, ReactionType},
+        event::MessageUpdateEvent,
         gateway::{Activity, Ready},
         guild::Member,
         id::ChannelId,
         id::GuildId,
         id::RoleId,
+        interactions::Interaction,
         user::User,
     },
     prelude::*,
     utils::MessageBuilder,
 };
+use std::sync::Arc;
 use tracing::info;
 
 pub struct Handler;
@@ -75,9 +84,7 @@ impl EventHandler for Handler {
     async fn guild_member_addition(&self, ctx: Context, guild_id: GuildId, mut new_member: Member) {
         let pool = get_client(&ctx).await.unwrap();
 
-        if let Ok(mut config) = ServerConfig::get(&pool, *guild_id.as_u64() as i64).await {
-            let g_cfg: Guild = serde_json::from_value(config.config.take()).unwrap();
-
+        if let Ok(g_cfg) = guild_config(&pool, *guild_id.as_u64() as i64).await {
             let user_info = UserInfo {
                 created_at: new_member
                     .user
@@ -119,6 +126,8 @@ impl EventHandler for Handler {
                 );
             }
 
+            automod::check_join(&ctx, guild_id, &new_member, &g_cfg).await;
+
             let mute =
                 Mute::get(&pool, *guild_id.as_u64() as i64, *member_id.as_u64() as i64).await;
 
@@ -138,9 +147,7 @@ impl EventHandler for Handler {
         _old_member: Option<Member>,
     ) {
         let pool = get_client(&ctx).await.unwrap();
-        if let Ok(mut config) = ServerConfig::get(&pool, *guild_id.as_u64() as i64).await {
-            let g_cfg: Guild = serde_json::from_value(config.config.take()).unwrap();
-
+        if let Ok(g_cfg) = guild_config(&pool, *guild_id.as_u64() as i64).await {
             let user_info = UserInfo {
                 created_at: user.created_at().format("%d.%m.%Y %H:%M:%S").to_string(),
                 created_at_ago: Utc::now()
@@ -179,12 +186,71 @@ impl EventHandler for Handler {
         }
     }
 
+    async fn message(&self, ctx: Context, msg: Message) {
+        if let Some((guild_id, g_cfg)) = message_config(&ctx, &msg).await {
+            let filtered = filter::check_message(&ctx, guild_id, &msg, &g_cfg).await;
+            let violated = automod::check_message(&ctx, guild_id, &msg, &g_cfg).await;
+            // spam that was deleted or punished should not count towards levels
+            if !filtered && !violated {
+                levels::award_xp(&ctx, guild_id, &msg, &g_cfg).await;
+            }
+        }
+    }
+
+    async fn message_update(
+        &self,
+        ctx: Context,
+        _old_if_available: Option<Message>,
+        new: Option<Message>,
+        event: MessageUpdateEvent,
+    ) {
+        let msg = match new {
+            Some(msg) => msg,
+            None => match event.channel_id.message(&ctx, event.id).await {
+                Ok(msg) => msg,
+                Err(_) => return,
+            },
+        };
+
+        if let Some((guild_id, g_cfg)) = message_config(&ctx, &msg).await {
+            filter::check_message(&ctx, guild_id, &msg, &g_cfg).await;
+        }
+    }
+
     async fn reaction_add(&self, ctx: Context, reaction: Reaction) {
         match reaction.emoji {
             ReactionType::Unicode(ref s) if s.starts_with('📗') => {
+                fav::hall_of_fame(&ctx, &reaction).await;
                 fav::add(ctx, reaction).await;
             }
-            _ => (),
+            ReactionType::Unicode(ref s) if s == "\u{2705}" || s == "\u{274c}" => {
+                let accepted = s == "\u{2705}";
+                appeal::decide(ctx, reaction, accepted).await;
+            }
+            _ => poll::reaction(&ctx, &reaction, true).await,
+        }
+    }
+
+    async fn reaction_remove(&self, ctx: Context, reaction: Reaction) {
+        poll::reaction(&ctx, &reaction, false).await;
+    }
+
+    async fn interaction_create(&self, ctx: Context, interaction: Interaction) {
+        if let Interaction::MessageComponent(component) = interaction {
+            if let Some(button) = FavButton::parse(&component.data.custom_id) {
+                fav::button(ctx, component, button).await;
+            } else if let Some(button) = PollButton::parse(&component.data.custom_id) {
+                poll::button(ctx, component, button).await;
+            }
         }
     }
 }
+
+/// Loads the config of the guild the message was sent in, ignoring bots and dms
+async fn message_config(ctx: &Context, msg: &Message) -> Option<(GuildId, Arc<Guild>)> {
+    let guild_id = msg.guild_id.filter(|_| !msg.author.bot)?;
+    let pool = get_client(ctx).await.ok()?;
+    let g_cfg = guild_config(&pool, *guild_id.as_u64() as i64).await.ok()?;
+
+    Some((guild_id, g_cfg))
+}
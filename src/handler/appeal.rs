@@ -0,0 +1,101 @@
+use crate::commands::config::Guild;
+use crate::commands::moderation::{lift_mute, send_modlog};
+use crate::models::sanction::Sanction;
+use crate::models::server_config::ServerConfig;
+use crate::util::get_client;
+use serenity::{
+    model::{channel::Reaction, id::GuildId, id::UserId},
+    prelude::*,
+    utils::MessageBuilder,
+};
+use tracing::error;
+
+pub async fn decide(ctx: Context, reaction: Reaction, accepted: bool) {
+    let moderator = match reaction.user_id {
+        Some(user_id) if user_id != ctx.cache.current_user_id().await => user_id,
+        _ => return,
+    };
+    let pool = get_client(&ctx).await.unwrap();
+
+    // not every reaction is on an appeal
+    let sanction = match Sanction::by_appeal_msg(&pool, *reaction.message_id.as_u64() as i64).await
+    {
+        Ok(sanction) => sanction,
+        Err(_) => return,
+    };
+
+    // only the mods decide, like the commands allowed for the Mods role
+    let guild_id = GuildId(sanction.server_id as u64);
+    let is_mod = match (
+        guild_id.member(&ctx, moderator).await,
+        guild_id.roles(&ctx).await,
+    ) {
+        (Ok(member), Ok(roles)) => member
+            .roles
+            .iter()
+            .any(|r| roles.get(r).map_or(false, |role| role.name == "Mods")),
+        _ => false,
+    };
+    if !is_mod {
+        return;
+    }
+
+    // only the first decision counts
+    let sanction =
+        match Sanction::decide(&pool, sanction.id, accepted, *moderator.as_u64() as i64).await {
+            Ok(sanction) => sanction,
+            Err(_) => return,
+        };
+
+    let user_id = UserId(sanction.user_id as u64);
+    let g_cfg: Guild = match ServerConfig::get(&pool, sanction.server_id).await {
+        Ok(mut config) => serde_json::from_value(config.config.take()).unwrap(),
+        Err(_e) => Guild::default(),
+    };
+
+    if accepted {
+        let result = match sanction.kind.as_str() {
+            "ban" => guild_id.unban(&ctx, user_id).await.map_err(Into::into),
+            _ => match g_cfg.mute_role {
+                Some(mute_role) => lift_mute(&ctx, &pool, guild_id, user_id, mute_role)
+                    .await
+                    .map(|_| ()),
+                None => Err("No mute role configured".into()),
+            },
+        };
+
+        if let Err(e) = result {
+            error!(?e, "failed to lift sanction after appeal");
+        }
+    }
+
+    let decision = if accepted { "accepted" } else { "denied" };
+    let guild_name = guild_id
+        .name(&ctx)
+        .await
+        .unwrap_or_else(|| "the server".to_string());
+
+    if let Ok(dm_channel) = user_id.create_dm_channel(&ctx).await {
+        std::mem::drop(
+            dm_channel
+                .say(
+                    &ctx,
+                    format!(
+                        "Your appeal for the {} on {} was {}.",
+                        sanction.kind, guild_name, decision
+                    ),
+                )
+                .await,
+        );
+    }
+
+    let description = MessageBuilder::new()
+        .push(format!("Appeal for {} #{} of ", sanction.kind, sanction.id))
+        .mention(&user_id)
+        .push(format!(" was **{}** by ", decision))
+        .mention(&moderator)
+        .build();
+
+    std::mem::drop(reaction.channel_id.say(&ctx, &description).await);
+    send_modlog(&ctx, g_cfg.modlog_channel, &description).await;
+}
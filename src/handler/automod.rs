@@ -0,0 +1,212 @@
+use crate::automod::{Action, MessageEvent};
+use crate::commands::config::Guild;
+use crate::commands::moderation::{lock, remove_mute, send_modlog};
+use crate::models::mute::Mute;
+use crate::util::get_client;
+use crate::AutoModState;
+use chrono::{Duration, Utc};
+use serenity::{
+    model::{channel::Message, guild::Member, id::GuildId, id::RoleId, id::UserId},
+    prelude::*,
+    utils::MessageBuilder,
+};
+use tracing::{debug, error};
+
+/// Feeds a message to the automod engine, returning whether any rule was violated
+pub async fn check_message(ctx: &Context, guild_id: GuildId, msg: &Message, g_cfg: &Guild) -> bool {
+    if !g_cfg.automod.enabled {
+        return false;
+    }
+
+    let engine = ctx
+        .data
+        .read()
+        .await
+        .get::<AutoModState>()
+        .expect("Expected automod state")
+        .clone();
+
+    let violations = engine.lock().await.on_message(
+        &g_cfg.automod,
+        &MessageEvent {
+            guild_id: *guild_id.as_u64(),
+            user_id: *msg.author.id.as_u64(),
+            at: Utc::now(),
+            content: &msg.content,
+            mentions: msg.mentions.len() + msg.mention_roles.len(),
+            attachments: msg.attachments.len(),
+        },
+    );
+
+    let acted = !violations.is_empty();
+    let mut applied = Vec::new();
+    for violation in violations {
+        debug!(?violation, user = ?msg.author.id, "Automod violation");
+
+        if !applied.contains(&violation.action) {
+            apply(
+                ctx,
+                guild_id,
+                g_cfg,
+                msg.author.id,
+                Some(msg),
+                violation.action,
+                violation.rule.describe(),
+            )
+            .await;
+            applied.push(violation.action);
+        }
+
+        send_modlog(
+            ctx,
+            g_cfg.modlog_channel,
+            &MessageBuilder::new()
+                .push_bold("Automod: ")
+                .push(violation.rule.describe())
+                .push(" by ")
+                .mention(&msg.author)
+                .push(" in ")
+                .mention(&msg.channel_id)
+                .push(" | action: ")
+                .push(violation.action.to_string())
+                .build(),
+        )
+        .await;
+    }
+
+    acted
+}
+
+pub async fn check_join(ctx: &Context, guild_id: GuildId, member: &Member, g_cfg: &Guild) {
+    let engine = ctx
+        .data
+        .read()
+        .await
+        .get::<AutoModState>()
+        .expect("Expected automod state")
+        .clone();
+
+    let violation = engine
+        .lock()
+        .await
+        .on_join(&g_cfg.automod, *guild_id.as_u64(), Utc::now());
+
+    if let Some(violation) = violation {
+        apply(
+            ctx,
+            guild_id,
+            g_cfg,
+            member.user.id,
+            None,
+            violation.action,
+            violation.rule.describe(),
+        )
+        .await;
+
+        send_modlog(
+            ctx,
+            g_cfg.modlog_channel,
+            &MessageBuilder::new()
+                .push_bold("Automod: ")
+                .push(violation.rule.describe())
+                .push(" detected on join of ")
+                .mention(&member.user)
+                .push(" | action: ")
+                .push(violation.action.to_string())
+                .build(),
+        )
+        .await;
+    }
+}
+
+/// Applies an automod action, `reason` is shown to the user and in the audit log
+pub async fn apply(
+    ctx: &Context,
+    guild_id: GuildId,
+    g_cfg: &Guild,
+    user_id: UserId,
+    msg: Option<&Message>,
+    action: Action,
+    reason: &str,
+) {
+    match action {
+        Action::Delete => {
+            if let Some(msg) = msg {
+                std::mem::drop(msg.delete(ctx).await);
+            }
+        }
+        Action::Warn => {
+            if let Some(msg) = msg {
+                std::mem::drop(
+                    msg.channel_id
+                        .say(
+                            ctx,
+                            MessageBuilder::new()
+                                .mention(&user_id)
+                                .push(" please stop ")
+                                .push(reason)
+                                .push("!")
+                                .build(),
+                        )
+                        .await,
+                );
+            }
+        }
+        Action::Mute { secs } => {
+            let mute_role = match g_cfg.mute_role {
+                Some(mute_role) => mute_role,
+                None => {
+                    debug!("No mute role configured");
+                    return;
+                }
+            };
+
+            match guild_id.member(ctx, user_id).await {
+                Ok(mut member) => {
+                    if let Err(e) = member.add_role(ctx, RoleId(mute_role)).await {
+                        error!(?e, "failed to add mute role to member");
+                    }
+                }
+                Err(e) => error!("could not get member: {:?}", e),
+            }
+
+            let duration = Duration::seconds(secs as i64);
+            if let Ok(pool) = get_client(ctx).await {
+                if let Err(e) = Mute::create(
+                    &pool,
+                    *guild_id.as_u64() as i64,
+                    *user_id.as_u64() as i64,
+                    Utc::now() + duration,
+                )
+                .await
+                {
+                    debug!(error = ?e, "failed to create mute");
+                }
+            }
+
+            tokio::spawn(remove_mute(
+                ctx.clone(),
+                guild_id,
+                *user_id.as_u64(),
+                duration,
+                mute_role,
+            ));
+        }
+        Action::Kick => {
+            std::mem::drop(guild_id.kick_with_reason(ctx, user_id, reason).await);
+        }
+        Action::Lockdown { secs } => {
+            // spam is locked down where it happens, raids lock the whole server
+            if let Err(e) = lock(
+                ctx,
+                guild_id,
+                msg.map(|m| m.channel_id),
+                secs.map(|secs| Duration::seconds(secs as i64)),
+            )
+            .await
+            {
+                error!(?e, "failed to lock down");
+            }
+        }
+    }
+}
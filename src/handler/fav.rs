@@ -1,17 +1,44 @@
+use crate::commands::config::Guild;
+use crate::commands::fav::{fav_content, prefers_live, send_fav, FavButton, LABEL_REQUEST};
+use crate::fav_index;
+use crate::models::fav::query::{Atom, Query, Term};
 use crate::models::fav::Fav;
 use crate::models::fav_block::FavBlock;
-use crate::models::tag::Tag;
+use crate::models::hall_of_fame::HallOfFame;
+use crate::models::server_config::ServerConfig;
+use crate::models::tag::{normalize, Tag, TagChange};
+use crate::util;
 use crate::util::get_client;
-use serenity::{model::channel::Reaction, prelude::*};
+use crate::OptOut;
+use rand::prelude::*;
+use serenity::{
+    model::interactions::{
+        message_component::MessageComponentInteraction,
+        InteractionApplicationCommandCallbackDataFlags, InteractionResponseType,
+    },
+    model::{channel::Reaction, id::ChannelId, id::UserId},
+    prelude::*,
+};
+use sqlx::postgres::PgPool;
+use std::collections::HashSet;
 use std::time::Duration;
-use tracing::trace;
+use tracing::{debug, error, trace};
 
 pub async fn add(ctx: Context, add_reaction: Reaction) {
     let pool = get_client(&ctx).await.unwrap();
+    let fav_msg = add_reaction
+        .message(&ctx.http)
+        .await
+        .expect("Could not get faved message");
+
     if FavBlock::check_blocked(
         &pool,
+        add_reaction
+            .guild_id
+            .map_or(0, |g_id| *g_id.as_u64() as i64),
         *add_reaction.channel_id.as_u64() as i64,
         *add_reaction.message_id.as_u64() as i64,
+        *fav_msg.author.id.as_u64() as i64,
     )
     .await
     {
@@ -39,13 +66,8 @@ pub async fn add(ctx: Context, add_reaction: Reaction) {
         *add_reaction.channel_id.as_u64() as i64,
         *add_reaction.message_id.as_u64() as i64,
         *add_reaction.user_id.unwrap().as_u64() as i64,
-        *add_reaction
-            .message(&ctx.http)
-            .await
-            .unwrap()
-            .author
-            .id
-            .as_u64() as i64,
+        *fav_msg.author.id.as_u64() as i64,
+        &crate::commands::fav::snapshot_of(&ctx, &fav_msg).await,
     )
     .await
     .expect("Could not create fav");
@@ -53,36 +75,345 @@ pub async fn add(ctx: Context, add_reaction: Reaction) {
     if let Ok(dm_channel) = add_reaction.user_id.unwrap().create_dm_channel(&ctx).await {
         trace!(user = ?add_reaction.user_id, "Requesting tags from user");
 
-        let content = format!(
-            "Tags please! (space-separated): {}",
-            add_reaction.message(&ctx.http).await.unwrap().content
-        );
+        let content = format!("Tags please! (space-separated): {}", fav_msg.content);
         std::mem::drop(dm_channel.say(&ctx, content).await);
 
-        if let Some(label_reply) = dm_channel
-            .id
-            .await_reply(&ctx)
-            .author_id(add_reaction.user_id.unwrap())
-            .timeout(Duration::from_secs(120))
-            .await
+        if let Some(label_reply) = util::await_reply(
+            &ctx,
+            dm_channel.id,
+            add_reaction.user_id.unwrap(),
+            Duration::from_secs(120),
+        )
+        .await
         {
-            // fresh fav so it has no old tags to be deleted, just add the new ones
-
-            // TODO: make this a single statement
-            for tag in label_reply.content.split(' ') {
-                let r = Tag::create(&pool, created_fav.id, tag).await;
-
-                trace!(tag_creation = ?r, "Tag created!");
-            }
+            let r = Tag::apply(
+                &pool,
+                created_fav.id,
+                &TagChange::Replace(normalize(&label_reply.content)),
+            )
+            .await;
+            trace!(tag_creation = ?r, "Tags created!");
 
             std::mem::drop(
                 label_reply
                     .reply(
                         &ctx,
-                        "Tags added! To edit tags, react with 🏷️ on the posted fav",
+                        "Tags added! To edit them, press the 🏷️ Tags button on the posted fav",
                     )
                     .await,
             );
         }
     }
+
+    // index after tagging, so the tags are searchable too
+    if let Err(e) = fav_index::index_fav(&ctx, &pool, &created_fav).await {
+        debug!(?e, "Could not index fav");
+    }
+}
+
+/// Reposts the message to the hall of fame channel once enough distinct users faved it
+pub async fn hall_of_fame(ctx: &Context, reaction: &Reaction) {
+    let guild_id = match reaction.guild_id {
+        Some(guild_id) => guild_id,
+        None => return,
+    };
+    let pool = get_client(ctx).await.unwrap();
+
+    let config = match ServerConfig::get(&pool, *guild_id.as_u64() as i64).await {
+        Ok(mut config) => match serde_json::from_value::<Guild>(config.config.take()) {
+            Ok(g_cfg) => g_cfg.favs,
+            Err(_e) => return,
+        },
+        Err(_e) => return,
+    };
+    let hall = match config.hall_of_fame {
+        Some(channel_id) if channel_id != *reaction.channel_id.as_u64() => ChannelId(channel_id),
+        _ => return,
+    };
+
+    let message = match reaction.message(ctx).await {
+        Ok(message) => message,
+        Err(e) => {
+            debug!(?e, "Could not get message for the hall of fame");
+            return;
+        }
+    };
+
+    if FavBlock::check_blocked(
+        &pool,
+        *guild_id.as_u64() as i64,
+        *reaction.channel_id.as_u64() as i64,
+        *reaction.message_id.as_u64() as i64,
+        *message.author.id.as_u64() as i64,
+    )
+    .await
+    {
+        return;
+    }
+
+    let opted_out = match ctx.data.read().await.get::<OptOut>() {
+        Some(opt_out) => opt_out
+            .lock()
+            .await
+            .set
+            .contains(message.author.id.as_u64()),
+        None => true,
+    };
+    if opted_out {
+        return;
+    }
+
+    // favs of the author and of bots don't count
+    let favs = match reaction
+        .users(ctx, reaction.emoji.clone(), Some(100), None::<UserId>)
+        .await
+    {
+        Ok(users) => users
+            .iter()
+            .filter(|user| !user.bot && user.id != message.author.id)
+            .map(|user| user.id)
+            .collect::<HashSet<_>>()
+            .len() as u64,
+        Err(e) => {
+            debug!(?e, "Could not get the users who faved");
+            return;
+        }
+    };
+    if favs < config.hall_of_fame_threshold {
+        return;
+    }
+
+    // claiming first makes sure concurrent reactions repost only once
+    let entry = match HallOfFame::create(
+        &pool,
+        *guild_id.as_u64() as i64,
+        *reaction.channel_id.as_u64() as i64,
+        *reaction.message_id.as_u64() as i64,
+    )
+    .await
+    {
+        Ok(Some(entry)) => entry,
+        _ => return,
+    };
+
+    let snapshot = crate::commands::fav::snapshot_of(ctx, &message).await;
+    let link = format!(
+        "https://discord.com/channels/{}/{}/{}",
+        guild_id, reaction.channel_id, reaction.message_id
+    );
+    let post = hall
+        .send_message(ctx, |m| {
+            m.content(format!("📗 {} | <#{}>", favs, reaction.channel_id))
+                .embed(|e| {
+                    let mut embed = e
+                        .author(|a| {
+                            a.name(&snapshot.author_name)
+                                .icon_url(&snapshot.author_avatar)
+                        })
+                        .description(format!(
+                            "{}\n\n[Jump to message]({})",
+                            snapshot.content, link
+                        ))
+                        .color((0, 120, 220))
+                        .footer(|f| {
+                            f.text(format!(
+                                "{} (UTC) | #{}",
+                                snapshot.timestamp.format("%d.%m.%Y, %H:%M:%S"),
+                                snapshot.channel_name
+                            ))
+                        });
+
+                    if let Some(image) = snapshot.image() {
+                        embed = embed.image(image);
+                    }
+
+                    embed
+                })
+        })
+        .await;
+
+    match post {
+        Ok(post) => {
+            std::mem::drop(HallOfFame::set_post(&pool, entry.id, *post.id.as_u64() as i64).await)
+        }
+        Err(e) => {
+            error!(?e, "Could not post to the hall of fame");
+            // give it another chance with the next reaction
+            std::mem::drop(HallOfFame::delete(&pool, entry.id).await);
+        }
+    }
+}
+
+async fn respond(ctx: &Context, component: &MessageComponentInteraction, content: &str) {
+    if let Err(e) = component
+        .create_interaction_response(ctx, |r| {
+            r.kind(InteractionResponseType::ChannelMessageWithSource)
+                .interaction_response_data(|d| {
+                    d.content(content)
+                        .flags(InteractionApplicationCommandCallbackDataFlags::EPHEMERAL)
+                })
+        })
+        .await
+    {
+        debug!(?e, "Could not respond to fav button");
+    }
+}
+
+/// Like `respond`, for interactions that were already acknowledged
+async fn follow_up(ctx: &Context, component: &MessageComponentInteraction, content: &str) {
+    if let Err(e) = component
+        .create_followup_message(ctx, |f| {
+            f.content(content)
+                .flags(InteractionApplicationCommandCallbackDataFlags::EPHEMERAL)
+        })
+        .await
+    {
+        debug!(?e, "Could not follow up on fav button");
+    }
+}
+
+/// Handles the buttons of posted favs. Everyone may look at the source, the rest is only
+/// for who saved the fav.
+pub async fn button(ctx: Context, component: MessageComponentInteraction, button: FavButton) {
+    let pool = get_client(&ctx).await.unwrap();
+
+    let fav = match Fav::by_ids(&pool, &[button.fav_id()])
+        .await
+        .ok()
+        .and_then(|favs| favs.into_iter().next())
+    {
+        Some(fav) => fav,
+        None => {
+            respond(&ctx, &component, "This fav does not exist anymore").await;
+            return;
+        }
+    };
+
+    let is_owner = fav.user_id == *component.user.id.as_u64() as i64;
+    match button {
+        FavButton::Source(_) => {
+            let link = format!(
+                "https://discord.com/channels/{}/{}/{}",
+                fav.server_id, fav.channel_id, fav.msg_id
+            );
+            respond(&ctx, &component, &link).await;
+        }
+        _ if !is_owner => {
+            respond(&ctx, &component, "Only who saved the fav can do that").await;
+        }
+        FavButton::Delete(_) => {
+            std::mem::drop(Fav::delete(&pool, fav.id).await);
+            std::mem::drop(fav_index::remove_favs(&[fav.id]).await);
+            respond(&ctx, &component, "Fav deleted").await;
+            std::mem::drop(component.message.delete(&ctx).await);
+        }
+        FavButton::Tags(_) => {
+            let dm_channel = match component.user.create_dm_channel(&ctx).await {
+                Ok(dm_channel) => dm_channel,
+                Err(_e) => {
+                    respond(&ctx, &component, "I can not send you DMs").await;
+                    return;
+                }
+            };
+            respond(&ctx, &component, "Check your DMs").await;
+
+            trace!(user = ?component.user.id, "Requesting labels from user");
+            std::mem::drop(dm_channel.say(&ctx, LABEL_REQUEST).await);
+            if let Some(label_reply) = util::await_reply(
+                &ctx,
+                dm_channel.id,
+                component.user.id,
+                Duration::from_secs(120),
+            )
+            .await
+            {
+                let change = TagChange::parse(&label_reply.content);
+                std::mem::drop(Tag::apply(&pool, fav.id, &change).await);
+                std::mem::drop(fav_index::index_fav(&ctx, &pool, &fav).await);
+
+                std::mem::drop(label_reply.reply(&ctx, "Tags changed!").await);
+            }
+        }
+        FavButton::Another(_) => another(&ctx, &pool, &component, &fav).await,
+    }
+}
+
+async fn another(ctx: &Context, pool: &PgPool, component: &MessageComponentInteraction, fav: &Fav) {
+    // the lookups can take longer than discord waits for an answer
+    if let Err(e) = component
+        .create_interaction_response(ctx, |r| {
+            r.kind(InteractionResponseType::DeferredUpdateMessage)
+        })
+        .await
+    {
+        debug!(?e, "Could not acknowledge fav button");
+    }
+
+    let query = Query {
+        groups: match Tag::of_fav(pool, fav.id).await {
+            Ok(tags) => tags
+                .into_iter()
+                .map(|tag| {
+                    vec![Term {
+                        negated: false,
+                        atom: Atom::Tag(tag.label),
+                    }]
+                })
+                .collect(),
+            Err(_e) => Vec::new(),
+        },
+    };
+
+    let opted_out = match ctx.data.read().await.get::<OptOut>() {
+        Some(opt_out) => opt_out.lock().await.set.clone(),
+        None => HashSet::new(),
+    };
+    let next = match Fav::query(
+        pool,
+        fav.user_id,
+        component.guild_id.map(|g_id| *g_id.as_u64() as i64),
+        None,
+        &query,
+    )
+    .await
+    {
+        Ok(favs) => favs
+            .into_iter()
+            .filter(|f| f.id != fav.id && !opted_out.contains(&(f.author_id as u64)))
+            .choose(&mut rand::thread_rng()),
+        Err(e) => {
+            debug!(?e, "Could not query favs");
+            None
+        }
+    };
+    let next = match next {
+        Some(next) => next,
+        None => {
+            follow_up(ctx, component, "There is no other fav with these tags").await;
+            return;
+        }
+    };
+
+    let shown = match fav_content(ctx, &next, prefers_live(pool, component.guild_id).await).await {
+        Ok(shown) => shown,
+        Err(e) => {
+            debug!(?e, "Could not get fav content");
+            follow_up(ctx, component, "The fav is not available anymore").await;
+            return;
+        }
+    };
+
+    if let Err(e) = send_fav(
+        ctx,
+        pool,
+        component.channel_id,
+        component.guild_id,
+        &component.user,
+        &next,
+        &shown,
+    )
+    .await
+    {
+        error!(?e, "Could not post fav");
+    }
 }
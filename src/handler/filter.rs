@@ -0,0 +1,78 @@
+use super::automod::apply;
+use crate::commands::config::Guild;
+use crate::commands::filter::filter_set;
+use crate::commands::moderation::send_modlog;
+use serenity::{
+    model::{channel::Message, id::GuildId},
+    prelude::*,
+    utils::MessageBuilder,
+};
+use tracing::{debug, error};
+
+/// Applies the word filter to a message, returning whether it matched and was acted on
+pub async fn check_message(ctx: &Context, guild_id: GuildId, msg: &Message, g_cfg: &Guild) -> bool {
+    let set = match filter_set(ctx, guild_id).await {
+        Ok(set) => set,
+        Err(e) => {
+            error!(?e, "Could not load word filter");
+            return false;
+        }
+    };
+
+    let matched = set.matches(&msg.content);
+    if matched.is_empty() {
+        return false;
+    }
+
+    let roles = match &msg.member {
+        Some(member) => member.roles.iter().map(|r| *r.as_u64()).collect(),
+        None => guild_id
+            .member(ctx, msg.author.id)
+            .await
+            .map(|m| m.roles.iter().map(|r| *r.as_u64()).collect())
+            .unwrap_or_else(|_| Vec::new()),
+    };
+
+    if g_cfg.filter.is_exempt(*msg.channel_id.as_u64(), &roles) {
+        debug!(user = ?msg.author.id, "Filter match is exempt");
+        return false;
+    }
+
+    let mut applied = Vec::new();
+    for entry in matched {
+        if !applied.contains(&entry.action) {
+            apply(
+                ctx,
+                guild_id,
+                g_cfg,
+                msg.author.id,
+                Some(msg),
+                entry.action,
+                "using filtered words",
+            )
+            .await;
+            applied.push(entry.action);
+        }
+
+        send_modlog(
+            ctx,
+            g_cfg.modlog_channel,
+            &MessageBuilder::new()
+                .push_bold("Filter: ")
+                .push("message by ")
+                .mention(&msg.author)
+                .push(" in ")
+                .mention(&msg.channel_id)
+                .push(format!(" matched filter {} ", entry.id))
+                .push_mono_safe(&entry.pattern)
+                .push(" | action: ")
+                .push(entry.action.to_string())
+                .push_line("")
+                .push_quote_safe(&msg.content)
+                .build(),
+        )
+        .await;
+    }
+
+    true
+}
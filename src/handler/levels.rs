@@ -0,0 +1,60 @@
+use crate::commands::config::Guild;
+use crate::commands::levels::{grant_rewards, level_of};
+use crate::models::level::Level;
+use crate::util::get_client;
+use serenity::{
+    model::{channel::Message, id::GuildId},
+    prelude::*,
+};
+use tracing::error;
+
+pub async fn award_xp(ctx: &Context, guild_id: GuildId, msg: &Message, g_cfg: &Guild) {
+    let xp = match g_cfg
+        .levels
+        .xp_for(*msg.channel_id.as_u64(), &mut rand::thread_rng())
+    {
+        Some(xp) => xp,
+        None => return,
+    };
+    let pool = match get_client(ctx).await {
+        Ok(pool) => pool,
+        Err(e) => {
+            error!(?e, "Could not get db pool");
+            return;
+        }
+    };
+
+    let level = match Level::award(
+        &pool,
+        *guild_id.as_u64() as i64,
+        *msg.author.id.as_u64() as i64,
+        xp,
+        g_cfg.levels.cooldown_secs,
+    )
+    .await
+    {
+        Ok(Some(level)) => level,
+        Ok(None) => return,
+        Err(e) => {
+            error!(?e, "Could not award xp");
+            return;
+        }
+    };
+
+    let (before, after) = (level_of(level.xp - xp), level_of(level.xp));
+    if after <= before {
+        return;
+    }
+
+    grant_rewards(ctx, guild_id, msg.author.id, &g_cfg.levels, before, after).await;
+    if g_cfg.levels.announce {
+        std::mem::drop(
+            msg.channel_id
+                .say(
+                    ctx,
+                    format!("<@{}> reached level {}!", msg.author.id, after),
+                )
+                .await,
+        );
+    }
+}
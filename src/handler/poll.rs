@@ -0,0 +1,124 @@
+use crate::commands::poll::{answer_emoji, emoji_answer, update_poll_message, PollButton};
+use crate::models::poll::Poll;
+use crate::util::get_client;
+use serenity::{
+    model::channel::{Reaction, ReactionType},
+    model::interactions::{
+        message_component::MessageComponentInteraction,
+        InteractionApplicationCommandCallbackDataFlags, InteractionResponseType,
+    },
+    prelude::*,
+};
+use tracing::{debug, error};
+
+/// Counts number reactions on public polls, single choice polls drop the other reactions
+pub async fn reaction(ctx: &Context, reaction: &Reaction, added: bool) {
+    let answer = match &reaction.emoji {
+        ReactionType::Unicode(emoji) => match emoji_answer(emoji) {
+            Some(answer) => answer,
+            None => return,
+        },
+        _ => return,
+    };
+    let user_id = match reaction.user_id {
+        Some(user_id) if user_id != ctx.cache.current_user_id().await => user_id,
+        _ => return,
+    };
+    let pool = match get_client(ctx).await {
+        Ok(pool) => pool,
+        Err(_) => return,
+    };
+
+    // not every reaction is on a poll
+    let poll = match Poll::by_msg(&pool, *reaction.message_id.as_u64() as i64).await {
+        Ok(poll) if !poll.anonymous && !poll.closed && answer < poll.answers.len() => poll,
+        _ => return,
+    };
+
+    let result = if added {
+        poll.vote(&pool, *user_id.as_u64() as i64, answer as i32)
+            .await
+    } else {
+        poll.unvote(&pool, *user_id.as_u64() as i64, answer as i32)
+            .await
+            .map(|_| Vec::new())
+    };
+    let replaced = match result {
+        Ok(replaced) => replaced,
+        Err(e) => {
+            error!(?e, "failed to count poll vote");
+            return;
+        }
+    };
+
+    for answer in replaced {
+        std::mem::drop(
+            reaction
+                .channel_id
+                .delete_reaction(
+                    ctx,
+                    reaction.message_id,
+                    Some(user_id),
+                    ReactionType::Unicode(answer_emoji(answer as usize)),
+                )
+                .await,
+        );
+    }
+
+    update_poll_message(&ctx.http, &pool, &poll).await;
+}
+
+async fn respond(ctx: &Context, component: &MessageComponentInteraction, content: &str) {
+    if let Err(e) = component
+        .create_interaction_response(ctx, |r| {
+            r.kind(InteractionResponseType::ChannelMessageWithSource)
+                .interaction_response_data(|d| {
+                    d.content(content)
+                        .flags(InteractionApplicationCommandCallbackDataFlags::EPHEMERAL)
+                })
+        })
+        .await
+    {
+        debug!(?e, "Could not respond to poll button");
+    }
+}
+
+/// Votes of anonymous polls, a second click on an answer takes the vote back
+pub async fn button(ctx: Context, component: MessageComponentInteraction, button: PollButton) {
+    let pool = get_client(&ctx).await.unwrap();
+    let server_id = component.guild_id.map_or(0, |g_id| *g_id.as_u64() as i64);
+
+    let poll = match Poll::get(&pool, server_id, button.poll_id).await {
+        Ok(poll) if button.answer < poll.answers.len() => poll,
+        _ => {
+            respond(&ctx, &component, "This poll does not exist anymore").await;
+            return;
+        }
+    };
+    if poll.closed {
+        respond(&ctx, &component, "This poll is closed").await;
+        return;
+    }
+
+    let user_id = *component.user.id.as_u64() as i64;
+    let answer = &poll.answers[button.answer];
+    let result = match poll.unvote(&pool, user_id, button.answer as i32).await {
+        Ok(0) => poll
+            .vote(&pool, user_id, button.answer as i32)
+            .await
+            .map(|_| format!("You voted for {}", answer)),
+        Ok(_) => Ok(format!("You took back your vote for {}", answer)),
+        Err(e) => Err(e),
+    };
+
+    match result {
+        Ok(feedback) => {
+            respond(&ctx, &component, &feedback).await;
+            update_poll_message(&ctx.http, &pool, &poll).await;
+        }
+        Err(e) => {
+            error!(?e, "failed to count poll vote");
+            respond(&ctx, &component, "Your vote could not be counted").await;
+        }
+    }
+}
@@ -16,8 +16,11 @@
 #[macro_use]
 extern crate tantivy;
 
+mod automod;
 mod commands;
 mod config;
+mod fav_index;
+mod filter;
 mod handler;
 mod migrations;
 mod models;
@@ -41,7 +44,7 @@ use serenity::{
     prelude::*,
 };
 use sqlx::postgres::{PgPool, PgPoolOptions};
-use std::collections::HashSet;
+use std::collections::{HashMap, HashSet};
 use std::sync::Arc;
 use tokio::sync::Mutex;
 use tracing::{debug, error, trace, warn};
@@ -66,6 +69,21 @@ impl TypeMapKey for RulesState {
     type Value = Arc<Mutex<self::rules::State>>;
 }
 
+struct AutoModState;
+impl TypeMapKey for AutoModState {
+    type Value = Arc<Mutex<self::automod::Engine>>;
+}
+
+struct WordFilterCache;
+impl TypeMapKey for WordFilterCache {
+    type Value = Arc<Mutex<HashMap<u64, Arc<self::filter::FilterSet>>>>;
+}
+
+struct AwaitingReply;
+impl TypeMapKey for AwaitingReply {
+    type Value = Arc<Mutex<PendingReplies>>;
+}
+
 struct OptOut;
 impl TypeMapKey for OptOut {
     type Value = Arc<Mutex<OptOutStore>>;
@@ -114,6 +132,15 @@ impl XkcdIndexStorage {
     }
 }
 
+/// Users waiting for a reply collector, so their answers are not handled as normal messages
+#[derive(Default)]
+struct PendingReplies {
+    /// Running collectors per user
+    pub users: HashMap<u64, usize>,
+    /// Collected messages and when they were collected
+    pub collected: HashMap<u64, std::time::Instant>,
+}
+
 #[derive(Serialize, Deserialize)]
 struct OptOutStore {
     pub set: HashSet<u64>,
@@ -143,6 +170,10 @@ impl OptOutStore {
 static XKCD_INDEX: OnceCell<tantivy::Index> = OnceCell::new();
 static XKCD_INDEX_READER: OnceCell<tantivy::IndexReader> = OnceCell::new();
 static XKCD_INDEX_SCHEMA: OnceCell<tantivy::schema::Schema> = OnceCell::new();
+static FAV_INDEX: OnceCell<tantivy::Index> = OnceCell::new();
+static FAV_INDEX_READER: OnceCell<tantivy::IndexReader> = OnceCell::new();
+static FAV_INDEX_SCHEMA: OnceCell<tantivy::schema::Schema> = OnceCell::new();
+static FAV_INDEX_WRITER: OnceCell<std::sync::Mutex<tantivy::IndexWriter>> = OnceCell::new();
 static MESSAGE_REGEX: OnceCell<regex::Regex> = OnceCell::new();
 
 #[help]
@@ -191,8 +222,12 @@ async fn unknown_command(_ctx: &Context, _msg: &Message, unknown_command_name: &
 }
 
 #[hook]
-async fn normal_message(_ctx: &Context, msg: &Message) {
+async fn normal_message(ctx: &Context, msg: &Message) {
     trace!("Message is not a command '{}'", msg.content);
+
+    if msg.is_private() {
+        commands::modmail::handle_dm(ctx, msg).await;
+    }
 }
 
 #[hook]
@@ -283,7 +318,10 @@ async fn main() {
         // .group(&commands::groups::rules::RULES_GROUP)
         .group(&commands::groups::account::ACCOUNT_GROUP)
         .group(&commands::groups::moderation::MODERATION_GROUP)
+        .group(&commands::groups::filter::FILTER_GROUP)
+        .group(&commands::groups::modmail::MODMAIL_GROUP)
         .group(&commands::groups::misc::MISC_GROUP)
+        .group(&commands::groups::levels::LEVELS_GROUP)
         .group(&commands::groups::lastfm::LASTFM_GROUP);
     debug!("Framework created");
 
@@ -307,6 +345,7 @@ async fn main() {
     let opt_out = Arc::new(Mutex::new(OptOutStore::load_or_init()));
 
     startup::init_xkcd(&config).await;
+    startup::init_fav_index(&config).await;
 
     MESSAGE_REGEX
         .set(
@@ -322,6 +361,9 @@ async fn main() {
 
         data.insert::<ShardManagerContainer>(Arc::clone(&client.shard_manager));
         data.insert::<RulesState>(rules_state);
+        data.insert::<AutoModState>(Arc::new(Mutex::new(self::automod::Engine::new())));
+        data.insert::<WordFilterCache>(Arc::new(Mutex::new(HashMap::new())));
+        data.insert::<AwaitingReply>(Arc::new(Mutex::new(PendingReplies::default())));
         data.insert::<OptOut>(Arc::clone(&opt_out));
         data.insert::<DatabasePool>(pool);
         data.insert::<ReqwestClient>(reqwest::Client::new());
@@ -1,9 +1,25 @@
 pub mod bank;
+pub mod bank_transaction;
+pub mod blackjack_round;
+pub mod collection;
+pub mod duel;
 pub mod fav;
 pub mod fav_block;
+pub mod fav_post;
+pub mod hall_of_fame;
+pub mod inventory_item;
 pub mod lastfm;
+pub mod level;
+pub mod lockdown;
 pub mod mute;
+pub mod poll;
 pub mod reminder;
+pub mod sanction;
 pub mod server_config;
 pub mod shiny;
+pub mod shiny_contribution;
+pub mod shop_item;
 pub mod tag;
+pub mod ticket;
+pub mod ticket_message;
+pub mod word_filter;
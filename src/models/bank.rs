@@ -1,61 +1,407 @@
-use chrono::NaiveDateTime;
-use sqlx::postgres::PgPool;
+use super::bank_transaction::Kind;
+use chrono::{NaiveDateTime, Utc};
+use sqlx::postgres::{PgPool, Postgres};
+use sqlx::Transaction;
+use std::fmt;
 
 pub type DbError = sqlx::Error;
 
 #[derive(Debug, sqlx::FromRow)]
 pub struct Bank {
     pub id: i64,
+    pub server_id: i64,
     pub user_id: i64,
     pub user_name: String,
     pub amount: i64,
     pub last_payday: NaiveDateTime,
 }
 
+/// One change of a balance
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Entry {
+    pub user_id: i64,
+    pub kind: Kind,
+    pub amount: i64,
+    pub counterparty_id: Option<i64>,
+}
+
+impl Entry {
+    pub const fn new(user_id: i64, kind: Kind, amount: i64) -> Self {
+        Self {
+            user_id,
+            kind,
+            amount,
+            counterparty_id: None,
+        }
+    }
+
+    /// The debit of the sender and the credit of the recipient
+    pub const fn transfer(from: i64, to: i64, amount: i64) -> [Self; 2] {
+        [
+            Self {
+                user_id: from,
+                kind: Kind::Transfer,
+                amount: -amount,
+                counterparty_id: Some(to),
+            },
+            Self {
+                user_id: to,
+                kind: Kind::Transfer,
+                amount,
+                counterparty_id: Some(from),
+            },
+        ]
+    }
+}
+
+#[derive(Debug)]
+pub enum LedgerError {
+    Db(DbError),
+    /// The user has no bank
+    NoBank(i64),
+    /// A debit is more than the user has
+    Insufficient {
+        user_id: i64,
+        balance: i64,
+    },
+    /// A credit is more than a balance can hold
+    Overflow(i64),
+}
+
+impl fmt::Display for LedgerError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Db(e) => write!(f, "{}", e),
+            Self::NoBank(user_id) => write!(f, "<@{}> has no bank yet", user_id),
+            Self::Insufficient { user_id, balance } => {
+                write!(f, "<@{}> only has {} credits", user_id, balance)
+            }
+            Self::Overflow(user_id) => write!(f, "<@{}> can not hold that many credits", user_id),
+        }
+    }
+}
+
+impl std::error::Error for LedgerError {}
+
+impl From<DbError> for LedgerError {
+    fn from(e: DbError) -> Self {
+        Self::Db(e)
+    }
+}
+
+/// Applies the entries in order to the banks, returning the balance after each entry. Debits
+/// may not make a balance negative or overflow it.
+fn book(banks: &mut [Bank], entries: &[Entry]) -> Result<Vec<i64>, LedgerError> {
+    entries
+        .iter()
+        .map(|entry| {
+            let bank = banks
+                .iter_mut()
+                .find(|b| b.user_id == entry.user_id)
+                .ok_or(LedgerError::NoBank(entry.user_id))?;
+            let amount = bank
+                .amount
+                .checked_add(entry.amount)
+                .ok_or(LedgerError::Overflow(bank.user_id))?;
+            if entry.amount < 0 && amount < 0 {
+                return Err(LedgerError::Insufficient {
+                    user_id: bank.user_id,
+                    balance: bank.amount,
+                });
+            }
+            bank.amount = amount;
+            Ok(bank.amount)
+        })
+        .collect()
+}
+
 impl Bank {
-    pub async fn get(pool: &PgPool, user_id: i64) -> Result<Self, DbError> {
-        sqlx::query_as::<_, Self>("SELECT * FROM banks WHERE user_id = $1")
+    pub async fn get(pool: &PgPool, server_id: i64, user_id: i64) -> Result<Self, DbError> {
+        sqlx::query_as::<_, Self>("SELECT * FROM banks WHERE server_id = $1 AND user_id = $2")
+            .bind(server_id)
             .bind(user_id)
             .fetch_one(pool)
             .await
     }
 
-    pub async fn top10(pool: &PgPool) -> Result<Vec<Self>, DbError> {
-        sqlx::query_as::<_, Self>("SELECT * FROM banks ORDER BY amount DESC LIMIT 10")
-            .fetch_all(pool)
-            .await
+    /// One page of the richest banks of the server
+    pub async fn top(
+        pool: &PgPool,
+        server_id: i64,
+        offset: i64,
+        limit: i64,
+    ) -> Result<Vec<Self>, DbError> {
+        sqlx::query_as::<_, Self>(
+            "SELECT * FROM banks WHERE server_id = $1 ORDER BY amount DESC, id OFFSET $2 LIMIT $3",
+        )
+        .bind(server_id)
+        .bind(offset)
+        .bind(limit)
+        .fetch_all(pool)
+        .await
     }
 
+    pub async fn count_of_server(pool: &PgPool, server_id: i64) -> Result<i64, DbError> {
+        let (count,): (i64,) = sqlx::query_as("SELECT COUNT(*) FROM banks WHERE server_id = $1")
+            .bind(server_id)
+            .fetch_one(pool)
+            .await?;
+
+        Ok(count)
+    }
+
+    /// Creates the bank with the opening entry of its balance. A bank from before banks were
+    /// kept per server is taken over instead, with its ledger.
     pub async fn create(
         pool: &PgPool,
+        server_id: i64,
         user_id: i64,
         user_name: String,
         amount: i64,
         last_payday: NaiveDateTime,
     ) -> Result<Self, DbError> {
-        sqlx::query_as::<_, Self>(
-            "INSERT INTO banks (user_id, user_name, amount, last_payday) VALUES ($1,$2,$3,$4) RETURNING *")
+        let mut tx = pool.begin().await?;
+        let legacy = sqlx::query_as::<_, Self>(
+            "UPDATE banks SET server_id = $1 WHERE id = (SELECT id FROM banks WHERE server_id = 0 AND user_id = $2 ORDER BY id LIMIT 1) RETURNING *",
+        )
+        .bind(server_id)
+        .bind(user_id)
+        .fetch_optional(&mut tx)
+        .await?;
+        if let Some(bank) = legacy {
+            sqlx::query(
+                "UPDATE bank_transactions SET server_id = $1 WHERE server_id = 0 AND user_id = $2",
+            )
+            .bind(server_id)
+            .bind(user_id)
+            .execute(&mut tx)
+            .await?;
+            tx.commit().await?;
+
+            return Ok(bank);
+        }
+
+        let bank = sqlx::query_as::<_, Self>(
+            "INSERT INTO banks (server_id, user_id, user_name, amount, last_payday) VALUES ($1,$2,$3,$4,$5) RETURNING *")
+        .bind(server_id)
         .bind(user_id)
         .bind(user_name)
         .bind(amount)
         .bind(last_payday)
-        .fetch_one(pool)
-        .await
+        .fetch_one(&mut tx)
+        .await?;
+        sqlx::query(
+            "INSERT INTO bank_transactions (server_id, user_id, kind, amount, balance) VALUES ($1, $2, $3, $4, $4)",
+        )
+        .bind(server_id)
+        .bind(user_id)
+        .bind(Kind::Opening.as_str())
+        .bind(amount)
+        .execute(&mut tx)
+        .await?;
+        tx.commit().await?;
+
+        Ok(bank)
     }
 
-    pub async fn update(
+    /// Books the entries on the banks of the server in one transaction, all or nothing
+    pub async fn apply(
         pool: &PgPool,
+        server_id: i64,
+        entries: &[Entry],
+    ) -> Result<Vec<Self>, LedgerError> {
+        let mut tx = pool.begin().await?;
+        let banks = Self::apply_in(&mut tx, server_id, entries).await?;
+        tx.commit().await?;
+
+        Ok(banks)
+    }
+
+    /// Books the entries as part of a larger transaction. The banks stay locked until the end
+    /// and are locked in the order of the user ids, so concurrent bookings can not deadlock or
+    /// lose updates.
+    pub async fn apply_in(
+        tx: &mut Transaction<'_, Postgres>,
+        server_id: i64,
+        entries: &[Entry],
+    ) -> Result<Vec<Self>, LedgerError> {
+        let mut user_ids = entries.iter().map(|e| e.user_id).collect::<Vec<_>>();
+        user_ids.sort_unstable();
+        user_ids.dedup();
+
+        let mut banks = sqlx::query_as::<_, Self>(
+            "SELECT * FROM banks WHERE server_id = $1 AND user_id = ANY($2) ORDER BY user_id FOR UPDATE",
+        )
+        .bind(server_id)
+        .bind(&user_ids)
+        .fetch_all(&mut *tx)
+        .await?;
+
+        let balances = book(&mut banks, entries)?;
+        for (entry, balance) in entries.iter().zip(balances) {
+            sqlx::query(
+                "INSERT INTO bank_transactions (server_id, user_id, kind, amount, balance, counterparty_id) VALUES ($1, $2, $3, $4, $5, $6)",
+            )
+            .bind(server_id)
+            .bind(entry.user_id)
+            .bind(entry.kind.as_str())
+            .bind(entry.amount)
+            .bind(balance)
+            .bind(entry.counterparty_id)
+            .execute(&mut *tx)
+            .await?;
+        }
+        for bank in &banks {
+            sqlx::query("UPDATE banks SET amount = $1 WHERE id = $2")
+                .bind(bank.amount)
+                .bind(bank.id)
+                .execute(&mut *tx)
+                .await?;
+        }
+
+        Ok(banks)
+    }
+
+    /// Pays the allowance if the last payday was at least `cooldown_hours` ago, a negative
+    /// balance is cleared first. `None` if it is too early.
+    pub async fn payday(
+        pool: &PgPool,
+        server_id: i64,
         user_id: i64,
-        amount: i64,
-        last_payday: NaiveDateTime,
-    ) -> Result<Self, DbError> {
-        sqlx::query_as::<_, Self>(
-            "UPDATE banks SET (amount, last_payday) = ($1,$2) WHERE user_id = $3 RETURNING *",
+        allowance: i64,
+        cooldown_hours: i64,
+    ) -> Result<Option<Self>, LedgerError> {
+        let mut tx = pool.begin().await?;
+        let mut bank = sqlx::query_as::<_, Self>(
+            "SELECT * FROM banks WHERE server_id = $1 AND user_id = $2 FOR UPDATE",
+        )
+        .bind(server_id)
+        .bind(user_id)
+        .fetch_optional(&mut tx)
+        .await?
+        .ok_or(LedgerError::NoBank(user_id))?;
+
+        let now = Utc::now().naive_utc();
+        if now.signed_duration_since(bank.last_payday).num_hours() < cooldown_hours {
+            return Ok(None);
+        }
+
+        let amount = allowance
+            .checked_sub(bank.amount.min(0))
+            .ok_or(LedgerError::Overflow(user_id))?;
+        let balances = book(
+            std::slice::from_mut(&mut bank),
+            &[Entry::new(user_id, Kind::Payday, amount)],
+        )?;
+        sqlx::query(
+            "INSERT INTO bank_transactions (server_id, user_id, kind, amount, balance) VALUES ($1, $2, $3, $4, $5)",
         )
+        .bind(server_id)
+        .bind(user_id)
+        .bind(Kind::Payday.as_str())
         .bind(amount)
-        .bind(last_payday)
+        .bind(balances[0])
+        .execute(&mut tx)
+        .await?;
+        bank.last_payday = now;
+        sqlx::query("UPDATE banks SET (amount, last_payday) = ($1, $2) WHERE id = $3")
+            .bind(bank.amount)
+            .bind(bank.last_payday)
+            .bind(bank.id)
+            .execute(&mut tx)
+            .await?;
+        tx.commit().await?;
+
+        Ok(Some(bank))
+    }
+
+    /// Sets the balance to the amount with an adjustment entry
+    pub async fn reset(
+        pool: &PgPool,
+        server_id: i64,
+        user_id: i64,
+        amount: i64,
+    ) -> Result<Self, LedgerError> {
+        let mut tx = pool.begin().await?;
+        let mut bank = sqlx::query_as::<_, Self>(
+            "SELECT * FROM banks WHERE server_id = $1 AND user_id = $2 FOR UPDATE",
+        )
+        .bind(server_id)
         .bind(user_id)
-        .fetch_one(pool)
-        .await
+        .fetch_optional(&mut tx)
+        .await?
+        .ok_or(LedgerError::NoBank(user_id))?;
+
+        let change = amount
+            .checked_sub(bank.amount)
+            .ok_or(LedgerError::Overflow(user_id))?;
+        bank.amount = amount;
+        sqlx::query(
+            "INSERT INTO bank_transactions (server_id, user_id, kind, amount, balance) VALUES ($1, $2, $3, $4, $5)",
+        )
+        .bind(server_id)
+        .bind(user_id)
+        .bind(Kind::Adjustment.as_str())
+        .bind(change)
+        .bind(amount)
+        .execute(&mut tx)
+        .await?;
+        sqlx::query("UPDATE banks SET amount = $1 WHERE id = $2")
+            .bind(amount)
+            .bind(bank.id)
+            .execute(&mut tx)
+            .await?;
+        tx.commit().await?;
+
+        Ok(bank)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn bank(user_id: i64, amount: i64) -> Bank {
+        Bank {
+            id: user_id,
+            server_id: 1,
+            user_id,
+            user_name: user_id.to_string(),
+            amount,
+            last_payday: Utc::now().naive_utc(),
+        }
+    }
+
+    #[test]
+    fn booking() {
+        let mut banks = vec![bank(1, 100), bank(2, 0)];
+        let [debit, credit] = Entry::transfer(1, 2, 60);
+        assert_eq!(book(&mut banks, &[debit, credit]).unwrap(), vec![40, 60]);
+
+        // the second debit would overdraw, so nothing may be written
+        assert!(matches!(
+            book(&mut banks, &[debit, credit]),
+            Err(LedgerError::Insufficient {
+                user_id: 1,
+                balance: 40
+            })
+        ));
+        assert!(matches!(
+            book(&mut banks, &Entry::transfer(2, 3, 10)),
+            Err(LedgerError::NoBank(3))
+        ));
+
+        let mut banks = vec![bank(1, 10)];
+        let entries = [
+            Entry::new(1, Kind::SlotBet, -10),
+            Entry::new(1, Kind::SlotPayout, 500),
+        ];
+        assert_eq!(book(&mut banks, &entries).unwrap(), vec![0, 500]);
+
+        assert!(matches!(
+            book(&mut banks, &[Entry::new(1, Kind::Adjustment, i64::MAX)]),
+            Err(LedgerError::Overflow(1))
+        ));
+        assert_eq!(banks[0].amount, 500);
     }
 }
@@ -0,0 +1,134 @@
+use chrono::{DateTime, Utc};
+use sqlx::postgres::PgPool;
+
+pub type DbError = sqlx::Error;
+
+/// An entry of the bank ledger, written together with every balance change
+#[derive(Debug, Clone, sqlx::FromRow)]
+pub struct BankTransaction {
+    pub id: i64,
+    pub server_id: i64,
+    pub user_id: i64,
+    pub kind: String,
+    pub amount: i64,
+    pub balance: i64,
+    pub counterparty_id: Option<i64>,
+    pub created_at: DateTime<Utc>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Kind {
+    /// The starting balance of a new bank
+    Opening,
+    Payday,
+    Transfer,
+    SlotBet,
+    SlotPayout,
+    /// Changed by hand, e.g. by mods
+    Adjustment,
+    /// Stake of the casino games besides slot
+    Bet,
+    Payout,
+    /// Stake of a duel, held until the duel is decided
+    Escrow,
+    /// Returned escrow of a duel that did not happen
+    Refund,
+    /// Bought from the shop of the server
+    Purchase,
+}
+
+impl Kind {
+    pub const fn as_str(self) -> &'static str {
+        match self {
+            Self::Opening => "opening",
+            Self::Payday => "payday",
+            Self::Transfer => "transfer",
+            Self::SlotBet => "slot_bet",
+            Self::SlotPayout => "slot_payout",
+            Self::Adjustment => "adjustment",
+            Self::Bet => "bet",
+            Self::Payout => "payout",
+            Self::Escrow => "escrow",
+            Self::Refund => "refund",
+            Self::Purchase => "purchase",
+        }
+    }
+
+    pub fn parse(kind: &str) -> Option<Self> {
+        [
+            Self::Opening,
+            Self::Payday,
+            Self::Transfer,
+            Self::SlotBet,
+            Self::SlotPayout,
+            Self::Adjustment,
+            Self::Bet,
+            Self::Payout,
+            Self::Escrow,
+            Self::Refund,
+            Self::Purchase,
+        ]
+        .iter()
+        .copied()
+        .find(|k| k.as_str() == kind)
+    }
+}
+
+impl BankTransaction {
+    pub fn kind(&self) -> Option<Kind> {
+        Kind::parse(&self.kind)
+    }
+
+    pub async fn recent_of_user(
+        pool: &PgPool,
+        server_id: i64,
+        user_id: i64,
+        limit: i64,
+    ) -> Result<Vec<Self>, DbError> {
+        sqlx::query_as::<_, Self>(
+            "SELECT * FROM bank_transactions WHERE server_id = $1 AND user_id = $2 ORDER BY id DESC LIMIT $3",
+        )
+        .bind(server_id)
+        .bind(user_id)
+        .bind(limit)
+        .fetch_all(pool)
+        .await
+    }
+
+    /// The balance of the user as the sum of the ledger
+    pub async fn balance_of_user(
+        pool: &PgPool,
+        server_id: i64,
+        user_id: i64,
+    ) -> Result<i64, DbError> {
+        let (balance,): (Option<i64>,) = sqlx::query_as(
+            "SELECT SUM(amount)::INT8 FROM bank_transactions WHERE server_id = $1 AND user_id = $2",
+        )
+        .bind(server_id)
+        .bind(user_id)
+        .fetch_one(pool)
+        .await?;
+
+        Ok(balance.unwrap_or(0))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Kind;
+
+    #[test]
+    fn kinds() {
+        for kind in [
+            Kind::Opening,
+            Kind::Transfer,
+            Kind::SlotPayout,
+            Kind::Adjustment,
+        ]
+        .iter()
+        {
+            assert_eq!(Kind::parse(kind.as_str()), Some(*kind));
+        }
+        assert_eq!(Kind::parse("gift"), None);
+    }
+}
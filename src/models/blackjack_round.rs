@@ -0,0 +1,100 @@
+use super::bank::{Bank, Entry, LedgerError};
+use super::bank_transaction::Kind;
+use chrono::{DateTime, Utc};
+use sqlx::postgres::PgPool;
+
+pub type DbError = sqlx::Error;
+
+/// A blackjack round, the bet is taken when it opens and paid out or returned at the end
+#[derive(Debug, Clone, sqlx::FromRow)]
+pub struct BlackjackRound {
+    pub id: i64,
+    pub server_id: i64,
+    pub user_id: i64,
+    pub bet: i64,
+    pub status: String,
+    pub created_at: DateTime<Utc>,
+}
+
+impl BlackjackRound {
+    /// Takes the bet and opens the round
+    pub async fn open(
+        pool: &PgPool,
+        server_id: i64,
+        user_id: i64,
+        bet: i64,
+    ) -> Result<Self, LedgerError> {
+        let mut tx = pool.begin().await?;
+        Bank::apply_in(&mut tx, server_id, &[Entry::new(user_id, Kind::Bet, -bet)]).await?;
+        let round = sqlx::query_as::<_, Self>(
+            "INSERT INTO blackjack_rounds (server_id, user_id, bet) VALUES ($1, $2, $3) RETURNING *",
+        )
+        .bind(server_id)
+        .bind(user_id)
+        .bind(bet)
+        .fetch_one(&mut tx)
+        .await?;
+        tx.commit().await?;
+
+        Ok(round)
+    }
+
+    /// Pays out the decided round, `None` if the round is not open anymore
+    pub async fn settle(pool: &PgPool, id: i64, payout: i64) -> Result<Option<Self>, LedgerError> {
+        let mut tx = pool.begin().await?;
+        let round = match sqlx::query_as::<_, Self>(
+            "UPDATE blackjack_rounds SET status = 'done' WHERE id = $1 AND status = 'open' RETURNING *",
+        )
+        .bind(id)
+        .fetch_optional(&mut tx)
+        .await?
+        {
+            Some(round) => round,
+            None => return Ok(None),
+        };
+
+        if payout > 0 {
+            Bank::apply_in(
+                &mut tx,
+                round.server_id,
+                &[Entry::new(round.user_id, Kind::Payout, payout)],
+            )
+            .await?;
+        }
+        tx.commit().await?;
+
+        Ok(Some(round))
+    }
+
+    /// Returns the bet, `None` if the round is not open anymore
+    pub async fn refund(pool: &PgPool, id: i64) -> Result<Option<Self>, LedgerError> {
+        let mut tx = pool.begin().await?;
+        let round = match sqlx::query_as::<_, Self>(
+            "UPDATE blackjack_rounds SET status = 'refunded' WHERE id = $1 AND status = 'open' RETURNING *",
+        )
+        .bind(id)
+        .fetch_optional(&mut tx)
+        .await?
+        {
+            Some(round) => round,
+            None => return Ok(None),
+        };
+
+        Bank::apply_in(
+            &mut tx,
+            round.server_id,
+            &[Entry::new(round.user_id, Kind::Refund, round.bet)],
+        )
+        .await?;
+        tx.commit().await?;
+
+        Ok(Some(round))
+    }
+
+    /// Rounds that were never finished, e.g. because of a restart
+    pub async fn list_open(pool: &PgPool) -> Result<Vec<Self>, DbError> {
+        sqlx::query_as::<_, Self>("SELECT * FROM blackjack_rounds WHERE status = 'open'")
+            .fetch_all(pool)
+            .await
+    }
+}
@@ -0,0 +1,120 @@
+use sqlx::postgres::PgPool;
+
+pub type DbError = sqlx::Error;
+
+/// A named set of favs of a user, can be shared with everyone on its server
+#[derive(Debug, Clone, sqlx::FromRow)]
+pub struct Collection {
+    pub id: i64,
+    pub server_id: i64,
+    pub user_id: i64,
+    pub name: String,
+    pub public: bool,
+}
+
+impl Collection {
+    pub async fn get(
+        pool: &PgPool,
+        server_id: i64,
+        user_id: i64,
+        name: &str,
+    ) -> Result<Self, DbError> {
+        sqlx::query_as::<_, Self>(
+            "SELECT * FROM collections WHERE server_id = $1 AND user_id = $2 AND name = $3",
+        )
+        .bind(server_id)
+        .bind(user_id)
+        .bind(name)
+        .fetch_one(pool)
+        .await
+    }
+
+    pub async fn of_user(
+        pool: &PgPool,
+        server_id: i64,
+        user_id: i64,
+    ) -> Result<Vec<Self>, DbError> {
+        sqlx::query_as::<_, Self>(
+            "SELECT * FROM collections WHERE server_id = $1 AND user_id = $2 ORDER BY name",
+        )
+        .bind(server_id)
+        .bind(user_id)
+        .fetch_all(pool)
+        .await
+    }
+
+    pub async fn create(
+        pool: &PgPool,
+        server_id: i64,
+        user_id: i64,
+        name: &str,
+    ) -> Result<Self, DbError> {
+        sqlx::query_as::<_, Self>(
+            "INSERT INTO collections (server_id, user_id, name) VALUES ($1, $2, $3) RETURNING *",
+        )
+        .bind(server_id)
+        .bind(user_id)
+        .bind(name)
+        .fetch_one(pool)
+        .await
+    }
+
+    pub async fn set_public(pool: &PgPool, id: i64, public: bool) -> Result<Self, DbError> {
+        sqlx::query_as::<_, Self>("UPDATE collections SET public = $1 WHERE id = $2 RETURNING *")
+            .bind(public)
+            .bind(id)
+            .fetch_one(pool)
+            .await
+    }
+
+    pub async fn delete(pool: &PgPool, id: i64) -> Result<u64, DbError> {
+        let mut tx = pool.begin().await?;
+        sqlx::query("DELETE FROM collection_favs WHERE collection_id = $1")
+            .bind(id)
+            .execute(&mut tx)
+            .await?;
+        let deleted = sqlx::query("DELETE FROM collections WHERE id = $1")
+            .bind(id)
+            .execute(&mut tx)
+            .await?
+            .rows_affected();
+        tx.commit().await?;
+
+        Ok(deleted)
+    }
+
+    /// Adds the favs, skipping the ones already in the collection. Returns the number added.
+    pub async fn add_favs(pool: &PgPool, id: i64, fav_ids: &[i64]) -> Result<u64, DbError> {
+        Ok(sqlx::query(
+            "INSERT INTO collection_favs (collection_id, fav_id) SELECT $1, * FROM UNNEST($2::INT8[]) ON CONFLICT DO NOTHING",
+        )
+        .bind(id)
+        .bind(fav_ids)
+        .execute(pool)
+        .await?
+        .rows_affected())
+    }
+
+    pub async fn remove_favs(pool: &PgPool, id: i64, fav_ids: &[i64]) -> Result<u64, DbError> {
+        Ok(
+            sqlx::query(
+                "DELETE FROM collection_favs WHERE collection_id = $1 AND fav_id = ANY($2)",
+            )
+            .bind(id)
+            .bind(fav_ids)
+            .execute(pool)
+            .await?
+            .rows_affected(),
+        )
+    }
+
+    pub async fn size(pool: &PgPool, id: i64) -> Result<i64, DbError> {
+        let (count,): (i64,) =
+            sqlx::query_as("SELECT COUNT(*) FROM collection_favs WHERE collection_id = $1")
+                .bind(id)
+                .fetch_one(pool)
+                .await?;
+
+        Ok(count)
+    }
+}
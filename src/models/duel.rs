@@ -0,0 +1,150 @@
+use super::bank::{Bank, Entry, LedgerError};
+use super::bank_transaction::Kind;
+use chrono::{DateTime, Utc};
+use sqlx::postgres::PgPool;
+
+pub type DbError = sqlx::Error;
+
+/// A duel for credits between two users, the stakes stay in escrow until it is decided
+#[derive(Debug, Clone, sqlx::FromRow)]
+pub struct Duel {
+    pub id: i64,
+    pub server_id: i64,
+    pub challenger_id: i64,
+    pub opponent_id: i64,
+    pub stake: i64,
+    pub status: String,
+    pub winner_id: Option<i64>,
+    pub created_at: DateTime<Utc>,
+}
+
+impl Duel {
+    /// Opens the duel with the stake of the challenger in escrow
+    pub async fn open(
+        pool: &PgPool,
+        server_id: i64,
+        challenger_id: i64,
+        opponent_id: i64,
+        stake: i64,
+    ) -> Result<Self, LedgerError> {
+        let mut tx = pool.begin().await?;
+        Bank::apply_in(
+            &mut tx,
+            server_id,
+            &[Entry {
+                user_id: challenger_id,
+                kind: Kind::Escrow,
+                amount: -stake,
+                counterparty_id: Some(opponent_id),
+            }],
+        )
+        .await?;
+        let duel = sqlx::query_as::<_, Self>(
+            "INSERT INTO duels (server_id, challenger_id, opponent_id, stake) VALUES ($1, $2, $3, $4) RETURNING *",
+        )
+        .bind(server_id)
+        .bind(challenger_id)
+        .bind(opponent_id)
+        .bind(stake)
+        .fetch_one(&mut tx)
+        .await?;
+        tx.commit().await?;
+
+        Ok(duel)
+    }
+
+    /// Escrows the stake of the opponent and pays both stakes to the winner. `None` if the
+    /// duel is not open anymore, an opponent without enough credits leaves it open.
+    pub async fn settle(
+        pool: &PgPool,
+        id: i64,
+        challenger_wins: bool,
+    ) -> Result<Option<Self>, LedgerError> {
+        let mut tx = pool.begin().await?;
+        let duel = match sqlx::query_as::<_, Self>(
+            "SELECT * FROM duels WHERE id = $1 AND status = 'open' FOR UPDATE",
+        )
+        .bind(id)
+        .fetch_optional(&mut tx)
+        .await?
+        {
+            Some(duel) => duel,
+            None => return Ok(None),
+        };
+
+        let (winner_id, loser_id) = if challenger_wins {
+            (duel.challenger_id, duel.opponent_id)
+        } else {
+            (duel.opponent_id, duel.challenger_id)
+        };
+        Bank::apply_in(
+            &mut tx,
+            duel.server_id,
+            &[
+                Entry {
+                    user_id: duel.opponent_id,
+                    kind: Kind::Escrow,
+                    amount: -duel.stake,
+                    counterparty_id: Some(duel.challenger_id),
+                },
+                Entry {
+                    user_id: winner_id,
+                    kind: Kind::Payout,
+                    amount: duel
+                        .stake
+                        .checked_mul(2)
+                        .ok_or(LedgerError::Overflow(winner_id))?,
+                    counterparty_id: Some(loser_id),
+                },
+            ],
+        )
+        .await?;
+        let duel = sqlx::query_as::<_, Self>(
+            "UPDATE duels SET (status, winner_id) = ('done', $1) WHERE id = $2 RETURNING *",
+        )
+        .bind(winner_id)
+        .bind(id)
+        .fetch_one(&mut tx)
+        .await?;
+        tx.commit().await?;
+
+        Ok(Some(duel))
+    }
+
+    /// Returns the stake to the challenger, `None` if the duel is not open anymore
+    pub async fn refund(pool: &PgPool, id: i64) -> Result<Option<Self>, LedgerError> {
+        let mut tx = pool.begin().await?;
+        let duel = match sqlx::query_as::<_, Self>(
+            "UPDATE duels SET status = 'refunded' WHERE id = $1 AND status = 'open' RETURNING *",
+        )
+        .bind(id)
+        .fetch_optional(&mut tx)
+        .await?
+        {
+            Some(duel) => duel,
+            None => return Ok(None),
+        };
+
+        Bank::apply_in(
+            &mut tx,
+            duel.server_id,
+            &[Entry {
+                user_id: duel.challenger_id,
+                kind: Kind::Refund,
+                amount: duel.stake,
+                counterparty_id: Some(duel.opponent_id),
+            }],
+        )
+        .await?;
+        tx.commit().await?;
+
+        Ok(Some(duel))
+    }
+
+    /// Duels that were never decided, e.g. because of a restart
+    pub async fn list_open(pool: &PgPool) -> Result<Vec<Self>, DbError> {
+        sqlx::query_as::<_, Self>("SELECT * FROM duels WHERE status = 'open'")
+            .fetch_all(pool)
+            .await
+    }
+}
@@ -1,3 +1,10 @@
+pub mod export;
+pub mod query;
+
+use super::fav_block::blocked_condition;
+use chrono::{DateTime, Utc};
+use query::{Bind, Query};
+use serde::{Deserialize, Serialize};
 use sqlx::postgres::PgPool;
 
 pub type DbError = sqlx::Error;
@@ -10,23 +17,55 @@ pub struct Fav {
     pub msg_id: i64,
     pub user_id: i64,
     pub author_id: i64,
+    // snapshot of the message at save time, favs saved before snapshots have none
+    pub content: Option<String>,
+    pub author_name: Option<String>,
+    pub author_avatar: Option<String>,
+    pub msg_timestamp: Option<DateTime<Utc>>,
+    pub channel_name: Option<String>,
+    pub attachments: serde_json::Value,
 }
 
-impl Fav {
-    pub async fn list(
-        pool: &PgPool,
-        user_id: i64,
-        server_id: Option<i64>,
-    ) -> Result<Vec<Self>, DbError> {
-        let server_id = server_id.unwrap_or(0);
-        sqlx::query_as::<_, Self>(
-        "SELECT favs.id, favs.server_id, favs.channel_id, favs.msg_id, favs.user_id, favs.author_id FROM favs LEFT JOIN fav_blocks ON favs.server_id = fav_blocks.server_id AND favs.channel_id = fav_blocks.channel_id AND favs.msg_id = fav_blocks.msg_id WHERE user_id = $1 AND (fav_blocks.id IS NULL OR fav_blocks.server_id != $2)"
-        )
-        .bind(user_id)
-        .bind(server_id)
+/// The parts of a message shown when a fav is posted
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Snapshot {
+    pub content: String,
+    pub author_name: String,
+    pub author_avatar: String,
+    pub timestamp: DateTime<Utc>,
+    pub channel_name: String,
+    pub attachments: Vec<SnapshotAttachment>,
+}
 
-        .fetch_all(pool)
-        .await
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SnapshotAttachment {
+    pub url: String,
+    pub filename: String,
+    pub size: u64,
+    pub width: Option<u64>,
+    pub height: Option<u64>,
+}
+
+impl Snapshot {
+    /// The first attachment that is an image
+    pub fn image(&self) -> Option<&str> {
+        self.attachments
+            .iter()
+            .find(|a| a.width.is_some())
+            .map(|a| a.url.as_str())
+    }
+}
+
+impl Fav {
+    pub fn snapshot(&self) -> Option<Snapshot> {
+        Some(Snapshot {
+            content: self.content.clone()?,
+            author_name: self.author_name.clone()?,
+            author_avatar: self.author_avatar.clone().unwrap_or_default(),
+            timestamp: self.msg_timestamp?,
+            channel_name: self.channel_name.clone().unwrap_or_default(),
+            attachments: serde_json::from_value(self.attachments.clone()).unwrap_or_default(),
+        })
     }
 
     pub async fn list_by_channel_msg(
@@ -41,6 +80,29 @@ impl Fav {
             .await
     }
 
+    pub async fn of_user(pool: &PgPool, user_id: i64) -> Result<Vec<Self>, DbError> {
+        sqlx::query_as::<_, Self>("SELECT * FROM favs WHERE user_id = $1 ORDER BY id")
+            .bind(user_id)
+            .fetch_all(pool)
+            .await
+    }
+
+    pub async fn count_of_user(pool: &PgPool, user_id: i64) -> Result<i64, DbError> {
+        let (count,): (i64,) = sqlx::query_as("SELECT COUNT(*) FROM favs WHERE user_id = $1")
+            .bind(user_id)
+            .fetch_one(pool)
+            .await?;
+
+        Ok(count)
+    }
+
+    pub async fn by_ids(pool: &PgPool, ids: &[i64]) -> Result<Vec<Self>, DbError> {
+        sqlx::query_as::<_, Self>("SELECT * FROM favs WHERE id = ANY($1)")
+            .bind(ids)
+            .fetch_all(pool)
+            .await
+    }
+
     pub async fn list_all_from_server(pool: &PgPool, server_id: i64) -> Result<Vec<Self>, DbError> {
         sqlx::query_as::<_, Self>("SELECT * FROM favs WHERE server_id = $1")
             .bind(server_id)
@@ -48,6 +110,12 @@ impl Fav {
             .await
     }
 
+    pub async fn list_without_snapshot(pool: &PgPool) -> Result<Vec<Self>, DbError> {
+        sqlx::query_as::<_, Self>("SELECT * FROM favs WHERE content IS NULL ORDER BY id")
+            .fetch_all(pool)
+            .await
+    }
+
     pub async fn create(
         pool: &PgPool,
         server_id: i64,
@@ -55,15 +123,41 @@ impl Fav {
         msg_id: i64,
         user_id: i64,
         author_id: i64,
+        snapshot: &Snapshot,
     ) -> Result<Self, DbError> {
         sqlx::query_as::<_, Self>(
-            "INSERT INTO favs (server_id, channel_id, msg_id, user_id, author_id) VALUES ($1, $2, $3, $4, $5) RETURNING *"
+            "INSERT INTO favs (server_id, channel_id, msg_id, user_id, author_id, content, author_name, author_avatar, msg_timestamp, channel_name, attachments) VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10, $11) RETURNING *"
         )
         .bind(server_id)
         .bind(channel_id)
         .bind(msg_id)
         .bind(user_id)
         .bind(author_id)
+        .bind(&snapshot.content)
+        .bind(&snapshot.author_name)
+        .bind(&snapshot.author_avatar)
+        .bind(snapshot.timestamp)
+        .bind(&snapshot.channel_name)
+        .bind(serde_json::to_value(&snapshot.attachments).unwrap_or_default())
+        .fetch_one(pool)
+        .await
+    }
+
+    pub async fn set_snapshot(
+        pool: &PgPool,
+        id: i64,
+        snapshot: &Snapshot,
+    ) -> Result<Self, DbError> {
+        sqlx::query_as::<_, Self>(
+            "UPDATE favs SET (content, author_name, author_avatar, msg_timestamp, channel_name, attachments) = ($1, $2, $3, $4, $5, $6) WHERE id = $7 RETURNING *"
+        )
+        .bind(&snapshot.content)
+        .bind(&snapshot.author_name)
+        .bind(&snapshot.author_avatar)
+        .bind(snapshot.timestamp)
+        .bind(&snapshot.channel_name)
+        .bind(serde_json::to_value(&snapshot.attachments).unwrap_or_default())
+        .bind(id)
         .fetch_one(pool)
         .await
     }
@@ -78,27 +172,52 @@ impl Fav {
 
     pub async fn untagged(pool: &PgPool, user_id: i64) -> Result<Vec<Self>, DbError> {
         sqlx::query_as::<_, Self>(
-            "SELECT favs.id, favs.server_id, favs.channel_id, favs.msg_id, favs.user_id, favs.author_id FROM favs LEFT JOIN tags ON favs.id = tags.fav_id WHERE favs.user_id = $1 AND tags.id IS NULL"
+            "SELECT favs.* FROM favs LEFT JOIN tags ON favs.id = tags.fav_id WHERE favs.user_id = $1 AND tags.id IS NULL"
         )
         .bind(user_id)
         .fetch_all(pool)
         .await
     }
 
-    pub async fn tagged_with(
+    pub async fn count_untagged(pool: &PgPool, user_id: i64) -> Result<i64, DbError> {
+        let (count,): (i64,) = sqlx::query_as(
+            "SELECT COUNT(*) FROM favs WHERE user_id = $1 AND NOT EXISTS (SELECT 1 FROM tags WHERE tags.fav_id = favs.id)",
+        )
+        .bind(user_id)
+        .fetch_one(pool)
+        .await?;
+
+        Ok(count)
+    }
+
+    /// Favs of the user matching the query, without the ones blocked on the server.
+    /// With a collection only the favs in it are considered.
+    pub async fn query(
         pool: &PgPool,
         user_id: i64,
         server_id: Option<i64>,
-        tags: Vec<String>,
+        collection_id: Option<i64>,
+        query: &Query,
     ) -> Result<Vec<Self>, DbError> {
         let server_id = server_id.unwrap_or(0);
-        sqlx::query_as::<_, Self>(
-            "SELECT favs.id, favs.server_id, favs.channel_id, favs.msg_id, favs.user_id, favs.author_id FROM favs INNER JOIN tags ON favs.id = tags.fav_id LEFT JOIN fav_blocks ON favs.server_id = fav_blocks.server_id AND favs.channel_id = fav_blocks.channel_id AND favs.msg_id = fav_blocks.msg_id WHERE favs.user_id = $1 AND tags.label = ANY($2) AND (fav_blocks.id IS NULL OR fav_blocks.server_id != $3)"
-        )
-        .bind(user_id)
-        .bind(&tags)
-        .bind(server_id)
-        .fetch_all(pool)
-        .await
+        let (condition, binds) = query.to_sql(4);
+        let sql = format!(
+            "SELECT favs.* FROM favs WHERE favs.user_id = $1 AND NOT {} AND ($3::INT8 IS NULL OR EXISTS (SELECT 1 FROM collection_favs WHERE collection_favs.collection_id = $3 AND collection_favs.fav_id = favs.id)) AND {}",
+            blocked_condition("$2"),
+            condition
+        );
+
+        let mut query = sqlx::query_as::<_, Self>(&sql)
+            .bind(user_id)
+            .bind(server_id)
+            .bind(collection_id);
+        for bind in binds {
+            query = match bind {
+                Bind::Text(text) => query.bind(text),
+                Bind::Int(int) => query.bind(int),
+            };
+        }
+
+        query.fetch_all(pool).await
     }
 }
@@ -0,0 +1,219 @@
+//! File format for `fav export` and `fav import`
+//!
+//! JSON is a list of records. CSV has one record per row, tags are separated by spaces and
+//! the attachments are kept as a JSON list, so both formats round trip.
+use super::{Fav, Snapshot, SnapshotAttachment};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::str::FromStr;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Format {
+    Json,
+    Csv,
+}
+
+impl Format {
+    pub const fn extension(self) -> &'static str {
+        match self {
+            Self::Json => "json",
+            Self::Csv => "csv",
+        }
+    }
+
+    /// Guesses the format from the file name of an upload
+    pub fn of_file(filename: &str) -> Option<Self> {
+        filename.rsplit('.').next()?.to_lowercase().parse().ok()
+    }
+}
+
+impl FromStr for Format {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "json" => Ok(Self::Json),
+            "csv" => Ok(Self::Csv),
+            other => Err(format!("Unknown format {}, use json or csv", other)),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Record {
+    pub link: String,
+    /// Who saved the fav, ignored on import
+    pub user_id: u64,
+    pub author_id: u64,
+    pub tags: Vec<String>,
+    pub author_name: String,
+    pub author_avatar: String,
+    pub content: String,
+    pub timestamp: Option<DateTime<Utc>>,
+    pub channel_name: String,
+    pub attachments: Vec<SnapshotAttachment>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct CsvRow {
+    link: String,
+    user_id: u64,
+    author_id: u64,
+    tags: String,
+    author_name: String,
+    author_avatar: String,
+    content: String,
+    timestamp: Option<DateTime<Utc>>,
+    channel_name: String,
+    attachments: String,
+}
+
+impl Record {
+    pub fn new(fav: &Fav, snapshot: Option<&Snapshot>, tags: Vec<String>) -> Self {
+        let link = format!(
+            "https://discord.com/channels/{}/{}/{}",
+            fav.server_id, fav.channel_id, fav.msg_id
+        );
+
+        match snapshot {
+            Some(snapshot) => Self {
+                link,
+                user_id: fav.user_id as u64,
+                author_id: fav.author_id as u64,
+                tags,
+                author_name: snapshot.author_name.clone(),
+                author_avatar: snapshot.author_avatar.clone(),
+                content: snapshot.content.clone(),
+                timestamp: Some(snapshot.timestamp),
+                channel_name: snapshot.channel_name.clone(),
+                attachments: snapshot.attachments.clone(),
+            },
+            None => Self {
+                link,
+                user_id: fav.user_id as u64,
+                author_id: fav.author_id as u64,
+                tags,
+                author_name: String::new(),
+                author_avatar: String::new(),
+                content: String::new(),
+                timestamp: None,
+                channel_name: String::new(),
+                attachments: Vec::new(),
+            },
+        }
+    }
+}
+
+pub fn write(records: &[Record], format: Format) -> Result<Vec<u8>, String> {
+    match format {
+        Format::Json => serde_json::to_vec_pretty(records).map_err(|e| e.to_string()),
+        Format::Csv => {
+            let mut writer = csv::Writer::from_writer(Vec::new());
+            for record in records {
+                writer
+                    .serialize(CsvRow {
+                        link: record.link.clone(),
+                        user_id: record.user_id,
+                        author_id: record.author_id,
+                        tags: record.tags.join(" "),
+                        author_name: record.author_name.clone(),
+                        author_avatar: record.author_avatar.clone(),
+                        content: record.content.clone(),
+                        timestamp: record.timestamp,
+                        channel_name: record.channel_name.clone(),
+                        attachments: serde_json::to_string(&record.attachments)
+                            .map_err(|e| e.to_string())?,
+                    })
+                    .map_err(|e| e.to_string())?;
+            }
+
+            writer.into_inner().map_err(|e| e.to_string())
+        }
+    }
+}
+
+pub fn read(data: &[u8], format: Format) -> Result<Vec<Record>, String> {
+    match format {
+        Format::Json => serde_json::from_slice(data).map_err(|e| format!("Invalid JSON: {}", e)),
+        Format::Csv => csv::Reader::from_reader(data)
+            .deserialize::<CsvRow>()
+            .map(|row| {
+                let row = row.map_err(|e| format!("Invalid CSV: {}", e))?;
+                Ok(Record {
+                    link: row.link,
+                    user_id: row.user_id,
+                    author_id: row.author_id,
+                    tags: row.tags.split_whitespace().map(str::to_string).collect(),
+                    author_name: row.author_name,
+                    author_avatar: row.author_avatar,
+                    content: row.content,
+                    timestamp: row.timestamp,
+                    channel_name: row.channel_name,
+                    attachments: if row.attachments.is_empty() {
+                        Vec::new()
+                    } else {
+                        serde_json::from_str(&row.attachments)
+                            .map_err(|e| format!("Invalid attachments: {}", e))?
+                    },
+                })
+            })
+            .collect(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::TimeZone;
+
+    fn records() -> Vec<Record> {
+        vec![
+            Record {
+                link: "https://discord.com/channels/1/2/3".to_string(),
+                user_id: 4,
+                author_id: 5,
+                tags: vec!["haus".to_string(), "turm".to_string()],
+                author_name: "someone".to_string(),
+                author_avatar: "https://cdn.discordapp.com/avatar.png".to_string(),
+                content: "line one\nline \"two\", with; separators".to_string(),
+                timestamp: Some(Utc.ymd(2021, 5, 1).and_hms(12, 30, 0)),
+                channel_name: "memes".to_string(),
+                attachments: vec![SnapshotAttachment {
+                    url: "https://cdn.discordapp.com/image.png".to_string(),
+                    filename: "image.png".to_string(),
+                    size: 1234,
+                    width: Some(100),
+                    height: Some(50),
+                }],
+            },
+            Record {
+                link: "https://discord.com/channels/1/2/6".to_string(),
+                user_id: 4,
+                author_id: 7,
+                tags: Vec::new(),
+                author_name: String::new(),
+                author_avatar: String::new(),
+                content: String::new(),
+                timestamp: None,
+                channel_name: String::new(),
+                attachments: Vec::new(),
+            },
+        ]
+    }
+
+    #[test]
+    fn round_trip() {
+        for format in &[Format::Json, Format::Csv] {
+            let data = write(&records(), *format).unwrap();
+            assert_eq!(read(&data, *format).unwrap(), records());
+        }
+    }
+
+    #[test]
+    fn formats() {
+        assert_eq!(Format::of_file("favs.CSV"), Some(Format::Csv));
+        assert_eq!(Format::of_file("favs.json"), Some(Format::Json));
+        assert_eq!(Format::of_file("favs.txt"), None);
+        assert!(read(b"not json", Format::Json).is_err());
+    }
+}
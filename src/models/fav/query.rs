@@ -0,0 +1,234 @@
+//! Query language for `fav post`
+//!
+//! Terms separated by whitespace must all match, terms joined with `|` need only one match,
+//! `-` excludes a term. Besides tags there are `author:@user` and `channel:#channel` filters.
+use nom::{
+    branch::alt,
+    bytes::complete::{tag, take_till1},
+    character::complete::{char, digit1, multispace0, multispace1},
+    combinator::{all_consuming, map, map_res, opt},
+    multi::{separated_list0, separated_list1},
+    sequence::{delimited, pair, preceded},
+    IResult,
+};
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ChannelRef {
+    Id(u64),
+    /// A plain channel name, has to be resolved to an id before compiling
+    Name(String),
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Atom {
+    Tag(String),
+    Author(u64),
+    Channel(ChannelRef),
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Term {
+    pub negated: bool,
+    pub atom: Atom,
+}
+
+/// A conjunction of disjunctions: every group needs at least one matching term
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct Query {
+    pub groups: Vec<Vec<Term>>,
+}
+
+/// Values for the placeholders of the compiled SQL, in order
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Bind {
+    Text(String),
+    Int(i64),
+}
+
+fn snowflake(input: &str) -> IResult<&str, u64> {
+    map_res(digit1, str::parse)(input)
+}
+
+fn word(input: &str) -> IResult<&str, &str> {
+    take_till1(|c: char| c.is_whitespace() || c == '|')(input)
+}
+
+fn user(input: &str) -> IResult<&str, u64> {
+    alt((
+        delimited(alt((tag("<@!"), tag("<@"))), snowflake, char('>')),
+        snowflake,
+    ))(input)
+}
+
+fn channel(input: &str) -> IResult<&str, ChannelRef> {
+    alt((
+        map(delimited(tag("<#"), snowflake, char('>')), ChannelRef::Id),
+        map(preceded(opt(char('#')), word), |name| {
+            ChannelRef::Name(name.to_string())
+        }),
+    ))(input)
+}
+
+fn atom(input: &str) -> IResult<&str, Atom> {
+    alt((
+        map(preceded(tag("author:"), user), Atom::Author),
+        map(preceded(tag("channel:"), channel), Atom::Channel),
+        map(word, |label| Atom::Tag(label.to_lowercase())),
+    ))(input)
+}
+
+fn term(input: &str) -> IResult<&str, Term> {
+    map(pair(opt(char('-')), atom), |(negated, atom)| Term {
+        negated: negated.is_some(),
+        atom,
+    })(input)
+}
+
+fn group(input: &str) -> IResult<&str, Vec<Term>> {
+    separated_list1(delimited(multispace0, char('|'), multispace0), term)(input)
+}
+
+impl Query {
+    pub fn parse(input: &str) -> Result<Self, String> {
+        all_consuming(delimited(
+            multispace0,
+            separated_list0(multispace1, group),
+            multispace0,
+        ))(input)
+        .map(|(_, groups)| Self { groups })
+        .map_err(|e| format!("Invalid query: {}", e))
+    }
+
+    /// Replaces channel names with their ids, fails on the first unknown name
+    pub fn resolve_channels(
+        &mut self,
+        resolve: impl Fn(&str) -> Option<u64>,
+    ) -> Result<(), String> {
+        for term in self.groups.iter_mut().flatten() {
+            if let Atom::Channel(ChannelRef::Name(ref name)) = term.atom {
+                let id = resolve(name).ok_or_else(|| format!("Unknown channel: {}", name))?;
+                term.atom = Atom::Channel(ChannelRef::Id(id));
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Compiles the query into a condition on the `favs` table, numbering the placeholders
+    /// from `first` on. Tags are checked with `EXISTS` so every fav is returned only once.
+    /// Unresolved channel names never match.
+    pub fn to_sql(&self, first: usize) -> (String, Vec<Bind>) {
+        let mut binds = Vec::new();
+        let mut groups = Vec::with_capacity(self.groups.len());
+
+        for group in &self.groups {
+            let mut terms = Vec::with_capacity(group.len());
+            for term in group {
+                let placeholder = first + binds.len();
+                let condition = match term.atom {
+                    Atom::Tag(ref label) => {
+                        binds.push(Bind::Text(label.clone()));
+                        format!(
+                            "EXISTS (SELECT 1 FROM tags WHERE tags.fav_id = favs.id AND tags.label = ${})",
+                            placeholder
+                        )
+                    }
+                    Atom::Author(id) => {
+                        binds.push(Bind::Int(id as i64));
+                        format!("favs.author_id = ${}", placeholder)
+                    }
+                    Atom::Channel(ChannelRef::Id(id)) => {
+                        binds.push(Bind::Int(id as i64));
+                        format!("favs.channel_id = ${}", placeholder)
+                    }
+                    Atom::Channel(ChannelRef::Name(_)) => "FALSE".to_string(),
+                };
+
+                terms.push(if term.negated {
+                    format!("NOT {}", condition)
+                } else {
+                    condition
+                });
+            }
+            groups.push(format!("({})", terms.join(" OR ")));
+        }
+
+        if groups.is_empty() {
+            ("TRUE".to_string(), binds)
+        } else {
+            (groups.join(" AND "), binds)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn tag(label: &str, negated: bool) -> Term {
+        Term {
+            negated,
+            atom: Atom::Tag(label.to_string()),
+        }
+    }
+
+    #[test]
+    fn parse_and_or_not() {
+        let query = Query::parse(" haus turm|auto  -fenster ").unwrap();
+
+        assert_eq!(
+            query.groups,
+            vec![
+                vec![tag("haus", false)],
+                vec![tag("turm", false), tag("auto", false)],
+                vec![tag("fenster", true)],
+            ]
+        );
+        assert_eq!(Query::parse("a | b").unwrap().groups.len(), 1);
+        assert!(Query::parse("").unwrap().groups.is_empty());
+    }
+
+    #[test]
+    fn parse_filters() {
+        let query = Query::parse("author:<@!123> -channel:<#456> channel:#memes").unwrap();
+
+        assert_eq!(query.groups[0][0].atom, Atom::Author(123));
+        assert_eq!(
+            query.groups[1][0],
+            Term {
+                negated: true,
+                atom: Atom::Channel(ChannelRef::Id(456)),
+            }
+        );
+        assert_eq!(
+            query.groups[2][0].atom,
+            Atom::Channel(ChannelRef::Name("memes".to_string()))
+        );
+        assert!(Query::parse("haus |").is_err());
+    }
+
+    #[test]
+    fn compile() {
+        let mut query = Query::parse("haus|author:<@1> -channel:memes").unwrap();
+        assert!(query.to_sql(3).0.ends_with("(NOT FALSE)"));
+
+        query
+            .resolve_channels(|name| if name == "memes" { Some(7) } else { None })
+            .unwrap();
+        let (sql, binds) = query.to_sql(3);
+
+        assert_eq!(
+            sql,
+            "(EXISTS (SELECT 1 FROM tags WHERE tags.fav_id = favs.id AND tags.label = $3) OR favs.author_id = $4) AND (NOT favs.channel_id = $5)"
+        );
+        assert_eq!(
+            binds,
+            vec![Bind::Text("haus".to_string()), Bind::Int(1), Bind::Int(7)]
+        );
+        assert_eq!(Query::default().to_sql(1).0, "TRUE");
+        assert!(Query::parse("channel:nope")
+            .unwrap()
+            .resolve_channels(|_| None)
+            .is_err());
+    }
+}
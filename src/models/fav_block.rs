@@ -1,3 +1,4 @@
+use super::fav::Fav;
 use sqlx::postgres::PgPool;
 
 pub type DbError = sqlx::Error;
@@ -5,37 +6,127 @@ pub type DbError = sqlx::Error;
 #[derive(Debug, Clone, sqlx::FromRow)]
 pub struct FavBlock {
     pub id: i64,
-    pub server_id: i64,  // server on which this fav is blocked
-    pub channel_id: i64, // blocked channel id
-    pub msg_id: i64,     // blocked message id
+    pub server_id: i64,          // server on which this fav is blocked
+    pub channel_id: Option<i64>, // blocked channel id, for message and channel blocks
+    pub msg_id: Option<i64>,     // blocked message id, for message blocks
+    pub kind: String,            // message, channel or author
+    pub author_id: Option<i64>,  // blocked author id, for author blocks
+}
+
+/// What a block applies to
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Target {
+    Message { channel_id: i64, msg_id: i64 },
+    Channel(i64),
+    Author(i64),
+}
+
+/// Condition on the `favs` table that is true when a block of the server in the placeholder
+/// applies to the fav
+pub fn blocked_condition(server_placeholder: &str) -> String {
+    format!(
+        "EXISTS (SELECT 1 FROM fav_blocks WHERE fav_blocks.server_id = {} AND ((fav_blocks.kind = 'message' AND fav_blocks.channel_id = favs.channel_id AND fav_blocks.msg_id = favs.msg_id) OR (fav_blocks.kind = 'channel' AND fav_blocks.channel_id = favs.channel_id) OR (fav_blocks.kind = 'author' AND fav_blocks.author_id = favs.author_id)))",
+        server_placeholder
+    )
 }
 
 impl FavBlock {
-    pub async fn check_blocked(pool: &PgPool, channel_id: i64, msg_id: i64) -> bool {
-        match sqlx::query("SELECT * FROM fav_blocks WHERE channel_id = $1 AND msg_id = $2")
-            .bind(channel_id)
-            .bind(msg_id)
-            .fetch_all(pool)
-            .await
-        {
-            Ok(rows) => !rows.is_empty(),
-            Err(_) => false,
+    pub fn target(&self) -> Option<Target> {
+        match self.kind.as_str() {
+            "message" => Some(Target::Message {
+                channel_id: self.channel_id?,
+                msg_id: self.msg_id?,
+            }),
+            "channel" => Some(Target::Channel(self.channel_id?)),
+            "author" => Some(Target::Author(self.author_id?)),
+            _ => None,
         }
     }
 
-    pub async fn create(
+    /// Whether a message of the server may not be faved
+    pub async fn check_blocked(
         pool: &PgPool,
         server_id: i64,
         channel_id: i64,
         msg_id: i64,
-    ) -> Result<Self, DbError> {
+        author_id: i64,
+    ) -> bool {
+        match sqlx::query(
+            "SELECT * FROM fav_blocks WHERE (kind = 'message' AND channel_id = $1 AND msg_id = $2) OR (kind = 'channel' AND channel_id = $1) OR (kind = 'author' AND server_id = $3 AND author_id = $4)",
+        )
+        .bind(channel_id)
+        .bind(msg_id)
+        .bind(server_id)
+        .bind(author_id)
+        .fetch_all(pool)
+        .await
+        {
+            Ok(rows) => !rows.is_empty(),
+            Err(_) => false,
+        }
+    }
+
+    pub async fn list(pool: &PgPool, server_id: i64) -> Result<Vec<Self>, DbError> {
+        sqlx::query_as::<_, Self>("SELECT * FROM fav_blocks WHERE server_id = $1 ORDER BY id")
+            .bind(server_id)
+            .fetch_all(pool)
+            .await
+    }
+
+    pub async fn create(pool: &PgPool, server_id: i64, target: Target) -> Result<Self, DbError> {
+        let (kind, channel_id, msg_id, author_id) = match target {
+            Target::Message { channel_id, msg_id } => {
+                ("message", Some(channel_id), Some(msg_id), None)
+            }
+            Target::Channel(channel_id) => ("channel", Some(channel_id), None, None),
+            Target::Author(author_id) => ("author", None, None, Some(author_id)),
+        };
+
         sqlx::query_as::<_, Self>(
-            "INSERT INTO fav_blocks (server_id, channel_id, msg_id) VALUES ($1,$2,$3) RETURNING *",
+            "INSERT INTO fav_blocks (server_id, kind, channel_id, msg_id, author_id) VALUES ($1, $2, $3, $4, $5) RETURNING *",
         )
         .bind(server_id)
+        .bind(kind)
         .bind(channel_id)
         .bind(msg_id)
+        .bind(author_id)
         .fetch_one(pool)
         .await
     }
+
+    /// Removes the block of the server, `None` if there is no such block
+    pub async fn delete(pool: &PgPool, server_id: i64, id: i64) -> Result<Option<Self>, DbError> {
+        sqlx::query_as::<_, Self>(
+            "DELETE FROM fav_blocks WHERE server_id = $1 AND id = $2 RETURNING *",
+        )
+        .bind(server_id)
+        .bind(id)
+        .fetch_optional(pool)
+        .await
+    }
+
+    /// All favs the block applies to
+    pub async fn favs(&self, pool: &PgPool) -> Result<Vec<Fav>, DbError> {
+        match self.target() {
+            Some(Target::Message { channel_id, msg_id }) => {
+                Fav::list_by_channel_msg(pool, channel_id, msg_id).await
+            }
+            Some(Target::Channel(channel_id)) => {
+                sqlx::query_as::<_, Fav>("SELECT * FROM favs WHERE channel_id = $1")
+                    .bind(channel_id)
+                    .fetch_all(pool)
+                    .await
+            }
+            Some(Target::Author(author_id)) => {
+                sqlx::query_as::<_, Fav>(
+                    "SELECT * FROM favs WHERE server_id = $1 AND author_id = $2",
+                )
+                .bind(self.server_id)
+                .bind(author_id)
+                .fetch_all(pool)
+                .await
+            }
+            None => Ok(Vec::new()),
+        }
+    }
 }
@@ -0,0 +1,116 @@
+use super::fav_block::blocked_condition;
+use chrono::{DateTime, Utc};
+use sqlx::postgres::PgPool;
+
+pub type DbError = sqlx::Error;
+
+/// A fav was posted somewhere
+#[derive(Debug, Clone, sqlx::FromRow)]
+pub struct FavPost {
+    pub id: i64,
+    pub fav_id: i64,
+    pub server_id: Option<i64>,
+    pub channel_id: i64,
+    pub user_id: i64,
+    pub posted_at: DateTime<Utc>,
+}
+
+/// How many entries the top lists have
+const TOP: i64 = 5;
+
+impl FavPost {
+    pub async fn create(
+        pool: &PgPool,
+        fav_id: i64,
+        server_id: Option<i64>,
+        channel_id: i64,
+        user_id: i64,
+    ) -> Result<Self, DbError> {
+        sqlx::query_as::<_, Self>(
+            "INSERT INTO fav_posts (fav_id, server_id, channel_id, user_id) VALUES ($1, $2, $3, $4) RETURNING *",
+        )
+        .bind(fav_id)
+        .bind(server_id)
+        .bind(channel_id)
+        .bind(user_id)
+        .fetch_one(pool)
+        .await
+    }
+
+    /// Ids and post counts of the favs of the user that were posted most, without the
+    /// excluded authors
+    pub async fn top_favs_of_user(
+        pool: &PgPool,
+        user_id: i64,
+        excluded_authors: &[i64],
+    ) -> Result<Vec<(i64, i64)>, DbError> {
+        sqlx::query_as(
+            "SELECT favs.id, COUNT(*) AS count FROM fav_posts INNER JOIN favs ON fav_posts.fav_id = favs.id WHERE favs.user_id = $1 AND NOT favs.author_id = ANY($2) GROUP BY favs.id ORDER BY count DESC, favs.id LIMIT $3",
+        )
+        .bind(user_id)
+        .bind(excluded_authors)
+        .bind(TOP)
+        .fetch_all(pool)
+        .await
+    }
+
+    /// The authors whose messages the user posted most, without the excluded authors
+    pub async fn top_authors_of_user(
+        pool: &PgPool,
+        user_id: i64,
+        excluded_authors: &[i64],
+    ) -> Result<Vec<(i64, i64)>, DbError> {
+        sqlx::query_as(
+            "SELECT favs.author_id, COUNT(*) AS count FROM fav_posts INNER JOIN favs ON fav_posts.fav_id = favs.id WHERE favs.user_id = $1 AND NOT favs.author_id = ANY($2) GROUP BY favs.author_id ORDER BY count DESC, favs.author_id LIMIT $3",
+        )
+        .bind(user_id)
+        .bind(excluded_authors)
+        .bind(TOP)
+        .fetch_all(pool)
+        .await
+    }
+
+    /// The favs posted most on the server, without the excluded authors and blocked favs
+    pub async fn top_favs_of_server(
+        pool: &PgPool,
+        server_id: i64,
+        excluded_authors: &[i64],
+    ) -> Result<Vec<(i64, i64)>, DbError> {
+        let sql = format!(
+            "SELECT favs.id, COUNT(*) AS count FROM fav_posts INNER JOIN favs ON fav_posts.fav_id = favs.id WHERE fav_posts.server_id = $1 AND NOT favs.author_id = ANY($2) AND NOT {} GROUP BY favs.id ORDER BY count DESC, favs.id LIMIT $3",
+            blocked_condition("$1")
+        );
+        sqlx::query_as(&sql)
+            .bind(server_id)
+            .bind(excluded_authors)
+            .bind(TOP)
+            .fetch_all(pool)
+            .await
+    }
+
+    /// The authors quoted most on the server, without the excluded authors
+    pub async fn top_authors_of_server(
+        pool: &PgPool,
+        server_id: i64,
+        excluded_authors: &[i64],
+    ) -> Result<Vec<(i64, i64)>, DbError> {
+        sqlx::query_as(
+            "SELECT favs.author_id, COUNT(*) AS count FROM fav_posts INNER JOIN favs ON fav_posts.fav_id = favs.id WHERE fav_posts.server_id = $1 AND NOT favs.author_id = ANY($2) GROUP BY favs.author_id ORDER BY count DESC, favs.author_id LIMIT $3",
+        )
+        .bind(server_id)
+        .bind(excluded_authors)
+        .bind(TOP)
+        .fetch_all(pool)
+        .await
+    }
+
+    pub async fn count_of_server(pool: &PgPool, server_id: i64) -> Result<i64, DbError> {
+        let (count,): (i64,) =
+            sqlx::query_as("SELECT COUNT(*) FROM fav_posts WHERE server_id = $1")
+                .bind(server_id)
+                .fetch_one(pool)
+                .await?;
+
+        Ok(count)
+    }
+}
@@ -0,0 +1,48 @@
+use sqlx::postgres::PgPool;
+
+pub type DbError = sqlx::Error;
+
+/// A message that was reposted to the hall of fame channel of its server
+#[derive(Debug, Clone, sqlx::FromRow)]
+pub struct HallOfFame {
+    pub id: i64,
+    pub server_id: i64,
+    pub channel_id: i64,
+    pub msg_id: i64,
+    pub post_id: Option<i64>,
+}
+
+impl HallOfFame {
+    /// Claims the message for the hall of fame, `None` if it already was
+    pub async fn create(
+        pool: &PgPool,
+        server_id: i64,
+        channel_id: i64,
+        msg_id: i64,
+    ) -> Result<Option<Self>, DbError> {
+        sqlx::query_as::<_, Self>(
+            "INSERT INTO hall_of_fame (server_id, channel_id, msg_id) VALUES ($1, $2, $3) ON CONFLICT DO NOTHING RETURNING *",
+        )
+        .bind(server_id)
+        .bind(channel_id)
+        .bind(msg_id)
+        .fetch_optional(pool)
+        .await
+    }
+
+    pub async fn set_post(pool: &PgPool, id: i64, post_id: i64) -> Result<Self, DbError> {
+        sqlx::query_as::<_, Self>("UPDATE hall_of_fame SET post_id = $1 WHERE id = $2 RETURNING *")
+            .bind(post_id)
+            .bind(id)
+            .fetch_one(pool)
+            .await
+    }
+
+    pub async fn delete(pool: &PgPool, id: i64) -> Result<u64, DbError> {
+        Ok(sqlx::query("DELETE FROM hall_of_fame WHERE id = $1")
+            .bind(id)
+            .execute(pool)
+            .await?
+            .rows_affected())
+    }
+}
@@ -0,0 +1,155 @@
+use super::bank::{Bank, Entry, LedgerError};
+use super::bank_transaction::Kind;
+use super::shop_item::{ItemKind, ShopItem};
+use chrono::{DateTime, Utc};
+use sqlx::postgres::PgPool;
+
+pub type DbError = sqlx::Error;
+
+/// A bought shop item, it keeps a copy of the shop item as it was at the time of purchase
+#[derive(Debug, Clone, sqlx::FromRow)]
+pub struct InventoryItem {
+    pub id: i64,
+    pub server_id: i64,
+    pub user_id: i64,
+    pub shop_item_id: Option<i64>,
+    pub name: String,
+    pub kind: String,
+    pub price: i64,
+    pub duration_hours: Option<i64>,
+    pub role_id: Option<i64>,
+    pub used_at: Option<DateTime<Utc>>,
+    pub expires_at: Option<DateTime<Utc>>,
+    pub expired: bool,
+    pub created_at: DateTime<Utc>,
+}
+
+impl InventoryItem {
+    pub fn kind(&self) -> Option<ItemKind> {
+        ItemKind::parse(&self.kind)
+    }
+
+    /// Pays the price and puts the item into the inventory of the user
+    pub async fn buy(pool: &PgPool, user_id: i64, item: &ShopItem) -> Result<Self, LedgerError> {
+        let mut tx = pool.begin().await?;
+        Bank::apply_in(
+            &mut tx,
+            item.server_id,
+            &[Entry::new(user_id, Kind::Purchase, -item.price)],
+        )
+        .await?;
+        let bought = sqlx::query_as::<_, Self>(
+            "INSERT INTO inventory_items (server_id, user_id, shop_item_id, name, kind, price, duration_hours, role_id) VALUES ($1, $2, $3, $4, $5, $6, $7, $8) RETURNING *",
+        )
+        .bind(item.server_id)
+        .bind(user_id)
+        .bind(item.id)
+        .bind(&item.name)
+        .bind(&item.kind)
+        .bind(item.price)
+        .bind(item.duration_hours)
+        .bind(item.role_id)
+        .fetch_one(&mut tx)
+        .await?;
+        tx.commit().await?;
+
+        Ok(bought)
+    }
+
+    /// Unused items and roles that are still granted
+    pub async fn list_of_user(
+        pool: &PgPool,
+        server_id: i64,
+        user_id: i64,
+    ) -> Result<Vec<Self>, DbError> {
+        sqlx::query_as::<_, Self>(
+            "SELECT * FROM inventory_items WHERE server_id = $1 AND user_id = $2 AND (used_at IS NULL OR (role_id IS NOT NULL AND NOT expired)) ORDER BY id",
+        )
+        .bind(server_id)
+        .bind(user_id)
+        .fetch_all(pool)
+        .await
+    }
+
+    pub async fn get_unused(
+        pool: &PgPool,
+        server_id: i64,
+        user_id: i64,
+        id: i64,
+    ) -> Result<Self, DbError> {
+        sqlx::query_as::<_, Self>(
+            "SELECT * FROM inventory_items WHERE server_id = $1 AND user_id = $2 AND id = $3 AND used_at IS NULL",
+        )
+        .bind(server_id)
+        .bind(user_id)
+        .bind(id)
+        .fetch_one(pool)
+        .await
+    }
+
+    /// Marks the item as used with the granted role, `None` if it was used in the meantime
+    pub async fn mark_used(
+        pool: &PgPool,
+        id: i64,
+        role_id: Option<i64>,
+        expires_at: Option<DateTime<Utc>>,
+    ) -> Result<Option<Self>, DbError> {
+        sqlx::query_as::<_, Self>(
+            "UPDATE inventory_items SET (used_at, role_id, expires_at) = (NOW(), $1, $2) WHERE id = $3 AND used_at IS NULL RETURNING *",
+        )
+        .bind(role_id)
+        .bind(expires_at)
+        .bind(id)
+        .fetch_optional(pool)
+        .await
+    }
+
+    /// Makes the item usable again after using it failed, color items forget their role
+    pub async fn mark_unused(pool: &PgPool, id: i64) -> Result<u64, DbError> {
+        Ok(sqlx::query(
+            "UPDATE inventory_items SET (used_at, expires_at, role_id) = (NULL, NULL, CASE WHEN kind = 'color' THEN NULL ELSE role_id END) WHERE id = $1",
+        )
+        .bind(id)
+        .execute(pool)
+        .await?
+        .rows_affected())
+    }
+
+    /// Granted roles that still have to be removed
+    pub async fn list_expiring(pool: &PgPool) -> Result<Vec<Self>, DbError> {
+        sqlx::query_as::<_, Self>(
+            "SELECT * FROM inventory_items WHERE expires_at IS NOT NULL AND NOT expired",
+        )
+        .fetch_all(pool)
+        .await
+    }
+
+    /// Returns 0 if the item already expired
+    pub async fn expire(pool: &PgPool, id: i64) -> Result<u64, DbError> {
+        Ok(
+            sqlx::query("UPDATE inventory_items SET expired = TRUE WHERE id = $1 AND NOT expired")
+                .bind(id)
+                .execute(pool)
+                .await?
+                .rows_affected(),
+        )
+    }
+
+    /// Whether another used item still grants the role to the user
+    pub async fn grants_role(
+        pool: &PgPool,
+        server_id: i64,
+        user_id: i64,
+        role_id: i64,
+    ) -> Result<bool, DbError> {
+        sqlx::query_as::<_, (bool,)>(
+            "SELECT EXISTS (SELECT 1 FROM inventory_items WHERE server_id = $1 AND user_id = $2 AND role_id = $3 AND used_at IS NOT NULL AND NOT expired)",
+        )
+        .bind(server_id)
+        .bind(user_id)
+        .bind(role_id)
+        .fetch_one(pool)
+        .await
+        .map(|(exists,)| exists)
+    }
+}
@@ -0,0 +1,130 @@
+use chrono::{DateTime, Utc};
+use sqlx::postgres::PgPool;
+
+pub type DbError = sqlx::Error;
+
+/// The xp of a user on a server
+#[derive(Debug, Clone, sqlx::FromRow)]
+pub struct Level {
+    pub id: i64,
+    pub server_id: i64,
+    pub user_id: i64,
+    pub xp: i64,
+    pub last_xp_at: DateTime<Utc>,
+}
+
+impl Level {
+    pub async fn get(pool: &PgPool, server_id: i64, user_id: i64) -> Result<Self, DbError> {
+        sqlx::query_as::<_, Self>("SELECT * FROM levels WHERE server_id = $1 AND user_id = $2")
+            .bind(server_id)
+            .bind(user_id)
+            .fetch_one(pool)
+            .await
+    }
+
+    /// Adds the xp unless the user got xp within the cooldown, `None` if the user did
+    pub async fn award(
+        pool: &PgPool,
+        server_id: i64,
+        user_id: i64,
+        xp: i64,
+        cooldown_secs: i64,
+    ) -> Result<Option<Self>, DbError> {
+        sqlx::query_as::<_, Self>(
+            "INSERT INTO levels (server_id, user_id, xp) VALUES ($1, $2, $3)
+            ON CONFLICT (server_id, user_id) DO UPDATE SET (xp, last_xp_at) = (levels.xp + $3, NOW())
+            WHERE levels.last_xp_at <= NOW() - make_interval(secs => $4)
+            RETURNING *",
+        )
+        .bind(server_id)
+        .bind(user_id)
+        .bind(xp)
+        .bind(cooldown_secs as f64)
+        .fetch_optional(pool)
+        .await
+    }
+
+    pub async fn set(
+        pool: &PgPool,
+        server_id: i64,
+        user_id: i64,
+        xp: i64,
+    ) -> Result<Self, DbError> {
+        sqlx::query_as::<_, Self>(
+            "INSERT INTO levels (server_id, user_id, xp) VALUES ($1, $2, $3)
+            ON CONFLICT (server_id, user_id) DO UPDATE SET xp = $3
+            RETURNING *",
+        )
+        .bind(server_id)
+        .bind(user_id)
+        .bind(xp)
+        .fetch_one(pool)
+        .await
+    }
+
+    /// Resets a single user, or everyone on the server without a user
+    pub async fn reset(
+        pool: &PgPool,
+        server_id: i64,
+        user_id: Option<i64>,
+    ) -> Result<u64, DbError> {
+        Ok(sqlx::query(
+            "DELETE FROM levels WHERE server_id = $1 AND ($2::INT8 IS NULL OR user_id = $2)",
+        )
+        .bind(server_id)
+        .bind(user_id)
+        .execute(pool)
+        .await?
+        .rows_affected())
+    }
+
+    /// The leaderboard without the hidden users
+    pub async fn top(
+        pool: &PgPool,
+        server_id: i64,
+        hidden: &[i64],
+        offset: i64,
+        limit: i64,
+    ) -> Result<Vec<Self>, DbError> {
+        sqlx::query_as::<_, Self>(
+            "SELECT * FROM levels WHERE server_id = $1 AND NOT (user_id = ANY($2)) ORDER BY xp DESC, id OFFSET $3 LIMIT $4",
+        )
+        .bind(server_id)
+        .bind(hidden)
+        .bind(offset)
+        .bind(limit)
+        .fetch_all(pool)
+        .await
+    }
+
+    pub async fn count_of_server(
+        pool: &PgPool,
+        server_id: i64,
+        hidden: &[i64],
+    ) -> Result<i64, DbError> {
+        let (count,): (i64,) = sqlx::query_as(
+            "SELECT COUNT(*) FROM levels WHERE server_id = $1 AND NOT (user_id = ANY($2))",
+        )
+        .bind(server_id)
+        .bind(hidden)
+        .fetch_one(pool)
+        .await?;
+
+        Ok(count)
+    }
+
+    /// Position on the leaderboard without the hidden users, starting at 1
+    pub async fn position(&self, pool: &PgPool, hidden: &[i64]) -> Result<i64, DbError> {
+        let (ahead,): (i64,) = sqlx::query_as(
+            "SELECT COUNT(*) FROM levels WHERE server_id = $1 AND NOT (user_id = ANY($2)) AND (xp > $3 OR (xp = $3 AND id < $4))",
+        )
+        .bind(self.server_id)
+        .bind(hidden)
+        .bind(self.xp)
+        .bind(self.id)
+        .fetch_one(pool)
+        .await?;
+
+        Ok(ahead + 1)
+    }
+}
@@ -0,0 +1,78 @@
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use sqlx::postgres::PgPool;
+
+pub type DbError = sqlx::Error;
+
+#[derive(Clone, Debug, sqlx::FromRow)]
+pub struct Lockdown {
+    pub id: i64,
+    pub server_id: i64,
+    pub channel_id: Option<i64>, // None if the whole server is locked
+    pub end_time: Option<DateTime<Utc>>, // None until a manual unlock
+    pub overwrites: serde_json::Value,
+}
+
+/// The @everyone overwrite of a channel from before it was locked
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq)]
+pub struct SavedOverwrite {
+    pub channel_id: u64,
+    /// False if the channel had no overwrite for @everyone
+    pub existed: bool,
+    pub allow: u64,
+    pub deny: u64,
+}
+
+impl Lockdown {
+    /// Lockdowns that end on their own
+    pub async fn list_timed(pool: &PgPool) -> Result<Vec<Self>, DbError> {
+        sqlx::query_as::<_, Self>("SELECT * FROM lockdowns WHERE end_time IS NOT NULL")
+            .fetch_all(pool)
+            .await
+    }
+
+    pub async fn create(
+        pool: &PgPool,
+        server_id: i64,
+        channel_id: Option<i64>,
+        end_time: Option<DateTime<Utc>>,
+        overwrites: &[SavedOverwrite],
+    ) -> Result<Self, DbError> {
+        sqlx::query_as::<_, Self>(
+            "INSERT INTO lockdowns (server_id, channel_id, end_time, overwrites) VALUES ($1,$2,$3,$4) RETURNING *",
+        )
+        .bind(server_id)
+        .bind(channel_id)
+        .bind(end_time)
+        .bind(serde_json::to_value(overwrites).unwrap_or_default())
+        .fetch_one(pool)
+        .await
+    }
+
+    /// `None` if the lockdown was lifted already
+    pub async fn delete(pool: &PgPool, id: i64) -> Result<Option<Self>, DbError> {
+        sqlx::query_as::<_, Self>("DELETE FROM lockdowns WHERE id = $1 RETURNING *")
+            .bind(id)
+            .fetch_optional(pool)
+            .await
+    }
+
+    /// Removes the lockdowns of the channel, or all of the server if `channel_id` is `None`
+    pub async fn delete_for(
+        pool: &PgPool,
+        server_id: i64,
+        channel_id: Option<i64>,
+    ) -> Result<Vec<Self>, DbError> {
+        sqlx::query_as::<_, Self>(
+            "DELETE FROM lockdowns WHERE server_id = $1 AND ($2::BIGINT IS NULL OR channel_id = $2) RETURNING *",
+        )
+        .bind(server_id)
+        .bind(channel_id)
+        .fetch_all(pool)
+        .await
+    }
+
+    pub fn saved_overwrites(&self) -> Vec<SavedOverwrite> {
+        serde_json::from_value(self.overwrites.clone()).unwrap_or_default()
+    }
+}
@@ -0,0 +1,172 @@
+use chrono::{DateTime, Utc};
+use sqlx::postgres::PgPool;
+
+pub type DbError = sqlx::Error;
+
+#[derive(Debug, Clone, sqlx::FromRow)]
+pub struct Poll {
+    pub id: i64,
+    pub server_id: i64,
+    pub channel_id: i64,
+    pub msg_id: Option<i64>,
+    pub author_id: i64,
+    pub question: String,
+    pub answers: Vec<String>,
+    pub multiple: bool,
+    pub anonymous: bool,
+    pub end_time: DateTime<Utc>,
+    pub closed: bool,
+    pub created_at: DateTime<Utc>,
+}
+
+/// A poll as it is asked, before it is posted
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct NewPoll {
+    pub question: String,
+    pub answers: Vec<String>,
+    pub multiple: bool,
+    pub anonymous: bool,
+    pub end_time: DateTime<Utc>,
+}
+
+impl Poll {
+    pub async fn create(
+        pool: &PgPool,
+        server_id: i64,
+        channel_id: i64,
+        author_id: i64,
+        new: &NewPoll,
+    ) -> Result<Self, DbError> {
+        sqlx::query_as::<_, Self>(
+            "INSERT INTO polls (server_id, channel_id, author_id, question, answers, multiple, anonymous, end_time) VALUES ($1, $2, $3, $4, $5, $6, $7, $8) RETURNING *",
+        )
+        .bind(server_id)
+        .bind(channel_id)
+        .bind(author_id)
+        .bind(&new.question)
+        .bind(&new.answers)
+        .bind(new.multiple)
+        .bind(new.anonymous)
+        .bind(new.end_time)
+        .fetch_one(pool)
+        .await
+    }
+
+    pub async fn set_msg_id(pool: &PgPool, id: i64, msg_id: i64) -> Result<Self, DbError> {
+        sqlx::query_as::<_, Self>("UPDATE polls SET msg_id = $1 WHERE id = $2 RETURNING *")
+            .bind(msg_id)
+            .bind(id)
+            .fetch_one(pool)
+            .await
+    }
+
+    /// Removes a poll whose message could not be posted
+    pub async fn delete(pool: &PgPool, id: i64) -> Result<u64, DbError> {
+        sqlx::query("DELETE FROM polls WHERE id = $1")
+            .bind(id)
+            .execute(pool)
+            .await
+            .map(|r| r.rows_affected())
+    }
+
+    pub async fn get(pool: &PgPool, server_id: i64, id: i64) -> Result<Self, DbError> {
+        sqlx::query_as::<_, Self>("SELECT * FROM polls WHERE server_id = $1 AND id = $2")
+            .bind(server_id)
+            .bind(id)
+            .fetch_one(pool)
+            .await
+    }
+
+    pub async fn by_msg(pool: &PgPool, msg_id: i64) -> Result<Self, DbError> {
+        sqlx::query_as::<_, Self>("SELECT * FROM polls WHERE msg_id = $1")
+            .bind(msg_id)
+            .fetch_one(pool)
+            .await
+    }
+
+    /// Polls that still have to be closed, e.g. after a restart
+    pub async fn list_open(pool: &PgPool) -> Result<Vec<Self>, DbError> {
+        sqlx::query_as::<_, Self>("SELECT * FROM polls WHERE NOT closed")
+            .fetch_all(pool)
+            .await
+    }
+
+    /// `None` if the poll was closed already
+    pub async fn close(pool: &PgPool, id: i64) -> Result<Option<Self>, DbError> {
+        sqlx::query_as::<_, Self>(
+            "UPDATE polls SET closed = TRUE WHERE id = $1 AND NOT closed RETURNING *",
+        )
+        .bind(id)
+        .fetch_optional(pool)
+        .await
+    }
+
+    /// Votes per answer, in the order of the answers
+    pub async fn counts(&self, pool: &PgPool) -> Result<Vec<i64>, DbError> {
+        let rows: Vec<(i32, i64)> = sqlx::query_as(
+            "SELECT answer, COUNT(*) FROM poll_votes WHERE poll_id = $1 GROUP BY answer",
+        )
+        .bind(self.id)
+        .fetch_all(pool)
+        .await?;
+
+        let mut counts = vec![0; self.answers.len()];
+        for (answer, count) in rows {
+            if let Some(c) = counts.get_mut(answer as usize) {
+                *c = count;
+            }
+        }
+
+        Ok(counts)
+    }
+
+    /// Adds the vote, a single choice poll replaces the other votes of the user. Returns the
+    /// replaced answers.
+    pub async fn vote(
+        &self,
+        pool: &PgPool,
+        user_id: i64,
+        answer: i32,
+    ) -> Result<Vec<i32>, DbError> {
+        let mut tx = pool.begin().await?;
+        let replaced = if self.multiple {
+            Vec::new()
+        } else {
+            sqlx::query_as::<_, (i32,)>(
+                "DELETE FROM poll_votes WHERE poll_id = $1 AND user_id = $2 AND answer <> $3 RETURNING answer",
+            )
+            .bind(self.id)
+            .bind(user_id)
+            .bind(answer)
+            .fetch_all(&mut tx)
+            .await?
+            .into_iter()
+            .map(|(answer,)| answer)
+            .collect()
+        };
+        sqlx::query(
+            "INSERT INTO poll_votes (poll_id, user_id, answer) VALUES ($1, $2, $3) ON CONFLICT DO NOTHING",
+        )
+        .bind(self.id)
+        .bind(user_id)
+        .bind(answer)
+        .execute(&mut tx)
+        .await?;
+        tx.commit().await?;
+
+        Ok(replaced)
+    }
+
+    /// Returns 0 if there was no such vote
+    pub async fn unvote(&self, pool: &PgPool, user_id: i64, answer: i32) -> Result<u64, DbError> {
+        Ok(sqlx::query(
+            "DELETE FROM poll_votes WHERE poll_id = $1 AND user_id = $2 AND answer = $3",
+        )
+        .bind(self.id)
+        .bind(user_id)
+        .bind(answer)
+        .execute(pool)
+        .await?
+        .rows_affected())
+    }
+}
@@ -0,0 +1,112 @@
+use chrono::{DateTime, Utc};
+use sqlx::postgres::PgPool;
+
+pub type DbError = sqlx::Error;
+
+#[derive(Clone, Debug, sqlx::FromRow)]
+pub struct Sanction {
+    pub id: i64,
+    pub server_id: i64,
+    pub user_id: i64,
+    pub moderator_id: i64,
+    pub kind: String, // mute or ban
+    pub reason: String,
+    pub end_time: Option<DateTime<Utc>>,
+    pub created_at: DateTime<Utc>,
+    pub appeal: Option<String>,
+    pub appeal_msg_id: Option<i64>,
+    pub appeal_status: String, // none, pending, accepted or denied
+    pub decided_by: Option<i64>,
+}
+
+impl Sanction {
+    pub async fn get(pool: &PgPool, id: i64) -> Result<Self, DbError> {
+        sqlx::query_as::<_, Self>("SELECT * FROM sanctions WHERE id = $1")
+            .bind(id)
+            .fetch_one(pool)
+            .await
+    }
+
+    pub async fn by_appeal_msg(pool: &PgPool, appeal_msg_id: i64) -> Result<Self, DbError> {
+        sqlx::query_as::<_, Self>("SELECT * FROM sanctions WHERE appeal_msg_id = $1")
+            .bind(appeal_msg_id)
+            .fetch_one(pool)
+            .await
+    }
+
+    pub async fn create(
+        pool: &PgPool,
+        server_id: i64,
+        user_id: i64,
+        moderator_id: i64,
+        kind: &str,
+        reason: &str,
+        end_time: Option<DateTime<Utc>>,
+    ) -> Result<Self, DbError> {
+        sqlx::query_as::<_, Self>(
+            "INSERT INTO sanctions (server_id, user_id, moderator_id, kind, reason, end_time) VALUES ($1,$2,$3,$4,$5,$6) RETURNING *",
+        )
+        .bind(server_id)
+        .bind(user_id)
+        .bind(moderator_id)
+        .bind(kind)
+        .bind(reason)
+        .bind(end_time)
+        .fetch_one(pool)
+        .await
+    }
+
+    /// Claims the sanction for an appeal, `None` if it was appealed already
+    pub async fn appeal(pool: &PgPool, id: i64, appeal: &str) -> Result<Option<Self>, DbError> {
+        sqlx::query_as::<_, Self>(
+            "UPDATE sanctions SET (appeal, appeal_status) = ($1,'pending') WHERE id = $2 AND appeal_status = 'none' RETURNING *",
+        )
+        .bind(appeal)
+        .bind(id)
+        .fetch_optional(pool)
+        .await
+    }
+
+    /// Links the posted appeal, the mods decide with reactions on it
+    pub async fn set_appeal_msg(
+        pool: &PgPool,
+        id: i64,
+        appeal_msg_id: i64,
+    ) -> Result<Self, DbError> {
+        sqlx::query_as::<_, Self>(
+            "UPDATE sanctions SET appeal_msg_id = $1 WHERE id = $2 RETURNING *",
+        )
+        .bind(appeal_msg_id)
+        .bind(id)
+        .fetch_one(pool)
+        .await
+    }
+
+    /// Releases a claimed appeal that could not be posted, so the user can try again
+    pub async fn withdraw_appeal(pool: &PgPool, id: i64) -> Result<u64, DbError> {
+        sqlx::query(
+            "UPDATE sanctions SET (appeal, appeal_status) = (NULL,'none') WHERE id = $1 AND appeal_status = 'pending' AND appeal_msg_id IS NULL",
+        )
+        .bind(id)
+        .execute(pool)
+        .await
+        .map(|r| r.rows_affected())
+    }
+
+    /// Records the decision, only pending appeals can be decided
+    pub async fn decide(
+        pool: &PgPool,
+        id: i64,
+        accepted: bool,
+        decided_by: i64,
+    ) -> Result<Self, DbError> {
+        sqlx::query_as::<_, Self>(
+            "UPDATE sanctions SET (appeal_status, decided_by) = ($1,$2) WHERE id = $3 AND appeal_status = 'pending' RETURNING *",
+        )
+        .bind(if accepted { "accepted" } else { "denied" })
+        .bind(decided_by)
+        .bind(id)
+        .fetch_one(pool)
+        .await
+    }
+}
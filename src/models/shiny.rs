@@ -1,7 +1,9 @@
+use super::shiny_contribution::ShinyContribution;
 use sqlx::postgres::PgPool;
 
 pub type DbError = sqlx::Error;
 
+/// The shiny total of a user on a server
 #[derive(Debug, sqlx::FromRow)]
 pub struct Shiny {
     pub id: i64,
@@ -12,48 +14,124 @@ pub struct Shiny {
 }
 
 impl Shiny {
-    pub async fn get(pool: &PgPool, user_id: i64) -> Result<Self, DbError> {
-        sqlx::query_as::<_, Self>("SELECT * FROM shinys WHERE user_id = $1")
+    pub async fn get(pool: &PgPool, server_id: i64, user_id: i64) -> Result<Self, DbError> {
+        sqlx::query_as::<_, Self>("SELECT * FROM shinys WHERE server_id = $1 AND user_id = $2")
+            .bind(server_id)
             .bind(user_id)
             .fetch_one(pool)
             .await
     }
 
     pub async fn list(pool: &PgPool, server_id: i64) -> Result<Vec<Self>, DbError> {
-        sqlx::query_as::<_, Self>("SELECT * FROM shinys WHERE server_id = $1")
-            .bind(server_id)
-            .fetch_all(pool)
-            .await
+        sqlx::query_as::<_, Self>(
+            "SELECT * FROM shinys WHERE server_id = $1 ORDER BY amount DESC, id",
+        )
+        .bind(server_id)
+        .fetch_all(pool)
+        .await
     }
 
-    pub async fn create(
+    /// Adds the amount to the total and records it in the history, the username is refreshed
+    pub async fn contribute(
         pool: &PgPool,
         server_id: i64,
         user_id: i64,
-        username: String,
+        username: &str,
         amount: i64,
-    ) -> Result<Self, DbError> {
-        sqlx::query_as::<_, Self>("INSERT INTO shinys (server_id, user_id, username, amount) VALUES ($1, $2, $3, $4) RETURNING *")
-            .bind(server_id)
-            .bind(user_id)
-            .bind(username)
-            .bind(amount)
-            .fetch_one(pool).await
+        note: Option<&str>,
+    ) -> Result<(Self, ShinyContribution), DbError> {
+        let mut tx = pool.begin().await?;
+        let shiny = sqlx::query_as::<_, Self>(
+            "INSERT INTO shinys (server_id, user_id, username, amount) VALUES ($1, $2, $3, $4)
+            ON CONFLICT (server_id, user_id) DO UPDATE SET (username, amount) = ($3, shinys.amount + $4)
+            RETURNING *",
+        )
+        .bind(server_id)
+        .bind(user_id)
+        .bind(username)
+        .bind(amount)
+        .fetch_one(&mut tx)
+        .await?;
+        let contribution = sqlx::query_as::<_, ShinyContribution>(
+            "INSERT INTO shiny_contributions (server_id, user_id, amount, note) VALUES ($1, $2, $3, $4) RETURNING *",
+        )
+        .bind(server_id)
+        .bind(user_id)
+        .bind(amount)
+        .bind(note)
+        .fetch_one(&mut tx)
+        .await?;
+        tx.commit().await?;
+
+        Ok((shiny, contribution))
     }
 
-    pub async fn update(pool: &PgPool, user_id: i64, amount: i64) -> Result<Self, DbError> {
-        sqlx::query_as::<_, Self>("UPDATE shinys SET amount = $1 WHERE user_id = $2 RETURNING *")
-            .bind(amount)
-            .bind(user_id)
-            .fetch_one(pool)
-            .await
+    /// Sets the total, the difference is recorded in the history. The row is locked while the
+    /// difference is worked out, so contributions in the meantime are not lost.
+    pub async fn set(
+        pool: &PgPool,
+        server_id: i64,
+        user_id: i64,
+        username: &str,
+        amount: i64,
+        note: &str,
+    ) -> Result<Self, DbError> {
+        let mut tx = pool.begin().await?;
+        // a new user gets a row first, there would be nothing to lock otherwise
+        sqlx::query(
+            "INSERT INTO shinys (server_id, user_id, username, amount) VALUES ($1, $2, $3, 0)
+            ON CONFLICT (server_id, user_id) DO NOTHING",
+        )
+        .bind(server_id)
+        .bind(user_id)
+        .bind(username)
+        .execute(&mut tx)
+        .await?;
+        let current = sqlx::query_as::<_, Self>(
+            "SELECT * FROM shinys WHERE server_id = $1 AND user_id = $2 FOR UPDATE",
+        )
+        .bind(server_id)
+        .bind(user_id)
+        .fetch_one(&mut tx)
+        .await?;
+        let shiny = sqlx::query_as::<_, Self>(
+            "UPDATE shinys SET (username, amount) = ($2, $3) WHERE id = $1 RETURNING *",
+        )
+        .bind(current.id)
+        .bind(username)
+        .bind(amount)
+        .fetch_one(&mut tx)
+        .await?;
+        sqlx::query(
+            "INSERT INTO shiny_contributions (server_id, user_id, amount, note) VALUES ($1, $2, $3, $4)",
+        )
+        .bind(server_id)
+        .bind(user_id)
+        .bind(amount - current.amount)
+        .bind(note)
+        .execute(&mut tx)
+        .await?;
+        tx.commit().await?;
+
+        Ok(shiny)
     }
 
-    pub async fn delete(pool: &PgPool, user_id: i64) -> Result<u64, DbError> {
-        Ok(sqlx::query("DELETE FROM shinys WHERE user_id = $1")
+    /// Removes the total of the user together with the history
+    pub async fn delete(pool: &PgPool, server_id: i64, user_id: i64) -> Result<u64, DbError> {
+        let mut tx = pool.begin().await?;
+        sqlx::query("DELETE FROM shiny_contributions WHERE server_id = $1 AND user_id = $2")
+            .bind(server_id)
             .bind(user_id)
-            .execute(pool)
+            .execute(&mut tx)
+            .await?;
+        let deleted = sqlx::query("DELETE FROM shinys WHERE server_id = $1 AND user_id = $2")
+            .bind(server_id)
+            .bind(user_id)
+            .execute(&mut tx)
             .await?
-            .rows_affected())
+            .rows_affected();
+        tx.commit().await?;
+
+        Ok(deleted)
     }
 }
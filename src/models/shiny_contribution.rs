@@ -0,0 +1,92 @@
+use chrono::{DateTime, Utc};
+use sqlx::postgres::PgPool;
+
+pub type DbError = sqlx::Error;
+
+/// A single change of a shiny total
+#[derive(Debug, Clone, sqlx::FromRow)]
+pub struct ShinyContribution {
+    pub id: i64,
+    pub server_id: i64,
+    pub user_id: i64,
+    pub amount: i64,
+    pub note: Option<String>,
+    pub created_at: DateTime<Utc>,
+}
+
+impl ShinyContribution {
+    /// Newest first, of a single user or of everyone on the server
+    pub async fn recent(
+        pool: &PgPool,
+        server_id: i64,
+        user_id: Option<i64>,
+        limit: i64,
+    ) -> Result<Vec<Self>, DbError> {
+        sqlx::query_as::<_, Self>(
+            "SELECT * FROM shiny_contributions WHERE server_id = $1 AND ($2::INT8 IS NULL OR user_id = $2) ORDER BY id DESC LIMIT $3",
+        )
+        .bind(server_id)
+        .bind(user_id)
+        .bind(limit)
+        .fetch_all(pool)
+        .await
+    }
+
+    /// Sums per period, newest first. `period` is a unit of `date_trunc` like `week`.
+    pub async fn totals(
+        pool: &PgPool,
+        server_id: i64,
+        period: &str,
+        limit: i64,
+    ) -> Result<Vec<(DateTime<Utc>, i64)>, DbError> {
+        sqlx::query_as(
+            "SELECT date_trunc($2, created_at) AS period, SUM(amount)::INT8 FROM shiny_contributions WHERE server_id = $1 GROUP BY period ORDER BY period DESC LIMIT $3",
+        )
+        .bind(server_id)
+        .bind(period)
+        .bind(limit)
+        .fetch_all(pool)
+        .await
+    }
+
+    pub async fn sum_since(
+        pool: &PgPool,
+        server_id: i64,
+        since: DateTime<Utc>,
+    ) -> Result<i64, DbError> {
+        let (sum,): (i64,) = sqlx::query_as(
+            "SELECT COALESCE(SUM(amount), 0)::INT8 FROM shiny_contributions WHERE server_id = $1 AND created_at >= $2",
+        )
+        .bind(server_id)
+        .bind(since)
+        .fetch_one(pool)
+        .await?;
+
+        Ok(sum)
+    }
+
+    /// Removes the contribution and takes it back from the total, `None` if it does not exist
+    pub async fn undo(pool: &PgPool, server_id: i64, id: i64) -> Result<Option<Self>, DbError> {
+        let mut tx = pool.begin().await?;
+        let contribution = match sqlx::query_as::<_, Self>(
+            "DELETE FROM shiny_contributions WHERE server_id = $1 AND id = $2 RETURNING *",
+        )
+        .bind(server_id)
+        .bind(id)
+        .fetch_optional(&mut tx)
+        .await?
+        {
+            Some(contribution) => contribution,
+            None => return Ok(None),
+        };
+        sqlx::query("UPDATE shinys SET amount = amount - $1 WHERE server_id = $2 AND user_id = $3")
+            .bind(contribution.amount)
+            .bind(server_id)
+            .bind(contribution.user_id)
+            .execute(&mut tx)
+            .await?;
+        tx.commit().await?;
+
+        Ok(Some(contribution))
+    }
+}
@@ -0,0 +1,104 @@
+use chrono::{DateTime, Utc};
+use sqlx::postgres::PgPool;
+
+pub type DbError = sqlx::Error;
+
+/// An item mods list in the shop of a server
+#[derive(Debug, Clone, sqlx::FromRow)]
+pub struct ShopItem {
+    pub id: i64,
+    pub server_id: i64,
+    pub name: String,
+    pub kind: String,
+    pub price: i64,
+    pub role_id: Option<i64>,
+    pub duration_hours: Option<i64>,
+    pub created_at: DateTime<Utc>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ItemKind {
+    /// Grants an existing role
+    Role,
+    /// Creates a role in a color of the buyer's choice
+    Color,
+    /// Changes the nickname of the buyer once
+    Nickname,
+    /// Lets the bot ping the channel with a message of the buyer
+    Ping,
+}
+
+impl ItemKind {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Self::Role => "role",
+            Self::Color => "color",
+            Self::Nickname => "nickname",
+            Self::Ping => "ping",
+        }
+    }
+
+    pub fn parse(kind: &str) -> Option<Self> {
+        [Self::Role, Self::Color, Self::Nickname, Self::Ping]
+            .iter()
+            .copied()
+            .find(|k| k.as_str() == kind)
+    }
+}
+
+impl ShopItem {
+    pub fn kind(&self) -> Option<ItemKind> {
+        ItemKind::parse(&self.kind)
+    }
+
+    pub async fn get(pool: &PgPool, server_id: i64, id: i64) -> Result<Self, DbError> {
+        sqlx::query_as::<_, Self>("SELECT * FROM shop_items WHERE server_id = $1 AND id = $2")
+            .bind(server_id)
+            .bind(id)
+            .fetch_one(pool)
+            .await
+    }
+
+    pub async fn list(pool: &PgPool, server_id: i64) -> Result<Vec<Self>, DbError> {
+        sqlx::query_as::<_, Self>(
+            "SELECT * FROM shop_items WHERE server_id = $1 ORDER BY price, id",
+        )
+        .bind(server_id)
+        .fetch_all(pool)
+        .await
+    }
+
+    pub async fn create(
+        pool: &PgPool,
+        server_id: i64,
+        name: &str,
+        kind: ItemKind,
+        price: i64,
+        role_id: Option<i64>,
+        duration_hours: Option<i64>,
+    ) -> Result<Self, DbError> {
+        sqlx::query_as::<_, Self>(
+            "INSERT INTO shop_items (server_id, name, kind, price, role_id, duration_hours) VALUES ($1, $2, $3, $4, $5, $6) RETURNING *",
+        )
+        .bind(server_id)
+        .bind(name)
+        .bind(kind.as_str())
+        .bind(price)
+        .bind(role_id)
+        .bind(duration_hours)
+        .fetch_one(pool)
+        .await
+    }
+
+    /// Takes the item out of the shop, bought items stay in the inventories
+    pub async fn delete(pool: &PgPool, server_id: i64, id: i64) -> Result<u64, DbError> {
+        Ok(
+            sqlx::query("DELETE FROM shop_items WHERE server_id = $1 AND id = $2")
+                .bind(server_id)
+                .bind(id)
+                .execute(pool)
+                .await?
+                .rows_affected(),
+        )
+    }
+}
@@ -10,21 +10,169 @@ pub struct Tag {
     pub label: String,
 }
 
+/// What a tag reply does to the tags of a fav
+#[derive(Debug, PartialEq, Eq)]
+pub enum TagChange {
+    /// Plain labels replace all tags
+    Replace(Vec<String>),
+    /// `+label` and `-label` add or remove single tags and keep the rest
+    Edit {
+        add: Vec<String>,
+        remove: Vec<String>,
+    },
+}
+
+/// Case folds the whitespace separated labels and drops duplicates, keeping their order
+pub fn normalize(input: &str) -> Vec<String> {
+    let mut labels: Vec<String> = Vec::new();
+    for label in input.split_whitespace().map(str::to_lowercase) {
+        if !labels.contains(&label) {
+            labels.push(label);
+        }
+    }
+
+    labels
+}
+
+impl TagChange {
+    pub fn parse(input: &str) -> Self {
+        let labels = normalize(input);
+        if !labels
+            .iter()
+            .any(|l| l.starts_with('+') || l.starts_with('-'))
+        {
+            return Self::Replace(labels);
+        }
+
+        let (mut add, mut remove) = (Vec::new(), Vec::new());
+        for label in labels {
+            if let Some(label) = label.strip_prefix('-') {
+                remove.push(label.to_string());
+            } else {
+                add.push(label.trim_start_matches('+').to_string());
+            }
+        }
+        add.retain(|l| !l.is_empty());
+        remove.retain(|l| !l.is_empty());
+
+        Self::Edit { add, remove }
+    }
+}
+
+fn distance(a: &str, b: &str) -> usize {
+    let b = b.chars().collect::<Vec<_>>();
+    let mut row = (0..=b.len()).collect::<Vec<_>>();
+
+    for (i, ca) in a.chars().enumerate() {
+        let mut diagonal = row[0];
+        row[0] = i + 1;
+        for (j, cb) in b.iter().enumerate() {
+            let above = row[j + 1];
+            row[j + 1] = (above + 1)
+                .min(row[j] + 1)
+                .min(diagonal + usize::from(ca != *cb));
+            diagonal = above;
+        }
+    }
+
+    row[b.len()]
+}
+
+/// Known labels the input might have meant: completions first, then close typos
+pub fn suggest<'a>(known: &'a [String], input: &str) -> Vec<&'a str> {
+    let input = input.to_lowercase();
+    let mut completions = known
+        .iter()
+        .filter(|l| l.starts_with(&input) && **l != input)
+        .map(String::as_str)
+        .collect::<Vec<_>>();
+    let mut typos = known
+        .iter()
+        .map(|l| (distance(l, &input), l.as_str()))
+        .filter(|(d, l)| *d > 0 && *d <= 2 && !l.starts_with(&input))
+        .collect::<Vec<_>>();
+    typos.sort_unstable();
+
+    completions.sort_unstable();
+    completions.extend(typos.into_iter().map(|(_, l)| l));
+    completions.truncate(5);
+    completions
+}
+
 impl Tag {
-    pub async fn create(pool: &PgPool, fav_id: i64, label: &str) -> Result<Self, DbError> {
-        sqlx::query_as::<_, Self>("INSERT INTO tags (fav_id, label) VALUES ($1,$2) RETURNING *")
+    /// Changes the tags of the fav in one transaction, new labels are inserted in one batch
+    pub async fn apply(pool: &PgPool, fav_id: i64, change: &TagChange) -> Result<(), DbError> {
+        let mut tx = pool.begin().await?;
+        let nothing = Vec::new();
+        let (add, remove) = match change {
+            TagChange::Replace(labels) => {
+                sqlx::query("DELETE FROM tags WHERE fav_id = $1")
+                    .bind(fav_id)
+                    .execute(&mut tx)
+                    .await?;
+                (labels, &nothing)
+            }
+            TagChange::Edit { add, remove } => (add, remove),
+        };
+
+        sqlx::query("DELETE FROM tags WHERE fav_id = $1 AND label = ANY($2)")
             .bind(fav_id)
-            .bind(label)
-            .fetch_one(pool)
-            .await
+            .bind(remove)
+            .execute(&mut tx)
+            .await?;
+        sqlx::query(
+            "INSERT INTO tags (fav_id, label) SELECT $1, * FROM UNNEST($2::TEXT[]) ON CONFLICT DO NOTHING",
+        )
+        .bind(fav_id)
+        .bind(add)
+        .execute(&mut tx)
+        .await?;
+
+        tx.commit().await
     }
 
-    pub async fn delete(pool: &PgPool, fav_id: i64) -> Result<u64, DbError> {
-        Ok(sqlx::query("DELETE FROM tags WHERE fav_id = $1")
+    /// Changes the label on all favs of the user, favs that already have the new label
+    /// just lose the old one. Returns the ids of the changed favs.
+    pub async fn relabel(
+        pool: &PgPool,
+        user_id: i64,
+        old: &str,
+        new: &str,
+    ) -> Result<Vec<i64>, DbError> {
+        let mut tx = pool.begin().await?;
+        let fav_ids: Vec<(i64,)> = sqlx::query_as(
+            "SELECT tags.fav_id FROM tags INNER JOIN favs ON tags.fav_id = favs.id WHERE favs.user_id = $1 AND tags.label = $2",
+        )
+        .bind(user_id)
+        .bind(old)
+        .fetch_all(&mut tx)
+        .await?;
+        let fav_ids = fav_ids.into_iter().map(|(id,)| id).collect::<Vec<_>>();
+
+        sqlx::query(
+            "DELETE FROM tags WHERE fav_id = ANY($1) AND label = $2 AND EXISTS (SELECT 1 FROM tags AS t WHERE t.fav_id = tags.fav_id AND t.id <> tags.id AND t.label = $3)",
+        )
+        .bind(&fav_ids)
+        .bind(old)
+        .bind(new)
+        .execute(&mut tx)
+        .await?;
+        sqlx::query("UPDATE tags SET label = $1 WHERE fav_id = ANY($2) AND label = $3")
+            .bind(new)
+            .bind(&fav_ids)
+            .bind(old)
+            .execute(&mut tx)
+            .await?;
+        tx.commit().await?;
+
+        Ok(fav_ids)
+    }
+
+    pub async fn of_fav(pool: &PgPool, fav_id: i64) -> Result<Vec<Self>, DbError> {
+        sqlx::query_as::<_, Self>("SELECT * FROM tags WHERE fav_id = $1")
             .bind(fav_id)
-            .execute(pool)
-            .await?
-            .rows_affected())
+            .fetch_all(pool)
+            .await
     }
 
     pub async fn of_user(pool: &PgPool, user_id: i64) -> Result<Vec<Self>, DbError> {
@@ -32,4 +180,77 @@ impl Tag {
             .bind(user_id)
             .fetch(pool).try_collect::<Vec<_>>().await?)
     }
+
+    pub async fn of_server(pool: &PgPool, server_id: i64) -> Result<Vec<Self>, DbError> {
+        sqlx::query_as::<_, Self>("SELECT tags.id, tags.fav_id, tags.label FROM tags INNER JOIN favs ON tags.fav_id = favs.id WHERE favs.server_id = $1")
+            .bind(server_id)
+            .fetch_all(pool)
+            .await
+    }
+
+    /// The labels the user tagged most favs with and on how many
+    pub async fn top_of_user(
+        pool: &PgPool,
+        user_id: i64,
+        limit: i64,
+    ) -> Result<Vec<(String, i64)>, DbError> {
+        sqlx::query_as(
+            "SELECT tags.label, COUNT(*) AS count FROM tags INNER JOIN favs ON tags.fav_id = favs.id WHERE favs.user_id = $1 GROUP BY tags.label ORDER BY count DESC, tags.label LIMIT $2",
+        )
+        .bind(user_id)
+        .bind(limit)
+        .fetch_all(pool)
+        .await
+    }
+
+    /// The distinct labels the user has, sorted
+    pub async fn labels_of_user(pool: &PgPool, user_id: i64) -> Result<Vec<String>, DbError> {
+        let labels: Vec<(String,)> = sqlx::query_as(
+            "SELECT DISTINCT tags.label FROM tags INNER JOIN favs ON tags.fav_id = favs.id WHERE favs.user_id = $1 ORDER BY tags.label",
+        )
+        .bind(user_id)
+        .fetch_all(pool)
+        .await?;
+
+        Ok(labels.into_iter().map(|(label,)| label).collect())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn normalize_labels() {
+        assert_eq!(normalize(" Haus  turm haus\tTURM "), vec!["haus", "turm"]);
+        assert!(normalize("   ").is_empty());
+    }
+
+    #[test]
+    fn parse_change() {
+        assert_eq!(
+            TagChange::parse("Haus turm"),
+            TagChange::Replace(vec!["haus".to_string(), "turm".to_string()])
+        );
+        assert_eq!(
+            TagChange::parse("+auto -Fenster haus -"),
+            TagChange::Edit {
+                add: vec!["auto".to_string(), "haus".to_string()],
+                remove: vec!["fenster".to_string()],
+            }
+        );
+    }
+
+    #[test]
+    fn suggestions() {
+        let known = ["haus", "hausboot", "maus", "turm"]
+            .iter()
+            .map(ToString::to_string)
+            .collect::<Vec<_>>();
+
+        assert_eq!(suggest(&known, "Hau"), vec!["haus", "hausboot", "maus"]);
+        assert_eq!(suggest(&known, "haus"), vec!["hausboot", "maus"]);
+        assert_eq!(suggest(&known, "trum"), vec!["turm"]);
+        assert!(suggest(&known, "xyz").is_empty());
+    }
 }
@@ -0,0 +1,80 @@
+use chrono::{DateTime, Utc};
+use sqlx::postgres::PgPool;
+
+pub type DbError = sqlx::Error;
+
+#[derive(Clone, Debug, sqlx::FromRow)]
+pub struct Ticket {
+    pub id: i64,
+    pub server_id: i64,
+    pub user_id: i64,
+    pub channel_id: i64,
+    pub open: bool,
+    pub created_at: DateTime<Utc>,
+    pub closed_at: Option<DateTime<Utc>>,
+    pub closed_by: Option<i64>,
+}
+
+impl Ticket {
+    /// The open tickets of a user over all servers
+    pub async fn open_of_user(pool: &PgPool, user_id: i64) -> Result<Vec<Self>, DbError> {
+        sqlx::query_as::<_, Self>(
+            "SELECT * FROM tickets WHERE user_id = $1 AND open ORDER BY created_at DESC",
+        )
+        .bind(user_id)
+        .fetch_all(pool)
+        .await
+    }
+
+    pub async fn in_channel(pool: &PgPool, channel_id: i64) -> Result<Vec<Self>, DbError> {
+        sqlx::query_as::<_, Self>("SELECT * FROM tickets WHERE channel_id = $1 ORDER BY id")
+            .bind(channel_id)
+            .fetch_all(pool)
+            .await
+    }
+
+    pub async fn list_open(pool: &PgPool, server_id: i64) -> Result<Vec<Self>, DbError> {
+        sqlx::query_as::<_, Self>(
+            "SELECT * FROM tickets WHERE server_id = $1 AND open ORDER BY created_at",
+        )
+        .bind(server_id)
+        .fetch_all(pool)
+        .await
+    }
+
+    pub async fn create(
+        pool: &PgPool,
+        server_id: i64,
+        user_id: i64,
+        channel_id: i64,
+    ) -> Result<Self, DbError> {
+        sqlx::query_as::<_, Self>(
+            "INSERT INTO tickets (server_id, user_id, channel_id) VALUES ($1,$2,$3) RETURNING *",
+        )
+        .bind(server_id)
+        .bind(user_id)
+        .bind(channel_id)
+        .fetch_one(pool)
+        .await
+    }
+
+    /// Moves the ticket to another channel, used once the staff thread is created
+    pub async fn set_channel(pool: &PgPool, id: i64, channel_id: i64) -> Result<Self, DbError> {
+        sqlx::query_as::<_, Self>("UPDATE tickets SET channel_id = $1 WHERE id = $2 RETURNING *")
+            .bind(channel_id)
+            .bind(id)
+            .fetch_one(pool)
+            .await
+    }
+
+    /// Closes the ticket, fails if it is already closed
+    pub async fn close(pool: &PgPool, id: i64, closed_by: i64) -> Result<Self, DbError> {
+        sqlx::query_as::<_, Self>(
+            "UPDATE tickets SET (open, closed_at, closed_by) = (FALSE, NOW(), $1) WHERE id = $2 AND open RETURNING *",
+        )
+        .bind(closed_by)
+        .bind(id)
+        .fetch_one(pool)
+        .await
+    }
+}
@@ -0,0 +1,46 @@
+use chrono::{DateTime, Utc};
+use sqlx::postgres::PgPool;
+
+pub type DbError = sqlx::Error;
+
+#[derive(Debug, sqlx::FromRow)]
+pub struct TicketMessage {
+    pub id: i64,
+    pub ticket_id: i64,
+    pub author_id: i64,
+    pub from_staff: bool,
+    pub anonymous: bool,
+    pub content: String,
+    pub created_at: DateTime<Utc>,
+}
+
+impl TicketMessage {
+    pub async fn of_ticket(pool: &PgPool, ticket_id: i64) -> Result<Vec<Self>, DbError> {
+        sqlx::query_as::<_, Self>(
+            "SELECT * FROM ticket_messages WHERE ticket_id = $1 ORDER BY created_at",
+        )
+        .bind(ticket_id)
+        .fetch_all(pool)
+        .await
+    }
+
+    pub async fn create(
+        pool: &PgPool,
+        ticket_id: i64,
+        author_id: i64,
+        from_staff: bool,
+        anonymous: bool,
+        content: &str,
+    ) -> Result<Self, DbError> {
+        sqlx::query_as::<_, Self>(
+            "INSERT INTO ticket_messages (ticket_id, author_id, from_staff, anonymous, content) VALUES ($1,$2,$3,$4,$5) RETURNING *",
+        )
+        .bind(ticket_id)
+        .bind(author_id)
+        .bind(from_staff)
+        .bind(anonymous)
+        .bind(content)
+        .fetch_one(pool)
+        .await
+    }
+}
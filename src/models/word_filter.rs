@@ -0,0 +1,50 @@
+use sqlx::postgres::PgPool;
+
+pub type DbError = sqlx::Error;
+
+#[derive(Debug, sqlx::FromRow)]
+pub struct WordFilter {
+    pub id: i64,
+    pub server_id: i64,
+    pub kind: String,
+    pub pattern: String,
+    pub action: serde_json::Value,
+}
+
+impl WordFilter {
+    pub async fn list(pool: &PgPool, server_id: i64) -> Result<Vec<Self>, DbError> {
+        sqlx::query_as::<_, Self>("SELECT * FROM word_filters WHERE server_id = $1 ORDER BY id")
+            .bind(server_id)
+            .fetch_all(pool)
+            .await
+    }
+
+    pub async fn create(
+        pool: &PgPool,
+        server_id: i64,
+        kind: &str,
+        pattern: &str,
+        action: serde_json::Value,
+    ) -> Result<Self, DbError> {
+        sqlx::query_as::<_, Self>(
+            "INSERT INTO word_filters (server_id, kind, pattern, action) VALUES ($1,$2,$3,$4) RETURNING *",
+        )
+        .bind(server_id)
+        .bind(kind)
+        .bind(pattern)
+        .bind(action)
+        .fetch_one(pool)
+        .await
+    }
+
+    pub async fn delete(pool: &PgPool, server_id: i64, id: i64) -> Result<u64, DbError> {
+        Ok(
+            sqlx::query("DELETE FROM word_filters WHERE server_id = $1 AND id = $2")
+                .bind(server_id)
+                .bind(id)
+                .execute(pool)
+                .await?
+                .rows_affected(),
+        )
+    }
+}
@@ -1,4 +1,12 @@
+use crate::commands::account::shop::expire_role;
+use crate::commands::moderation::lift_lockdown;
+use crate::commands::poll::close_poll;
+use crate::models::blackjack_round::BlackjackRound;
+use crate::models::duel::Duel;
+use crate::models::inventory_item::InventoryItem;
+use crate::models::lockdown::Lockdown;
 use crate::models::mute::Mute;
+use crate::models::poll::Poll;
 use crate::models::reminder::Reminder;
 use crate::models::server_config::ServerConfig;
 use crate::DatabasePool;
@@ -81,6 +89,47 @@ pub async fn init(client: &serenity::Client) {
         }
     }
 
+    // duels interrupted by a restart can not be decided anymore
+    for d in Duel::list_open(&pool).await.unwrap() {
+        if let Err(e) = Duel::refund(&pool, d.id).await {
+            error!("Could not refund duel {}: {:?}", d.id, e);
+        }
+    }
+
+    // same for blackjack rounds, the player gets the bet back
+    for r in BlackjackRound::list_open(&pool).await.unwrap() {
+        if let Err(e) = BlackjackRound::refund(&pool, r.id).await {
+            error!("Could not refund blackjack round {}: {:?}", r.id, e);
+        }
+    }
+
+    // restart removals of timed shop roles
+    for item in InventoryItem::list_expiring(&pool).await.unwrap() {
+        tokio::spawn(expire_role(
+            client.cache_and_http.http.clone(),
+            pool.clone(),
+            item,
+        ));
+    }
+
+    // restart closing of polls
+    for p in Poll::list_open(&pool).await.unwrap() {
+        tokio::spawn(close_poll(
+            client.cache_and_http.http.clone(),
+            pool.clone(),
+            p,
+        ));
+    }
+
+    // restart lockdown removals
+    for l in Lockdown::list_timed(&pool).await.unwrap() {
+        tokio::spawn(lift_lockdown(
+            client.cache_and_http.http.clone(),
+            pool.clone(),
+            l,
+        ));
+    }
+
     // restart unmute futures
     let server_configs = ServerConfig::list(&pool).await.unwrap();
 
@@ -184,3 +233,66 @@ pub async fn init_xkcd(config: &crate::config::Config) {
         .map_err(|_| "Could not init xkcd index reader")
         .unwrap();
 }
+
+pub async fn init_fav_index(config: &crate::config::Config) {
+    use crate::FAV_INDEX;
+    use crate::FAV_INDEX_READER;
+    use crate::FAV_INDEX_SCHEMA;
+    use crate::FAV_INDEX_WRITER;
+    use tantivy::schema::*;
+
+    let mut schema_builder = Schema::builder();
+    schema_builder.add_u64_field("fav_id", IntOptions::default().set_stored().set_indexed());
+    schema_builder.add_u64_field("user_id", IntOptions::default().set_indexed());
+    schema_builder.add_u64_field(
+        "author_id",
+        IntOptions::default().set_stored().set_indexed(),
+    );
+    schema_builder.add_text_field("content", TEXT | STORED);
+    schema_builder.add_text_field("author", TEXT | STORED);
+    schema_builder.add_text_field("channel", TEXT | STORED);
+    schema_builder.add_text_field("tags", TEXT | STORED);
+    schema_builder.add_text_field("link", STORED);
+    FAV_INDEX_SCHEMA
+        .set(schema_builder.build())
+        .map_err(|_| "Could not init fav index schema")
+        .unwrap();
+
+    let schema = FAV_INDEX_SCHEMA
+        .get()
+        .expect("Fav index schema not initialized");
+
+    let index = if std::path::Path::new(&config.fav_index).is_dir() {
+        tantivy::Index::open_in_dir(&config.fav_index)
+            .map_err(|e| format!("Failed to load tantivy index: {}", e))
+            .expect("Could not load fav index")
+    } else {
+        tokio::fs::create_dir_all(&config.fav_index)
+            .await
+            .expect("Could not create folder for fav index");
+        tantivy::Index::create_in_dir(&config.fav_index, schema.clone())
+            .map_err(|e| format!("Failed to create tantivy index: {}", e))
+            .expect("Could not create fav index")
+    };
+
+    // there can only be one writer per index, it is shared by all fav updates
+    let writer = index
+        .writer_with_num_threads(1, 15_000_000)
+        .expect("Failed to init fav index writer");
+    FAV_INDEX_WRITER
+        .set(std::sync::Mutex::new(writer))
+        .map_err(|_| "Could not init fav index writer")
+        .unwrap();
+
+    let reader: tantivy::IndexReader = index
+        .reader_builder()
+        .reload_policy(tantivy::ReloadPolicy::OnCommit)
+        .try_into()
+        .expect("Failed to init fav index reader");
+
+    FAV_INDEX.set(index).unwrap();
+    FAV_INDEX_READER
+        .set(reader)
+        .map_err(|_| "Could not init fav index reader")
+        .unwrap();
+}
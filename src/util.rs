@@ -1,9 +1,12 @@
+use crate::AwaitingReply;
 use crate::DatabasePool;
 use crate::ReqwestClient;
 use chrono::Duration;
 use regex::Regex;
+use serenity::model::{channel::Message, id::ChannelId, id::UserId};
 use serenity::prelude::Context;
 use sqlx::postgres::PgPool;
+use std::sync::Arc;
 use std::time::Instant;
 
 pub async fn timed_request(
@@ -38,6 +41,63 @@ pub async fn get_client(ctx: &Context) -> Result<PgPool, Box<dyn std::error::Err
         .clone())
 }
 
+/// How long a collected reply is remembered for `is_awaited_reply`
+const COLLECTED_REPLY_TTL: std::time::Duration = std::time::Duration::from_secs(60);
+
+/// Waits for the next message of the user in the channel, marking it as awaited so it is not
+/// handled as a normal message (e.g. modmail) as well
+pub async fn await_reply(
+    ctx: &Context,
+    channel_id: ChannelId,
+    user_id: UserId,
+    timeout: std::time::Duration,
+) -> Option<Arc<Message>> {
+    let pending = ctx.data.read().await.get::<AwaitingReply>()?.clone();
+    *pending
+        .lock()
+        .await
+        .users
+        .entry(*user_id.as_u64())
+        .or_insert(0) += 1;
+
+    let reply = channel_id
+        .await_reply(ctx)
+        .author_id(user_id)
+        .timeout(timeout)
+        .await;
+
+    let mut pending = pending.lock().await;
+    if let Some(waiting) = pending.users.get_mut(user_id.as_u64()) {
+        *waiting -= 1;
+        if *waiting == 0 {
+            pending.users.remove(user_id.as_u64());
+        }
+    }
+
+    // replies that were not handled as a normal message, like commands, are forgotten later
+    let now = Instant::now();
+    pending
+        .collected
+        .retain(|_, collected_at| now.duration_since(*collected_at) < COLLECTED_REPLY_TTL);
+    if let Some(ref reply) = reply {
+        pending.collected.insert(*reply.id.as_u64(), now);
+    }
+
+    reply
+}
+
+/// Whether the message is (or is about to be) consumed by `await_reply`
+pub async fn is_awaited_reply(ctx: &Context, msg: &Message) -> bool {
+    match ctx.data.read().await.get::<AwaitingReply>() {
+        Some(pending) => {
+            let mut pending = pending.lock().await;
+            pending.collected.remove(msg.id.as_u64()).is_some()
+                || pending.users.contains_key(msg.author.id.as_u64())
+        }
+        None => false,
+    }
+}
+
 static OTHER_MOD_CMD: [char; 3] = ['%', '=', '$'];
 
 pub fn sanitize_for_other_bot_commands(output: &str) -> String {
@@ -115,9 +175,29 @@ pub fn parse_message_link(regex: &Regex, link: &str) -> Result<(u64, u64, u64),
     Ok((server_id, channel_id, msg_id))
 }
 
+/// Extracts the ids of all `<#channel>` mentions in the content
+pub fn channel_mentions(content: &str) -> Vec<u64> {
+    static CHANNEL_MENTION: once_cell::sync::Lazy<Regex> = once_cell::sync::Lazy::new(|| {
+        Regex::new(r"<#(\d+)>").expect("Could not compile channel mention regex")
+    });
+
+    CHANNEL_MENTION
+        .captures_iter(content)
+        .filter_map(|c| c.get(1).and_then(|m| m.as_str().parse::<u64>().ok()))
+        .collect()
+}
+
 #[cfg(test)]
 mod tests {
-    use super::{humanize_duration, Duration};
+    use super::{channel_mentions, humanize_duration, Duration};
+
+    #[test]
+    fn check_channel_mentions() {
+        assert_eq!(
+            channel_mentions("<#123> and <#456>, not <@789>"),
+            vec![123, 456]
+        );
+    }
 
     #[test]
     fn check_humanized_duration() {